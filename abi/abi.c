@@ -0,0 +1,68 @@
+/*
+    The C side of the ABI tests in src/lib.rs. Every function here takes or
+    returns `ustr_t` by value, alone and mixed with other arguments, so that
+    any difference between how Rust and C pass it shows up as a wrong value.
+*/
+#include "ustr.h"
+
+#include <stdint.h>
+
+typedef struct {
+    ustr_t name;
+    uint32_t value;
+} abi_pair_t;
+
+size_t abi_sizeof_ustr(void) { return sizeof(ustr_t); }
+
+size_t abi_alignof_ustr(void) { return _Alignof(ustr_t); }
+
+ustr_t abi_identity(ustr_t u) { return u; }
+
+ustr_t abi_select(uint8_t a, ustr_t b, double c, ustr_t d, int which) {
+    (void)a;
+    (void)c;
+    return which ? d : b;
+}
+
+abi_pair_t abi_make_pair(ustr_t name, uint32_t value) {
+    abi_pair_t pair = {name, value};
+    return pair;
+}
+
+ustr_t abi_pair_name(abi_pair_t pair) { return pair.name; }
+
+const char* abi_chars(ustr_t u) { return u.ptr; }
+
+/* Call back into the functions from ustr_extern.rs. */
+ustr_t abi_intern(const char* chars) { return ustr(chars); }
+
+size_t abi_len(ustr_t u) { return ustr_len(u); }
+
+uint64_t abi_hash(ustr_t u) { return ustr_hash(u); }
+
+ustr_t abi_intern_utf16(const uint16_t* units) {
+    return ustr_from_utf16(units);
+}
+
+const uint16_t* abi_utf16(ustr_t u) { return ustr_to_utf16(u); }
+
+/* Intern `chars` in a new private cache, returning whether interning it again
+   and looking it up give the same pointer. */
+int abi_private_cache(const char* chars) {
+    ustr_cache_t* cache = ustr_cache_new();
+    const char* a = ustr_cache_intern(cache, chars);
+    int same = a == ustr_cache_intern(cache, chars) &&
+               a == ustr_cache_get(cache, chars) &&
+               ustr_cache_get(cache, "not interned") == NULL;
+    ustr_cache_free(cache);
+    return same;
+}
+
+/* Returns whether ustr_abi_version() has the version in this header. */
+int abi_version_matches(void) {
+    return ustr_abi_version() >> 32 == USTR_ABI_VERSION;
+}
+
+ustr_t abi_call(ustr_t (*f)(ustr_t, uint32_t), ustr_t u, uint32_t n) {
+    return f(u, n);
+}
@@ -0,0 +1,9 @@
+fn main() {
+    println!("cargo:rerun-if-changed=abi.c");
+    println!("cargo:rerun-if-changed=../include/ustr.h");
+    cc::Build::new()
+        .file("abi.c")
+        .include("../include")
+        .warnings_into_errors(true)
+        .compile("ustr_abi");
+}
@@ -0,0 +1,148 @@
+//! Tests that `Ustr` has the ABI of `ustr_t` from `include/ustr.h`, by passing
+//! values to and from the C functions in `abi.c`.
+//!
+//! This also links the functions from `src/ustr_extern.rs`, exactly as a crate
+//! exposing `Ustr`s to C would.
+#[cfg(test)]
+#[path = "../../src/ustr_extern.rs"]
+mod ustr_extern;
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, c_int, CStr};
+    use ustr::{ustr as u, Ustr};
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Pair {
+        name: Ustr,
+        value: u32,
+    }
+
+    extern "C" {
+        fn abi_sizeof_ustr() -> usize;
+        fn abi_alignof_ustr() -> usize;
+        fn abi_identity(u: Ustr) -> Ustr;
+        fn abi_select(a: u8, b: Ustr, c: f64, d: Ustr, which: c_int) -> Ustr;
+        fn abi_make_pair(name: Ustr, value: u32) -> Pair;
+        fn abi_pair_name(pair: Pair) -> Ustr;
+        fn abi_chars(u: Ustr) -> *const c_char;
+        fn abi_intern(chars: *const c_char) -> Ustr;
+        fn abi_len(u: Ustr) -> usize;
+        fn abi_hash(u: Ustr) -> u64;
+        fn abi_intern_utf16(units: *const u16) -> Ustr;
+        fn abi_utf16(u: Ustr) -> *const u16;
+        fn abi_private_cache(chars: *const c_char) -> c_int;
+        fn abi_version_matches() -> c_int;
+        fn abi_call(
+            f: extern "C" fn(Ustr, u32) -> Ustr,
+            u: Ustr,
+            n: u32,
+        ) -> Ustr;
+    }
+
+    #[test]
+    fn test_layout() {
+        unsafe {
+            assert_eq!(abi_sizeof_ustr(), std::mem::size_of::<Ustr>());
+            assert_eq!(abi_alignof_ustr(), std::mem::align_of::<Ustr>());
+        }
+        assert_eq!(std::mem::size_of::<Ustr>(), std::mem::size_of::<usize>());
+        assert_eq!(
+            std::mem::size_of::<Option<Ustr>>(),
+            std::mem::size_of::<Ustr>()
+        );
+    }
+
+    #[test]
+    fn test_abi_version() {
+        unsafe {
+            assert_eq!(abi_version_matches(), 1);
+        }
+    }
+
+    #[test]
+    fn test_by_value() {
+        let (a, b) = (u("abi a"), u("abi b"));
+        unsafe {
+            assert_eq!(abi_identity(a), a);
+            assert_eq!(abi_select(1, a, 2.0, b, 0), a);
+            assert_eq!(abi_select(1, a, 2.0, b, 1), b);
+            let pair = abi_make_pair(a, 42);
+            assert_eq!(pair, Pair { name: a, value: 42 });
+            assert_eq!(abi_pair_name(pair), a);
+            assert_eq!(abi_chars(a), a.as_char_ptr());
+        }
+    }
+
+    #[test]
+    fn test_extern_functions() {
+        let chars = c"from C";
+        unsafe {
+            let from_c = abi_intern(chars.as_ptr());
+            assert_eq!(from_c, u("from C"));
+            assert_eq!(abi_len(from_c), 6);
+            assert_eq!(abi_hash(from_c), from_c.precomputed_hash());
+            assert_eq!(CStr::from_ptr(abi_chars(from_c)), chars);
+        }
+    }
+
+    #[test]
+    fn test_utf16_functions() {
+        let wide = "wide 🦀".encode_utf16().chain([0]).collect::<Vec<_>>();
+        unsafe {
+            let from_c = abi_intern_utf16(wide.as_ptr());
+            assert_eq!(from_c, u("wide 🦀"));
+            let units = abi_utf16(from_c);
+            assert_eq!(units, from_c.as_wide_cached().as_ptr());
+            assert_eq!(std::slice::from_raw_parts(units, wide.len()), wide);
+            assert_eq!(
+                abi_intern_utf16([0xd800, 0x61, 0].as_ptr()),
+                "\u{fffd}a"
+            );
+        }
+    }
+
+    #[test]
+    fn test_private_cache() {
+        use super::ustr_extern::*;
+
+        let cache = ustr_cache_new();
+        let global = u("already global");
+        let chars = ustr_cache_intern(cache, c"already global".as_ptr());
+        assert_eq!(chars, global.as_char_ptr());
+        let private = ustr_cache_intern(cache, c"only private".as_ptr());
+        unsafe {
+            assert_eq!(CStr::from_ptr(private), c"only private");
+        }
+        assert_eq!(ustr_cache_get(cache, c"only private".as_ptr()), private);
+        assert!(ustr_cache_get(cache, c"neither".as_ptr()).is_null());
+        assert_eq!(ustr::existing_ustr("only private"), None);
+        ustr_cache_free(cache);
+        ustr_cache_free(std::ptr::null_mut());
+
+        unsafe {
+            assert_eq!(abi_private_cache(c"from a C plugin".as_ptr()), 1);
+        }
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let cache = super::ustr_extern::ustr_shared_cache();
+        assert_eq!(cache, ustr::shared_cache() as *const _ as *const _);
+        // Attaching to the cache we're already using does nothing.
+        unsafe {
+            assert_eq!(ustr::set_external_cache(cache as *const _), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_callback() {
+        extern "C" fn suffix(u: Ustr, n: u32) -> Ustr {
+            Ustr::from(&format!("{}{}", u, n))
+        }
+        unsafe {
+            assert_eq!(abi_call(suffix, u("callback"), 7), u("callback7"));
+        }
+    }
+}
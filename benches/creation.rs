@@ -13,6 +13,8 @@ use ustr::*;
 use parking_lot::Mutex;
 
 fn criterion_benchmark(c: &mut Criterion) {
+    // Hash the same way every run, so the bins fill up the same way.
+    let _ = set_hash_seed([1, 2, 3, 4]);
     let path =
         std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap())
             .join("data")
@@ -42,6 +44,34 @@ fn criterion_benchmark(c: &mut Criterion) {
         });
     });
 
+    // The same strings as a newline-delimited word list.
+    let lines = Arc::new(
+        raft.iter()
+            .cycle()
+            .take(100_000)
+            .flat_map(|s| [s.as_bytes(), b"\n"])
+            .flatten()
+            .copied()
+            .collect::<Vec<u8>>(),
+    );
+
+    let l = lines.clone();
+    c.bench_function("single raft ustr lines", move |b| {
+        b.iter(|| {
+            unsafe { ustr::_clear_cache() };
+            for s in l.split(|b| *b == b'\n') {
+                black_box(ustr(std::str::from_utf8(s).unwrap()));
+            }
+        });
+    });
+
+    c.bench_function("single raft ustr intern_lines", move |b| {
+        b.iter(|| {
+            unsafe { ustr::_clear_cache() };
+            black_box(intern_lines(&lines[..]).unwrap());
+        });
+    });
+
     let s = raft.clone();
     c.bench_function("single raft string-interner", move |b| {
         b.iter(|| {
@@ -122,7 +152,7 @@ fn criterion_benchmark(c: &mut Criterion) {
             &format!("raft string-interner x {} threads", num_threads),
             move |b| {
                 let (tx1, rx1) = bounded::<
-                    Arc<Mutex<StringInterner<string_interner::DefaultSymbol>>>,
+                    Arc<Mutex<StringInterner<string_interner::DefaultBackend>>>,
                 >(0);
                 let (tx2, rx2) = bounded(0);
                 scope(|scope| {
@@ -0,0 +1,205 @@
+//! Benchmarks of the read path: looking up strings that are already in the
+//! cache, which is what most programs spend their time doing once they've
+//! warmed up. `creation.rs` covers filling an empty cache.
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, Criterion,
+};
+use std::collections::HashMap;
+use string_cache::DefaultAtom;
+use string_interner::StringInterner;
+
+use ustr::*;
+
+const NUM: usize = 100_000;
+
+// The words in the raft data set, joined into paths of `words_per_string`.
+fn raft(words_per_string: usize) -> Vec<String> {
+    // Hash the same way every run, so the bins fill up the same way.
+    let _ = set_hash_seed([1, 2, 3, 4]);
+    let path =
+        std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("data")
+            .join("raft-large-directories.txt");
+    let raft = std::fs::read_to_string(path).unwrap();
+    raft.split_whitespace()
+        .collect::<Vec<_>>()
+        .chunks(words_per_string)
+        .map(|s| s.join("/"))
+        .collect()
+}
+
+// `NUM` strings to look up, of which 1 in 100 isn't in `strings`.
+fn mostly_hits(strings: &[String]) -> Vec<String> {
+    strings
+        .iter()
+        .cycle()
+        .take(NUM)
+        .enumerate()
+        .map(|(i, s)| {
+            if i % 100 == 99 {
+                format!("miss {}", i)
+            } else {
+                s.clone()
+            }
+        })
+        .collect()
+}
+
+fn hits(c: &mut Criterion) {
+    let raft = raft(3);
+    let lookups = mostly_hits(&raft);
+    let mut group = c.benchmark_group("99% hits");
+
+    // Each iteration starts from a cache holding `raft`, so the misses are
+    // misses every time.
+    group.bench_function("ustr", |b| {
+        b.iter_batched(
+            || {
+                unsafe { ustr::_clear_cache() };
+                for s in &raft {
+                    ustr(s);
+                }
+            },
+            |_| {
+                for s in &lookups {
+                    black_box(ustr(s));
+                }
+            },
+            BatchSize::PerIteration,
+        );
+    });
+
+    group.bench_function("string-interner", |b| {
+        let mut warm = StringInterner::default();
+        for s in &raft {
+            warm.get_or_intern(s);
+        }
+        b.iter_batched(
+            || warm.clone(),
+            |mut interner| {
+                for s in &lookups {
+                    black_box(interner.get_or_intern(s));
+                }
+                interner
+            },
+            BatchSize::PerIteration,
+        );
+    });
+
+    group.bench_function("string-cache", |b| {
+        let warm = raft
+            .iter()
+            .map(|s| DefaultAtom::from(s.as_str()))
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            let mut v = Vec::with_capacity(NUM);
+            for s in &lookups {
+                v.push(DefaultAtom::from(s.as_str()));
+            }
+            black_box(v);
+        });
+        drop(warm);
+    });
+
+    group.finish();
+}
+
+fn long_strings(c: &mut Criterion) {
+    let raft = raft(11);
+    let mut group = c.benchmark_group("long string hits");
+
+    unsafe { ustr::_clear_cache() };
+    for s in &raft {
+        ustr(s);
+    }
+    group.bench_function("ustr", |b| {
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(ustr(s));
+            }
+        });
+    });
+
+    group.bench_function("existing_ustr", |b| {
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(existing_ustr(s));
+            }
+        });
+    });
+
+    group.bench_function("string-interner", |b| {
+        let mut interner = StringInterner::default();
+        for s in &raft {
+            interner.get_or_intern(s);
+        }
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(interner.get(s));
+            }
+        });
+    });
+
+    group.finish();
+}
+
+fn hash_only(c: &mut Criterion) {
+    let raft = raft(3);
+    let mut group = c.benchmark_group("hash-only lookups");
+
+    unsafe { ustr::_clear_cache() };
+    let ustrs = raft.iter().map(|s| ustr(s)).collect::<Vec<_>>();
+
+    group.bench_function("existing_ustr", |b| {
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(existing_ustr(s));
+            }
+        });
+    });
+
+    set_verification(Verification::HashOnly);
+    group.bench_function("existing_ustr unverified", |b| {
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(existing_ustr(s));
+            }
+        });
+    });
+    set_verification(Verification::Full);
+
+    let map = ustrs
+        .iter()
+        .enumerate()
+        .map(|(i, u)| (*u, i))
+        .collect::<UstrMap<_>>();
+    group.bench_function("UstrMap::get", |b| {
+        b.iter(|| {
+            for u in ustrs.iter().cycle().take(NUM) {
+                black_box(map.get(u));
+            }
+        });
+    });
+
+    let map = raft
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.as_str(), i))
+        .collect::<HashMap<_, _>>();
+    group.bench_function("HashMap<&str>::get", |b| {
+        b.iter(|| {
+            for s in raft.iter().cycle().take(NUM) {
+                black_box(map.get(s.as_str()));
+            }
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().sample_size(30);
+    targets = hits, long_strings, hash_only
+);
+criterion_main!(benches);
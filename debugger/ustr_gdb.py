@@ -0,0 +1,39 @@
+"""GDB pretty-printer for `ustr::Ustr`.
+
+This is embedded in binaries built with ustr's `debug-helpers` feature, so GDB
+loads it automatically if auto-loading is enabled (`rust-gdb` does this). It
+can also be loaded by hand with `source path/to/ustr_gdb.py`.
+"""
+
+import gdb
+
+
+class UstrPrinter:
+    """Shows a `Ustr` as the string it points to."""
+
+    def __init__(self, val):
+        self.val = val
+
+    def to_string(self):
+        ptr = self.val["char_ptr"]["pointer"]
+        if int(ptr) == 0:
+            return "<null Ustr>"
+        # The length is the last field of the header before the chars.
+        usize = gdb.lookup_type("usize")
+        length = int((ptr.cast(usize.pointer()) - 1).dereference())
+        chars = ptr.cast(gdb.lookup_type("u8").pointer())
+        return chars.lazy_string(encoding="utf-8", length=length)
+
+    def display_hint(self):
+        return "string"
+
+
+def lookup(val):
+    tag = val.type.strip_typedefs().tag
+    if tag == "ustr::Ustr":
+        return UstrPrinter(val)
+    return None
+
+
+objfile = gdb.current_objfile()
+(objfile if objfile is not None else gdb).pretty_printers.append(lookup)
@@ -0,0 +1,45 @@
+"""LLDB summary provider for `ustr::Ustr`.
+
+Load it with `command script import path/to/ustr_lldb.py`, e.g. from
+`~/.lldbinit`, to see `Ustr`s as the strings they point to.
+"""
+
+import json
+
+import lldb
+
+# Strings longer than this are cut short in the summary.
+MAX_LEN = 4096
+
+
+def ustr_summary(valobj, internal_dict):
+    ptr = (
+        valobj.GetNonSyntheticValue()
+        .GetChildMemberWithName("char_ptr")
+        .GetChildMemberWithName("pointer")
+        .GetValueAsUnsigned(0)
+    )
+    if ptr == 0:
+        return "<null Ustr>"
+
+    process = valobj.GetProcess()
+    error = lldb.SBError()
+    # The length is the last field of the header before the chars.
+    ptr_size = valobj.GetTarget().GetAddressByteSize()
+    length = process.ReadUnsignedFromMemory(ptr - ptr_size, ptr_size, error)
+    if error.Fail():
+        return "<invalid Ustr>"
+    data = process.ReadMemory(ptr, min(length, MAX_LEN), error)
+    if error.Fail():
+        return "<invalid Ustr>"
+    summary = json.dumps(data.decode("utf-8", "replace"), ensure_ascii=False)
+    if length > MAX_LEN:
+        summary += "..."
+    return summary
+
+
+def __lldb_init_module(debugger, internal_dict):
+    debugger.HandleCommand(
+        "type summary add -w ustr -F ustr_lldb.ustr_summary ustr::Ustr"
+    )
+    debugger.HandleCommand("type category enable ustr")
@@ -0,0 +1,185 @@
+//! Derive macros for the [`ustr`](https://docs.rs/ustr) crate.
+//!
+//! Use them through `ustr` with its `derive` feature rather than depending on
+//! this crate directly.
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields,
+    LitStr, Result,
+};
+
+/// Derive `ustr::UstrEnum` for an enum with only unit variants.
+///
+/// Each variant is named by its identifier, unless it has a
+/// `#[ustr(rename = "...")]` attribute, or the enum has a
+/// `#[ustr(rename_all = "...")]` attribute with one of `"lowercase"`,
+/// `"UPPERCASE"`, `"snake_case"`, `"SCREAMING_SNAKE_CASE"`, `"kebab-case"`
+/// or `"camelCase"`.
+#[proc_macro_derive(UstrEnum, attributes(ustr))]
+pub fn derive_ustr_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new(
+            Span::call_site(),
+            "UstrEnum can only be derived for enums",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "UstrEnum can't be derived for generic enums",
+        ));
+    }
+
+    let rename_all = match rename_attr(&input.attrs, "rename_all")? {
+        Some(lit) => Some((RenameRule::parse(&lit)?, lit)),
+        None => None,
+    };
+
+    let mut idents = Vec::new();
+    let mut names = Vec::<(String, Span)>::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new(
+                variant.span(),
+                "UstrEnum variants can't have fields",
+            ));
+        }
+        let ident = &variant.ident;
+        let name = match rename_attr(&variant.attrs, "rename")? {
+            Some(lit) => lit.value(),
+            None => match &rename_all {
+                Some((rule, _)) => rule.apply(&ident.to_string()),
+                None => ident.to_string(),
+            },
+        };
+        if names.iter().any(|(n, _)| *n == name) {
+            return Err(Error::new(
+                variant.span(),
+                format!("two variants are named \"{}\"", name),
+            ));
+        }
+        idents.push(ident);
+        names.push((name, variant.span()));
+    }
+
+    let enum_ident = &input.ident;
+    let names = names.iter().map(|(n, _)| n);
+    let indices = 0..idents.len();
+    Ok(quote! {
+        impl ::ustr::UstrEnum for #enum_ident {
+            const NAMES: &'static [&'static str] = &[#(#names),*];
+            const VARIANTS: &'static [Self] = &[#(Self::#idents),*];
+
+            #[inline]
+            fn index(self) -> usize {
+                match self {
+                    #(Self::#idents => #indices,)*
+                }
+            }
+
+            fn table() -> &'static ::ustr::UstrEnumTable {
+                static TABLE: ::std::sync::OnceLock<::ustr::UstrEnumTable> =
+                    ::std::sync::OnceLock::new();
+                TABLE.get_or_init(|| {
+                    ::ustr::UstrEnumTable::new(
+                        <Self as ::ustr::UstrEnum>::NAMES,
+                    )
+                })
+            }
+        }
+
+        impl ::std::convert::From<#enum_ident> for ::ustr::Ustr {
+            #[inline]
+            fn from(value: #enum_ident) -> ::ustr::Ustr {
+                ::ustr::UstrEnum::to_ustr(value)
+            }
+        }
+
+        impl ::std::convert::TryFrom<::ustr::Ustr> for #enum_ident {
+            type Error = ::ustr::Ustr;
+
+            #[inline]
+            fn try_from(
+                u: ::ustr::Ustr,
+            ) -> ::std::result::Result<#enum_ident, ::ustr::Ustr> {
+                <#enum_ident as ::ustr::UstrEnum>::from_ustr(u).ok_or(u)
+            }
+        }
+    })
+}
+
+// The value of `#[ustr(<key> = "...")]` among `attrs`, if there is one.
+fn rename_attr(attrs: &[syn::Attribute], key: &str) -> Result<Option<LitStr>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("ustr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                value = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error(format!("expected `{}`", key)))
+            }
+        })?;
+    }
+    Ok(value)
+}
+
+enum RenameRule {
+    Lower,
+    Upper,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    Camel,
+}
+
+impl RenameRule {
+    fn parse(lit: &LitStr) -> Result<RenameRule> {
+        Ok(match lit.value().as_str() {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "camelCase" => RenameRule::Camel,
+            _ => return Err(Error::new(lit.span(), "unknown rename_all rule")),
+        })
+    }
+
+    // Rename a variant identifier, which is in PascalCase.
+    fn apply(&self, ident: &str) -> String {
+        let separated = |sep: char| {
+            let mut out = String::new();
+            for (i, c) in ident.chars().enumerate() {
+                if c.is_uppercase() && i > 0 {
+                    out.push(sep);
+                }
+                out.extend(c.to_lowercase());
+            }
+            out
+        };
+        match self {
+            RenameRule::Lower => ident.to_lowercase(),
+            RenameRule::Upper => ident.to_uppercase(),
+            RenameRule::Snake => separated('_'),
+            RenameRule::ScreamingSnake => separated('_').to_uppercase(),
+            RenameRule::Kebab => separated('-'),
+            RenameRule::Camel => {
+                let mut chars = ident.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_lowercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
@@ -2,11 +2,17 @@
 #define __USTR_H__
 
 #include <stddef.h>
+#include <stdint.h>
 
 #ifdef __cplusplus
 extern "C" {
 #endif
 
+/*
+    A handle to a string in the cache, with the same layout and calling
+    convention as `Ustr`, so the two can be passed by value between Rust and
+    C. `ptr` is never null and points to the null-terminated UTF-8 chars.
+*/
 typedef struct {
     const char* ptr;
 } ustr_t;
@@ -15,7 +21,7 @@ typedef struct {
     Create a new ustr_t from the given char*.
     It is assumed that `str` is a valid, non-null pointer. Passing anything else
     will result in undefined behaviour.
-    Any invlid UTF-8 in `str` will be replaced by U+FFFD REPLACEMENT CHARACTER
+    Any invalid UTF-8 in `str` will be replaced by U+FFFD REPLACEMENT CHARACTER
 */
 ustr_t ustr(const char* str);
 
@@ -29,6 +35,85 @@ size_t ustr_len(ustr_t u);
 */
 uint64_t ustr_hash(ustr_t u);
 
+/*
+    The version of the layout of ustr_t and the string headers it points to,
+    which is the top 32 bits of `ustr_abi_version()`.
+*/
+#define USTR_ABI_VERSION 1
+
+/*
+    Identifies the ABI of the copy of ustr that exports it, when it's built
+    with the `stable-abi` feature. A host should check that each plugin's
+    value is equal to its own before passing ustr_t's to it.
+*/
+uint64_t ustr_abi_version(void);
+
+/*
+    Returns a handle to the string cache, to pass to `set_external_cache()` in
+    plugins that statically link their own copy of ustr, so that they share
+    this one's strings.
+*/
+const void* ustr_shared_cache(void);
+
+/*
+    Create a new ustr_t from the given null-terminated UTF-16 string.
+    It is assumed that `str` is a valid, non-null pointer. Passing anything else
+    will result in undefined behaviour.
+    Any unpaired surrogates in `str` will be replaced by U+FFFD REPLACEMENT
+    CHARACTER
+*/
+ustr_t ustr_from_utf16(const uint16_t* str);
+
+/*
+    Returns the given ustr_t as null-terminated UTF-16. The conversion is done
+    the first time this is called for each string, and the returned buffer is
+    valid for the rest of the program.
+*/
+const uint16_t* ustr_to_utf16(ustr_t u);
+
+/*
+    A private cache, layered on top of the global one. Strings that aren't in
+    the global cache are added to the private cache only, and are freed along
+    with it.
+*/
+typedef struct ustr_cache_t ustr_cache_t;
+
+/*
+    Create a new, empty private cache. Free it with `ustr_cache_free()`.
+*/
+ustr_cache_t* ustr_cache_new(void);
+
+/*
+    Free a cache created by `ustr_cache_new()`, along with every string that
+    was added to it. Does nothing if `cache` is null.
+*/
+void ustr_cache_free(ustr_cache_t* cache);
+
+/*
+    Intern the given char* in `cache`, returning the null-terminated chars of
+    the interned string. Interning the same string in the same cache always
+    returns the same pointer, so the results can be compared by address.
+    Strings already in the global cache are returned from there, otherwise
+    the chars are valid until `cache` is freed.
+    Any invalid UTF-8 in `str` will be replaced by U+FFFD REPLACEMENT CHARACTER
+*/
+const char* ustr_cache_intern(ustr_cache_t* cache, const char* str);
+
+/*
+    Like `ustr_cache_intern()`, but returns null rather than adding `str` if
+    it isn't in `cache` or the global cache already.
+*/
+const char* ustr_cache_get(ustr_cache_t* cache, const char* str);
+
+#ifdef _WIN32
+/*
+    Like `ustr_from_utf16()` and `ustr_to_utf16()`, for the wide strings used
+    by Windows APIs.
+*/
+ustr_t ustr_from_wide(const wchar_t* str);
+const wchar_t* ustr_to_wide(ustr_t u);
+#endif
+
 #ifdef __cplusplus
 }
 #endif
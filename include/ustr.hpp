@@ -15,13 +15,23 @@ public:
     /// Create a new Ustr from a const char*
     /// It is assumed that `str` is a valid, non-null pointer. Passing anything
     /// else will result in undefined behaviour.
-    /// Any invlid UTF-8 in `str` will be replaced by U+FFFD REPLACEMENT
+    /// Any invalid UTF-8 in `str` will be replaced by U+FFFD REPLACEMENT
     /// CHARACTER
     Ustr(const char* ptr) { _u = ustr(ptr); }
 
     /// Create a new Ustr from a std::string
     Ustr(const std::string& s) { _u = ustr(s.c_str()); }
 
+#ifdef _WIN32
+    /// Create a new Ustr from a wide string, as used by Windows APIs.
+    /// Any unpaired surrogates in `str` will be replaced by U+FFFD
+    /// REPLACEMENT CHARACTER
+    Ustr(const wchar_t* ptr) { _u = ustr_from_wide(ptr); }
+
+    /// Create a new Ustr from a std::wstring
+    Ustr(const std::wstring& s) { _u = ustr_from_wide(s.c_str()); }
+#endif
+
     /// Returns true if the string is empty
     bool is_empty() const { return len() == 0; }
 
@@ -36,6 +46,12 @@ public:
 
     /// Get the interned chars
     const char* c_str() const { return _u.ptr; }
+
+#ifdef _WIN32
+    /// Get the string as a wide string, which is converted the first time
+    /// this is called and kept for the rest of the program
+    const wchar_t* c_wstr() const { return ustr_to_wide(_u); }
+#endif
 };
 
 #endif
\ No newline at end of file
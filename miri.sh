@@ -14,3 +14,4 @@ cargo miri setup
 
 export RUST_TEST_THREADS=1
 cargo miri test --features=serde
+cargo miri test --features=serde --target i686-unknown-linux-gnu
@@ -0,0 +1,100 @@
+// The ABI that plugins built against different copies of this crate can rely
+// on, and the symbol they use to check it.
+//
+// The layout below is what `include/ustr.h` and `ustr_extern.rs` assume, and
+// what a host and its plugins must agree on to pass `Ustr`s between them.
+// Changing any of it, or the hash function, must bump `ABI_VERSION`, which
+// the assertions here are meant to force.
+use super::{hash_str, StringCacheEntry, Ustr};
+use std::{
+    mem::{align_of, offset_of, size_of},
+    ptr::NonNull,
+    sync::OnceLock,
+};
+
+/// The version of the layout of `Ustr`, and of the string headers it points
+/// to, that this copy of the crate uses.
+///
+/// It only changes when one of the following does, which is a breaking
+/// change for plugins, independent of the crate's own version:
+///
+/// - A `Ustr` is a single non-null pointer to the string's UTF-8 chars, which
+///   are followed by a null terminator, with the size, alignment and calling
+///   convention of `const char*`. `Option<Ustr>` is the same size, with
+///   `None` as null.
+/// - The chars are directly preceded by the string's header, a `#[repr(C)]`
+///   struct of its 64-bit hash, two 32-bit fields that are private to the
+///   crate, and its length in bytes as a `usize`, in that order.
+/// - The hash is the one returned by
+///   [`precomputed_hash()`](Ustr::precomputed_hash) and `ustr_hash()`.
+///
+/// See [`ustr_abi_version()`] for checking that two copies agree.
+pub const ABI_VERSION: u32 = 1;
+
+const _: () = {
+    assert!(size_of::<Ustr>() == size_of::<*const u8>());
+    assert!(align_of::<Ustr>() == align_of::<*const u8>());
+    assert!(size_of::<Option<Ustr>>() == size_of::<Ustr>());
+    assert!(offset_of!(Ustr, char_ptr) == 0);
+    assert!(size_of::<NonNull<u8>>() == size_of::<usize>());
+    assert!(offset_of!(StringCacheEntry, hash) == 0);
+    assert!(offset_of!(StringCacheEntry, generation) == 8);
+    assert!(offset_of!(StringCacheEntry, hash32) == 12);
+    assert!(offset_of!(StringCacheEntry, len) == 16);
+};
+
+/// Identifies the ABI of this copy of the crate, for a host to compare with
+/// the value from each plugin it loads before passing `Ustr`s to it.
+///
+/// The top 32 bits are [`ABI_VERSION`], so that a mismatch can be reported
+/// as one side being older than the other. The bottom 32 bits are a check
+/// of the pointer width, the size of the string header and the hash
+/// function, which can differ between copies with the same `ABI_VERSION`
+/// if they're built for different targets or with a different `ahash`.
+/// Two copies can share `Ustr`s only if the whole value is equal.
+///
+/// It's exported unmangled, so a host can look it up with `dlsym()` or
+/// `GetProcAddress()` in a plugin that links this crate with the
+/// `stable-abi` feature, and it's declared in `include/ustr.h` along with
+/// `USTR_ABI_VERSION` for C.
+///
+/// # Examples
+///
+/// ```
+/// let ours = ustr::ustr_abi_version();
+/// assert_eq!(ours >> 32, ustr::ABI_VERSION as u64);
+/// // In a host, with `theirs` from the plugin's `ustr_abi_version`:
+/// let theirs = ours;
+/// assert_eq!(ours, theirs, "plugin was built against an incompatible ustr");
+/// ```
+#[no_mangle]
+pub extern "C" fn ustr_abi_version() -> u64 {
+    static VERSION: OnceLock<u64> = OnceLock::new();
+    *VERSION.get_or_init(|| {
+        let check = hash_str(&format!(
+            "{} {} {}",
+            size_of::<usize>(),
+            size_of::<StringCacheEntry>(),
+            hash_str("ustr"),
+        ));
+        (ABI_VERSION as u64) << 32 | (check & 0xffff_ffff)
+    })
+}
+
+#[test]
+fn test_abi_version() {
+    use super::ustr as u;
+
+    let version = ustr_abi_version();
+    assert_eq!(version >> 32, ABI_VERSION as u64);
+    assert_eq!(ustr_abi_version(), version);
+
+    // The documented layout, read back through raw pointers.
+    let s = u("stable abi");
+    let chars = unsafe { *(&s as *const Ustr as *const *const u8) };
+    assert_eq!(chars, s.as_ptr());
+    let header = unsafe { &*(chars as *const StringCacheEntry).sub(1) };
+    assert_eq!(header.hash, s.precomputed_hash());
+    assert_eq!(header.len, s.len());
+    assert_eq!(unsafe { *chars.add(s.len()) }, 0);
+}
@@ -0,0 +1,366 @@
+use super::Ustr;
+use std::{
+    fmt,
+    ptr::NonNull,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// A `Ustr` that can be shared between threads and updated without a lock.
+///
+/// Since a `Ustr` is just a pointer to a string that lives forever, this is a
+/// thin wrapper around an `AtomicPtr` and has the same cost. Comparisons in
+/// [`compare_exchange()`](AtomicUstr::compare_exchange) are pointer
+/// comparisons, which is the same as comparing the strings.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use ustr::{ustr as u, AtomicUstr};
+///
+/// let state = AtomicUstr::new(u("idle"));
+/// assert_eq!(
+///     state.compare_exchange(
+///         u("idle"),
+///         u("running"),
+///         Ordering::AcqRel,
+///         Ordering::Acquire
+///     ),
+///     Ok(u("idle"))
+/// );
+/// assert_eq!(state.load(Ordering::Acquire), u("running"));
+/// ```
+#[repr(transparent)]
+pub struct AtomicUstr {
+    ptr: AtomicPtr<u8>,
+}
+
+// Any non-null pointer stored in the atomics came from a `Ustr`.
+#[inline]
+unsafe fn from_ptr(ptr: *mut u8) -> Ustr {
+    Ustr {
+        char_ptr: NonNull::new_unchecked(ptr),
+    }
+}
+
+#[inline]
+fn to_ptr(u: Ustr) -> *mut u8 {
+    u.char_ptr.as_ptr()
+}
+
+#[inline]
+fn to_opt_ptr(u: Option<Ustr>) -> *mut u8 {
+    u.map_or(std::ptr::null_mut(), to_ptr)
+}
+
+#[inline]
+fn from_opt_ptr(ptr: *mut u8) -> Option<Ustr> {
+    NonNull::new(ptr).map(|char_ptr| Ustr { char_ptr })
+}
+
+impl AtomicUstr {
+    /// Create a new `AtomicUstr` holding `u`.
+    pub const fn new(u: Ustr) -> AtomicUstr {
+        AtomicUstr {
+            ptr: AtomicPtr::new(u.char_ptr.as_ptr()),
+        }
+    }
+
+    /// Load the current value.
+    #[inline]
+    pub fn load(&self, order: Ordering) -> Ustr {
+        unsafe { from_ptr(self.ptr.load(order)) }
+    }
+
+    /// Store `u`.
+    #[inline]
+    pub fn store(&self, u: Ustr, order: Ordering) {
+        self.ptr.store(to_ptr(u), order)
+    }
+
+    /// Store `u`, returning the previous value.
+    #[inline]
+    pub fn swap(&self, u: Ustr, order: Ordering) -> Ustr {
+        unsafe { from_ptr(self.ptr.swap(to_ptr(u), order)) }
+    }
+
+    /// Store `new` if the current value is `current`.
+    ///
+    /// Returns the previous value, as `Ok` if it was replaced and `Err`
+    /// otherwise. See [`AtomicPtr::compare_exchange`] for the meaning of the
+    /// orderings.
+    #[inline]
+    pub fn compare_exchange(
+        &self,
+        current: Ustr,
+        new: Ustr,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Ustr, Ustr> {
+        match self.ptr.compare_exchange(
+            to_ptr(current),
+            to_ptr(new),
+            success,
+            failure,
+        ) {
+            Ok(p) => Ok(unsafe { from_ptr(p) }),
+            Err(p) => Err(unsafe { from_ptr(p) }),
+        }
+    }
+
+    /// Like [`compare_exchange()`](AtomicUstr::compare_exchange), but may fail
+    /// spuriously, which can be faster in a loop.
+    #[inline]
+    pub fn compare_exchange_weak(
+        &self,
+        current: Ustr,
+        new: Ustr,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Ustr, Ustr> {
+        match self.ptr.compare_exchange_weak(
+            to_ptr(current),
+            to_ptr(new),
+            success,
+            failure,
+        ) {
+            Ok(p) => Ok(unsafe { from_ptr(p) }),
+            Err(p) => Err(unsafe { from_ptr(p) }),
+        }
+    }
+
+    /// Consume the atomic and return the value it holds.
+    pub fn into_inner(self) -> Ustr {
+        unsafe { from_ptr(self.ptr.into_inner()) }
+    }
+}
+
+impl Default for AtomicUstr {
+    /// An `AtomicUstr` holding the empty string.
+    fn default() -> AtomicUstr {
+        AtomicUstr::new(Ustr::default())
+    }
+}
+
+impl From<Ustr> for AtomicUstr {
+    fn from(u: Ustr) -> AtomicUstr {
+        AtomicUstr::new(u)
+    }
+}
+
+impl fmt::Debug for AtomicUstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+    }
+}
+
+/// An `Option<Ustr>` that can be shared between threads and updated without a
+/// lock.
+///
+/// `None` is stored as a null pointer, so this is the same size as an
+/// [`AtomicUstr`].
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use ustr::{ustr as u, AtomicOptionUstr};
+///
+/// let owner = AtomicOptionUstr::default();
+/// let claim = |name| {
+///     owner.compare_exchange(
+///         None,
+///         Some(u(name)),
+///         Ordering::AcqRel,
+///         Ordering::Acquire,
+///     )
+/// };
+/// assert_eq!(claim("alice"), Ok(None));
+/// assert_eq!(claim("bob"), Err(Some(u("alice"))));
+/// assert_eq!(owner.take(Ordering::AcqRel), Some(u("alice")));
+/// ```
+#[repr(transparent)]
+pub struct AtomicOptionUstr {
+    ptr: AtomicPtr<u8>,
+}
+
+impl AtomicOptionUstr {
+    /// Create a new `AtomicOptionUstr` holding `u`.
+    pub const fn new(u: Option<Ustr>) -> AtomicOptionUstr {
+        AtomicOptionUstr {
+            ptr: AtomicPtr::new(match u {
+                Some(u) => u.char_ptr.as_ptr(),
+                None => std::ptr::null_mut(),
+            }),
+        }
+    }
+
+    /// Create a new `AtomicOptionUstr` holding `None`.
+    pub const fn none() -> AtomicOptionUstr {
+        AtomicOptionUstr::new(None)
+    }
+
+    /// Load the current value.
+    #[inline]
+    pub fn load(&self, order: Ordering) -> Option<Ustr> {
+        from_opt_ptr(self.ptr.load(order))
+    }
+
+    /// Store `u`.
+    #[inline]
+    pub fn store(&self, u: Option<Ustr>, order: Ordering) {
+        self.ptr.store(to_opt_ptr(u), order)
+    }
+
+    /// Store `u`, returning the previous value.
+    #[inline]
+    pub fn swap(&self, u: Option<Ustr>, order: Ordering) -> Option<Ustr> {
+        from_opt_ptr(self.ptr.swap(to_opt_ptr(u), order))
+    }
+
+    /// Store `None`, returning the previous value.
+    #[inline]
+    pub fn take(&self, order: Ordering) -> Option<Ustr> {
+        self.swap(None, order)
+    }
+
+    /// Store `new` if the current value is `current`.
+    ///
+    /// Returns the previous value, as `Ok` if it was replaced and `Err`
+    /// otherwise. See [`AtomicPtr::compare_exchange`] for the meaning of the
+    /// orderings.
+    #[inline]
+    pub fn compare_exchange(
+        &self,
+        current: Option<Ustr>,
+        new: Option<Ustr>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Option<Ustr>, Option<Ustr>> {
+        self.ptr
+            .compare_exchange(
+                to_opt_ptr(current),
+                to_opt_ptr(new),
+                success,
+                failure,
+            )
+            .map(from_opt_ptr)
+            .map_err(from_opt_ptr)
+    }
+
+    /// Like [`compare_exchange()`](AtomicOptionUstr::compare_exchange), but
+    /// may fail spuriously, which can be faster in a loop.
+    #[inline]
+    pub fn compare_exchange_weak(
+        &self,
+        current: Option<Ustr>,
+        new: Option<Ustr>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Option<Ustr>, Option<Ustr>> {
+        self.ptr
+            .compare_exchange_weak(
+                to_opt_ptr(current),
+                to_opt_ptr(new),
+                success,
+                failure,
+            )
+            .map(from_opt_ptr)
+            .map_err(from_opt_ptr)
+    }
+
+    /// Consume the atomic and return the value it holds.
+    pub fn into_inner(self) -> Option<Ustr> {
+        from_opt_ptr(self.ptr.into_inner())
+    }
+}
+
+impl Default for AtomicOptionUstr {
+    /// An `AtomicOptionUstr` holding `None`.
+    fn default() -> AtomicOptionUstr {
+        AtomicOptionUstr::none()
+    }
+}
+
+impl From<Option<Ustr>> for AtomicOptionUstr {
+    fn from(u: Option<Ustr>) -> AtomicOptionUstr {
+        AtomicOptionUstr::new(u)
+    }
+}
+
+impl From<Ustr> for AtomicOptionUstr {
+    fn from(u: Ustr) -> AtomicOptionUstr {
+        AtomicOptionUstr::new(Some(u))
+    }
+}
+
+impl fmt::Debug for AtomicOptionUstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.load(Ordering::Relaxed), f)
+    }
+}
+
+#[test]
+fn test_atomic_ustr() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+    use std::sync::Arc;
+
+    assert_eq!(
+        std::mem::size_of::<AtomicOptionUstr>(),
+        std::mem::size_of::<Ustr>()
+    );
+
+    let a = AtomicUstr::new(u("a"));
+    assert_eq!(a.swap(u("b"), Ordering::Relaxed), u("a"));
+    assert_eq!(
+        a.compare_exchange(
+            u("a"),
+            u("c"),
+            Ordering::Relaxed,
+            Ordering::Relaxed
+        ),
+        Err(u("b"))
+    );
+    a.store(u("d"), Ordering::Relaxed);
+    assert_eq!(format!("{:?}", a), "u!(\"d\")");
+    assert_eq!(a.into_inner(), u("d"));
+    assert_eq!(AtomicUstr::default().into_inner(), u(""));
+
+    let o = AtomicOptionUstr::from(u("x"));
+    assert_eq!(o.take(Ordering::Relaxed), Some(u("x")));
+    assert_eq!(o.load(Ordering::Relaxed), None);
+    o.store(Some(u("y")), Ordering::Relaxed);
+    assert_eq!(o.into_inner(), Some(u("y")));
+
+    // Many threads bumping a counter stored as a string: every increment must
+    // be applied exactly once.
+    let counter = Arc::new(AtomicUstr::new(u("0")));
+    let threads = (0..4)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    let mut current = counter.load(Ordering::Acquire);
+                    loop {
+                        let n: u32 = current.parse().unwrap();
+                        let next = u(&(n + 1).to_string());
+                        match counter.compare_exchange_weak(
+                            current,
+                            next,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        ) {
+                            Ok(_) => break,
+                            Err(actual) => current = actual,
+                        }
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(counter.load(Ordering::Acquire), u("400"));
+}
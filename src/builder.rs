@@ -0,0 +1,205 @@
+use super::{numeric::with_scratch, InternError, Ustr};
+use std::{fmt, iter::FromIterator};
+
+/// Builds a string up piece by piece and then interns it.
+///
+/// This is for code that produces strings incrementally, such as decoders
+/// that process escapes or repair invalid UTF-8, which would otherwise have
+/// to build a `String` of their own for each one. The builder's buffer is
+/// kept between calls to [`finish()`](UstrBuilder::finish), so a decoder that
+/// reuses one builder only allocates when it sees a string longer than any
+/// before it, and interning a string that's already in the cache doesn't
+/// allocate at all.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrBuilder};
+///
+/// let mut builder = UstrBuilder::new();
+/// for c in r"tab\tseparated".chars() {
+///     builder.push_char(c);
+/// }
+/// builder.push_str("!");
+/// assert_eq!(builder.finish(), u(r"tab\tseparated!"));
+///
+/// // The builder is empty again, ready for the next string.
+/// assert!(builder.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UstrBuilder {
+    buf: String,
+}
+
+impl UstrBuilder {
+    /// Create an empty builder. This doesn't allocate until something is
+    /// pushed.
+    pub const fn new() -> UstrBuilder {
+        UstrBuilder { buf: String::new() }
+    }
+
+    /// Create an empty builder with room for a string of `capacity` bytes
+    /// without reallocating.
+    pub fn with_capacity(capacity: usize) -> UstrBuilder {
+        UstrBuilder {
+            buf: String::with_capacity(capacity),
+        }
+    }
+
+    /// Append `string` to the string being built.
+    #[inline]
+    pub fn push_str(&mut self, string: &str) {
+        self.buf.push_str(string);
+    }
+
+    /// Append `c` to the string being built.
+    #[inline]
+    pub fn push_char(&mut self, c: char) {
+        self.buf.push(c);
+    }
+
+    /// The string built so far.
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    /// The length of the string built so far, in bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if nothing has been pushed since the builder was
+    /// created or last finished.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Throw away the string built so far, keeping the buffer.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Intern the string built so far, and empty the builder so that it can
+    /// be used to build another one.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn finish(&mut self) -> Ustr {
+        let u = Ustr::from(&self.buf);
+        self.buf.clear();
+        u
+    }
+
+    /// Intern the string built so far, returning an error if it can't be
+    /// added to the cache, and empty the builder either way.
+    pub fn try_finish(&mut self) -> Result<Ustr, InternError> {
+        let u = Ustr::try_intern(&self.buf);
+        self.buf.clear();
+        u
+    }
+}
+
+impl fmt::Write for UstrBuilder {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push_char(c);
+        Ok(())
+    }
+}
+
+impl Extend<char> for UstrBuilder {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, iter: I) {
+        self.buf.extend(iter);
+    }
+}
+
+impl<'a> Extend<&'a str> for UstrBuilder {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, iter: I) {
+        self.buf.extend(iter);
+    }
+}
+
+/// Interns the string made of the chars, without allocating if it's already
+/// in the cache.
+///
+/// # Panics
+///
+/// Panics in the same cases as [`Ustr::from`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, Ustr};
+///
+/// let upper: Ustr = "shout".chars().map(|c| c.to_ascii_uppercase()).collect();
+/// assert_eq!(upper, u("SHOUT"));
+/// ```
+impl FromIterator<char> for Ustr {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Ustr {
+        with_scratch(|scratch| scratch.extend(iter))
+    }
+}
+
+/// Interns the concatenation of the strings, without allocating if it's
+/// already in the cache.
+///
+/// # Panics
+///
+/// Panics in the same cases as [`Ustr::from`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, Ustr};
+///
+/// let path: Ustr = ["assets", "/", "wood.png"].into_iter().collect();
+/// assert_eq!(path, u("assets/wood.png"));
+/// ```
+impl<'a> FromIterator<&'a str> for Ustr {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Ustr {
+        with_scratch(|scratch| scratch.extend(iter))
+    }
+}
+
+#[test]
+fn test_ustr_builder() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use std::fmt::Write;
+
+    unsafe { super::_clear_cache() };
+
+    let mut builder = UstrBuilder::with_capacity(4);
+    builder.push_str("abc");
+    builder.push_char('é');
+    assert_eq!(builder.as_str(), "abcé");
+    assert_eq!(builder.len(), 5);
+    write!(builder, "-{}", 42).unwrap();
+    builder.extend(['x', 'y']);
+    builder.extend(["!", "?"]);
+    assert_eq!(builder.finish(), u("abcé-42xy!?"));
+    assert!(builder.is_empty());
+
+    builder.push_str("thrown away");
+    builder.clear();
+    assert_eq!(builder.try_finish(), Ok(u("")));
+
+    assert_eq!("abc".chars().rev().collect::<Ustr>(), u("cba"));
+    assert_eq!(["a", "b", "c"].into_iter().collect::<Ustr>(), u("abc"));
+    assert_eq!(std::iter::empty::<char>().collect::<Ustr>(), u(""));
+
+    // Iterators that intern strings themselves get a buffer of their own.
+    let nested = (0..3)
+        .map(|i| {
+            let inner: Ustr = format!("{}", i).chars().collect();
+            inner.as_str()
+        })
+        .collect::<Ustr>();
+    assert_eq!(nested, u("012"));
+    assert_eq!(u("1"), Ustr::from_int(1));
+}
@@ -1,13 +1,79 @@
+use super::{oom, OutOfMemory};
 use std::alloc::{GlobalAlloc, Layout, System};
 
+/// Provides the memory backing the string cache's storage.
+///
+/// The cache hands out strings from large arenas that it bumps through and
+/// never frees (unless you call the hidden `_clear_cache()`), so this is only
+/// called when the cache needs a new arena, which happens rarely. Implementing
+/// this trait lets you put the string storage somewhere other than the system
+/// heap, e.g. a custom arena, huge pages or NUMA-pinned memory. Anything that
+/// implements [`GlobalAlloc`] (e.g. jemalloc or mimalloc allocators) can be
+/// used directly.
+///
+/// Install your allocator with [`set_allocator()`](crate::set_allocator)
+/// before the cache is first used.
+///
+/// # Safety
+///
+/// Implementations must uphold the same contract as [`GlobalAlloc`]: returned
+/// memory must be valid for reads and writes of `layout.size()` bytes, aligned
+/// to `layout.align()`, and must remain valid until passed back to
+/// `deallocate()`. Return null on failure.
+pub unsafe trait CacheAllocator: Send + Sync {
+    /// Allocate a new arena described by `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `layout` must have a non-zero size.
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// Free an arena previously returned by `allocate()` with the same
+    /// `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate()` on this allocator with
+    /// the same `layout`, and must not be used afterwards.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
+unsafe impl<T: GlobalAlloc + Send + Sync> CacheAllocator for T {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc(ptr, layout)
+    }
+}
+
+/// The default [`CacheAllocator`], which gets its memory from the system
+/// allocator.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemAllocator;
+
+unsafe impl CacheAllocator for SystemAllocator {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
 // The world's dumbest allocator. Just keep bumping a pointer until we run out
-// of memory, in which case we abort. StringCache is responsible for creating
-// a new allocator when that's about to happen.
+// of memory, in which case the OOM handler decides whether to abort.
+// StringCache is responsible for creating a new allocator when that's about
+// to happen.
 // This is now bumping downward rather than up, which simplifies the allocate()
 // method and gives a small (5-7%) performance improvement in multithreaded
 // benchmarks
 // See https://fitzgeraldnick.com/2019/11/01/always-bump-downwards.html
+// The memory for each arena comes from a `CacheAllocator`.
 pub(crate) struct LeakyBumpAlloc {
+    allocator: &'static dyn CacheAllocator,
     layout: Layout,
     start: *mut u8,
     end: *mut u8,
@@ -15,30 +81,66 @@ pub(crate) struct LeakyBumpAlloc {
 }
 
 impl LeakyBumpAlloc {
-    pub fn new(capacity: usize, alignment: usize) -> LeakyBumpAlloc {
-        let layout = Layout::from_size_align(capacity, alignment).unwrap();
-        let start = unsafe { System.alloc(layout) };
+    pub fn new(
+        capacity: usize,
+        alignment: usize,
+        allocator: &'static dyn CacheAllocator,
+    ) -> Result<LeakyBumpAlloc, OutOfMemory> {
+        let oom = OutOfMemory {
+            size: capacity,
+            align: alignment,
+        };
+        // A size too big for a `Layout` couldn't be allocated anyway.
+        let layout = Layout::from_size_align(capacity, alignment)
+            .map_err(|_| oom::handle(oom))?;
+        let start = unsafe { allocator.allocate(layout) };
         if start.is_null() {
-            panic!("oom");
+            return Err(oom::handle(oom));
         }
         let end = unsafe { start.add(layout.size()) };
         let ptr = end;
-        LeakyBumpAlloc {
+        Ok(LeakyBumpAlloc {
+            allocator,
             layout,
             start,
             end,
             ptr,
+        })
+    }
+
+    // An allocator with no capacity, which doesn't allocate anything. Used
+    // for bins that haven't had a string added to them yet.
+    pub fn empty(
+        alignment: usize,
+        allocator: &'static dyn CacheAllocator,
+    ) -> LeakyBumpAlloc {
+        let layout = Layout::from_size_align(0, alignment).unwrap();
+        // A dangling pointer, like an empty `Vec`'s, so that the empty range
+        // from `ptr` to `end` is never mistaken for a real arena.
+        let start = layout.align() as *mut u8;
+        LeakyBumpAlloc {
+            allocator,
+            layout,
+            start,
+            end: start,
+            ptr: start,
         }
     }
 
     #[doc(hidden)]
     // used for resetting the cache between benchmark runs. DO NOT CALL THIS.
     pub unsafe fn clear(&mut self) {
-        System.dealloc(self.start, self.layout);
+        if self.layout.size() != 0 {
+            self.allocator.deallocate(self.start, self.layout);
+        }
     }
 
-    // Allocates a new chunk. Aborts if out of memory.
-    pub unsafe fn allocate(&mut self, num_bytes: usize) -> *mut u8 {
+    // Allocates a new chunk. Calls the OOM handler if there isn't enough
+    // room left, which `StringCache` makes sure never happens.
+    pub unsafe fn allocate(
+        &mut self,
+        num_bytes: usize,
+    ) -> Result<*mut u8, OutOfMemory> {
         // Our new ptr will be offset down the heap by num_bytes bytes.
         let ptr = self.ptr as usize;
         let new_ptr = ptr.checked_sub(num_bytes).expect("ptr sub overflowed");
@@ -52,13 +154,14 @@ impl LeakyBumpAlloc {
                 self.end as usize - new_ptr,
                 self.capacity()
             );
-            // We have to abort here rather than panic or the mutex may
-            // deadlock.
-            std::process::abort();
+            return Err(oom::handle(OutOfMemory {
+                size: num_bytes,
+                align: self.layout.align(),
+            }));
         }
 
         self.ptr = self.ptr.sub(ptr - new_ptr);
-        self.ptr
+        Ok(self.ptr)
     }
 
     pub fn allocated(&self) -> usize {
@@ -69,6 +172,10 @@ impl LeakyBumpAlloc {
         self.layout.size()
     }
 
+    pub(crate) fn start(&self) -> *const u8 {
+        self.start
+    }
+
     pub(crate) fn end(&self) -> *const u8 {
         self.end
     }
@@ -77,3 +184,40 @@ impl LeakyBumpAlloc {
         self.ptr
     }
 }
+
+#[test]
+fn test_custom_allocator() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingAllocator(AtomicUsize);
+
+    unsafe impl CacheAllocator for CountingAllocator {
+        unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    static COUNTING: CountingAllocator = CountingAllocator(AtomicUsize::new(0));
+
+    let mut alloc = LeakyBumpAlloc::new(1024, 8, &COUNTING).unwrap();
+    assert_eq!(COUNTING.0.load(Ordering::Relaxed), 1024);
+    unsafe {
+        let ptr = alloc.allocate(17).unwrap();
+        assert_eq!(ptr as usize % 8, 0);
+        assert_eq!(alloc.allocated(), 24);
+        alloc.clear();
+    }
+    assert_eq!(COUNTING.0.load(Ordering::Relaxed), 0);
+
+    let mut empty = LeakyBumpAlloc::empty(8, &COUNTING);
+    assert_eq!(COUNTING.0.load(Ordering::Relaxed), 0);
+    assert_eq!(empty.capacity(), 0);
+    assert_eq!(empty.allocated(), 0);
+    unsafe { empty.clear() };
+}
@@ -0,0 +1,120 @@
+use super::STRING_CACHE;
+use std::sync::atomic::Ordering;
+
+/// A snapshot of the cache's counters and gauges, as returned by
+/// [`metrics()`].
+///
+/// The fields that only ever go up are suffixed with `_total`, following the
+/// Prometheus convention for counters. Everything else is a gauge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// Number of unique strings in the cache.
+    pub num_entries: usize,
+    /// Bytes of string storage in use, including headers and padding.
+    pub allocated_bytes: usize,
+    /// Bytes of string storage reserved.
+    pub capacity_bytes: usize,
+    /// Number of slots in the entry tables of all the bins.
+    pub table_slots: usize,
+    /// Number of string arenas that have been allocated.
+    pub num_arenas: usize,
+    /// Number of times a bin's entry table has been grown.
+    pub grows_total: u64,
+    /// Number of lookups that had to wait for a bin's lock.
+    pub contended_reads_total: u64,
+    /// Number of inserts that had to wait for a bin's lock.
+    pub contended_writes_total: u64,
+}
+
+/// Returns a snapshot of the cache's metrics, for exporting to a monitoring
+/// system.
+///
+/// This takes each bin's read lock in turn, so it's cheap enough to call on
+/// every scrape, but the values from different bins may be from slightly
+/// different times if other threads are adding strings.
+///
+/// With the `"metrics"` feature enabled, [`CacheMetrics::record()`] reports
+/// the values through the [`metrics`](https://docs.rs/metrics) crate.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let _ = u("some metric");
+/// let metrics = ustr::metrics();
+/// assert_eq!(metrics.num_entries, ustr::num_entries());
+/// assert_eq!(metrics.allocated_bytes, ustr::total_allocated());
+/// ```
+pub fn metrics() -> CacheMetrics {
+    STRING_CACHE
+        .0
+        .iter()
+        .fold(CacheMetrics::default(), |mut m, sc| {
+            let sc = sc.read();
+            m.num_entries += sc.num_entries();
+            m.allocated_bytes += sc.total_allocated();
+            m.capacity_bytes += sc.total_capacity();
+            m.table_slots += sc.table_capacity();
+            m.num_arenas += sc.old_allocs.len() + 1;
+            m.grows_total += sc.num_grows;
+            m.contended_reads_total +=
+                sc.contended_reads.load(Ordering::Relaxed);
+            m.contended_writes_total +=
+                sc.contended_writes.load(Ordering::Relaxed);
+            m
+        })
+}
+
+#[cfg(feature = "metrics")]
+impl CacheMetrics {
+    /// Report these values through the `metrics` crate, as gauges and
+    /// counters named `ustr_*`.
+    ///
+    /// Call this periodically, e.g. from a timer or just before each scrape:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     ustr::metrics().record();
+    ///     std::thread::sleep(std::time::Duration::from_secs(15));
+    /// }
+    /// ```
+    ///
+    /// This is available with the `metrics` feature.
+    pub fn record(&self) {
+        ::metrics::gauge!("ustr_entries").set(self.num_entries as f64);
+        ::metrics::gauge!("ustr_allocated_bytes")
+            .set(self.allocated_bytes as f64);
+        ::metrics::gauge!("ustr_capacity_bytes")
+            .set(self.capacity_bytes as f64);
+        ::metrics::gauge!("ustr_table_slots").set(self.table_slots as f64);
+        ::metrics::gauge!("ustr_arenas").set(self.num_arenas as f64);
+        ::metrics::counter!("ustr_grows_total").absolute(self.grows_total);
+        ::metrics::counter!("ustr_contended_reads_total")
+            .absolute(self.contended_reads_total);
+        ::metrics::counter!("ustr_contended_writes_total")
+            .absolute(self.contended_writes_total);
+    }
+}
+
+#[test]
+fn test_metrics() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{ustr as u, NUM_BINS};
+
+    unsafe { super::_clear_cache() };
+
+    let before = metrics();
+    assert_eq!(before.num_entries, 0);
+    assert_eq!(before.num_arenas, NUM_BINS);
+
+    for i in 0..100 {
+        u(&format!("metrics {}", i));
+    }
+    let after = metrics();
+    assert_eq!(after.num_entries, 100);
+    assert_eq!(after.allocated_bytes, super::total_allocated());
+    assert_eq!(after.capacity_bytes, super::total_capacity());
+    assert_eq!(after.table_slots, before.table_slots);
+    assert_eq!(after.grows_total, 0);
+}
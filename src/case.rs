@@ -0,0 +1,209 @@
+use super::{Ustr, UstrMap};
+use parking_lot::RwLock;
+
+// The ASCII-lowercase twin of each string that's been compared ignoring
+// case. Twins map to themselves, so comparing two strings that are both in
+// the map is two lookups and a pointer comparison.
+static ASCII_LOWER: RwLock<Option<UstrMap<Ustr>>> = RwLock::new(None);
+
+// The results of `to_lowercase_interned()` and `to_uppercase_interned()`.
+static LOWER: RwLock<Option<UstrMap<Ustr>>> = RwLock::new(None);
+static UPPER: RwLock<Option<UstrMap<Ustr>>> = RwLock::new(None);
+
+// Look up `u` in `map`, or convert it, intern the result and remember it if
+// it's not there yet.
+fn memoized(
+    map: &RwLock<Option<UstrMap<Ustr>>>,
+    u: Ustr,
+    convert: fn(&str) -> String,
+) -> Ustr {
+    if let Some(converted) =
+        map.read().as_ref().and_then(|map| map.get(&u).copied())
+    {
+        return converted;
+    }
+    // Convert outside the lock, and don't intern a copy of `u` if it's
+    // unchanged.
+    let converted = convert(&u);
+    let converted = if converted == u.as_str() {
+        u
+    } else {
+        Ustr::from(&converted)
+    };
+    map.write()
+        .get_or_insert_with(UstrMap::default)
+        .insert(u, converted);
+    converted
+}
+
+// Get the twin of `u`, interning it and adding it to the map if it's not
+// there yet.
+fn ascii_lowercase(u: Ustr) -> Ustr {
+    let lower = memoized(&ASCII_LOWER, u, str::to_ascii_lowercase);
+    if lower != u {
+        ASCII_LOWER
+            .write()
+            .get_or_insert_with(UstrMap::default)
+            .insert(lower, lower);
+    }
+    lower
+}
+
+impl Ustr {
+    /// Check if this string is equal to `other`, ignoring ASCII case.
+    ///
+    /// This is the same as [`str::eq_ignore_ascii_case()`], but the first
+    /// time it's called for each string, the string's ASCII-lowercase form is
+    /// interned and remembered. After that, comparing it is a pointer
+    /// comparison, so this is much faster for comparing the same identifiers
+    /// over and over. To compare with a `&str` instead, call
+    /// `as_str().eq_ignore_ascii_case()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert!(u("Diffuse_Color").eq_ignore_ascii_case(u("diffuse_color")));
+    /// assert!(u("NAÏVE").eq_ignore_ascii_case(u("naÏve")));
+    /// assert!(!u("NAÏVE").eq_ignore_ascii_case(u("naïve")));
+    /// ```
+    pub fn eq_ignore_ascii_case(&self, other: Ustr) -> bool {
+        if *self == other {
+            return true;
+        }
+        if self.len() != other.len() {
+            return false;
+        }
+        if let Some(map) = ASCII_LOWER.read().as_ref() {
+            if let (Some(a), Some(b)) = (map.get(self), map.get(&other)) {
+                return a == b;
+            }
+        }
+        ascii_lowercase(*self) == ascii_lowercase(other)
+    }
+
+    /// Get the lowercase form of this string as a `Ustr`, as defined by
+    /// [`str::to_lowercase()`].
+    ///
+    /// The result is remembered for each string, so this only allocates the
+    /// first time it's called on a string, and after that is a hash map
+    /// lookup. If the string is already lowercase, it's returned as is.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("Base_Color").to_lowercase_interned(), u("base_color"));
+    /// assert_eq!(u("ÜBER").to_lowercase_interned(), u("über"));
+    /// ```
+    pub fn to_lowercase_interned(&self) -> Ustr {
+        memoized(&LOWER, *self, str::to_lowercase)
+    }
+
+    /// Get the uppercase form of this string as a `Ustr`, as defined by
+    /// [`str::to_uppercase()`].
+    ///
+    /// Like [`Ustr::to_lowercase_interned()`], this only allocates the first
+    /// time it's called on a string.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("Base_Color").to_uppercase_interned(), u("BASE_COLOR"));
+    /// assert_eq!(u("straße").to_uppercase_interned(), u("STRASSE"));
+    /// ```
+    pub fn to_uppercase_interned(&self) -> Ustr {
+        memoized(&UPPER, *self, str::to_uppercase)
+    }
+}
+
+// Forget the twin and case conversions of every string. Only called by
+// `_clear_cache()`.
+pub(crate) fn clear() {
+    *ASCII_LOWER.write() = None;
+    *LOWER.write() = None;
+    *UPPER.write() = None;
+}
+
+#[test]
+fn test_eq_ignore_ascii_case() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let pairs = [
+        ("", "", true),
+        ("abc", "ABC", true),
+        ("MixedCase_1", "mIXEDcASE_1", true),
+        ("abc", "abd", false),
+        ("abc", "abcd", false),
+        ("ÀB", "Àb", true),
+        ("À", "à", false),
+    ];
+    for _ in 0..2 {
+        for (a, b, eq) in pairs {
+            assert_eq!(u(a).eq_ignore_ascii_case(u(b)), eq, "{} {}", a, b);
+            assert_eq!(u(b).eq_ignore_ascii_case(u(a)), eq, "{} {}", b, a);
+            assert_eq!(a.eq_ignore_ascii_case(b), eq);
+        }
+    }
+
+    // Each string maps to its twin, which maps to itself.
+    let twin = |s| ASCII_LOWER.read().as_ref().unwrap()[&u(s)];
+    assert_eq!(twin("MixedCase_1"), u("mixedcase_1"));
+    assert_eq!(twin("mixedcase_1"), u("mixedcase_1"));
+    assert_eq!(twin("abc"), u("abc"));
+
+    unsafe { super::_clear_cache() };
+    assert!(ASCII_LOWER.read().is_none());
+}
+
+#[test]
+fn test_case_conversion() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{string_cache_iter, ustr as u};
+
+    unsafe { super::_clear_cache() };
+
+    let strings =
+        ["", "lower", "UPPER", "Mixed_Case_2", "ÀÉÎ", "straße", "ΣΑΣ"];
+    for s in strings {
+        for _ in 0..2 {
+            assert_eq!(u(s).to_lowercase_interned(), s.to_lowercase());
+            assert_eq!(u(s).to_uppercase_interned(), s.to_uppercase());
+        }
+    }
+
+    // Strings that don't change aren't interned again, and the results are
+    // remembered.
+    let lower = u("lower");
+    assert_eq!(
+        lower.to_lowercase_interned().as_char_ptr(),
+        lower.as_char_ptr()
+    );
+    assert_eq!(LOWER.read().as_ref().unwrap()[&u("UPPER")], u("upper"));
+    assert_eq!(UPPER.read().as_ref().unwrap()[&u("straße")], u("STRASSE"));
+    let num_strings = string_cache_iter().count();
+    for s in strings {
+        u(s).to_lowercase_interned();
+        u(s).to_uppercase_interned();
+    }
+    assert_eq!(string_cache_iter().count(), num_strings);
+
+    unsafe { super::_clear_cache() };
+    assert!(LOWER.read().is_none());
+    assert!(UPPER.read().is_none());
+}
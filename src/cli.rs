@@ -0,0 +1,113 @@
+use super::Ustr;
+use clap::{
+    builder::{TypedValueParser, ValueParserFactory},
+    error::ErrorKind,
+    Arg, Command, Error,
+};
+use std::ffi::OsStr;
+
+/// Parses command-line arguments into `Ustr`s.
+///
+/// This is what `clap::value_parser!(Ustr)` returns, so `Ustr` can be used
+/// as an argument type directly, with both the builder and derive APIs. It's
+/// available with the `clap` feature.
+///
+/// Arguments that aren't valid UTF-8, or that can't be interned because of
+/// the limits set with [`set_max_len()`](crate::set_max_len) or
+/// [`freeze()`](crate::freeze), are reported as clap errors.
+///
+/// # Examples
+///
+/// ```
+/// use clap::{value_parser, Arg, Command};
+/// use ustr::{ustr as u, Ustr};
+///
+/// let cmd = Command::new("render")
+///     .arg(Arg::new("camera").long("camera").value_parser(value_parser!(Ustr)));
+/// let matches = cmd.get_matches_from(["render", "--camera", "persp"]);
+/// assert_eq!(matches.get_one::<Ustr>("camera"), Some(&u("persp")));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UstrValueParser;
+
+impl UstrValueParser {
+    /// Create a new `UstrValueParser`.
+    pub fn new() -> UstrValueParser {
+        UstrValueParser
+    }
+}
+
+impl TypedValueParser for UstrValueParser {
+    type Value = Ustr;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Ustr, Error> {
+        let value = value
+            .to_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidUtf8).with_cmd(cmd))?;
+        Ustr::try_intern(value).map_err(|e| {
+            let arg = arg.map_or_else(|| "...".to_owned(), |a| a.to_string());
+            Error::raw(
+                ErrorKind::ValueValidation,
+                format!("invalid value '{}' for '{}': {}\n", value, arg, e),
+            )
+            .with_cmd(cmd)
+        })
+    }
+}
+
+impl ValueParserFactory for Ustr {
+    type Parser = UstrValueParser;
+
+    fn value_parser() -> UstrValueParser {
+        UstrValueParser
+    }
+}
+
+#[test]
+fn test_value_parser() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{set_max_len, ustr as u, MaxLenPolicy};
+    use clap::value_parser;
+
+    let cmd = Command::new("test").arg(
+        Arg::new("name")
+            .long("name")
+            .num_args(1..)
+            .value_parser(value_parser!(Ustr)),
+    );
+    let matches = cmd
+        .clone()
+        .try_get_matches_from(["test", "--name", "a", "Τη γλώσσα"])
+        .unwrap();
+    let names = matches
+        .get_many::<Ustr>("name")
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(names, [&u("a"), &u("Τη γλώσσα")]);
+
+    set_max_len(4, MaxLenPolicy::Reject);
+    let err = cmd
+        .clone()
+        .try_get_matches_from(["test", "--name", "too long"])
+        .unwrap_err();
+    set_max_len(usize::MAX, MaxLenPolicy::Reject);
+    assert_eq!(err.kind(), ErrorKind::ValueValidation);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let err = cmd
+            .try_get_matches_from([
+                OsStr::new("test"),
+                OsStr::new("--name"),
+                OsStr::from_bytes(b"\xff"),
+            ])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidUtf8);
+    }
+}
@@ -0,0 +1,191 @@
+use super::{Ustr, UstrMap};
+use icu_collator::CollatorBorrowed;
+use parking_lot::RwLock;
+use std::{cmp::Ordering, fmt};
+
+/// Sorts `Ustr`s for display according to the rules of a locale, using a
+/// collator from the [`icu_collator`] crate.
+///
+/// Comparing two strings with a collator is much slower than comparing
+/// bytes, so sorting a long list of strings with one is slow too. This
+/// computes each string's collation sort key the first time it sees it and
+/// keeps it, so sorting is a byte comparison of sort keys, and sorting the
+/// same names again, e.g. every time a UI list is redrawn, doesn't recompute
+/// anything.
+///
+/// Each `UstrCollate` keeps a key for every string it has sorted, for as
+/// long as it lives, so keep one per locale around rather than making a new
+/// one for each sort.
+///
+/// This is available with the `icu` feature.
+///
+/// # Examples
+///
+/// ```
+/// use icu_collator::{options::CollatorOptions, Collator};
+/// use ustr::{ustr as u, UstrCollate};
+///
+/// let options = CollatorOptions::default();
+/// let collator = Collator::try_new(Default::default(), options).unwrap();
+/// let collate = UstrCollate::new(collator);
+///
+/// let mut names = [u("banana"), u("Apple"), u("cherry"), u("apple")];
+/// collate.sort(&mut names);
+/// assert_eq!(names, [u("apple"), u("Apple"), u("banana"), u("cherry")]);
+/// ```
+pub struct UstrCollate {
+    collator: CollatorBorrowed<'static>,
+    keys: RwLock<UstrMap<Box<[u8]>>>,
+}
+
+impl UstrCollate {
+    /// Create an adapter that sorts with `collator`.
+    pub fn new(collator: CollatorBorrowed<'static>) -> UstrCollate {
+        UstrCollate {
+            collator,
+            keys: RwLock::new(UstrMap::default()),
+        }
+    }
+
+    /// The collator strings are sorted with.
+    pub fn collator(&self) -> &CollatorBorrowed<'static> {
+        &self.collator
+    }
+
+    /// Compare `a` and `b` with the collator.
+    pub fn compare(&self, a: Ustr, b: Ustr) -> Ordering {
+        self.add_keys([a, b]);
+        let keys = self.keys.read();
+        keys[&a].cmp(&keys[&b])
+    }
+
+    /// Get the collation sort key for `u`.
+    ///
+    /// Sort keys compare bytewise in the same order as the collator compares
+    /// the strings. They're only meaningful for this version of the
+    /// collation data, so don't store them.
+    pub fn sort_key(&self, u: Ustr) -> Vec<u8> {
+        self.add_keys([u]);
+        self.keys.read()[&u].to_vec()
+    }
+
+    /// Sort `ustrs` with the collator.
+    pub fn sort(&self, ustrs: &mut [Ustr]) {
+        self.sort_by_key(ustrs, |u| *u);
+    }
+
+    /// Sort `items` by the `Ustr` that `f` returns for each of them, with
+    /// the collator.
+    ///
+    /// `f` is called a few times for each item, so it should be cheap, such
+    /// as getting a field.
+    pub fn sort_by_key<T>(&self, items: &mut [T], f: impl Fn(&T) -> Ustr) {
+        self.add_keys(items.iter().map(&f));
+        let keys = self.keys.read();
+        // Look up each key once rather than on every comparison.
+        let mut order = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (&*keys[&f(item)], i))
+            .collect::<Vec<_>>();
+        order.sort_by(|a, b| a.0.cmp(b.0));
+        let order = order.into_iter().map(|(_, i)| i).collect::<Vec<_>>();
+        drop(keys);
+        permute(items, order);
+    }
+
+    /// The number of strings whose sort keys are cached.
+    pub fn num_keys(&self) -> usize {
+        self.keys.read().len()
+    }
+
+    // Compute and store the keys of the strings in `ustrs` that don't have
+    // one yet.
+    fn add_keys(&self, ustrs: impl IntoIterator<Item = Ustr>) {
+        let keys = self.keys.read();
+        let missing = ustrs
+            .into_iter()
+            .filter(|u| !keys.contains_key(u))
+            .collect::<Vec<_>>();
+        drop(keys);
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut key = Vec::new();
+        let mut keys = self.keys.write();
+        for u in missing {
+            keys.entry(u).or_insert_with(|| {
+                key.clear();
+                let Ok(()) =
+                    self.collator.write_sort_key_to(u.as_str(), &mut key);
+                key.as_slice().into()
+            });
+        }
+    }
+}
+
+// Reorder `items` so that the item at `order[i]` ends up at `i`.
+fn permute<T>(items: &mut [T], mut order: Vec<usize>) {
+    for i in 0..items.len() {
+        // Follow the cycle starting at `i`, swapping each item into place
+        // and marking its slot as done.
+        let mut current = i;
+        while order[current] != i {
+            let next = order[current];
+            items.swap(current, next);
+            order[current] = current;
+            current = next;
+        }
+        order[current] = current;
+    }
+}
+
+impl fmt::Debug for UstrCollate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UstrCollate")
+            .field("num_keys", &self.num_keys())
+            .finish()
+    }
+}
+
+#[test]
+fn test_ustr_collate() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use icu_collator::{options::CollatorOptions, Collator};
+    use icu_locale_core::locale;
+
+    unsafe { super::_clear_cache() };
+
+    let collate = |locale: icu_locale_core::Locale| {
+        let collator =
+            Collator::try_new((&locale).into(), CollatorOptions::default())
+                .unwrap();
+        UstrCollate::new(collator)
+    };
+    let en = collate(locale!("en"));
+    let sv = collate(locale!("sv"));
+
+    // Swedish sorts "ä" after "z", English sorts it with "a".
+    let names = [u("zebra"), u("äpple"), u("apple"), u("banana")];
+    let mut sorted = names;
+    en.sort(&mut sorted);
+    assert_eq!(sorted, [u("apple"), u("äpple"), u("banana"), u("zebra")]);
+    sv.sort(&mut sorted);
+    assert_eq!(sorted, [u("apple"), u("banana"), u("zebra"), u("äpple")]);
+    assert_eq!(en.compare(u("äpple"), u("banana")), Ordering::Less);
+    assert_eq!(sv.compare(u("äpple"), u("banana")), Ordering::Greater);
+    assert_eq!(en.compare(u("apple"), u("apple")), Ordering::Equal);
+    assert!(sv.sort_key(u("apple")) < sv.sort_key(u("äpple")));
+    assert_eq!(en.num_keys(), 4);
+
+    // Sorting by key moves the whole items.
+    let mut items = (0..100)
+        .rev()
+        .map(|i| (u(&format!("item {:03}", i)), i))
+        .collect::<Vec<_>>();
+    en.sort_by_key(&mut items, |(name, _)| *name);
+    assert!(items.iter().enumerate().all(|(i, (_, n))| i == *n));
+    assert_eq!(en.num_keys(), 104);
+}
@@ -0,0 +1,122 @@
+use super::Ustr;
+use parking_lot::Mutex;
+use std::ptr::NonNull;
+
+/// Two different strings in the cache with the same 64-bit hash, as returned
+/// by [`hash_collisions()`].
+///
+/// The cache itself copes with collisions by comparing the strings, but
+/// `Hash for Ustr` only feeds the precomputed hash to the hasher, so colliding
+/// strings always land in the same bucket of a [`UstrMap`](crate::UstrMap)
+/// or [`UstrSet`](crate::UstrSet). That's still correct since `Ustr`s are
+/// compared by pointer, but many collisions would make those maps slow, and
+/// any code that treats equal hashes as equal strings would be wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashCollision {
+    /// The hash the strings share.
+    pub hash: u64,
+    /// The string that was in the cache first.
+    pub existing: Ustr,
+    /// The string whose insertion caused the collision.
+    pub new: Ustr,
+}
+
+static COLLISIONS: Mutex<Vec<HashCollision>> = Mutex::new(Vec::new());
+
+// Record that the string at `new` was added to the cache with the same hash
+// as the one at `existing`.
+pub(crate) fn record(hash: u64, existing: *const u8, new: *const u8) {
+    // Both pointers are to the chars of entries in the global cache.
+    let (existing, new) = unsafe {
+        (
+            Ustr {
+                char_ptr: NonNull::new_unchecked(existing as *mut u8),
+            },
+            Ustr {
+                char_ptr: NonNull::new_unchecked(new as *mut u8),
+            },
+        )
+    };
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        target: "ustr",
+        hash,
+        existing = existing.as_str(),
+        new = new.as_str(),
+        "hash collision"
+    );
+    #[cfg(not(feature = "tracing"))]
+    eprintln!(
+        "ustr: hash collision {:#018x} between {:?} and {:?}",
+        hash, existing, new
+    );
+    COLLISIONS.lock().push(HashCollision {
+        hash,
+        existing,
+        new,
+    });
+}
+
+pub(crate) fn clear() {
+    COLLISIONS.lock().clear();
+}
+
+/// Returns every 64-bit hash collision between strings in the cache, in the
+/// order they happened.
+///
+/// Each new string is checked against the strings already in the cache with
+/// the same hash as it's added, and each collision is logged, as a `tracing`
+/// warning with the `tracing` feature or to stderr otherwise. A string that
+/// collides with several others appears once for each of them.
+///
+/// This is available with the `debug-hash-collisions` feature, which makes
+/// adding strings a little slower. It's meant for checking a dataset for
+/// collisions rather than for production use.
+///
+/// # Examples
+///
+/// ```
+/// for i in 0..1000 {
+///     ustr::ustr(&format!("string {}", i));
+/// }
+/// // Collisions in a 64-bit hash are vanishingly rare.
+/// assert!(ustr::hash_collisions().is_empty());
+/// ```
+pub fn hash_collisions() -> Vec<HashCollision> {
+    COLLISIONS.lock().clone()
+}
+
+#[test]
+fn test_hash_collisions() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{whichbin, STRING_CACHE};
+
+    unsafe { super::_clear_cache() };
+
+    // Force a collision by inserting strings with a made-up hash.
+    let hash = 0x1234_5678_9abc_def0;
+    let bin = &STRING_CACHE.0[whichbin(hash)];
+    let first = bin.write().insert("first", hash);
+    let second = bin.write().insert("second", hash);
+    assert_eq!(bin.write().insert("first", hash), first);
+    let third = bin.write().insert("third", hash);
+
+    let collisions = hash_collisions();
+    assert_eq!(collisions.len(), 3);
+    assert!(collisions.iter().all(|c| c.hash == hash));
+    assert_eq!(collisions[0].existing.as_char_ptr() as *const u8, first);
+    assert_eq!(collisions[0].new.as_char_ptr() as *const u8, second);
+    assert_eq!(collisions[0].new, "second");
+    let mut third_with = collisions[1..]
+        .iter()
+        .map(|c| {
+            assert_eq!(c.new.as_char_ptr() as *const u8, third);
+            c.existing.as_str()
+        })
+        .collect::<Vec<_>>();
+    third_with.sort();
+    assert_eq!(third_with, ["first", "second"]);
+
+    unsafe { super::_clear_cache() };
+    assert!(hash_collisions().is_empty());
+}
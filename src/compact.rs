@@ -0,0 +1,356 @@
+//! Compact serialization of `Ustr`s against a pre-agreed [`Dictionary`].
+//!
+//! Messages that contain lots of repeated strings, e.g. attribute names in
+//! telemetry, spend most of their bytes on those strings. If both sides agree
+//! on a dictionary of strings up front then each `Ustr` can be sent as its
+//! index in the dictionary instead, which formats like `postcard` and
+//! `bitcode` encode as a single-byte varint for the first 128 entries.
+//!
+//! Use this module with serde's `with` attribute, and serialize or
+//! deserialize inside [`Dictionary::with()`]:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Sample {
+//!     #[serde(with = "ustr::compact")]
+//!     name: Ustr,
+//!     value: f32,
+//! }
+//!
+//! let bytes = dictionary.with(|| postcard::to_slice(&sample, &mut buf))?;
+//! ```
+//!
+//! Each `Ustr` is written as an enum: variant 0 holds the `u32` dictionary
+//! index, variant 1 holds the string itself for anything that isn't in the
+//! dictionary (or when no dictionary is active), so a message can always be
+//! decoded as long as the receiver uses the same dictionary. Serializing never
+//! allocates.
+//!
+//! This is available with the `serde` feature.
+use super::{entries_since, Ustr, UstrMap};
+use serde::{
+    de::{
+        self, Deserialize, Deserializer, EnumAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
+    ser::{Serialize, SerializeSeq, Serializer},
+};
+use std::{cell::Cell, fmt};
+
+/// An ordered set of strings that both ends of a connection agree on, used to
+/// serialize `Ustr`s as small integers.
+///
+/// A `Dictionary` can itself be serialized (as a sequence of strings) to share
+/// it ahead of time.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{compact::Dictionary, ustr as u};
+///
+/// let dictionary = Dictionary::new([u("temperature"), u("pressure")]);
+/// assert_eq!(dictionary.index_of(u("pressure")), Some(1));
+/// assert_eq!(dictionary.get(0), Some(u("temperature")));
+///
+/// let mut json = Vec::new();
+/// dictionary.with(|| {
+///     let mut serializer = serde_json::Serializer::new(&mut json);
+///     ustr::compact::serialize(&u("pressure"), &mut serializer).unwrap();
+/// });
+/// assert_eq!(json, br#"{"Id":1}"#);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    strings: Vec<Ustr>,
+    index: UstrMap<u32>,
+}
+
+impl Dictionary {
+    /// Create a dictionary of `strings`, in order. Duplicates are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` unique strings.
+    pub fn new<I: IntoIterator<Item = Ustr>>(strings: I) -> Dictionary {
+        let mut dictionary = Dictionary::default();
+        for u in strings {
+            dictionary.insert(u);
+        }
+        dictionary
+    }
+
+    /// Create a dictionary of everything currently in the cache, in the
+    /// order it was added.
+    pub fn from_cache() -> Dictionary {
+        Dictionary::new(entries_since(0))
+    }
+
+    fn insert(&mut self, u: Ustr) {
+        let next = u32::try_from(self.strings.len())
+            .expect("too many strings for a Dictionary");
+        if let std::collections::hash_map::Entry::Vacant(v) =
+            self.index.entry(u)
+        {
+            v.insert(next);
+            self.strings.push(u);
+        }
+    }
+
+    /// The number of strings in the dictionary.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns `true` if the dictionary has no strings.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// The index of `u` in the dictionary, if it's there.
+    pub fn index_of(&self, u: Ustr) -> Option<u32> {
+        self.index.get(&u).copied()
+    }
+
+    /// The string at `index`, if there is one.
+    pub fn get(&self, index: u32) -> Option<Ustr> {
+        self.strings.get(index as usize).copied()
+    }
+
+    /// The strings in the dictionary, in order.
+    pub fn strings(&self) -> &[Ustr] {
+        &self.strings
+    }
+
+    /// Make this the dictionary used by [`serialize()`] and [`deserialize()`]
+    /// on this thread while `f` runs.
+    pub fn with<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        // Put back whatever was active before, even if `f` panics.
+        struct Restore(*const Dictionary);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                ACTIVE.with(|active| active.set(self.0));
+            }
+        }
+
+        let _restore = Restore(ACTIVE.with(|active| active.replace(self)));
+        f()
+    }
+}
+
+thread_local! {
+    // The dictionary installed by `Dictionary::with()`, if any. This is only
+    // non-null while that `Dictionary` is borrowed by `with()`.
+    static ACTIVE: Cell<*const Dictionary> =
+        const { Cell::new(std::ptr::null()) };
+}
+
+fn with_active<R, F: FnOnce(Option<&Dictionary>) -> R>(f: F) -> R {
+    ACTIVE.with(|active| {
+        // SAFETY: the pointer is only set for the duration of `with()`, which
+        // holds a borrow of the dictionary.
+        f(unsafe { active.get().as_ref() })
+    })
+}
+
+const NAME: &str = "Ustr";
+const VARIANTS: &[&str] = &["Id", "Str"];
+
+/// Serialize `u` as its index in the active [`Dictionary`], or as a string if
+/// it isn't in the dictionary.
+pub fn serialize<S>(u: &Ustr, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match with_active(|dictionary| dictionary?.index_of(*u)) {
+        Some(index) => {
+            serializer.serialize_newtype_variant(NAME, 0, VARIANTS[0], &index)
+        }
+        None => serializer.serialize_newtype_variant(
+            NAME,
+            1,
+            VARIANTS[1],
+            u.as_str(),
+        ),
+    }
+}
+
+/// Deserialize a `Ustr` written by [`serialize()`] using the active
+/// [`Dictionary`].
+///
+/// Returns an error if the input refers to a dictionary index and there is no
+/// active dictionary, or the index is out of range.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Ustr, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_enum(NAME, VARIANTS, CompactVisitor)
+}
+
+enum Variant {
+    Id,
+    Str,
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D>(deserializer: D) -> Result<Variant, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VariantVisitor;
+
+        impl<'de> Visitor<'de> for VariantVisitor {
+            type Value = Variant;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("`Id` or `Str`")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Variant, E> {
+                match v {
+                    0 => Ok(Variant::Id),
+                    1 => Ok(Variant::Str),
+                    _ => Err(E::invalid_value(
+                        de::Unexpected::Unsigned(v),
+                        &"variant index 0 <= i < 2",
+                    )),
+                }
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Variant, E> {
+                match v {
+                    "Id" => Ok(Variant::Id),
+                    "Str" => Ok(Variant::Str),
+                    _ => Err(E::unknown_variant(v, VARIANTS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(VariantVisitor)
+    }
+}
+
+struct CompactVisitor;
+
+impl<'de> Visitor<'de> for CompactVisitor {
+    type Value = Ustr;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a dictionary index or a string")
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Ustr, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        match data.variant()? {
+            (Variant::Id, access) => {
+                let index = access.newtype_variant::<u32>()?;
+                with_active(|dictionary| {
+                    dictionary
+                        .ok_or_else(|| {
+                            de::Error::custom(
+                                "no ustr::compact::Dictionary is active",
+                            )
+                        })?
+                        .get(index)
+                        .ok_or_else(|| {
+                            de::Error::custom(format!(
+                                "dictionary index {} is out of range",
+                                index
+                            ))
+                        })
+                })
+            }
+            (Variant::Str, access) => access.newtype_variant::<Ustr>(),
+        }
+    }
+}
+
+impl Serialize for Dictionary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.strings.len()))?;
+        for u in &self.strings {
+            seq.serialize_element(u)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Dictionary {
+    fn deserialize<D>(deserializer: D) -> Result<Dictionary, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DictionaryVisitor;
+
+        impl<'de> Visitor<'de> for DictionaryVisitor {
+            type Value = Dictionary;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence of strings")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Dictionary, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut dictionary = Dictionary::default();
+                while let Some(u) = seq.next_element::<Ustr>()? {
+                    dictionary.insert(u);
+                }
+                Ok(dictionary)
+            }
+        }
+
+        deserializer.deserialize_seq(DictionaryVisitor)
+    }
+}
+
+#[test]
+fn test_compact() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let to_json = |x: Ustr| {
+        let mut json = Vec::new();
+        serialize(&x, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        String::from_utf8(json).unwrap()
+    };
+    let from_json =
+        |json: &str| deserialize(&mut serde_json::Deserializer::from_str(json));
+
+    u("attr.a");
+    u("attr.b");
+    let dictionary = Dictionary::from_cache();
+    assert_eq!(dictionary.strings(), [u("attr.a"), u("attr.b")]);
+
+    // Without an active dictionary everything goes as a string.
+    assert_eq!(to_json(u("attr.b")), r#"{"Str":"attr.b"}"#);
+    assert!(from_json(r#"{"Id":1}"#).is_err());
+
+    dictionary.with(|| {
+        assert_eq!(to_json(u("attr.b")), r#"{"Id":1}"#);
+        assert_eq!(to_json(u("other")), r#"{"Str":"other"}"#);
+        assert_eq!(from_json(r#"{"Id":1}"#).unwrap(), u("attr.b"));
+        assert_eq!(from_json(r#"{"Str":"other"}"#).unwrap(), u("other"));
+        assert!(from_json(r#"{"Id":2}"#).is_err());
+
+        // Nested dictionaries are restored when the inner one finishes.
+        Dictionary::new([u("other")]).with(|| {
+            assert_eq!(to_json(u("other")), r#"{"Id":0}"#);
+        });
+        assert_eq!(to_json(u("attr.a")), r#"{"Id":0}"#);
+    });
+    assert_eq!(to_json(u("attr.a")), r#"{"Str":"attr.a"}"#);
+
+    // Dictionaries round trip as a list of strings.
+    let json = serde_json::to_string(&dictionary).unwrap();
+    assert_eq!(json, r#"["attr.a","attr.b"]"#);
+    let de: Dictionary = serde_json::from_str(&json).unwrap();
+    assert_eq!(de.strings(), dictionary.strings());
+}
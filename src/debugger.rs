@@ -0,0 +1,83 @@
+use super::STRING_CACHE;
+use std::ffi::{c_char, CStr};
+
+// Returned instead of the chars when a pointer isn't a `Ustr`, so a debugger
+// shows why rather than reading garbage.
+const INVALID: &CStr = c"<invalid Ustr>";
+const NULL: &CStr = c"<null Ustr>";
+const LOCKED: &CStr = c"<ustr cache locked>";
+
+/// Get the chars of the `Ustr` whose chars are at `ptr`, for calling from a
+/// debugger.
+///
+/// The pretty-printers in the repository's `debugger/` directory read a
+/// `Ustr`'s chars straight from memory, which is fastest but trusts whatever
+/// the `Ustr` points to. This checks that `ptr` really is a string in the
+/// cache first, so it's the safe way to look at a `Ustr` in a corrupted
+/// program, or in a debugger without a Python interpreter:
+///
+/// ```text
+/// (gdb) print (char*)__ustr_debug_str(name.char_ptr.pointer)
+/// (lldb) expr (char*)__ustr_debug_str((char*)name.char_ptr.pointer)
+/// ```
+///
+/// Returns `ptr` if it's valid, or a static string describing the problem if
+/// it's null or isn't in the cache. This never blocks, since the debugger may
+/// have stopped a thread that's holding a lock on the cache; if it can't read
+/// a bin it returns `"<ustr cache locked>"` instead.
+///
+/// This is available with the `debug-helpers` feature. Its symbol is kept
+/// when linking so that it can be called even if nothing else calls it.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CStr;
+/// use ustr::{__ustr_debug_str, ustr as u};
+///
+/// let name = u("debugged");
+/// let chars = __ustr_debug_str(name.as_char_ptr());
+/// assert_eq!(chars, name.as_char_ptr());
+/// let invalid = unsafe { CStr::from_ptr(__ustr_debug_str(c"x".as_ptr())) };
+/// assert_eq!(invalid, c"<invalid Ustr>");
+/// ```
+#[no_mangle]
+pub extern "C" fn __ustr_debug_str(ptr: *const c_char) -> *const c_char {
+    if ptr.is_null() {
+        return NULL.as_ptr();
+    }
+    let mut locked = false;
+    for bin in STRING_CACHE.0.iter() {
+        match bin.try_read() {
+            Some(sc) if sc.contains_entry(ptr as *const u8) => return ptr,
+            Some(_) => {}
+            None => locked = true,
+        }
+    }
+    if locked { LOCKED } else { INVALID }.as_ptr()
+}
+
+// Stops the linker from discarding `__ustr_debug_str()` from binaries that
+// don't call it, which is all of them.
+#[used]
+static KEEP: extern "C" fn(*const c_char) -> *const c_char = __ustr_debug_str;
+
+#[test]
+fn test_debug_str() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let s = u("debug me");
+    assert_eq!(__ustr_debug_str(s.as_char_ptr()), s.as_char_ptr());
+    assert_eq!(__ustr_debug_str(std::ptr::null()), NULL.as_ptr());
+    // Not a string in the cache, or the middle of one.
+    assert_eq!(__ustr_debug_str(c"debug me".as_ptr()), INVALID.as_ptr());
+    let middle = s.as_char_ptr().wrapping_add(1);
+    assert_eq!(__ustr_debug_str(middle), INVALID.as_ptr());
+
+    // A locked bin is reported rather than waited on.
+    let _locked = STRING_CACHE.0.iter().map(|b| b.write()).collect::<Vec<_>>();
+    assert_eq!(__ustr_debug_str(s.as_char_ptr()), LOCKED.as_ptr());
+}
@@ -0,0 +1,227 @@
+use super::{OutOfMemory, Ustr};
+use std::{error::Error, fmt};
+
+/// The error returned when a string could not be added to the cache by
+/// [`Ustr::try_intern`](crate::Ustr::try_intern).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InternError {
+    /// The string is longer than the limit set with
+    /// [`set_max_len()`](crate::set_max_len) and the policy is
+    /// [`MaxLenPolicy::Reject`](crate::MaxLenPolicy::Reject).
+    TooLong {
+        /// The length of the string in bytes.
+        len: usize,
+        /// The maximum length allowed in bytes.
+        max_len: usize,
+    },
+    /// The cache has been frozen with
+    /// [`FreezePolicy::Reject`](crate::FreezePolicy::Reject) and the string
+    /// isn't in it.
+    Frozen,
+    /// The cache couldn't allocate the memory to store the string, and the
+    /// handler set with [`set_oom_handler()`](crate::set_oom_handler) chose
+    /// [`OomAction::Error`](crate::OomAction::Error).
+    OutOfMemory(OutOfMemory),
+}
+
+impl fmt::Display for InternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InternError::TooLong { len, max_len } => write!(
+                f,
+                "string of {} bytes exceeds the maximum length of {} bytes",
+                len, max_len
+            ),
+            InternError::Frozen => {
+                write!(f, "the string cache is frozen")
+            }
+            InternError::OutOfMemory(oom) => oom.fmt(f),
+        }
+    }
+}
+
+impl Error for InternError {}
+
+/// A lightweight error whose message is interned.
+///
+/// Creating a `UstrError` from a message that has been seen before doesn't
+/// allocate (unless it has a source), so error enums on hot paths can carry a
+/// human-readable message cheaply. An optional source error can be attached
+/// for [`Error::source()`] chains.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::UstrError;
+/// use std::error::Error;
+///
+/// let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+/// let err = UstrError::with_source("could not load config", io);
+/// assert_eq!(err.to_string(), "could not load config");
+/// assert_eq!(err.source().unwrap().to_string(), "no such file");
+///
+/// // Convert into a boxed error for `?` in functions returning one.
+/// let boxed: Box<dyn Error + Send + Sync> = UstrError::new("oops").into();
+/// assert_eq!(boxed.to_string(), "oops");
+/// ```
+#[derive(Debug)]
+pub struct UstrError {
+    message: Ustr,
+    source: Option<Box<dyn Error + Send + Sync + 'static>>,
+}
+
+impl UstrError {
+    /// Create an error with `message` and no source.
+    pub fn new<M: Into<Ustr>>(message: M) -> UstrError {
+        UstrError {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Create an error with `message` that was caused by `source`.
+    pub fn with_source<M, E>(message: M, source: E) -> UstrError
+    where
+        M: Into<Ustr>,
+        E: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        UstrError {
+            message: message.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// The error message.
+    pub fn message(&self) -> Ustr {
+        self.message
+    }
+
+    /// Take the source error out of this error, if there is one.
+    pub fn into_source(self) -> Option<Box<dyn Error + Send + Sync + 'static>> {
+        self.source
+    }
+}
+
+impl fmt::Display for UstrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for UstrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|e| &**e as &(dyn Error + 'static))
+    }
+}
+
+impl From<Ustr> for UstrError {
+    fn from(message: Ustr) -> UstrError {
+        UstrError::new(message)
+    }
+}
+
+impl From<ErrorCode> for UstrError {
+    fn from(code: ErrorCode) -> UstrError {
+        UstrError::new(code.0)
+    }
+}
+
+/// An error that is nothing but an interned code, such as `"E0425"` or
+/// `"config.missing_key"`.
+///
+/// `ErrorCode` is `Copy` and pointer-sized, so it's as cheap to return as a
+/// plain enum, and comparing two codes is a pointer comparison.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ErrorCode;
+///
+/// fn parse(input: &str) -> Result<u32, ErrorCode> {
+///     input.parse().map_err(|_| ErrorCode::new("parse.not_a_number"))
+/// }
+///
+/// let err = parse("twelve").unwrap_err();
+/// assert_eq!(err, ErrorCode::new("parse.not_a_number"));
+/// assert_eq!(err.to_string(), "parse.not_a_number");
+///
+/// let boxed: Box<dyn std::error::Error> = err.into();
+/// assert_eq!(boxed.to_string(), "parse.not_a_number");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ErrorCode(Ustr);
+
+impl ErrorCode {
+    /// Create an error code, interning `code`.
+    pub fn new(code: &str) -> ErrorCode {
+        ErrorCode(Ustr::from(code))
+    }
+
+    /// The code as a `Ustr`.
+    pub fn as_ustr(&self) -> Ustr {
+        self.0
+    }
+
+    /// The code as a string slice.
+    pub fn as_str(&self) -> &'static str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ErrorCode {}
+
+impl From<Ustr> for ErrorCode {
+    fn from(code: Ustr) -> ErrorCode {
+        ErrorCode(code)
+    }
+}
+
+impl From<ErrorCode> for Ustr {
+    fn from(code: ErrorCode) -> Ustr {
+        code.0
+    }
+}
+
+impl From<Ustr> for Box<dyn Error + Send + Sync> {
+    fn from(message: Ustr) -> Box<dyn Error + Send + Sync> {
+        Box::new(UstrError::new(message))
+    }
+}
+
+impl From<Ustr> for Box<dyn Error> {
+    fn from(message: Ustr) -> Box<dyn Error> {
+        Box::new(UstrError::new(message))
+    }
+}
+
+#[test]
+fn test_ustr_error() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    let err = UstrError::new("bad thing");
+    assert_eq!(err.message(), u("bad thing"));
+    assert!(err.source().is_none());
+
+    let err = UstrError::with_source("outer", UstrError::new("inner"));
+    assert_eq!(err.source().unwrap().to_string(), "inner");
+    assert_eq!(err.into_source().unwrap().to_string(), "inner");
+
+    let code = ErrorCode::new("E0001");
+    assert_eq!(code.as_ustr(), u("E0001"));
+    assert_eq!(code.as_str(), "E0001");
+    assert_eq!(UstrError::from(code).message(), u("E0001"));
+    assert_eq!(Into::<Ustr>::into(code), u("E0001"));
+
+    fn fails() -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err(u("failed"))?
+    }
+    assert_eq!(fails().unwrap_err().to_string(), "failed");
+}
@@ -0,0 +1,152 @@
+//! Recover strings from raw dumps of the cache's memory, such as those in a
+//! core dump or minidump.
+//!
+//! A `Ustr` is a pointer to the chars of an entry in the cache, so when
+//! looking at a crashed program offline all that's left of each string is an
+//! address. The cache's storage is a set of arenas that entries are packed
+//! into one after the other, so given the bytes of an arena, and the address
+//! it was dumped from, [`parse_arena()`] can find every string in it and so
+//! resolve those addresses.
+//!
+//! The dump must come from a program built for the same pointer width and
+//! byte order as the one reading it, since the entries' headers are read as
+//! they were laid out in memory.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::collections::HashMap;
+//!
+//! // The arena's memory, saved from a core dump along with its address.
+//! let bytes = std::fs::read("arena.bin")?;
+//! let base = 0x7f3a_2000_0000usize;
+//!
+//! // Map the address of each string's chars, which is what a `Ustr` holds,
+//! // to the string.
+//! let offset = |s: &str| s.as_ptr() as usize - bytes.as_ptr() as usize;
+//! let strings: HashMap<usize, &str> = ustr::forensics::parse_arena(&bytes)
+//!     .map(|(_, s)| (base + offset(s), s))
+//!     .collect();
+//! println!("{:?}", strings.get(&0x7f3a_2000_0418));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use super::stringcache::StringCacheEntry;
+use std::mem::{align_of, offset_of, size_of};
+
+const HEADER_SIZE: usize = size_of::<StringCacheEntry>();
+const ALIGN: usize = align_of::<StringCacheEntry>();
+
+/// Walk a raw dump of one of the cache's arenas and return the precomputed
+/// hash and string of each entry in it.
+///
+/// `bytes` should start at an address aligned to 8 bytes (4 on i686) in the
+/// dumped program, such as the start of the arena. The used part of an arena
+/// is at its end, since it's filled from the top down, and the unused part
+/// before it is skipped along with anything else that doesn't look like an
+/// entry: a header whose length fits in the dump, followed by that many bytes
+/// of UTF-8, a nul terminator and zeroed padding. Any number of arenas can be
+/// passed at once if they're adjacent in the dump.
+///
+/// The strings are borrowed from `bytes`, so the offset of each from the
+/// start of `bytes` is the offset of its chars from the start of the dump.
+/// Strings are returned in the order they're found, which is the reverse of
+/// the order they were added to the arena.
+///
+/// Unused memory that happens to look like an entry will be returned too, so
+/// it's best to only pass the used part of each arena when its bounds are
+/// known.
+pub fn parse_arena(bytes: &[u8]) -> impl Iterator<Item = (u64, &str)> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        while offset + HEADER_SIZE < bytes.len() {
+            match parse_entry(&bytes[offset..]) {
+                Some((hash, string, size)) => {
+                    offset += size;
+                    return Some((hash, string));
+                }
+                None => offset += ALIGN,
+            }
+        }
+        None
+    })
+}
+
+// Parse the entry at the start of `bytes`, returning its hash, its string and
+// the number of bytes it takes up, or `None` if it isn't an entry.
+fn parse_entry(bytes: &[u8]) -> Option<(u64, &str, usize)> {
+    let field = |offset: usize, len: usize| bytes.get(offset..offset + len);
+    let hash = offset_of!(StringCacheEntry, hash);
+    let hash = u64::from_ne_bytes(field(hash, 8)?.try_into().ok()?);
+    let len = offset_of!(StringCacheEntry, len);
+    let len =
+        usize::from_ne_bytes(field(len, size_of::<usize>())?.try_into().ok()?);
+    // A zeroed header looks like an empty string, but no string hashes to 0
+    // in practice, so this skips unused memory that has been cleared.
+    if hash == 0 || len >= bytes.len() - HEADER_SIZE {
+        return None;
+    }
+
+    // The padding of the last entry in a truncated dump may be missing.
+    let size = StringCacheEntry::size_for_len(len);
+    let rest = &bytes[HEADER_SIZE..size.min(bytes.len())];
+    let (chars, tail) = rest.split_at(len);
+    if tail.is_empty() || tail.iter().any(|&b| b != 0) {
+        return None;
+    }
+    let string = std::str::from_utf8(chars).ok()?;
+    Some((hash, string, size))
+}
+
+#[test]
+fn test_parse_arena() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{hash_str, string_cache_iter, ustr as u, STRING_CACHE};
+    use std::collections::HashSet;
+
+    unsafe { super::_clear_cache() };
+
+    let mut expected = HashSet::new();
+    for i in 0..1000 {
+        expected.insert(u(&format!("forensics {}", i)).as_str());
+    }
+    for s in ["", "a", "1234567", "12345678", "naïve", "with\0nul"] {
+        expected.insert(u(s).as_str());
+    }
+    assert_eq!(expected, string_cache_iter().collect::<HashSet<_>>());
+
+    // Copy the used part of each arena, as a dump would.
+    let mut found = HashSet::new();
+    for bin in STRING_CACHE.0.iter() {
+        let sc = bin.read();
+        let alloc = &sc.alloc;
+        let used = alloc.end() as usize - alloc.ptr() as usize;
+        let dump = unsafe { std::slice::from_raw_parts(alloc.ptr(), used) };
+
+        // Put junk in front: cleared memory, then bytes that can't be a
+        // header, at an unaligned length.
+        let mut bytes = vec![0u8; 64];
+        bytes.extend([0xab; 13]);
+        bytes.resize(bytes.len().next_multiple_of(ALIGN), 0xab);
+        bytes.extend_from_slice(dump);
+
+        for (hash, s) in parse_arena(&bytes) {
+            assert_eq!(hash, hash_str(s));
+            // The string is at the same offset it was in the arena.
+            let offset = s.as_ptr() as usize - bytes.as_ptr() as usize;
+            let original =
+                (offset + alloc.ptr() as usize) - (bytes.len() - used);
+            assert_eq!(original, u(s).as_char_ptr() as usize);
+            assert!(found.insert(u(s).as_str()));
+        }
+    }
+    assert_eq!(found, expected);
+
+    // An entry whose padding is cut off.
+    let entry = u("truncated");
+    let start = unsafe { (entry.as_char_ptr() as *const u8).sub(HEADER_SIZE) };
+    let dump = unsafe { std::slice::from_raw_parts(start, HEADER_SIZE + 10) };
+    let parsed = parse_arena(dump).collect::<Vec<_>>();
+    assert_eq!(parsed, [(entry.precomputed_hash(), "truncated")]);
+    assert_eq!(parse_arena(&dump[..HEADER_SIZE + 9]).count(), 0);
+    assert_eq!(parse_arena(&[]).count(), 0);
+}
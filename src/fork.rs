@@ -0,0 +1,540 @@
+use super::{
+    canonical_str, hash_str, InternError, Interner, InternerStats, StringCache,
+    StringCacheEntry, StringCacheIterator, Ustr, Verification,
+};
+use parking_lot::RwLock;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::Deref,
+    ptr::NonNull,
+};
+
+#[cfg(debug_assertions)]
+use parking_lot::Mutex;
+#[cfg(debug_assertions)]
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+// Generation counters that no live fork is using, for new forks to reuse.
+// They're never freed, since `LocalUstr`s from dropped forks may still check
+// them.
+#[cfg(debug_assertions)]
+static FREE_GENERATIONS: Mutex<Vec<&'static AtomicU32>> =
+    Mutex::new(Vec::new());
+
+/// A cache layered on top of the global cache, whose strings can be thrown
+/// away wholesale.
+///
+/// Lookups check the fork and then fall through to the global cache, while
+/// strings that aren't in either are added to the fork only. Dropping the fork
+/// (or calling [`discard()`](ForkedCache::discard)) frees everything it
+/// added, and [`commit()`](ForkedCache::commit) moves it all into the global
+/// cache instead. This is handy for speculative work that may be cancelled,
+/// and which shouldn't permanently pollute the global cache if it is.
+///
+/// Strings from a fork are handed out as [`LocalUstr`]s, which borrow the
+/// fork so that they can't outlive it.
+///
+/// Unsafe code can still get that wrong, so in debug builds each fork has a
+/// generation counter that's bumped when it's dropped, and each `LocalUstr`
+/// records the generation of the fork it came from, along with its own copy of
+/// the string. Using a `LocalUstr` after its fork has been dropped then panics
+/// with that string, rather than reading freed memory. Only the counters and
+/// those copies outlive their forks, and the counters are reused by new forks,
+/// so this doesn't keep dropped forks' storage around.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, ForkedCache};
+///
+/// let existing = ustr("already global");
+///
+/// let fork = ForkedCache::new();
+/// assert_eq!(fork.intern("already global"), existing);
+/// let speculative = fork.intern("only in the fork");
+/// assert!(!speculative.is_global());
+/// assert_eq!(ustr::existing_ustr("only in the fork"), None);
+///
+/// fork.discard();
+/// assert_eq!(ustr::existing_ustr("only in the fork"), None);
+/// ```
+pub struct ForkedCache {
+    cache: RwLock<StringCache>,
+    // Bumped when the fork is dropped, after which a new fork may reuse it.
+    #[cfg(debug_assertions)]
+    generation: &'static AtomicU32,
+}
+
+impl ForkedCache {
+    /// Create a new, empty fork of the global cache.
+    pub fn new() -> ForkedCache {
+        ForkedCache::with_verification(Verification::Full)
+    }
+
+    /// Create a new, empty fork of the global cache whose lookups check
+    /// strings as described by `verification`.
+    ///
+    /// This only affects looking up strings in the fork itself. Lookups that
+    /// fall through to the global cache use the mode set with
+    /// [`set_verification()`](crate::set_verification).
+    pub fn with_verification(verification: Verification) -> ForkedCache {
+        let mut cache = StringCache::new_local();
+        cache.hash_only = verification == Verification::HashOnly;
+        ForkedCache {
+            cache: RwLock::new(cache),
+            #[cfg(debug_assertions)]
+            generation: FREE_GENERATIONS
+                .lock()
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(AtomicU32::new(0)))),
+        }
+    }
+
+    /// Intern `string`, adding it to the fork if it isn't in the fork or the
+    /// global cache already.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string is longer than the limit set with
+    /// [`set_max_len()`](crate::set_max_len) and the policy is
+    /// [`MaxLenPolicy::Reject`](crate::MaxLenPolicy::Reject), or if there's no
+    /// memory to store it and the handler set with
+    /// [`set_oom_handler()`](crate::set_oom_handler) doesn't abort.
+    pub fn intern(&self, string: &str) -> LocalUstr<'_> {
+        match self.try_intern(string) {
+            Ok(u) => u,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Intern `string`, returning an error if it's too long or there's no
+    /// memory to store it.
+    pub fn try_intern(
+        &self,
+        string: &str,
+    ) -> Result<LocalUstr<'_>, InternError> {
+        let canonical = canonical_str(string)?;
+        let string: &str = &canonical;
+        let hash = hash_str(string);
+
+        if let Some(u) = self.get_canonical(string, hash) {
+            return Ok(u);
+        }
+
+        // `try_insert()` checks whether another thread got here first.
+        let ptr = self
+            .cache
+            .write()
+            .try_insert(string, hash)
+            .map_err(InternError::OutOfMemory)?;
+        Ok(unsafe { LocalUstr::from_char_ptr(ptr, self) })
+    }
+
+    /// Returns the string if it's in the fork or the global cache.
+    pub fn get(&self, string: &str) -> Option<LocalUstr<'_>> {
+        let canonical = canonical_str(string).ok()?;
+        let string: &str = &canonical;
+        self.get_canonical(string, hash_str(string))
+    }
+
+    fn get_canonical(&self, string: &str, hash: u64) -> Option<LocalUstr<'_>> {
+        if let Some(ptr) = self.cache.read().get_existing(string, hash) {
+            return Some(unsafe { LocalUstr::from_char_ptr(ptr, self) });
+        }
+        Ustr::from_existing(string).map(LocalUstr::from)
+    }
+
+    /// The number of strings that have been added to the fork (not counting
+    /// those found in the global cache).
+    pub fn num_entries(&self) -> usize {
+        self.cache.read().num_entries()
+    }
+
+    /// Iterate over the strings that have been added to the fork, in no
+    /// particular order.
+    pub fn iter_local(&self) -> impl Iterator<Item = LocalUstr<'_>> {
+        let mut allocs = Vec::new();
+        self.cache.read().alloc_ranges(&mut allocs);
+        StringCacheIterator::new(allocs)
+            .map(|s| unsafe { LocalUstr::from_char_ptr(s.as_ptr(), self) })
+    }
+
+    /// Add every string in the fork to the global cache and free the fork,
+    /// returning the new global strings in the order they were added to the
+    /// fork.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`], such as when the global
+    /// cache is frozen with [`FreezePolicy::Reject`](crate::FreezePolicy) and
+    /// doesn't have one of the strings, or there's no memory to store them.
+    /// Strings added before the one that panicked stay in the global cache.
+    pub fn commit(self) -> Vec<Ustr> {
+        let mut strings = self
+            .iter_local()
+            .map(|u| (u.entry().generation, u))
+            .collect::<Vec<_>>();
+        strings.sort_unstable_by_key(|(generation, _)| *generation);
+        // These have already been through `canonical_str()`, so they can't be
+        // too long, but the global cache may be frozen or out of memory.
+        strings
+            .into_iter()
+            .map(|(_, u)| Ustr::from(u.as_str()))
+            .collect()
+    }
+
+    /// Throw away everything that has been added to the fork. This is the same
+    /// as dropping it.
+    pub fn discard(self) {}
+}
+
+impl Default for ForkedCache {
+    fn default() -> Self {
+        ForkedCache::new()
+    }
+}
+
+impl Drop for ForkedCache {
+    fn drop(&mut self) {
+        // Every `LocalUstr` borrows us, so nothing can point into the
+        // storage any more, unless unsafe code got that wrong, which
+        // `LocalUstr::check()` catches in debug builds.
+        #[cfg(debug_assertions)]
+        {
+            self.generation.fetch_add(1, Ordering::Release);
+            FREE_GENERATIONS.lock().push(self.generation);
+        }
+        unsafe { self.cache.get_mut().free() };
+    }
+}
+
+impl fmt::Debug for ForkedCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ForkedCache")
+            .field("num_entries", &self.num_entries())
+            .finish()
+    }
+}
+
+/// A string interned in a [`ForkedCache`], which may live in the fork or in
+/// the global cache.
+///
+/// Like [`Ustr`], this is a single pointer with the precomputed hash stored
+/// alongside the string, but it can't outlive the fork it came from.
+///
+/// In debug builds it also carries the generation of the fork it came from and
+/// a copy of the string, so that using it after the fork is dropped panics
+/// with the string it held. See [`ForkedCache`]. That copy is also why it's
+/// only `Clone` and not `Copy`.
+#[derive(Clone)]
+#[cfg_attr(not(debug_assertions), repr(transparent))]
+pub struct LocalUstr<'a> {
+    char_ptr: NonNull<u8>,
+    // The fork's generation counter, its value when this was created and a
+    // copy of the string to report if it's used after the fork is dropped, or
+    // `None` for strings in the global cache, which live forever.
+    #[cfg(debug_assertions)]
+    generation: Option<(&'static AtomicU32, u32, Arc<str>)>,
+    _marker: PhantomData<&'a ForkedCache>,
+}
+
+impl<'a> LocalUstr<'a> {
+    // `char_ptr` must point to the chars of an entry in `fork`'s storage.
+    unsafe fn from_char_ptr(
+        char_ptr: *const u8,
+        #[allow(unused_variables)] fork: &'a ForkedCache,
+    ) -> LocalUstr<'a> {
+        let u = LocalUstr {
+            char_ptr: NonNull::new_unchecked(char_ptr as *mut u8),
+            #[cfg(debug_assertions)]
+            generation: None,
+            _marker: PhantomData,
+        };
+        #[cfg(debug_assertions)]
+        let u = LocalUstr {
+            generation: Some((
+                fork.generation,
+                fork.generation.load(Ordering::Acquire),
+                Arc::from(u.as_str_unchecked()),
+            )),
+            ..u
+        };
+        u
+    }
+
+    // Panic if the fork this came from has been dropped.
+    #[cfg(debug_assertions)]
+    fn check(&self) {
+        if let Some((counter, generation, value)) = &self.generation {
+            if counter.load(Ordering::Acquire) != *generation {
+                // The dropped fork's storage has been freed, so show our own
+                // copy of the string.
+                panic!(
+                    "LocalUstr {:?} used after its ForkedCache was dropped",
+                    value
+                );
+            }
+        }
+    }
+
+    fn entry(&self) -> &StringCacheEntry {
+        #[cfg(debug_assertions)]
+        self.check();
+        self.entry_unchecked()
+    }
+
+    fn entry_unchecked(&self) -> &StringCacheEntry {
+        // The entry header sits right before the chars, in the fork or the
+        // global cache.
+        unsafe { &*(self.char_ptr.as_ptr().cast::<StringCacheEntry>().sub(1)) }
+    }
+
+    /// Get the string as a `str`, borrowed for as long as the fork.
+    pub fn as_str(&self) -> &'a str {
+        #[cfg(debug_assertions)]
+        self.check();
+        self.as_str_unchecked()
+    }
+
+    fn as_str_unchecked(&self) -> &'a str {
+        unsafe {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+                self.char_ptr.as_ptr(),
+                self.entry_unchecked().len,
+            ))
+        }
+    }
+
+    /// Call `f` with the string, returning what it returns.
+    ///
+    /// This is the same as [`Ustr::with_str()`], so code that takes either
+    /// kind of string can be written the same way.
+    #[inline]
+    pub fn with_str<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        f(self.as_str())
+    }
+
+    /// Get the length (in bytes) of this string.
+    pub fn len(&self) -> usize {
+        self.entry().len
+    }
+
+    /// Returns true if the length is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the precomputed hash for this string.
+    pub fn precomputed_hash(&self) -> u64 {
+        self.entry().hash
+    }
+
+    /// Returns `true` if this string is in the global cache, i.e. it will
+    /// still be around when the fork is gone.
+    pub fn is_global(&self) -> bool {
+        self.to_global().is_some()
+    }
+
+    /// Returns this string as a `Ustr` if it's in the global cache.
+    pub fn to_global(&self) -> Option<Ustr> {
+        Ustr::from_existing(self.as_str())
+            .filter(|u| u.as_char_ptr() == self.char_ptr.as_ptr() as *const _)
+    }
+
+    /// Add this string to the global cache (if it isn't already there) and
+    /// return it as a `Ustr`.
+    pub fn to_ustr(&self) -> Ustr {
+        Ustr::from(self.as_str())
+    }
+}
+
+impl<'a> From<Ustr> for LocalUstr<'a> {
+    fn from(u: Ustr) -> Self {
+        // Strings in the global cache live forever.
+        LocalUstr {
+            char_ptr: u.char_ptr,
+            #[cfg(debug_assertions)]
+            generation: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A string may be added to the fork and then later to the global cache as
+/// well, so fall back to comparing the strings if the pointers differ.
+impl PartialEq for LocalUstr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.char_ptr == other.char_ptr
+            || (self.precomputed_hash() == other.precomputed_hash()
+                && self.as_str() == other.as_str())
+    }
+}
+
+impl Eq for LocalUstr<'_> {}
+
+impl PartialEq<Ustr> for LocalUstr<'_> {
+    fn eq(&self, other: &Ustr) -> bool {
+        self.char_ptr == other.char_ptr
+            || (self.precomputed_hash() == other.precomputed_hash()
+                && self.as_str() == other.as_str())
+    }
+}
+
+impl PartialEq<str> for LocalUstr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for LocalUstr<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for LocalUstr<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.precomputed_hash().hash(state);
+    }
+}
+
+impl Deref for LocalUstr<'_> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for LocalUstr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for LocalUstr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "u!({:?})", self.as_str())
+    }
+}
+
+// The fork is `Sync`, and the strings it points to are never modified.
+unsafe impl Send for LocalUstr<'_> {}
+unsafe impl Sync for LocalUstr<'_> {}
+
+impl Interner for ForkedCache {
+    type Symbol<'a> = LocalUstr<'a>;
+    type Iter<'a> = Box<dyn Iterator<Item = LocalUstr<'a>> + 'a>;
+
+    fn intern(&self, string: &str) -> LocalUstr<'_> {
+        ForkedCache::intern(self, string)
+    }
+
+    fn get(&self, string: &str) -> Option<LocalUstr<'_>> {
+        ForkedCache::get(self, string)
+    }
+
+    /// Iterates over the fork's strings followed by the global cache's.
+    fn iter(&self) -> Self::Iter<'_> {
+        Box::new(
+            self.iter_local()
+                .chain(super::cache().iter().map(LocalUstr::from)),
+        )
+    }
+
+    /// Statistics for the fork only.
+    fn stats(&self) -> InternerStats {
+        let sc = self.cache.read();
+        InternerStats {
+            num_entries: sc.num_entries(),
+            total_allocated: sc.total_allocated(),
+            total_capacity: sc.total_capacity(),
+        }
+    }
+}
+
+#[test]
+fn test_forked_cache() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let global = u("global");
+    let fork = ForkedCache::new();
+
+    // Global strings are found through the fork.
+    assert_eq!(fork.intern("global"), global);
+    assert_eq!(fork.get("global").unwrap().to_global(), Some(global));
+    assert_eq!(fork.num_entries(), 0);
+
+    let a = fork.intern("local a");
+    let b = fork.intern("local b");
+    assert_eq!(fork.intern("local a"), a);
+    assert_ne!(a, b);
+    assert_eq!(a, "local a");
+    assert_eq!(a.len(), 7);
+    assert_eq!(a.with_str(|s| s.to_uppercase()), "LOCAL A");
+    assert_eq!(global.with_str(str::len), 6);
+    assert_eq!(a.precomputed_hash(), hash_str("local a"));
+    assert!(!a.is_global());
+    assert_eq!(fork.num_entries(), 2);
+    assert_eq!(super::num_entries(), 1);
+    assert_eq!(super::existing_ustr("local a"), None);
+
+    // If the string is added to the global cache later it still compares
+    // equal, even though the pointers differ.
+    assert_eq!(a, u("local a"));
+    assert!(!a.is_global());
+    assert!(a.to_ustr().as_str() == "local a");
+
+    let mut local = fork.iter_local().map(|s| s.as_str()).collect::<Vec<_>>();
+    local.sort();
+    assert_eq!(local, ["local a", "local b"]);
+    assert_eq!(Interner::iter(&fork).count(), 4);
+    assert_eq!(fork.stats().num_entries, 2);
+
+    let committed = fork.commit();
+    assert_eq!(committed, [u("local a"), u("local b")]);
+    assert_eq!(super::num_entries(), 3);
+
+    // Dropping a fork frees its strings without touching the global cache.
+    let fork = ForkedCache::new();
+    for i in 0..10_000 {
+        fork.intern(&format!("discarded {}", i));
+    }
+    drop(fork);
+    assert_eq!(super::num_entries(), 3);
+    assert_eq!(super::existing_ustr("discarded 0"), None);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(
+    expected = "LocalUstr \"short-lived\" used after its ForkedCache"
+)]
+fn test_local_ustr_after_drop() {
+    let _t = super::TEST_LOCK.lock();
+
+    let fork = ForkedCache::new();
+    let global = LocalUstr::from(crate::ustr("long-lived"));
+    // Simulate unsafe code that lets a `LocalUstr` escape its fork.
+    let stale = unsafe {
+        std::mem::transmute::<LocalUstr<'_>, LocalUstr<'static>>(
+            fork.intern("short-lived"),
+        )
+    };
+    assert_eq!(stale, "short-lived");
+    drop(fork);
+    // A new fork reuses the dropped one's generation counter.
+    let reused = ForkedCache::new();
+    assert_eq!(reused.intern("short-lived"), "short-lived");
+
+    // Global strings are unaffected.
+    assert_eq!(global, "long-lived");
+    let _ = stale.as_str();
+}
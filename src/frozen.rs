@@ -0,0 +1,302 @@
+use super::{
+    canonical_str, hash_str, simd, verify, write_current_bins, PerfectHash,
+    StringCacheEntry, Ustr,
+};
+use std::{
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        OnceLock,
+    },
+};
+
+/// What happens to strings that aren't in the cache when it's frozen, as
+/// passed to [`freeze()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreezePolicy {
+    /// Refuse to add new strings: [`Ustr::try_intern`] returns
+    /// [`InternError::Frozen`](crate::InternError::Frozen) and [`Ustr::from`]
+    /// panics.
+    Reject,
+    /// Add new strings to the regular, locked cache. Strings that were in the
+    /// cache when it was frozen are still found without taking any locks.
+    Fallback,
+}
+
+/// A read-only snapshot of the cache, as returned by [`freeze()`].
+///
+/// All the strings that were in the cache when it was frozen are stored in a
+/// single open-addressed table that is never modified, so looking them up
+/// doesn't need any locks.
+#[derive(Debug)]
+pub struct FrozenCache {
+    entries: Box<[*const StringCacheEntry]>,
+    mask: usize,
+    len: usize,
+    policy: FreezePolicy,
+    phf: OnceLock<Option<PerfectHash>>,
+}
+
+// The table is never modified after it's built, and the entries it points to
+// are immutable and live forever.
+unsafe impl Send for FrozenCache {}
+unsafe impl Sync for FrozenCache {}
+
+impl FrozenCache {
+    fn new(
+        entries: Vec<*const StringCacheEntry>,
+        policy: FreezePolicy,
+    ) -> FrozenCache {
+        // Keep the load factor at or below 0.5 like the regular cache, so
+        // probe sequences stay short.
+        let capacity = (entries.len() * 2).next_power_of_two().max(2);
+        let mask = capacity - 1;
+        let mut table: Box<[*const StringCacheEntry]> =
+            vec![std::ptr::null(); capacity].into_boxed_slice();
+        for e in &entries {
+            let mut pos = unsafe { (**e).hash } as usize & mask;
+            let mut dist = 0;
+            while !table[pos].is_null() {
+                dist += 1;
+                pos = (pos + dist) & mask;
+            }
+            table[pos] = *e;
+        }
+        FrozenCache {
+            entries: table,
+            mask,
+            len: entries.len(),
+            policy,
+            phf: OnceLock::new(),
+        }
+    }
+
+    // Look up a string that has already been through `canonical_str()`.
+    pub(crate) fn get_canonical(
+        &self,
+        string: &str,
+        hash: u64,
+    ) -> Option<Ustr> {
+        if let Some(Some(phf)) = self.phf.get() {
+            return phf.get_canonical(string, hash);
+        }
+
+        let mut pos = hash as usize & self.mask;
+        let mut dist = 0;
+        loop {
+            let entry = self.entries[pos];
+            if entry.is_null() {
+                return None;
+            }
+            // Non-null entries always point to valid entries in the cache.
+            let sce = unsafe { &*entry };
+            if sce.hash == hash && sce.len == string.len() {
+                let chars = unsafe {
+                    std::slice::from_raw_parts(sce.char_ptr(), sce.len)
+                };
+                if verify::hash_only()
+                    || simd::bytes_eq(chars, string.as_bytes())
+                {
+                    return Some(Ustr {
+                        char_ptr: unsafe {
+                            NonNull::new_unchecked(sce.char_ptr() as *mut _)
+                        },
+                    });
+                }
+            }
+            dist += 1;
+            pos = (pos + dist) & self.mask;
+        }
+    }
+
+    /// Returns the `Ustr` for `string` if it was in the cache when it was
+    /// frozen.
+    pub fn get(&self, string: &str) -> Option<Ustr> {
+        let canonical = canonical_str(string).ok()?;
+        self.get_canonical(&canonical, hash_str(&canonical))
+    }
+
+    /// The number of strings in the snapshot.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the snapshot has no strings.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// What happens to strings that aren't in the snapshot.
+    pub fn policy(&self) -> FreezePolicy {
+        self.policy
+    }
+
+    /// Build a minimal perfect hash over the strings in the snapshot.
+    ///
+    /// Once this has been called, lookups of frozen strings (through
+    /// [`existing_ustr()`](crate::existing_ustr), [`Ustr::from`] and so on)
+    /// use the perfect hash instead of probing, so a hit costs one hash and a
+    /// couple of table reads. This is a good fit for a fixed set of keywords
+    /// that is known up front. Building takes time roughly linear in the
+    /// number of strings, and only happens once: later calls return the same
+    /// table.
+    ///
+    /// Returns `None`, and leaves lookups as they were, in the astronomically
+    /// unlikely case that two frozen strings have the same 64-bit hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{existing_ustr, ustr, FreezePolicy};
+    ///
+    /// for keyword in ["fn", "let", "match", "struct"] {
+    ///     ustr(keyword);
+    /// }
+    /// let phf = ustr::freeze(FreezePolicy::Fallback).compile_phf().unwrap();
+    /// assert_eq!(phf.len(), 4);
+    ///
+    /// // Keyword lookups are now a single slot read, with no probing.
+    /// assert_eq!(existing_ustr("match"), Some(ustr("match")));
+    /// // Identifiers still go through the regular cache.
+    /// assert_eq!(existing_ustr("my_var"), None);
+    /// let ident = ustr("my_var");
+    /// assert_eq!(existing_ustr("my_var"), Some(ident));
+    /// ```
+    pub fn compile_phf(&self) -> Option<&PerfectHash> {
+        self.phf
+            .get_or_init(|| {
+                let entries = self
+                    .entries
+                    .iter()
+                    .filter(|e| !e.is_null())
+                    .copied()
+                    .collect::<Vec<_>>();
+                PerfectHash::new(&entries)
+            })
+            .as_ref()
+    }
+
+    /// Iterate over the strings in the snapshot, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = Ustr> + '_ {
+        self.entries.iter().filter(|e| !e.is_null()).map(|e| Ustr {
+            char_ptr: unsafe {
+                NonNull::new_unchecked((**e).char_ptr() as *mut _)
+            },
+        })
+    }
+}
+
+static FROZEN: AtomicPtr<FrozenCache> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Freeze the cache, so that looking up the strings that are in it no longer
+/// takes any locks.
+///
+/// Call this after warming up, once the working set of strings is known.
+/// Strings that aren't in the frozen snapshot are handled according to
+/// `policy`. The cache can only be frozen once: subsequent calls return the
+/// existing snapshot and ignore `policy`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, FreezePolicy, Ustr};
+///
+/// let keyword = ustr("fn");
+/// let frozen = ustr::freeze(FreezePolicy::Reject);
+/// assert_eq!(frozen.len(), 1);
+///
+/// // Hits don't take any locks now.
+/// assert_eq!(ustr("fn"), keyword);
+/// assert!(Ustr::try_intern("struct").is_err());
+/// ```
+pub fn freeze(policy: FreezePolicy) -> &'static FrozenCache {
+    if let Some(frozen) = frozen() {
+        return frozen;
+    }
+
+    // Hold every write lock while we take the snapshot and publish it, so
+    // that nothing can be added in between.
+    let bins = write_current_bins();
+    if let Some(frozen) = frozen() {
+        return frozen;
+    }
+    let entries = bins.iter().flat_map(|sc| sc.entry_ptrs()).collect();
+    let frozen = Box::into_raw(Box::new(FrozenCache::new(entries, policy)));
+    FROZEN.store(frozen, Ordering::Release);
+    drop(bins);
+    unsafe { &*frozen }
+}
+
+/// Returns the frozen snapshot of the cache, if [`freeze()`] has been called.
+#[inline]
+pub fn frozen() -> Option<&'static FrozenCache> {
+    // Once set, this is only ever cleared by `_clear_cache()`, and the
+    // snapshot is never freed.
+    unsafe { FROZEN.load(Ordering::Acquire).as_ref() }
+}
+
+// Unfreeze the cache. Only called by `_clear_cache()`. The old snapshot is
+// leaked since there may still be references to it.
+pub(crate) fn clear() {
+    FROZEN.store(std::ptr::null_mut(), Ordering::Release);
+}
+
+// Replace the snapshot, returning the old one, so that `CacheGuard` can set
+// it aside and restore it.
+pub(crate) fn replace(frozen: *mut FrozenCache) -> *mut FrozenCache {
+    FROZEN.swap(frozen, Ordering::AcqRel)
+}
+
+#[test]
+fn test_freeze() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{existing_ustr, InternError};
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let words = (0..1000)
+        .map(|i| u(&format!("frozen {}", i)))
+        .collect::<Vec<_>>();
+    assert!(frozen().is_none());
+
+    let frozen = freeze(FreezePolicy::Reject);
+    assert_eq!(frozen.len(), 1000);
+    assert_eq!(frozen.iter().count(), 1000);
+    for w in &words {
+        assert_eq!(frozen.get(w), Some(*w));
+        assert_eq!(u(w), *w);
+        assert_eq!(existing_ustr(w), Some(*w));
+        assert!(Ustr::is_interned(w));
+    }
+    assert_eq!(frozen.get("not frozen"), None);
+    assert_eq!(existing_ustr("not frozen"), None);
+    assert_eq!(Ustr::try_intern("not frozen"), Err(InternError::Frozen));
+    assert!(std::panic::catch_unwind(|| u("not frozen")).is_err());
+
+    // Lookups give the same answers through the perfect hash.
+    let phf = frozen.compile_phf().unwrap();
+    assert_eq!(phf.len(), 1000);
+    assert!(std::ptr::eq(frozen.compile_phf().unwrap(), phf));
+    for w in &words {
+        assert_eq!(existing_ustr(w), Some(*w));
+        assert_eq!(u(w), *w);
+    }
+    assert_eq!(existing_ustr("not frozen"), None);
+    assert_eq!(Ustr::try_intern("not frozen"), Err(InternError::Frozen));
+
+    // Freezing again just hands back the same snapshot.
+    assert!(std::ptr::eq(freeze(FreezePolicy::Fallback), frozen));
+
+    unsafe { super::_clear_cache() };
+    assert!(super::frozen().is_none());
+
+    u("before");
+    freeze(FreezePolicy::Fallback);
+    let after = u("after");
+    assert_eq!(super::frozen().unwrap().get("after"), None);
+    assert_eq!(existing_ustr("after"), Some(after));
+    assert_eq!(super::num_entries(), 2);
+
+    unsafe { super::_clear_cache() };
+}
@@ -0,0 +1,95 @@
+use super::Ustr;
+use async_graphql::{
+    InputValueError, InputValueResult, Scalar, ScalarType, Value,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static MAX_LEN: AtomicUsize = AtomicUsize::new(1024);
+
+/// Limit the length of `Ustr` inputs accepted from GraphQL queries.
+///
+/// Strings in queries come from clients, and since interned strings are never
+/// freed, a client could otherwise fill the cache with arbitrarily long
+/// strings. Inputs longer than `max_len` bytes are rejected with an error
+/// before they're interned. The default is 1024 bytes. The limit set with
+/// [`set_max_len()`](crate::set_max_len) applies too.
+///
+/// This is available with the `async-graphql` feature.
+pub fn set_graphql_max_len(max_len: usize) {
+    MAX_LEN.store(max_len, Ordering::Relaxed);
+}
+
+/// Returns the maximum length of GraphQL inputs set with
+/// [`set_graphql_max_len()`].
+pub fn graphql_max_len() -> usize {
+    MAX_LEN.load(Ordering::Relaxed)
+}
+
+/// `Ustr` is a GraphQL scalar named `Ustr`, which is a string on the wire.
+#[Scalar(name = "Ustr")]
+impl ScalarType for Ustr {
+    fn parse(value: Value) -> InputValueResult<Ustr> {
+        let Value::String(s) = &value else {
+            return Err(InputValueError::expected_type(value));
+        };
+        let max_len = graphql_max_len();
+        if s.len() > max_len {
+            return Err(InputValueError::custom(format!(
+                "string of {} bytes exceeds the maximum length of {} bytes",
+                s.len(),
+                max_len
+            )));
+        }
+        Ustr::try_intern(s).map_err(InputValueError::custom)
+    }
+
+    fn is_valid(value: &Value) -> bool {
+        matches!(value, Value::String(_))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.as_str().to_owned())
+    }
+}
+
+#[test]
+fn test_graphql() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn echo(&self, name: Ustr) -> Ustr {
+            name
+        }
+
+        async fn is_root(&self, name: Ustr) -> bool {
+            name == u("root")
+        }
+    }
+
+    let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+    let run = |query: &str| {
+        futures_executor::block_on(schema.execute(query)).into_result()
+    };
+
+    let data = run(r#"{ echo(name: "node/1") isRoot(name: "root") }"#)
+        .unwrap()
+        .data
+        .into_json()
+        .unwrap();
+    assert_eq!(data["echo"], "node/1");
+    assert_eq!(data["isRoot"], true);
+    assert!(schema.sdl().contains("scalar Ustr"));
+
+    // Inputs that are too long or of the wrong type are rejected.
+    assert!(run(r#"{ echo(name: 17) }"#).is_err());
+    set_graphql_max_len(4);
+    let too_long = run(r#"{ echo(name: "too long") }"#);
+    set_graphql_max_len(1024);
+    assert!(too_long.is_err());
+    assert_eq!(graphql_max_len(), 1024);
+}
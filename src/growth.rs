@@ -0,0 +1,203 @@
+use super::MAX_ALLOC;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Controls how the cache grows, as passed to [`set_growth_policy()`].
+///
+/// The defaults keep each bin's table at most half full and double the size
+/// of each new string arena, which keeps lookups fast and the number of
+/// allocations low. On memory-constrained machines, a higher load factor and a
+/// smaller growth factor or arena cap trade a little speed for a much smaller
+/// peak footprint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GrowthPolicy {
+    /// The fraction of a bin's table slots that can be in use before the table
+    /// doubles in size. Must be greater than 0 and at most 0.9. The default is
+    /// 0.5.
+    pub max_load_factor: f64,
+    /// How much bigger each new string arena is than the last. Must be at
+    /// least 1, where 1 means every arena is the same size as the first. The
+    /// default is 2.
+    pub arena_growth: f64,
+    /// The largest a string arena will grow to, in bytes. Strings bigger than
+    /// this still get an arena of their own. The default is 1TB on 64-bit
+    /// targets and 64MB otherwise.
+    pub max_arena_size: usize,
+    /// If set, every new string arena is exactly this many bytes (or the size
+    /// of the string, if that's bigger) and `arena_growth` and
+    /// `max_arena_size` are ignored. See [`GrowthPolicy::chunked()`]. The
+    /// default is `None`.
+    pub chunk_size: Option<usize>,
+}
+
+impl GrowthPolicy {
+    /// The policy the cache uses unless [`set_growth_policy()`] is called.
+    pub const DEFAULT: GrowthPolicy = GrowthPolicy {
+        max_load_factor: 0.5,
+        arena_growth: 2.0,
+        max_arena_size: MAX_ALLOC,
+        chunk_size: None,
+    };
+
+    /// The default policy, but with string arenas allocated in fixed-size
+    /// chunks of `chunk_size` bytes instead of growing geometrically.
+    ///
+    /// Every arena after the first is the same size, so the cache never asks
+    /// for a block much bigger than the last one. This gives predictable
+    /// allocation sizes and avoids the fragmentation that repeatedly doubling
+    /// can cause in long-running processes, at the cost of more allocations
+    /// (one per chunk per bin) and up to one partly used chunk per bin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::GrowthPolicy;
+    ///
+    /// ustr::set_growth_policy(GrowthPolicy::chunked(16 << 20));
+    /// # ustr::set_growth_policy(GrowthPolicy::DEFAULT);
+    /// ```
+    pub const fn chunked(chunk_size: usize) -> GrowthPolicy {
+        GrowthPolicy {
+            chunk_size: Some(chunk_size),
+            ..GrowthPolicy::DEFAULT
+        }
+    }
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> GrowthPolicy {
+        GrowthPolicy::DEFAULT
+    }
+}
+
+// The fields of the current policy, with the floats stored as their bits.
+static MAX_LOAD_FACTOR: AtomicU64 =
+    AtomicU64::new(GrowthPolicy::DEFAULT.max_load_factor.to_bits());
+static ARENA_GROWTH: AtomicU64 =
+    AtomicU64::new(GrowthPolicy::DEFAULT.arena_growth.to_bits());
+static MAX_ARENA_SIZE: AtomicUsize = AtomicUsize::new(MAX_ALLOC);
+// Zero means no chunk size.
+static CHUNK_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the policy for growing the cache's tables and string arenas.
+///
+/// This can be called at any time, and takes effect the next time a bin's
+/// table or arena fills up. Existing tables and arenas are never shrunk.
+///
+/// # Panics
+///
+/// Panics if any of the fields of `policy` are out of range, as described on
+/// [`GrowthPolicy`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::GrowthPolicy;
+///
+/// // Fill tables to 75% and grow arenas by 1.5x up to 256MB.
+/// ustr::set_growth_policy(GrowthPolicy {
+///     max_load_factor: 0.75,
+///     arena_growth: 1.5,
+///     max_arena_size: 256 << 20,
+///     chunk_size: None,
+/// });
+/// assert_eq!(ustr::growth_policy().arena_growth, 1.5);
+/// # ustr::set_growth_policy(GrowthPolicy::DEFAULT);
+/// ```
+pub fn set_growth_policy(policy: GrowthPolicy) {
+    assert!(
+        policy.max_load_factor > 0.0 && policy.max_load_factor <= 0.9,
+        "max_load_factor must be in (0, 0.9], got {}",
+        policy.max_load_factor
+    );
+    assert!(
+        policy.arena_growth >= 1.0 && policy.arena_growth.is_finite(),
+        "arena_growth must be at least 1, got {}",
+        policy.arena_growth
+    );
+    assert!(policy.max_arena_size > 0, "max_arena_size must not be 0");
+    assert!(policy.chunk_size != Some(0), "chunk_size must not be 0");
+    MAX_LOAD_FACTOR.store(policy.max_load_factor.to_bits(), Ordering::Relaxed);
+    ARENA_GROWTH.store(policy.arena_growth.to_bits(), Ordering::Relaxed);
+    MAX_ARENA_SIZE.store(policy.max_arena_size, Ordering::Relaxed);
+    CHUNK_SIZE.store(policy.chunk_size.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Returns the current policy set with [`set_growth_policy()`].
+pub fn growth_policy() -> GrowthPolicy {
+    GrowthPolicy {
+        max_load_factor: f64::from_bits(
+            MAX_LOAD_FACTOR.load(Ordering::Relaxed),
+        ),
+        arena_growth: f64::from_bits(ARENA_GROWTH.load(Ordering::Relaxed)),
+        max_arena_size: MAX_ARENA_SIZE.load(Ordering::Relaxed),
+        chunk_size: match CHUNK_SIZE.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        },
+    }
+}
+
+// The number of entries at which a table with `slots` slots should grow.
+#[inline]
+pub(crate) fn max_entries(slots: usize) -> usize {
+    let load = f64::from_bits(MAX_LOAD_FACTOR.load(Ordering::Relaxed));
+    ((slots as f64 * load) as usize).max(1)
+}
+
+#[test]
+fn test_growth_policy() {
+    let _t = super::TEST_LOCK.lock();
+
+    assert_eq!(growth_policy(), GrowthPolicy::DEFAULT);
+    assert_eq!(max_entries(1024), 512);
+
+    let policy = GrowthPolicy {
+        max_load_factor: 0.75,
+        arena_growth: 1.5,
+        max_arena_size: 1 << 20,
+        chunk_size: None,
+    };
+    set_growth_policy(policy);
+    assert_eq!(growth_policy(), policy);
+    assert_eq!(max_entries(1024), 768);
+
+    // A table can now be filled past half way without growing.
+    let mut sc = super::StringCache::new_local();
+    let slots = sc.table_capacity();
+    for i in 0..slots * 2 / 3 {
+        let s = format!("growth {}", i);
+        sc.insert(&s, super::hash_str(&s));
+    }
+    assert_eq!(sc.table_capacity(), slots);
+    assert_eq!(sc.num_grows, 0);
+    unsafe { sc.free() };
+
+    let bad = GrowthPolicy {
+        max_load_factor: 1.0,
+        ..GrowthPolicy::DEFAULT
+    };
+    assert!(std::panic::catch_unwind(|| set_growth_policy(bad)).is_err());
+    let bad = GrowthPolicy {
+        arena_growth: 0.5,
+        ..GrowthPolicy::DEFAULT
+    };
+    assert!(std::panic::catch_unwind(|| set_growth_policy(bad)).is_err());
+    assert_eq!(growth_policy(), policy);
+    let bad = GrowthPolicy::chunked(0);
+    assert!(std::panic::catch_unwind(|| set_growth_policy(bad)).is_err());
+
+    // In chunked mode every new arena is the same size.
+    set_growth_policy(GrowthPolicy::chunked(1 << 16));
+    assert_eq!(growth_policy().chunk_size, Some(1 << 16));
+    let mut sc = super::StringCache::new_local();
+    for i in 0..10_000 {
+        let s = format!("chunked {}", i);
+        sc.insert(&s, super::hash_str(&s));
+    }
+    assert!(sc.old_allocs.len() > 2);
+    assert!(sc.old_allocs[1..].iter().all(|a| a.capacity() == 1 << 16));
+    assert_eq!(sc.alloc.capacity(), 1 << 16);
+    unsafe { sc.free() };
+
+    set_growth_policy(GrowthPolicy::DEFAULT);
+}
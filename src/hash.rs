@@ -1,7 +1,7 @@
-use super::Ustr;
+use super::{existing_ustr, Ustr};
 use byteorder::{ByteOrder, NativeEndian};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map, HashMap, HashSet},
     hash::{BuildHasherDefault, Hasher},
 };
 
@@ -13,6 +13,217 @@ pub type UstrMap<V> = HashMap<Ustr, V, BuildHasherDefault<IdentityHasher>>;
 /// that just uses the precomputed hash for speed instead of calculating it.
 pub type UstrSet = HashSet<Ustr, BuildHasherDefault<IdentityHasher>>;
 
+/// An `IndexMap` using `Ustr` as the key type with a custom `Hasher` that just
+/// uses the precomputed hash for speed instead of calculating it.
+///
+/// Unlike [`UstrMap`], iteration follows insertion order, which is handy when
+/// the map needs to be serialized deterministically.
+///
+/// This is available with the `indexmap` feature.
+#[cfg(feature = "indexmap")]
+pub type UstrIndexMap<V> =
+    indexmap::IndexMap<Ustr, V, BuildHasherDefault<IdentityHasher>>;
+
+/// An `IndexSet` using `Ustr` as the key type with a custom `Hasher` that just
+/// uses the precomputed hash for speed instead of calculating it.
+///
+/// This is available with the `indexmap` feature.
+#[cfg(feature = "indexmap")]
+pub type UstrIndexSet =
+    indexmap::IndexSet<Ustr, BuildHasherDefault<IdentityHasher>>;
+
+/// A `DashMap` using `Ustr` as the key type with a custom `Hasher` that just
+/// uses the precomputed hash for speed instead of calculating it.
+///
+/// `DashMap` picks each key's shard from the bits of its hash just below the
+/// top seven, and `hashbrown` uses the top seven and the lowest bits within
+/// each shard. The precomputed hash is well mixed in all of them, so keys are
+/// spread as evenly across shards as with `DashMap`'s default hasher, without
+/// hashing the string a second time.
+///
+/// This is available with the `dashmap` feature.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrDashMap};
+///
+/// let map = UstrDashMap::default();
+/// std::thread::scope(|s| {
+///     for i in 0..4 {
+///         let map = &map;
+///         s.spawn(move || map.insert(u(&format!("worker {}", i)), i));
+///     }
+/// });
+/// assert_eq!(*map.get(&u("worker 2")).unwrap(), 2);
+/// ```
+#[cfg(feature = "dashmap")]
+pub type UstrDashMap<V> =
+    dashmap::DashMap<Ustr, V, BuildHasherDefault<IdentityHasher>>;
+
+/// A `DashSet` using `Ustr` as the key type with a custom `Hasher` that just
+/// uses the precomputed hash for speed instead of calculating it.
+///
+/// This is available with the `dashmap` feature.
+#[cfg(feature = "dashmap")]
+pub type UstrDashSet =
+    dashmap::DashSet<Ustr, BuildHasherDefault<IdentityHasher>>;
+
+/// Extension methods for [`UstrMap`].
+pub trait UstrMapExt<V> {
+    /// Gets the entry for the given string key for in-place manipulation.
+    ///
+    /// Unlike `map.entry(ustr(key))`, this only adds `key` to the string cache
+    /// if it isn't there already *and* a value is actually inserted, so
+    /// looking up tokens that turn out not to be wanted doesn't grow the
+    /// cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{existing_ustr, UstrMap, UstrMapExt};
+    ///
+    /// let mut counts: UstrMap<usize> = UstrMap::default();
+    /// for token in "the cat sat on the mat".split_whitespace() {
+    ///     *counts.entry_str(token).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(counts[&existing_ustr("the").unwrap()], 2);
+    ///
+    /// // Only modifying existing entries never interns anything.
+    /// counts.entry_str("never seen before").and_modify(|c| *c += 1);
+    /// assert_eq!(existing_ustr("never seen before"), None);
+    /// ```
+    fn entry_str<'a>(&'a mut self, key: &'a str) -> StrEntry<'a, V>;
+}
+
+impl<V> UstrMapExt<V> for UstrMap<V> {
+    fn entry_str<'a>(&'a mut self, key: &'a str) -> StrEntry<'a, V> {
+        // If the string isn't in the cache it can't be in the map either.
+        let u = match existing_ustr(key) {
+            Some(u) => u,
+            None => {
+                return StrEntry::Vacant(VacantStrEntry {
+                    inner: VacantInner::NotInterned { map: self, key },
+                })
+            }
+        };
+
+        match self.entry(u) {
+            hash_map::Entry::Occupied(o) => StrEntry::Occupied(o),
+            hash_map::Entry::Vacant(v) => StrEntry::Vacant(VacantStrEntry {
+                inner: VacantInner::Interned(v),
+            }),
+        }
+    }
+}
+
+/// A view into a single entry in a [`UstrMap`] keyed by a `&str`, as returned
+/// by [`UstrMapExt::entry_str`].
+pub enum StrEntry<'a, V> {
+    /// An occupied entry.
+    Occupied(hash_map::OccupiedEntry<'a, Ustr, V>),
+    /// A vacant entry.
+    Vacant(VacantStrEntry<'a, V>),
+}
+
+/// A view into a vacant entry in a [`UstrMap`] keyed by a `&str`.
+pub struct VacantStrEntry<'a, V> {
+    inner: VacantInner<'a, V>,
+}
+
+enum VacantInner<'a, V> {
+    // The key is already in the string cache.
+    Interned(hash_map::VacantEntry<'a, Ustr, V>),
+    // The key will have to be interned if a value is inserted.
+    NotInterned {
+        map: &'a mut UstrMap<V>,
+        key: &'a str,
+    },
+}
+
+impl<'a, V> StrEntry<'a, V> {
+    /// Returns the key of this entry as a `&str`.
+    pub fn key_str(&self) -> &str {
+        match self {
+            StrEntry::Occupied(o) => o.key().as_str(),
+            StrEntry::Vacant(v) => v.key_str(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting `default` if empty, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            StrEntry::Occupied(o) => o.into_mut(),
+            StrEntry::Vacant(v) => v.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `default` if
+    /// empty, and returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            StrEntry::Occupied(o) => o.into_mut(),
+            StrEntry::Vacant(v) => v.insert(default()),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the map.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let StrEntry::Occupied(o) = &mut self {
+            f(o.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, V: Default> StrEntry<'a, V> {
+    /// Ensures a value is in the entry by inserting the default value if
+    /// empty, and returns a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, V> VacantStrEntry<'a, V> {
+    /// Returns the key that would be used when inserting a value.
+    pub fn key_str(&self) -> &str {
+        match &self.inner {
+            VacantInner::Interned(v) => v.key().as_str(),
+            VacantInner::NotInterned { key, .. } => key,
+        }
+    }
+
+    /// Sets the value of the entry, adding the key to the string cache if
+    /// necessary, and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        match self.inner {
+            VacantInner::Interned(v) => v.insert(value),
+            VacantInner::NotInterned { map, key } => {
+                map.entry(Ustr::from(key)).or_insert(value)
+            }
+        }
+    }
+}
+
+/// The 32-bit FNV-1a hash of `s`.
+///
+/// This is the default function used to compute the secondary hash returned by
+/// [`Ustr::hash32()`]. Unlike the main hash it is the same on every platform.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(ustr::fnv1a_32(""), 0x811c9dc5);
+/// assert_eq!(ustr::fnv1a_32("a"), 0xe40c292c);
+/// ```
+pub fn fnv1a_32(s: &str) -> u32 {
+    s.bytes().fold(0x811c9dc5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    })
+}
+
 /// The worst hasher in the world -- the identity hasher.
 #[doc(hidden)]
 #[derive(Default)]
@@ -58,3 +269,105 @@ fn test_hashing() {
     assert_eq!(hm.get(&u1), Some(&17));
     assert_eq!(hm.get(&u2), Some(&42));
 }
+
+#[test]
+fn test_hash32() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    // The reference values from the FNV test suite.
+    assert_eq!(u("").hash32(), 0x811c9dc5);
+    assert_eq!(u("foobar").hash32(), 0xbf9cf968);
+    assert_eq!(u("foobar").hash32(), fnv1a_32("foobar"));
+}
+
+#[test]
+fn test_entry_str() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let mut map = UstrMap::<u32>::default();
+    u("cached but not in the map");
+
+    // Looking at a vacant entry doesn't intern the key.
+    match map.entry_str("not cached") {
+        StrEntry::Vacant(v) => assert_eq!(v.key_str(), "not cached"),
+        StrEntry::Occupied(_) => panic!("entry should be vacant"),
+    }
+    assert_eq!(existing_ustr("not cached"), None);
+    assert_eq!(super::num_entries(), 1);
+
+    *map.entry_str("not cached").or_default() += 1;
+    *map.entry_str("not cached").or_default() += 1;
+    *map.entry_str("cached but not in the map").or_insert(17) += 1;
+
+    assert_eq!(super::num_entries(), 2);
+    assert_eq!(map.get(&u("not cached")), Some(&2));
+    assert_eq!(map.get(&u("cached but not in the map")), Some(&18));
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn test_index_map() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    let keys = ["zebra", "apple", "mango", "banana", "cherry"]
+        .iter()
+        .map(|s| u(s))
+        .collect::<Vec<_>>();
+
+    let mut map = UstrIndexMap::default();
+    let mut set = UstrIndexSet::default();
+    for (i, k) in keys.iter().enumerate() {
+        map.insert(*k, i);
+        set.insert(*k);
+    }
+    // Re-inserting an existing key keeps its position.
+    map.insert(u("zebra"), 10);
+    set.insert(u("zebra"));
+
+    assert_eq!(map.keys().copied().collect::<Vec<_>>(), keys);
+    assert_eq!(set.iter().copied().collect::<Vec<_>>(), keys);
+    assert_eq!(map[&u("zebra")], 10);
+    assert_eq!(map.get_index_of(&u("mango")), Some(2));
+    assert!(set.contains(&u("cherry")));
+    assert!(!set.contains(&u("durian")));
+
+    map.shift_remove(&u("apple"));
+    assert_eq!(
+        map.keys().copied().collect::<Vec<_>>(),
+        [keys[0], keys[2], keys[3], keys[4]]
+    );
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn test_dash_map() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    let map = UstrDashMap::with_hasher_and_shard_amount(Default::default(), 64);
+    let set = UstrDashSet::default();
+    let keys = (0..64_000)
+        .map(|i| u(&format!("dash {}", i)))
+        .collect::<Vec<_>>();
+    for (i, k) in keys.iter().enumerate() {
+        map.insert(*k, i);
+        set.insert(*k);
+    }
+    assert_eq!(map.len(), keys.len());
+    assert_eq!(*map.get(&u("dash 123")).unwrap(), 123);
+    assert!(set.contains(&u("dash 63999")));
+    assert!(!set.contains(&u("dash 64000")));
+
+    // Every shard gets close to its share of the keys.
+    let mut shards = [0usize; 64];
+    for k in &keys {
+        shards[map.determine_map(k)] += 1;
+    }
+    let (min, max) = (shards.iter().min(), shards.iter().max());
+    assert!(*min.unwrap() > 800 && *max.unwrap() < 1200, "{:?}", shards);
+}
@@ -0,0 +1,131 @@
+use super::{numeric::with_scratch, Ustr};
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+// Blobs up to this many bytes are formatted on the stack. Longer ones use the
+// same thread-local buffer as `Ustr::from_int()`.
+const MAX_STACK_BYTES: usize = 64;
+
+// Write the lowercase hex digits of `bytes` into `out`, which must be twice as
+// long, and return them.
+fn encode_hex<'a>(bytes: &[u8], out: &'a mut [u8]) -> &'a str {
+    for (b, pair) in bytes.iter().zip(out.chunks_exact_mut(2)) {
+        pair[0] = HEX_DIGITS[(b >> 4) as usize];
+        pair[1] = HEX_DIGITS[(b & 0xf) as usize];
+    }
+    // SAFETY: every byte is one of `HEX_DIGITS`, which are ASCII.
+    unsafe { std::str::from_utf8_unchecked(out) }
+}
+
+impl Ustr {
+    /// Create a `Ustr` from the lowercase hex form of `bytes`, such as a hash
+    /// digest or binary id, with two digits for each byte and no prefix.
+    ///
+    /// This gives the same string as formatting each byte with `{:02x}` and
+    /// interning the result, but the digits are written into a buffer on the
+    /// stack (or, for blobs longer than 64 bytes, a buffer that's reused
+    /// between calls), so no intermediate `String` is allocated, and if the
+    /// string is already in the cache nothing is allocated at all.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// assert_eq!(Ustr::from_hex(&[0xde, 0xad, 0xbe, 0xef]), u("deadbeef"));
+    /// assert_eq!(Ustr::from_hex(&[0, 1, 0xff]), u("0001ff"));
+    /// assert_eq!(Ustr::from_hex(&[]), u(""));
+    /// ```
+    pub fn from_hex(bytes: &[u8]) -> Ustr {
+        if bytes.len() <= MAX_STACK_BYTES {
+            let mut buf = [0; 2 * MAX_STACK_BYTES];
+            return Ustr::from(encode_hex(bytes, &mut buf[..2 * bytes.len()]));
+        }
+        with_scratch(|s| {
+            s.reserve(2 * bytes.len());
+            for chunk in bytes.chunks(MAX_STACK_BYTES) {
+                let mut buf = [0; 2 * MAX_STACK_BYTES];
+                s.push_str(encode_hex(chunk, &mut buf[..2 * chunk.len()]));
+            }
+        })
+    }
+
+    /// Create a `Ustr` from the canonical form of a UUID: lowercase and
+    /// hyphenated, as in `"67e55044-10b1-426f-9247-bb680e5fe0c8"`.
+    ///
+    /// This gives the same string as `Ustr::from(&uuid.to_string())`, but the
+    /// UUID is formatted on the stack, so no intermediate `String` is
+    /// allocated, and if the string is already in the cache nothing is
+    /// allocated at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    /// use uuid::Uuid;
+    ///
+    /// let id = Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+    /// assert_eq!(
+    ///     Ustr::from_uuid(&id),
+    ///     u("67e55044-10b1-426f-9247-bb680e5fe0c8")
+    /// );
+    /// ```
+    #[cfg(feature = "uuid")]
+    pub fn from_uuid(uuid: &uuid::Uuid) -> Ustr {
+        let mut buf = uuid::Uuid::encode_buffer();
+        Ustr::from(&*uuid.hyphenated().encode_lower(&mut buf))
+    }
+}
+
+#[test]
+fn test_from_hex() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let to_hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+    let blob = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+    for len in [0, 1, 2, 16, 63, 64, 65, 128, 129, 1000] {
+        let bytes = &blob[..len];
+        assert_eq!(Ustr::from_hex(bytes), u(&to_hex(bytes)));
+        assert_eq!(Ustr::from_hex(bytes).len(), 2 * len);
+    }
+    assert_eq!(Ustr::from_hex(&[0x0f, 0xf0]), u("0ff0"));
+
+    // The scratch buffer doesn't leak into the next string.
+    assert_eq!(Ustr::from_hex(&blob[..100]), u(&to_hex(&blob[..100])));
+    assert_eq!(Ustr::from_int(7), u("7"));
+}
+
+#[test]
+#[cfg(feature = "uuid")]
+fn test_from_uuid() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use uuid::Uuid;
+
+    unsafe { super::_clear_cache() };
+
+    for id in [Uuid::nil(), Uuid::max(), Uuid::from_u128(0x1234 << 64)] {
+        assert_eq!(Ustr::from_uuid(&id), u(&id.to_string()));
+    }
+    let id = Uuid::from_bytes([0xab; 16]);
+    assert_eq!(
+        Ustr::from_uuid(&id),
+        u("abababab-abab-abab-abab-abababababab")
+    );
+    assert_eq!(
+        Ustr::from_uuid(&id),
+        u(&id.hyphenated().to_string().to_lowercase())
+    );
+}
@@ -0,0 +1,235 @@
+use super::CacheAllocator;
+use std::alloc::{GlobalAlloc, Layout, System};
+
+/// A [`CacheAllocator`] that backs the string storage with huge pages where
+/// the platform supports it.
+///
+/// Interning tens of millions of strings spreads them over a lot of memory,
+/// and the resulting TLB pressure slows down lookups and iteration. Backing
+/// the arenas with 2MB pages (or the platform's large page size) helps a lot.
+///
+/// * On Linux the arena is first requested with `MAP_HUGETLB`. If no huge
+///   pages are reserved, it falls back to a regular mapping with
+///   `madvise(MADV_HUGEPAGE)` so that transparent huge pages can be used.
+/// * On Windows the arena is requested with `MEM_LARGE_PAGES`, which requires
+///   the `SeLockMemoryPrivilege`. Without it we fall back to a regular
+///   allocation.
+/// * Everywhere else this just uses the system allocator.
+///
+/// Arenas smaller than [`HugePageAllocator::min_size`] are never backed by
+/// huge pages, since rounding them up would waste most of the page. The cache
+/// starts with small per-bin arenas and doubles them as they fill, so the
+/// bigger arenas of a busy cache will end up on huge pages.
+///
+/// This is available with the `hugepages` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ustr::HugePageAllocator;
+///
+/// static HUGE: HugePageAllocator = HugePageAllocator::new();
+///
+/// // Must be done before the cache is first used.
+/// assert!(ustr::set_allocator(&HUGE).is_ok());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HugePageAllocator {
+    min_size: usize,
+}
+
+// 2MB is the huge page size on x86_64 and the default on aarch64 Linux.
+const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+impl HugePageAllocator {
+    /// Create a new `HugePageAllocator` that uses huge pages for any arena of
+    /// at least 2MB.
+    pub const fn new() -> HugePageAllocator {
+        HugePageAllocator {
+            min_size: HUGE_PAGE_SIZE,
+        }
+    }
+
+    /// Create a new `HugePageAllocator` that uses huge pages for any arena of
+    /// at least `min_size` bytes.
+    pub const fn with_min_size(min_size: usize) -> HugePageAllocator {
+        HugePageAllocator { min_size }
+    }
+
+    /// The smallest arena size that will be backed by huge pages.
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+}
+
+impl Default for HugePageAllocator {
+    fn default() -> HugePageAllocator {
+        HugePageAllocator::new()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+fn round_up_to(n: usize, align: usize) -> Option<usize> {
+    debug_assert!(align.is_power_of_two());
+    Some(n.checked_add(align - 1)? & !(align - 1))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl HugePageAllocator {
+    // The size we actually map for `layout`. This must be the same in
+    // `allocate()` and `deallocate()`.
+    fn mapped_size(&self, layout: Layout) -> Option<usize> {
+        if layout.size() >= self.min_size {
+            round_up_to(layout.size(), HUGE_PAGE_SIZE)
+        } else {
+            round_up_to(layout.size(), 4096)
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe impl CacheAllocator for HugePageAllocator {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        // mmap only guarantees page alignment.
+        if layout.align() > 4096 {
+            return System.alloc(layout);
+        }
+
+        let map = |size: usize, flags: libc::c_int| {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                std::ptr::null_mut()
+            } else {
+                ptr as *mut u8
+            }
+        };
+
+        let size = match self.mapped_size(layout) {
+            Some(size) => size,
+            None => return std::ptr::null_mut(),
+        };
+
+        if layout.size() < self.min_size {
+            return map(size, 0);
+        }
+
+        let ptr = map(size, libc::MAP_HUGETLB);
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        // No reserved huge pages, so ask for transparent huge pages instead.
+        let ptr = map(size, 0);
+        if !ptr.is_null() {
+            // This is just a hint so we don't care if it fails.
+            libc::madvise(ptr as *mut libc::c_void, size, libc::MADV_HUGEPAGE);
+        }
+        ptr
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        if layout.align() > 4096 {
+            return System.dealloc(ptr, layout);
+        }
+        // This can't overflow since we succeeded in allocating it.
+        let size = self.mapped_size(layout).unwrap();
+        libc::munmap(ptr as *mut libc::c_void, size);
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    pub const MEM_COMMIT: u32 = 0x0000_1000;
+    pub const MEM_RESERVE: u32 = 0x0000_2000;
+    pub const MEM_RELEASE: u32 = 0x0000_8000;
+    pub const MEM_LARGE_PAGES: u32 = 0x2000_0000;
+    pub const PAGE_READWRITE: u32 = 0x04;
+
+    extern "system" {
+        pub fn VirtualAlloc(
+            address: *mut std::ffi::c_void,
+            size: usize,
+            allocation_type: u32,
+            protect: u32,
+        ) -> *mut std::ffi::c_void;
+        pub fn VirtualFree(
+            address: *mut std::ffi::c_void,
+            size: usize,
+            free_type: u32,
+        ) -> i32;
+        pub fn GetLargePageMinimum() -> usize;
+    }
+}
+
+#[cfg(windows)]
+unsafe impl CacheAllocator for HugePageAllocator {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        // VirtualAlloc only guarantees page alignment.
+        if layout.align() > 4096 {
+            return System.alloc(layout);
+        }
+
+        let large_page = win::GetLargePageMinimum();
+        if large_page != 0 && layout.size() >= self.min_size {
+            if let Some(size) = round_up_to(layout.size(), large_page) {
+                let ptr = win::VirtualAlloc(
+                    std::ptr::null_mut(),
+                    size,
+                    win::MEM_RESERVE | win::MEM_COMMIT | win::MEM_LARGE_PAGES,
+                    win::PAGE_READWRITE,
+                );
+                if !ptr.is_null() {
+                    return ptr as *mut u8;
+                }
+            }
+        }
+
+        win::VirtualAlloc(
+            std::ptr::null_mut(),
+            layout.size(),
+            win::MEM_RESERVE | win::MEM_COMMIT,
+            win::PAGE_READWRITE,
+        ) as *mut u8
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        if layout.align() > 4096 {
+            return System.dealloc(ptr, layout);
+        }
+        win::VirtualFree(ptr as *mut std::ffi::c_void, 0, win::MEM_RELEASE);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+unsafe impl CacheAllocator for HugePageAllocator {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[test]
+fn test_huge_page_allocator() {
+    let huge = HugePageAllocator::with_min_size(1 << 20);
+    for size in [64, 4 << 20] {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        unsafe {
+            let ptr = huge.allocate(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 8, 0);
+            // Make sure the whole range is writable.
+            std::ptr::write_bytes(ptr, 0xab, size);
+            huge.deallocate(ptr, layout);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use super::{primitives::AtomicPtr, Ustr, NEXT_GENERATION};
+use std::{ptr::NonNull, sync::atomic::Ordering};
+
+// Every string is given the next generation when it's inserted, which makes
+// generations dense ids in insertion order. This table maps them back to the
+// chars of each entry.
+//
+// The table is split into segments that double in size, so that it can grow
+// without ever moving (or locking) what's already there. Segment `n` holds ids
+// `FIRST_SEGMENT_LEN * (2^n - 1)` up to (but not including)
+// `FIRST_SEGMENT_LEN * (2^(n+1) - 1)`. Segments are allocated on first use by
+// whichever thread gets there first and, like the string storage itself, are
+// never freed (except by `_clear_cache()`).
+const FIRST_SEGMENT_SHIFT: u32 = 10;
+const FIRST_SEGMENT_LEN: u64 = 1 << FIRST_SEGMENT_SHIFT;
+// Enough segments to cover every u32 id.
+const NUM_SEGMENTS: usize = 33 - FIRST_SEGMENT_SHIFT as usize;
+
+#[cfg(not(loom))]
+#[allow(clippy::declare_interior_mutable_const)]
+const NULL_SEGMENT: AtomicPtr<AtomicPtr<u8>> =
+    AtomicPtr::new(std::ptr::null_mut());
+#[cfg(not(loom))]
+static SEGMENTS: [AtomicPtr<AtomicPtr<u8>>; NUM_SEGMENTS] =
+    [NULL_SEGMENT; NUM_SEGMENTS];
+// loom's atomics can't be created in a const.
+#[cfg(loom)]
+super::primitives::lazy_static! {
+    static ref SEGMENTS: [AtomicPtr<AtomicPtr<u8>>; NUM_SEGMENTS] =
+        std::array::from_fn(|_| AtomicPtr::new(std::ptr::null_mut()));
+}
+
+// The segment `id` falls in and its index within that segment.
+fn locate(id: u32) -> (usize, usize) {
+    let i = id as u64 + FIRST_SEGMENT_LEN;
+    let shift = 63 - i.leading_zeros();
+    let segment = (shift - FIRST_SEGMENT_SHIFT) as usize;
+    (segment, (i - (1 << shift)) as usize)
+}
+
+fn segment_len(segment: usize) -> usize {
+    (FIRST_SEGMENT_LEN as usize) << segment
+}
+
+fn new_segment(len: usize) -> *mut AtomicPtr<u8> {
+    let segment = (0..len)
+        .map(|_| AtomicPtr::<u8>::new(std::ptr::null_mut()))
+        .collect::<Box<[_]>>();
+    Box::into_raw(segment) as *mut AtomicPtr<u8>
+}
+
+// Record that the entry whose chars are at `char_ptr` has id `id`. This must
+// only be called once the entry has been completely written.
+pub(crate) fn publish(id: u32, char_ptr: *const u8) {
+    let (segment, index) = locate(id);
+    let slot = &SEGMENTS[segment];
+    let mut ptr = slot.load(Ordering::Acquire);
+    if ptr.is_null() {
+        let new = new_segment(segment_len(segment));
+        ptr = match slot.compare_exchange(
+            std::ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(existing) => {
+                // Another thread beat us to it.
+                unsafe { free_segment(new, segment_len(segment)) };
+                existing
+            }
+        };
+    }
+    // `index` is always within the segment by construction of `locate()`.
+    unsafe { &*ptr.add(index) }.store(char_ptr as *mut u8, Ordering::Release);
+}
+
+unsafe fn free_segment(ptr: *mut AtomicPtr<u8>, len: usize) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+// Drop the whole table. Only called by `_clear_cache()`. **DO NOT CALL THIS**.
+pub(crate) unsafe fn clear() {
+    for (segment, slot) in SEGMENTS.iter().enumerate() {
+        let ptr = slot.swap(std::ptr::null_mut(), Ordering::AcqRel);
+        if !ptr.is_null() {
+            free_segment(ptr, segment_len(segment));
+        }
+    }
+}
+
+// The segments of the table, so that `CacheGuard` can set them aside and
+// restore them.
+pub(crate) struct Table([*mut AtomicPtr<u8>; NUM_SEGMENTS]);
+
+// The segments are only ever read through `SEGMENTS`.
+unsafe impl Send for Table {}
+
+impl Table {
+    pub(crate) const EMPTY: Table = Table([std::ptr::null_mut(); NUM_SEGMENTS]);
+}
+
+// Swap the whole table for `table`, returning the old one.
+pub(crate) fn replace(table: Table) -> Table {
+    Table(std::array::from_fn(|segment| {
+        SEGMENTS[segment].swap(table.0[segment], Ordering::AcqRel)
+    }))
+}
+
+/// Returns the `Ustr` whose [`id()`](Ustr::id) is `id`, or `None` if there is
+/// no such string (yet).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, Ustr};
+///
+/// let u = ustr("find me by id");
+/// assert_eq!(ustr::from_id(u.id()), Some(u));
+/// assert_eq!(ustr::from_id(u32::MAX), None);
+/// ```
+pub fn from_id(id: u32) -> Option<Ustr> {
+    if id >= NEXT_GENERATION.load(Ordering::Acquire) {
+        return None;
+    }
+    let (segment, index) = locate(id);
+    let ptr = SEGMENTS[segment].load(Ordering::Acquire);
+    if ptr.is_null() {
+        return None;
+    }
+    // The id might have been handed out but not published yet, in which case
+    // it's still null.
+    let char_ptr = unsafe { &*ptr.add(index) }.load(Ordering::Acquire);
+    NonNull::new(char_ptr).map(|char_ptr| Ustr { char_ptr })
+}
+
+#[test]
+fn test_locate() {
+    assert_eq!(locate(0), (0, 0));
+    assert_eq!(locate(1023), (0, 1023));
+    assert_eq!(locate(1024), (1, 0));
+    assert_eq!(locate(3071), (1, 2047));
+    assert_eq!(locate(3072), (2, 0));
+    let (segment, index) = locate(u32::MAX);
+    assert_eq!(segment, NUM_SEGMENTS - 1);
+    assert!((index as u64) < FIRST_SEGMENT_LEN << segment);
+}
+
+#[test]
+fn test_from_id() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    assert_eq!(from_id(0), None);
+    // Enough strings to span a few segments.
+    let strings = (0..5000)
+        .map(|i| u(&format!("id {}", i)))
+        .collect::<Vec<_>>();
+    for (i, s) in strings.iter().enumerate() {
+        assert_eq!(s.id(), i as u32);
+        assert_eq!(from_id(i as u32), Some(*s));
+    }
+    assert_eq!(from_id(5000), None);
+
+    // Hits don't use up an id.
+    assert_eq!(u("id 17").id(), 17);
+    assert_eq!(u("new").id(), 5000);
+}
@@ -0,0 +1,195 @@
+use super::{INITIAL_ALLOC, INITIAL_CAPACITY, NUM_BINS};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+
+/// Controls how much memory the cache allocates up front, as passed to
+/// [`init_with()`].
+///
+/// Both sizes are totals that are split evenly across the cache's bins. By
+/// default the tables start out tiny and grow as strings are added, and each
+/// bin's first string arena is only allocated when a string is first added to
+/// it, so a program that only interns a handful of strings uses very little
+/// memory. A program that's going to intern millions of strings can avoid
+/// growing the tables over and over by starting them bigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitConfig {
+    /// The number of slots in the cache's tables. Each bin's share is rounded
+    /// up to a power of two. The default is 16 slots per bin.
+    pub initial_capacity: usize,
+    /// The size of the cache's first string arenas, in bytes. The default is
+    /// 4MB.
+    pub initial_arena_size: usize,
+    /// If set, the first string arenas are allocated along with the tables,
+    /// rather than as strings are added to each bin. The default is `false`.
+    pub preallocate_arenas: bool,
+}
+
+impl InitConfig {
+    /// The configuration the cache uses unless [`init_with()`] is called.
+    pub const DEFAULT: InitConfig = InitConfig {
+        initial_capacity: INITIAL_CAPACITY,
+        initial_arena_size: INITIAL_ALLOC,
+        preallocate_arenas: false,
+    };
+
+    // The number of table slots each bin starts with.
+    pub(crate) fn bin_capacity(&self) -> usize {
+        (self.initial_capacity / NUM_BINS)
+            .max(1)
+            .next_power_of_two()
+    }
+
+    // The size of each bin's first string arena.
+    pub(crate) fn bin_arena_size(&self) -> usize {
+        (self.initial_arena_size / NUM_BINS).max(1)
+    }
+}
+
+impl Default for InitConfig {
+    fn default() -> InitConfig {
+        InitConfig::DEFAULT
+    }
+}
+
+static CONFIG: OnceLock<InitConfig> = OnceLock::new();
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+// Get the configuration, fixing it as the default if `init_with()` hasn't
+// been called.
+pub(crate) fn config() -> &'static InitConfig {
+    CONFIG.get_or_init(InitConfig::default)
+}
+
+// Record that the global cache has been allocated. Only called while
+// initializing `LOCAL_CACHE`.
+pub(crate) fn mark_initialized() {
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Allocate the global cache now, sized according to `config`.
+///
+/// The cache is normally allocated the first time a string is interned.
+/// Calling this during startup moves that cost to a point of your choosing,
+/// and lets you make the cache smaller or bigger to begin with. Set
+/// [`InitConfig::preallocate_arenas`] to allocate all of the string storage
+/// now too. If you also want to use
+/// [`set_allocator()`](crate::set_allocator()), call that first.
+///
+/// # Panics
+///
+/// Panics if this is too late: if it's already been called, or if any string
+/// has already been interned, whether in the global cache or a
+/// [`ForkedCache`](crate::ForkedCache).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::InitConfig;
+///
+/// // At the start of `main()`, before anything else touches the cache.
+/// assert!(!ustr::is_initialized());
+/// ustr::init_with(InitConfig {
+///     initial_capacity: 1 << 16,
+///     initial_arena_size: 1 << 20,
+///     preallocate_arenas: true,
+/// });
+/// assert!(ustr::is_initialized());
+/// ```
+pub fn init_with(config: InitConfig) {
+    if CONFIG.set(config).is_err() || is_initialized() {
+        panic!("ustr::init_with() called after the cache was initialized");
+    }
+    init();
+}
+
+/// Allocate the global cache now, if it hasn't been already.
+///
+/// This is [`init_with()`] with the default configuration, except that it's
+/// fine to call it at any time.
+///
+/// # Examples
+///
+/// ```
+/// ustr::init();
+/// assert!(ustr::is_initialized());
+/// ```
+pub fn init() {
+    let _ = &*super::STRING_CACHE;
+}
+
+/// Check if the global cache has been allocated, either by [`init()`],
+/// [`init_with()`] or by interning the first string.
+///
+/// This is always `false` if the cache has been attached to another copy of
+/// the crate's cache with
+/// [`set_external_cache()`](crate::set_external_cache()) before it was used.
+///
+/// # Examples
+///
+/// ```
+/// let _ = ustr::ustr("hello");
+/// assert!(ustr::is_initialized());
+/// ```
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+#[test]
+fn test_init() {
+    let _t = super::TEST_LOCK.lock();
+
+    unsafe { super::_clear_cache() };
+    super::ustr("x");
+    assert!(is_initialized());
+    assert_eq!(*config(), InitConfig::DEFAULT);
+    init();
+
+    let result = std::panic::catch_unwind(|| {
+        init_with(InitConfig {
+            initial_capacity: 1024,
+            ..InitConfig::DEFAULT
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(*config(), InitConfig::DEFAULT);
+
+    let tiny = InitConfig {
+        initial_capacity: 0,
+        initial_arena_size: 0,
+        preallocate_arenas: true,
+    };
+    assert_eq!(tiny.bin_capacity(), 1);
+    assert_eq!(tiny.bin_arena_size(), 1);
+    let odd = InitConfig {
+        initial_capacity: NUM_BINS * 3,
+        initial_arena_size: NUM_BINS * 3,
+        preallocate_arenas: false,
+    };
+    assert_eq!(odd.bin_capacity(), 4);
+    assert_eq!(odd.bin_arena_size(), 3);
+}
+
+#[test]
+fn test_lazy_bins() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{bin_stats, total_capacity, ustr as u};
+
+    unsafe { super::_clear_cache() };
+    let slots = || bin_stats().iter().map(|b| b.table_capacity).sum::<usize>();
+    assert_eq!(slots(), InitConfig::DEFAULT.bin_capacity() * NUM_BINS);
+    assert_eq!(total_capacity(), 0);
+
+    // Only the bin the string goes in gets an arena.
+    u("only one");
+    assert_eq!(total_capacity(), InitConfig::DEFAULT.bin_arena_size());
+
+    // The tables grow as strings are added, and shrink back when cleared.
+    for i in 0..10_000 {
+        u(&format!("lazy {}", i));
+    }
+    assert!(slots() > 2 * 10_000);
+    unsafe { super::_clear_cache() };
+    assert_eq!(slots(), InitConfig::DEFAULT.bin_capacity() * NUM_BINS);
+}
@@ -0,0 +1,297 @@
+use super::Ustr;
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+/// The longest string a [`UstrOrInline`] stores inline.
+pub const MAX_INLINE_LEN: usize = 15;
+
+// The length of an inline string. Only having valid lengths as values leaves
+// the rest for the compiler to use as the discriminant of `Repr`, which keeps
+// it to 16 bytes. The variants are only created by `try_inline()`'s
+// transmute.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[rustfmt::skip]
+enum Len {
+    L0, L1, L2, L3, L4, L5, L6, L7, L8, L9, L10, L11, L12, L13, L14, L15,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Inline {
+    // Zeroed past the end of the string, so that equal strings have equal
+    // bytes.
+    bytes: [u8; MAX_INLINE_LEN],
+    len: Len,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Repr {
+    Inline(Inline),
+    Interned(Ustr),
+}
+
+/// A string that's stored inline if it's short, and interned otherwise.
+///
+/// Strings of up to [`MAX_INLINE_LEN`] bytes are stored in the value itself
+/// without touching the cache at all, while longer strings are interned as a
+/// [`Ustr`]. Either way it's 16 bytes and `Copy`, and is used the same way,
+/// so one type can be used for identifiers that are mostly tiny without
+/// filling the cache with them.
+///
+/// Each string only has one representation, so comparing two
+/// `UstrOrInline`s is a 16-byte comparison for short strings and a pointer
+/// comparison for long ones.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrOrInline};
+///
+/// let short = UstrOrInline::new("x");
+/// let long = UstrOrInline::new("a_much_longer_identifier");
+/// assert!(short.is_inline());
+/// assert!(!long.is_inline());
+/// assert_eq!(std::mem::size_of::<UstrOrInline>(), 16);
+///
+/// assert_eq!(short.as_str(), "x");
+/// assert_eq!(long.as_ustr(), Some(u("a_much_longer_identifier")));
+/// assert_eq!(UstrOrInline::from(u("x")), short);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UstrOrInline(Repr);
+
+impl UstrOrInline {
+    /// Store `string` inline if it's short enough, or intern it if not.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`], if `string` is too long
+    /// to store inline.
+    pub fn new(string: &str) -> UstrOrInline {
+        UstrOrInline::try_inline(string)
+            .unwrap_or_else(|| UstrOrInline(Repr::Interned(Ustr::from(string))))
+    }
+
+    // Store `string` inline, if it's short enough. Strings aren't normalized
+    // or truncated, since those only apply to the cache.
+    fn try_inline(string: &str) -> Option<UstrOrInline> {
+        let len = string.len();
+        if len > MAX_INLINE_LEN {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_INLINE_LEN];
+        bytes[..len].copy_from_slice(string.as_bytes());
+        // SAFETY: `Len` is `repr(u8)` with a variant for each length up to
+        // `MAX_INLINE_LEN`.
+        let len = unsafe { std::mem::transmute::<u8, Len>(len as u8) };
+        Some(UstrOrInline(Repr::Inline(Inline { bytes, len })))
+    }
+
+    /// The string as a string slice.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Inline(inline) => {
+                let bytes = &inline.bytes[..inline.len as usize];
+                // The bytes were copied from a `str` in `try_inline()`.
+                unsafe { std::str::from_utf8_unchecked(bytes) }
+            }
+            Repr::Interned(u) => u.as_str(),
+        }
+    }
+
+    /// Check if the string is stored inline rather than in the cache.
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline(_))
+    }
+
+    /// The `Ustr` if the string was interned, or `None` if it's stored
+    /// inline.
+    #[inline]
+    pub fn as_ustr(&self) -> Option<Ustr> {
+        match self.0 {
+            Repr::Inline(_) => None,
+            Repr::Interned(u) => Some(u),
+        }
+    }
+
+    /// Get the string as a `Ustr`, interning it if it's stored inline.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn to_ustr(&self) -> Ustr {
+        match self.0 {
+            Repr::Inline(_) => Ustr::from(self.as_str()),
+            Repr::Interned(u) => u,
+        }
+    }
+}
+
+impl Default for UstrOrInline {
+    fn default() -> UstrOrInline {
+        UstrOrInline::new("")
+    }
+}
+
+impl From<&str> for UstrOrInline {
+    fn from(s: &str) -> UstrOrInline {
+        UstrOrInline::new(s)
+    }
+}
+
+impl From<Ustr> for UstrOrInline {
+    /// Short strings are copied inline so that they compare equal to the
+    /// same string created from a `&str`.
+    fn from(u: Ustr) -> UstrOrInline {
+        UstrOrInline::try_inline(u.as_str())
+            .unwrap_or(UstrOrInline(Repr::Interned(u)))
+    }
+}
+
+impl Deref for UstrOrInline {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for UstrOrInline {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for UstrOrInline {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for UstrOrInline {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<Ustr> for UstrOrInline {
+    fn eq(&self, other: &Ustr) -> bool {
+        match self.0 {
+            Repr::Inline(_) => self.as_str() == other.as_str(),
+            Repr::Interned(u) => u == *other,
+        }
+    }
+}
+
+impl Ord for UstrOrInline {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for UstrOrInline {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for UstrOrInline {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Display for UstrOrInline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for UstrOrInline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Repr::Inline(_) => write!(f, "{:?}", self.as_str()),
+            Repr::Interned(u) => write!(f, "{:?}", u),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for UstrOrInline {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UstrOrInline {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<UstrOrInline, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = UstrOrInline;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a &str")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<UstrOrInline, E> {
+                Ok(UstrOrInline::new(s))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+#[test]
+fn test_ustr_or_inline() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use std::collections::HashSet;
+
+    unsafe { super::_clear_cache() };
+
+    assert_eq!(std::mem::size_of::<UstrOrInline>(), 16);
+    assert_eq!(std::mem::size_of::<Option<UstrOrInline>>(), 16);
+
+    for s in ["", "a", "fifteen bytes!!", "ΑΒΓΔΕΖΗ", "sixteen bytes!!!"]
+    {
+        let x = UstrOrInline::new(s);
+        assert_eq!(x.as_str(), s);
+        assert_eq!(x, s);
+        assert_eq!(x.is_inline(), s.len() <= MAX_INLINE_LEN);
+        assert_eq!(x.as_ustr().is_some(), !x.is_inline());
+        assert_eq!(x.to_ustr(), u(s));
+        assert_eq!(x, u(s));
+        assert_eq!(UstrOrInline::from(u(s)), x);
+        assert_eq!(x.to_string(), s);
+    }
+
+    // Short strings never touch the cache.
+    unsafe { super::_clear_cache() };
+    let short = UstrOrInline::new("short");
+    assert_eq!(super::num_entries(), 0);
+    assert_eq!(short, UstrOrInline::default().max(short));
+    assert_eq!(format!("{:?}", short), "\"short\"");
+    let long = UstrOrInline::new("definitely not short");
+    assert_eq!(super::num_entries(), 1);
+    assert_eq!(format!("{:?}", long), "u!(\"definitely not short\")");
+
+    let set = [short, long, UstrOrInline::new("short")]
+        .into_iter()
+        .collect::<HashSet<_>>();
+    assert_eq!(set.len(), 2);
+}
@@ -0,0 +1,126 @@
+use super::{ustr_iter, Bins, Ustr, UstrIter};
+use std::{fmt, hash::Hash, ops::Deref};
+
+/// A string interner.
+///
+/// This lets generic code accept any of the caches in this crate rather than
+/// hard-coding the global cache. The global cache implements it through the
+/// [`Bins`] returned by [`cache()`](crate::cache).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::Interner;
+///
+/// fn intern_words<I: Interner>(interner: &I, text: &str) -> usize {
+///     text.split_whitespace().map(|w| interner.intern(w)).count()
+/// }
+///
+/// assert_eq!(intern_words(ustr::cache(), "a generic interner"), 3);
+/// assert!(ustr::cache().get("generic").is_some());
+/// ```
+pub trait Interner {
+    /// The handle returned for an interned string.
+    ///
+    /// This is `Copy` for the global cache, but only `Clone` for
+    /// [`LocalUstr`](crate::LocalUstr), which holds a copy of its string in
+    /// debug builds.
+    type Symbol<'a>: Clone + Eq + Hash + fmt::Debug + Deref<Target = str>
+    where
+        Self: 'a;
+
+    /// An iterator over every string in the interner.
+    type Iter<'a>: Iterator<Item = Self::Symbol<'a>>
+    where
+        Self: 'a;
+
+    /// Intern `string`, adding it to the interner if it isn't already there.
+    fn intern<'a>(&'a self, string: &str) -> Self::Symbol<'a>;
+
+    /// Returns the handle for `string` if it has already been interned.
+    fn get<'a>(&'a self, string: &str) -> Option<Self::Symbol<'a>>;
+
+    /// Iterate over every string in the interner, in no particular order.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Returns statistics about the interner's memory use.
+    fn stats(&self) -> InternerStats;
+}
+
+/// Statistics about an [`Interner`], as returned by [`Interner::stats()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InternerStats {
+    /// Number of unique strings stored.
+    pub num_entries: usize,
+    /// Number of bytes of string storage used, including headers and null
+    /// terminators.
+    pub total_allocated: usize,
+    /// Number of bytes of string storage reserved.
+    pub total_capacity: usize,
+}
+
+impl Interner for Bins {
+    type Symbol<'a> = Ustr;
+    type Iter<'a> = UstrIter;
+
+    fn intern(&self, string: &str) -> Ustr {
+        Ustr::from(string)
+    }
+
+    fn get(&self, string: &str) -> Option<Ustr> {
+        Ustr::from_existing(string)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        ustr_iter()
+    }
+
+    fn stats(&self) -> InternerStats {
+        let mut stats = InternerStats {
+            num_entries: 0,
+            total_allocated: 0,
+            total_capacity: 0,
+        };
+        for bin in self.0.iter() {
+            let sc = bin.read();
+            stats.num_entries += sc.num_entries();
+            stats.total_allocated += sc.total_allocated();
+            stats.total_capacity += sc.total_capacity();
+        }
+        stats
+    }
+}
+
+#[test]
+fn test_global_interner() {
+    let _t = super::TEST_LOCK.lock();
+
+    unsafe { super::_clear_cache() };
+
+    fn check<I: Interner>(interner: &I) {
+        let a = interner.intern("alpha");
+        let b = interner.intern("beta");
+        assert_eq!(interner.intern("alpha"), a);
+        assert_ne!(a, b);
+        assert_eq!(&*a, "alpha");
+        assert_eq!(interner.get("beta"), Some(b));
+        assert_eq!(interner.get("gamma"), None);
+
+        let mut all =
+            interner.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, ["alpha", "beta"]);
+
+        let stats = interner.stats();
+        assert_eq!(stats.num_entries, 2);
+        assert!(stats.total_allocated > 0);
+        assert!(stats.total_capacity >= stats.total_allocated);
+    }
+
+    check(super::cache());
+    assert_eq!(super::cache().stats().num_entries, super::num_entries());
+    assert_eq!(
+        super::cache().stats().total_allocated,
+        super::total_allocated()
+    );
+}
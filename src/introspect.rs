@@ -0,0 +1,252 @@
+//! Read-only reports on the layout of the cache's tables and arenas, for
+//! tuning.
+//!
+//! [`metrics()`](crate::metrics) and [`bin_stats()`](crate::bin_stats) are
+//! cheap enough to export continuously. [`report()`] goes further and walks
+//! every bin's table to measure how long lookups probe for, so it's meant for
+//! one-off analysis, such as deciding on `USTR_BIN_SHIFT` or an
+//! [`InitConfig`](crate::InitConfig) for a particular workload. The report
+//! can be saved with [`CacheReport::to_json()`] to be analysed elsewhere.
+//!
+//! # Examples
+//!
+//! ```
+//! use ustr::ustr as u;
+//!
+//! for i in 0..1000 {
+//!     u(&format!("introspect {}", i));
+//! }
+//! let report = ustr::introspect::report();
+//! assert_eq!(report.bins.len(), report.num_bins);
+//! let worst = report.bins.iter().map(|b| b.max_probe_length()).max();
+//! println!("longest probe sequence: {:?}", worst);
+//! let json = report.to_json();
+//! assert!(json.starts_with(r#"{"bin_shift":"#));
+//! ```
+use super::{init, read_bin, BIN_SHIFT, NUM_BINS, STRING_CACHE};
+use std::{fmt::Write, sync::atomic::Ordering};
+
+/// A report on the whole cache, as returned by [`report()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheReport {
+    /// `log2` of the number of bins, set with `USTR_BIN_SHIFT` when building.
+    pub bin_shift: usize,
+    /// Number of bins the cache is split into.
+    pub num_bins: usize,
+    /// The [`InitConfig::initial_capacity`](crate::InitConfig) the cache was
+    /// created with, across all bins.
+    pub initial_capacity: usize,
+    /// The [`InitConfig::initial_arena_size`](crate::InitConfig) the cache
+    /// was created with, across all bins.
+    pub initial_arena_size: usize,
+    /// A report on each bin, in order.
+    pub bins: Vec<BinReport>,
+}
+
+/// A report on one bin of the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinReport {
+    /// Number of slots in the bin's entry table.
+    pub table_capacity: usize,
+    /// Number of strings in the bin.
+    pub num_entries: usize,
+    /// The number of strings by the length of the probe sequence it takes to
+    /// find them: `probe_lengths[n]` strings are `n` slots past the slot
+    /// their hash points to, so finding them takes `n + 1` probes. Entries
+    /// are never removed, so there are no tombstones to skip.
+    pub probe_lengths: Vec<usize>,
+    /// The string arenas of the bin, oldest first. The last one is the one
+    /// strings are currently being added to.
+    pub arenas: Vec<ArenaReport>,
+    /// Number of strings whose storage isn't in an arena, such as
+    /// [`StaticUstr`](crate::StaticUstr)s and strings from dictionaries.
+    pub num_static_entries: usize,
+    /// Number of times the entry table has been grown.
+    pub num_grows: u64,
+    /// Number of lookups that had to wait for the bin's lock.
+    pub contended_reads: u64,
+    /// Number of inserts that had to wait for the bin's lock.
+    pub contended_writes: u64,
+}
+
+/// The size of one of a bin's string arenas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaReport {
+    /// Bytes reserved for the arena.
+    pub capacity: usize,
+    /// Bytes of the arena holding strings, including headers and padding.
+    pub allocated: usize,
+}
+
+impl BinReport {
+    /// The longest probe sequence in the bin: the most slots past its home
+    /// slot that any string is.
+    pub fn max_probe_length(&self) -> usize {
+        self.probe_lengths.len().saturating_sub(1)
+    }
+
+    /// The average number of slots past its home slot that each string is.
+    pub fn mean_probe_length(&self) -> f64 {
+        let total = self
+            .probe_lengths
+            .iter()
+            .enumerate()
+            .map(|(length, count)| length * count)
+            .sum::<usize>();
+        total as f64 / self.num_entries.max(1) as f64
+    }
+
+    /// The fraction of the bin's table slots that hold a string.
+    pub fn load_factor(&self) -> f64 {
+        self.num_entries as f64 / self.table_capacity as f64
+    }
+}
+
+impl CacheReport {
+    /// Write the report as JSON.
+    ///
+    /// The object has the same fields as [`CacheReport`], with `bins` an
+    /// array of objects with the fields of [`BinReport`], and `arenas` an
+    /// array of objects with the fields of [`ArenaReport`]. All the numbers
+    /// are integers.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a `String` can't fail.
+        let _ = self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, json: &mut String) -> std::fmt::Result {
+        write!(
+            json,
+            "{{\"bin_shift\":{},\"num_bins\":{},\"initial_capacity\":{},\
+             \"initial_arena_size\":{},\"bins\":[",
+            self.bin_shift,
+            self.num_bins,
+            self.initial_capacity,
+            self.initial_arena_size
+        )?;
+        for (i, bin) in self.bins.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"table_capacity\":{},\"num_entries\":{},\
+                 \"probe_lengths\":{:?},\"arenas\":[",
+                bin.table_capacity, bin.num_entries, bin.probe_lengths
+            )?;
+            for (j, arena) in bin.arenas.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                write!(
+                    json,
+                    "{{\"capacity\":{},\"allocated\":{}}}",
+                    arena.capacity, arena.allocated
+                )?;
+            }
+            write!(
+                json,
+                "],\"num_static_entries\":{},\"num_grows\":{},\
+                 \"contended_reads\":{},\"contended_writes\":{}}}",
+                bin.num_static_entries,
+                bin.num_grows,
+                bin.contended_reads,
+                bin.contended_writes
+            )?;
+        }
+        json.push_str("]}");
+        Ok(())
+    }
+}
+
+/// Report on the layout of every bin of the cache.
+///
+/// This takes each bin's read lock in turn, and walks its whole table while
+/// holding it, so it's slow for a big cache and the bins may be reported at
+/// slightly different times if other threads are adding strings.
+pub fn report() -> CacheReport {
+    let config = init::config();
+    let bins = STRING_CACHE
+        .0
+        .iter()
+        .map(|sc| {
+            let sc = read_bin(sc);
+            BinReport {
+                table_capacity: sc.table_capacity(),
+                num_entries: sc.num_entries(),
+                probe_lengths: sc.probe_histogram(),
+                arenas: sc
+                    .old_allocs
+                    .iter()
+                    .chain([&sc.alloc])
+                    .map(|a| ArenaReport {
+                        capacity: a.capacity(),
+                        allocated: a.allocated(),
+                    })
+                    .collect(),
+                num_static_entries: sc.num_static_entries(),
+                num_grows: sc.num_grows,
+                contended_reads: sc.contended_reads.load(Ordering::Relaxed),
+                contended_writes: sc.contended_writes.load(Ordering::Relaxed),
+            }
+        })
+        .collect();
+    CacheReport {
+        bin_shift: BIN_SHIFT,
+        num_bins: NUM_BINS,
+        initial_capacity: config.initial_capacity,
+        initial_arena_size: config.initial_arena_size,
+        bins,
+    }
+}
+
+#[test]
+fn test_report() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let empty = report();
+    assert_eq!(empty.num_bins, NUM_BINS);
+    assert!(empty.bins.iter().all(|b| b.probe_lengths.is_empty()));
+    assert!(empty.bins.iter().all(|b| b.arenas[0].capacity == 0));
+
+    for i in 0..10_000 {
+        u(&format!("report {}", i));
+    }
+    let report = report();
+    let sum =
+        |f: fn(&BinReport) -> usize| report.bins.iter().map(f).sum::<usize>();
+    assert_eq!(sum(|b| b.num_entries), 10_000);
+    assert_eq!(sum(|b| b.probe_lengths.iter().sum()), 10_000);
+    assert_eq!(sum(|b| b.table_capacity), super::metrics().table_slots);
+    assert_eq!(
+        sum(|b| b.arenas.iter().map(|a| a.allocated).sum()),
+        super::total_allocated()
+    );
+    for bin in &report.bins {
+        assert!(bin.load_factor() <= 1.0);
+        assert!(bin.mean_probe_length() <= bin.max_probe_length() as f64);
+        assert!(bin.num_grows > 0);
+    }
+
+    // The JSON has every field.
+    let json: serde_json::Value =
+        serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["num_bins"], NUM_BINS);
+    assert_eq!(json["bins"].as_array().unwrap().len(), NUM_BINS);
+    let bin = &json["bins"][3];
+    assert_eq!(bin["num_entries"], report.bins[3].num_entries);
+    assert_eq!(
+        bin["probe_lengths"].as_array().unwrap().len(),
+        report.bins[3].probe_lengths.len()
+    );
+    assert_eq!(
+        bin["arenas"][0]["allocated"],
+        report.bins[3].arenas[0].allocated
+    );
+    assert_eq!(bin["contended_writes"], report.bins[3].contended_writes);
+}
@@ -0,0 +1,122 @@
+use super::{numeric::with_scratch, Ustr};
+use std::fmt;
+
+// The length of `ustrs` joined with `sep`.
+fn joined_len(ustrs: &[Ustr], sep: &str) -> usize {
+    let chars = ustrs.iter().map(|u| u.len()).sum::<usize>();
+    chars + sep.len() * ustrs.len().saturating_sub(1)
+}
+
+fn push_joined(string: &mut String, ustrs: &[Ustr], sep: &str) {
+    for (i, u) in ustrs.iter().enumerate() {
+        if i > 0 {
+            string.push_str(sep);
+        }
+        string.push_str(u.as_str());
+    }
+}
+
+/// Join `ustrs` into a single `String`, with `sep` between each one.
+///
+/// This is the same as `[&str]::join()`, without having to collect the
+/// `&str`s first: the length of the result is worked out up front, so it
+/// makes a single allocation.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let attrs = [u("color"), u("normal"), u("uv")];
+/// assert_eq!(ustr::join(&attrs, ", "), "color, normal, uv");
+/// assert_eq!(ustr::join(&[], ", "), "");
+/// ```
+pub fn join(ustrs: &[Ustr], sep: &str) -> String {
+    let mut string = String::with_capacity(joined_len(ustrs, sep));
+    push_joined(&mut string, ustrs, sep);
+    string
+}
+
+/// Join `ustrs` with `sep` between each one, and intern the result.
+///
+/// The strings are joined into a reused thread-local buffer, so this doesn't
+/// allocate if the result is already in the cache.
+///
+/// # Panics
+///
+/// Panics in the same cases as [`Ustr::from`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let path = [u("scene"), u("lights"), u("key")];
+/// assert_eq!(ustr::join_interned(&path, "/"), u("scene/lights/key"));
+/// ```
+pub fn join_interned(ustrs: &[Ustr], sep: &str) -> Ustr {
+    with_scratch(|scratch| {
+        scratch.reserve(joined_len(ustrs, sep));
+        push_joined(scratch, ustrs, sep)
+    })
+}
+
+/// Displays a list of `Ustr`s with a separator between each one, without
+/// joining them into a `String` first.
+///
+/// This is handy for log messages, which can write the list straight into
+/// their output.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, DisplayList};
+///
+/// let attrs = [u("color"), u("normal")];
+/// let message = format!("attributes: [{}]", DisplayList::new(&attrs, ", "));
+/// assert_eq!(message, "attributes: [color, normal]");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayList<'a> {
+    ustrs: &'a [Ustr],
+    sep: &'a str,
+}
+
+impl<'a> DisplayList<'a> {
+    /// Create a `DisplayList` of `ustrs` separated by `sep`.
+    pub fn new(ustrs: &'a [Ustr], sep: &'a str) -> DisplayList<'a> {
+        DisplayList { ustrs, sep }
+    }
+}
+
+impl fmt::Display for DisplayList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, u) in self.ustrs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(self.sep)?;
+            }
+            f.write_str(u.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_join() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    let lists: [&[&str]; 4] =
+        [&[], &["one"], &["a", "", "b"], &["Τη", "γλώσσα", "μου"]];
+    for list in lists {
+        let ustrs = list.iter().map(|s| u(s)).collect::<Vec<_>>();
+        for sep in ["", ", ", "::"] {
+            let expected = list.join(sep);
+            let joined = join(&ustrs, sep);
+            assert_eq!(joined, expected);
+            assert_eq!(joined.capacity(), joined_len(&ustrs, sep));
+            assert_eq!(join_interned(&ustrs, sep), u(&expected));
+            assert_eq!(DisplayList::new(&ustrs, sep).to_string(), expected);
+        }
+    }
+}
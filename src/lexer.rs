@@ -0,0 +1,252 @@
+//! Building blocks for tokenizers that produce `Ustr`s.
+//!
+//! Compilers and config languages all need the same glue: look up each word
+//! in a fixed set of keywords, and intern everything else as an identifier.
+//! A [`SymbolTable`] does both, using a [`PerfectHash`] over the keywords so
+//! that checking for a keyword is a single probe.
+//!
+//! # Examples
+//!
+//! ```
+//! use ustr::{lexer::{Symbol, SymbolTable}, ustr as u};
+//!
+//! let table = SymbolTable::new(&["let", "fn", "if"]);
+//! let symbols = table
+//!     .tokens("let answer = if ready")
+//!     .map(|token| token.symbol)
+//!     .collect::<Vec<_>>();
+//! assert_eq!(
+//!     symbols,
+//!     [
+//!         Symbol::Keyword(0),
+//!         Symbol::Ident(u("answer")),
+//!         Symbol::Keyword(2),
+//!         Symbol::Ident(u("ready")),
+//!     ]
+//! );
+//! ```
+use super::{
+    canonical_str, hash_str, PerfectHash, StringCacheEntry, Ustr, UstrSet,
+};
+use std::ops::Range;
+
+/// A word classified by a [`SymbolTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// One of the table's keywords, given by its index in the list passed to
+    /// [`SymbolTable::new()`].
+    Keyword(usize),
+    /// Any other word, interned.
+    Ident(Ustr),
+}
+
+/// A word found by [`SymbolTable::tokens()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// What the word is.
+    pub symbol: Symbol,
+    /// The interned word, for keywords as well as identifiers.
+    pub ustr: Ustr,
+    /// Where the word is in the source, in bytes.
+    pub span: Range<usize>,
+}
+
+/// A set of keywords, and the interning of every other identifier.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct SymbolTable {
+    keywords: Box<[Ustr]>,
+    phf: PerfectHash,
+    // The index in `keywords` of the keyword in each slot of `phf`.
+    slot_keyword: Box<[usize]>,
+}
+
+impl SymbolTable {
+    /// Create a table for `keywords`, interning them all.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a keyword is given more than once, or in the same cases as
+    /// [`Ustr::from`].
+    pub fn new(keywords: &[&str]) -> SymbolTable {
+        let keywords =
+            keywords.iter().map(|k| Ustr::from(k)).collect::<Box<[_]>>();
+        let unique = keywords.iter().copied().collect::<UstrSet>();
+        assert!(unique.len() == keywords.len(), "duplicate keyword");
+
+        let entries = keywords
+            .iter()
+            .map(|u| u.as_string_cache_entry() as *const StringCacheEntry)
+            .collect::<Vec<_>>();
+        // Different strings in the cache never have the same 64-bit hash.
+        let phf =
+            PerfectHash::new(&entries).expect("keywords have distinct hashes");
+        let mut slot_keyword = vec![0; keywords.len()].into_boxed_slice();
+        for (i, k) in keywords.iter().enumerate() {
+            let slot = phf.index_of(k).expect("keyword is in the table");
+            slot_keyword[slot] = i;
+        }
+
+        SymbolTable {
+            keywords,
+            phf,
+            slot_keyword,
+        }
+    }
+
+    /// The keywords, in the order they were given to
+    /// [`new()`](SymbolTable::new).
+    pub fn keywords(&self) -> &[Ustr] {
+        &self.keywords
+    }
+
+    /// The index of `word` if it's a keyword.
+    pub fn keyword_index(&self, word: &str) -> Option<usize> {
+        self.phf.index_of(word).map(|slot| self.slot_keyword[slot])
+    }
+
+    /// Classify `word` as a keyword or an identifier, interning it if it's an
+    /// identifier.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn symbol(&self, word: &str) -> Symbol {
+        self.lookup(word).0
+    }
+
+    // Classify `word`, returning its `Ustr` too.
+    fn lookup(&self, word: &str) -> (Symbol, Ustr) {
+        if let Ok(canonical) = canonical_str(word) {
+            let hash = hash_str(&canonical);
+            if let Some(slot) = self.phf.index_of_canonical(&canonical, hash) {
+                let index = self.slot_keyword[slot];
+                return (Symbol::Keyword(index), self.keywords[index]);
+            }
+        }
+        let u = Ustr::from(word);
+        (Symbol::Ident(u), u)
+    }
+
+    /// Iterate over the words in `source`, classifying each one.
+    ///
+    /// A word is a run of alphanumeric characters and underscores that
+    /// doesn't start with a digit. Everything else, such as whitespace,
+    /// punctuation and numbers, is skipped, so this is meant to be called on
+    /// the parts of the source your tokenizer has already found to be words,
+    /// or on simple inputs where that's all that matters.
+    pub fn tokens<'a>(&'a self, source: &'a str) -> Tokens<'a> {
+        Tokens {
+            table: self,
+            source,
+            pos: 0,
+        }
+    }
+}
+
+/// An iterator over the words in a string, as returned by
+/// [`SymbolTable::tokens()`].
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    table: &'a SymbolTable,
+    source: &'a str,
+    pos: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let rest = &self.source[self.pos..];
+        // Skip to the start of the next word, passing over numbers whole.
+        let mut start = None;
+        let mut in_number = false;
+        for (i, c) in rest.char_indices() {
+            if !is_word_char(c) {
+                in_number = false;
+            } else if !in_number {
+                if c.is_numeric() {
+                    in_number = true;
+                } else {
+                    start = Some(i);
+                    break;
+                }
+            }
+        }
+        let Some(start) = start else {
+            self.pos = self.source.len();
+            return None;
+        };
+        let len = rest[start..]
+            .find(|c| !is_word_char(c))
+            .unwrap_or(rest.len() - start);
+
+        let span = self.pos + start..self.pos + start + len;
+        self.pos = span.end;
+        let (symbol, ustr) = self.table.lookup(&self.source[span.clone()]);
+        Some(Token { symbol, ustr, span })
+    }
+}
+
+#[test]
+fn test_symbol_table() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    let keywords = ["struct", "fn", "let", "mut", "return", "ünïcode"];
+    let table = SymbolTable::new(&keywords);
+    assert_eq!(table.keywords().len(), keywords.len());
+    for (i, k) in keywords.iter().enumerate() {
+        assert_eq!(table.keyword_index(k), Some(i));
+        assert_eq!(table.symbol(k), Symbol::Keyword(i));
+        assert_eq!(table.keywords()[i], u(k));
+    }
+    assert_eq!(table.keyword_index("structure"), None);
+    assert_eq!(table.symbol("structure"), Symbol::Ident(u("structure")));
+
+    let source = "fn main() { let mut x2 = 42abc + _y; return ünïcode·x }";
+    let tokens = table.tokens(source).collect::<Vec<_>>();
+    let words = tokens
+        .iter()
+        .map(|t| &source[t.span.clone()])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        words,
+        [
+            "fn",
+            "main",
+            "let",
+            "mut",
+            "x2",
+            "_y",
+            "return",
+            "ünïcode",
+            "x"
+        ]
+    );
+    for t in &tokens {
+        assert_eq!(t.ustr, &source[t.span.clone()]);
+        match t.symbol {
+            Symbol::Keyword(i) => assert_eq!(t.ustr, table.keywords()[i]),
+            Symbol::Ident(u) => assert_eq!(u, t.ustr),
+        }
+    }
+    assert_eq!(tokens[0].symbol, Symbol::Keyword(1));
+    assert_eq!(tokens[1].symbol, Symbol::Ident(u("main")));
+
+    let empty = SymbolTable::new(&[]);
+    assert_eq!(empty.symbol("fn"), Symbol::Ident(u("fn")));
+    assert_eq!(empty.tokens("  ").count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "duplicate keyword")]
+fn test_symbol_table_duplicate() {
+    let _t = super::TEST_LOCK.lock();
+    SymbolTable::new(&["if", "else", "if"]);
+}
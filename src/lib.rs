@@ -58,6 +58,11 @@
 //! assert_eq!(*map.get(&u1).unwrap(), 17);
 //! ```
 //!
+//! If you need insertion-ordered iteration (e.g. for deterministic
+//! serialization), enable the `"indexmap"` feature for `UstrIndexMap` and
+//! `UstrIndexSet`. For maps shared between threads, the `"dashmap"` feature
+//! adds `UstrDashMap` and `UstrDashSet`.
+//!
 //! By enabling the `"serde"` feature you can serialize individual `Ustr`s
 //! or the whole cache with serde.
 //!
@@ -71,8 +76,17 @@
 //! # }
 //! ```
 //!
+//! Deserializing a `Ustr` interns the string directly from the deserializer's
+//! buffer without allocating a `String`. The [`serde_intern`] module does the
+//! same for fields that hold interned strings as `&'static str`.
+//!
 //! Since the cache is global, use the `ustr::DeserializedCache` dummy object to
-//! drive the deserialization.
+//! drive the deserialization. Only the strings are serialized, never their
+//! hashes, and likewise for the keys of a serialized `UstrMap`, so everything
+//! is rehashed by the build that loads it even if its hasher is seeded
+//! differently. Dictionaries written by [`write_dictionary()`] do store
+//! hashes, so they record which hasher computed them and are rehashed when
+//! loaded by a build that hashes differently.
 //!
 //! ```
 //! # #[cfg(feature = "serde")] {
@@ -87,6 +101,19 @@
 //! # }
 //! ```
 //!
+//! Enable the `"tracing"` feature to have the cache report what it's doing
+//! through [`tracing`](https://docs.rs/tracing): a `grow` span (target `ustr`)
+//! is entered while a bin's table is resized, and an event is emitted whenever
+//! a bin starts a new string arena. The event carries a
+//! `monotonic_counter.ustr_arena_allocations` field, which
+//! `tracing-opentelemetry` turns into a counter. `tracing::Value` is sealed, so
+//! record a `Ustr` as `u.as_str()`, which doesn't allocate, or with `%u`.
+//!
+//! [`metrics()`] returns the cache's counters and gauges in a plain struct for
+//! your monitoring system, and the `"metrics"` feature adds
+//! `CacheMetrics::record()` to report them through the
+//! [`metrics`](https://docs.rs/metrics) crate.
+//!
 //! ## Why?
 //!
 //! It is common in certain types of applications to use strings as identifiers,
@@ -153,33 +180,197 @@
 //!
 //! This crate contains a significant amount of unsafe but usage has been
 //! checked and is well-documented. It is also run through Miri as part of the
-//! CI process. I use it regularly on 64-bit systems, and it has passed Miri on
-//! a 32-bit system as well, bit 32-bit is not checked regularly. If you want to
-//! use it on 32-bit, please make sure to run Miri and open and issue if you
-//! find any problems.
-use parking_lot::Mutex;
+//! CI process, on both 64-bit and 32-bit (i686) targets. The path that adds
+//! strings to the cache and publishes them to other threads is also
+//! model-checked with loom (see `tests/loom.rs`).
+//!
+//! 32-bit targets such as i686, armv7 and wasm32 are supported. The layout of
+//! each string's header is checked at compile time for every pointer width,
+//! and on 32-bit targets the storage stops doubling in size once it reaches
+//! 64MB per block, so that it doesn't need huge contiguous blocks of the 4GB
+//! address space.
+#![cfg_attr(
+    feature = "debug-helpers",
+    debugger_visualizer(gdb_script_file = "../debugger/ustr_gdb.py")
+)]
+use primitives::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::{
     borrow::Cow,
     cmp::Ordering,
     ffi::{CStr, OsStr},
     fmt,
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, Hash, Hasher},
     ops::Deref,
     os::raw::c_char,
     path::Path,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     rc::Rc,
-    slice, str,
+    slice,
+    slice::SliceIndex,
+    str,
     str::FromStr,
-    sync::Arc,
+    sync::{atomic::Ordering as AtomicOrdering, Arc, OnceLock},
 };
 
+mod growth;
+pub use growth::{growth_policy, set_growth_policy, GrowthPolicy};
 mod hash;
 pub use hash::*;
+#[cfg(feature = "stable-abi")]
+mod abi;
+mod hex;
+#[cfg(feature = "stable-abi")]
+pub use abi::{ustr_abi_version, ABI_VERSION};
+mod atomic;
+pub use atomic::{AtomicOptionUstr, AtomicUstr};
+mod builder;
+pub use builder::UstrBuilder;
 mod bumpalloc;
+mod cache_metrics;
+mod case;
+pub use bumpalloc::{CacheAllocator, SystemAllocator};
+pub use cache_metrics::{metrics, CacheMetrics};
+#[cfg(feature = "hugepages")]
+mod hugepages;
+#[cfg(feature = "hugepages")]
+pub use hugepages::HugePageAllocator;
 
 mod stringcache;
 pub use stringcache::*;
+#[cfg(feature = "icu")]
+mod collate;
+#[cfg(feature = "icu")]
+pub use collate::UstrCollate;
+#[cfg(feature = "debug-hash-collisions")]
+mod collisions;
+#[cfg(feature = "debug-hash-collisions")]
+pub use collisions::{hash_collisions, HashCollision};
+#[cfg(feature = "debug-helpers")]
+mod debugger;
+#[cfg(feature = "debug-helpers")]
+pub use debugger::__ustr_debug_str;
+mod error;
+pub mod forensics;
+mod fork;
+mod frozen;
+pub use fork::{ForkedCache, LocalUstr};
+pub use frozen::{freeze, frozen, FreezePolicy, FrozenCache};
+#[cfg(feature = "clap")]
+mod cli;
+#[cfg(feature = "clap")]
+pub use cli::UstrValueParser;
+#[cfg(feature = "async-graphql")]
+mod graphql;
+#[cfg(feature = "async-graphql")]
+pub use graphql::{graphql_max_len, set_graphql_max_len};
+mod ids;
+pub use ids::from_id;
+mod init;
+pub use init::{init, init_with, is_initialized, InitConfig};
+mod inline;
+pub use inline::{UstrOrInline, MAX_INLINE_LEN};
+mod join;
+pub mod lexer;
+pub use join::{join, join_interned, DisplayList};
+mod interner;
+pub mod introspect;
+pub use interner::{Interner, InternerStats};
+pub mod sync;
+pub use error::{ErrorCode, InternError, UstrError};
+mod limits;
+mod lines;
+pub use lines::intern_lines;
+mod mapped;
+mod mark;
+pub use mark::{mark_and_report, UnreferencedReport};
+mod memoize;
+pub use memoize::{
+    memoize_display, memoize_display_by, type_name, type_name_of_val,
+};
+#[cfg(feature = "negative-filter")]
+mod negative;
+mod numeric;
+pub use numeric::{Float, Integer};
+mod oom;
+pub use oom::{set_oom_handler, OomAction, OutOfMemory};
+mod pair;
+pub use pair::UstrPair;
+#[cfg(any(feature = "nom", feature = "winnow"))]
+pub mod parse;
+mod path;
+mod phf;
+mod prewarm;
+pub use prewarm::prewarm_from_env;
+mod primitives;
+mod quarantine;
+pub use quarantine::Quarantine;
+#[cfg(feature = "rand")]
+mod sample;
+#[cfg(feature = "rand")]
+pub use sample::sample;
+mod scratch;
+pub use scratch::{ScratchInterner, ScratchUstr};
+mod shared;
+use limits::limit_len;
+pub use limits::{max_len, set_max_len, MaxLenPolicy, TRUNCATION_SUFFIX_LEN};
+#[cfg(all(feature = "mmap", unix))]
+pub use mapped::load_dictionary;
+pub use mapped::{
+    build_dictionary, load_dictionary_bytes, trusted, verify_dictionary,
+    write_dictionary, DictionaryLoad, DictionaryStats,
+};
+pub use path::{UstrPath, PATH_SEPARATOR};
+pub use phf::PerfectHash;
+pub use shared::{
+    is_external_cache, set_external_cache, shared_cache, AttachError,
+    SharedCache,
+};
+mod simd;
+mod similar;
+mod snapshot;
+mod sorted_set;
+#[cfg(any(feature = "sqlx", feature = "rusqlite"))]
+mod sql;
+mod static_ustr;
+pub use similar::{similar, SimilarityIndex};
+pub use snapshot::{snapshot, CacheDiff, CacheSnapshot};
+pub use sorted_set::UstrSortedSet;
+pub use static_ustr::StaticUstr;
+#[cfg(feature = "string-interner")]
+mod symbol;
+#[cfg(feature = "string-interner")]
+pub use symbol::{UstrBackend, UstrBackendIter};
+mod table;
+pub mod testing;
+pub use table::UstrTable;
+mod trie;
+#[cfg(any(feature = "egui", feature = "imgui"))]
+mod ui_id;
+mod vec_map;
+pub use trie::UstrTrie;
+pub use vec_map::UstrVecMap;
+#[cfg(feature = "debug-alloc-tracking")]
+mod tracking;
+mod transform;
+#[cfg(feature = "derive")]
+mod ustr_enum;
+mod utf16;
+mod verify;
+#[cfg(feature = "debug-alloc-tracking")]
+pub use tracking::{allocation_report, AllocationSite};
+#[cfg(feature = "derive")]
+pub use ustr_derive::UstrEnum;
+#[cfg(feature = "derive")]
+pub use ustr_enum::{UstrEnum, UstrEnumTable};
+pub use verify::{set_verification, verification, Verification};
+#[cfg(feature = "unicode-normalization")]
+mod normalization;
+#[cfg(feature = "unicode-normalization")]
+pub use normalization::{normalization, set_normalization, Normalization};
+#[cfg(feature = "serde")]
+pub mod compact;
+#[cfg(feature = "serde")]
+pub mod serde_intern;
 #[cfg(feature = "serde")]
 pub mod serialization;
 #[cfg(feature = "serde")]
@@ -190,8 +381,22 @@ pub use serialization::DeserializedCache;
 /// To use, create one using [`Ustr::from`] or the [`ustr`] function. You can
 /// freely copy, destroy or send `Ustr`s to other threads: the underlying string
 /// is always valid in memory (and is never destroyed).
+///
+/// # FFI
+///
+/// A `Ustr` is a `#[repr(C)]` struct holding a single non-null pointer to the
+/// string's null-terminated chars, so it has the same size, alignment and
+/// calling convention as the `ustr_t` struct in `include/ustr.h` on every
+/// platform, and can be passed by value to and from C, including inside
+/// other `#[repr(C)]` structs. This is a stable guarantee, and is checked
+/// against a C compiler by the `abi` crate in the repository. `Option<Ustr>`
+/// is the same size, but Rust doesn't guarantee how it's passed to C, so pass
+/// a nullable [`as_char_ptr()`](Ustr::as_char_ptr) across the boundary
+/// instead.
+///
+/// cbindgen:field-names=[ptr]
 #[derive(Copy, Clone, PartialEq)]
-#[repr(transparent)]
+#[repr(C)]
 pub struct Ustr {
     char_ptr: NonNull<u8>,
 }
@@ -233,33 +438,204 @@ impl Ustr {
     /// assert_eq!(u1, u2);
     /// assert_eq!(ustr::num_entries(), 1);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string is longer than the limit set with
+    /// [`set_max_len()`] and the policy is [`MaxLenPolicy::Reject`], or if
+    /// the cache runs out of memory and the handler set with
+    /// [`set_oom_handler()`] returns [`OomAction::Error`]. Use
+    /// [`Ustr::try_intern`] if you need to handle those cases.
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     pub fn from(string: &str) -> Ustr {
-        let hash = {
-            let mut hasher = ahash::AHasher::default();
-            hasher.write(string.as_bytes());
-            hasher.finish()
-        };
-        let mut sc = STRING_CACHE.0[whichbin(hash)].lock();
-        Ustr {
-            // SAFETY: sc.insert does not give back a null pointer
-            char_ptr: unsafe {
-                NonNull::new_unchecked(sc.insert(string, hash) as *mut _)
+        match Ustr::try_intern(string) {
+            Ok(u) => u,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new `Ustr` from the given `str`, returning an error if it
+    /// cannot be added to the cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{MaxLenPolicy, Ustr};
+    /// # unsafe { ustr::_clear_cache() };
+    ///
+    /// ustr::set_max_len(8, MaxLenPolicy::Reject);
+    /// assert!(Ustr::try_intern("short").is_ok());
+    /// assert!(Ustr::try_intern("much too long").is_err());
+    /// # ustr::set_max_len(usize::MAX, MaxLenPolicy::Reject);
+    /// ```
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+    pub fn try_intern(string: &str) -> Result<Ustr, InternError> {
+        let canonical = canonical_str(string)?;
+        Ustr::intern_canonical(&canonical, None)
+    }
+
+    /// Create a new `Ustr` from bytes that should be UTF-8, replacing any
+    /// invalid sequences with U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// This is like [`String::from_utf8_lossy()`], except that the result is
+    /// interned. Bytes that are valid UTF-8 are interned directly without
+    /// allocating. Use `Ustr::try_from(bytes)` to reject invalid UTF-8
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{Ustr, ustr as u};
+    ///
+    /// assert_eq!(Ustr::from_utf8_lossy(b"attr"), u("attr"));
+    /// let lossy = Ustr::from_utf8_lossy(b"bad \xff byte");
+    /// assert_eq!(lossy, u("bad \u{fffd} byte"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+    pub fn from_utf8_lossy(bytes: &[u8]) -> Ustr {
+        Ustr::from(&String::from_utf8_lossy(bytes))
+    }
+
+    // Intern a string that has already been through `canonical_str()`. If
+    // `storage` is given, it's used for the new entry instead of copying the
+    // string into the cache. It must point to the header of a `StaticUstr`
+    // for `string`.
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+    pub(crate) fn intern_canonical(
+        string: &str,
+        storage: Option<NonNull<StringCacheEntry>>,
+    ) -> Result<Ustr, InternError> {
+        let hash = hash_str(string);
+
+        // Once frozen, strings from the snapshot can be found without locking.
+        if let Some(frozen) = frozen() {
+            if let Some(u) = frozen.get_canonical(string, hash) {
+                return Ok(u);
+            }
+            if frozen.policy() == FreezePolicy::Reject {
+                return Err(InternError::Frozen);
+            }
+        }
+
+        let bin = &STRING_CACHE.0[whichbin(hash)];
+
+        // In steady state the vast majority of calls are for strings that are
+        // already in the cache, so try to find it under the shared lock first
+        // and only take the exclusive lock if we actually need to insert.
+        if let Some(ptr) = read_bin(bin).get_existing(string, hash) {
+            return Ok(Ustr {
+                // SAFETY: sc.get_existing does not give back a null pointer
+                char_ptr: unsafe { NonNull::new_unchecked(ptr as *mut _) },
+            });
+        }
+
+        // Another thread may have inserted the string between us dropping the
+        // read lock and taking the write lock, but `insert()` checks for that.
+        let mut sc = write_current_bin(whichbin(hash));
+        // The cache might have been frozen while we were waiting for the lock.
+        if frozen().map(FrozenCache::policy) == Some(FreezePolicy::Reject) {
+            return sc
+                .get_existing(string, hash)
+                .map(|ptr| Ustr {
+                    char_ptr: unsafe { NonNull::new_unchecked(ptr as *mut _) },
+                })
+                .ok_or(InternError::Frozen);
+        }
+        #[cfg(feature = "debug-alloc-tracking")]
+        let allocated = sc.total_allocated();
+        let char_ptr = match storage {
+            // SAFETY: the caller guarantees that `storage` is followed by the
+            // chars of `string` and lives forever.
+            Some(entry) => unsafe {
+                sc.insert_static(string, hash, entry.as_ptr())
             },
+            None => sc
+                .try_insert(string, hash)
+                .map_err(InternError::OutOfMemory)?,
+        };
+        #[cfg(feature = "debug-alloc-tracking")]
+        {
+            let bytes = sc.total_allocated() - allocated;
+            drop(sc);
+            if bytes != 0 {
+                tracking::record(std::panic::Location::caller(), bytes);
+            }
         }
+
+        Ok(Ustr {
+            // SAFETY: sc.insert does not give back a null pointer
+            char_ptr: unsafe { NonNull::new_unchecked(char_ptr as *mut _) },
+        })
     }
 
     pub fn from_existing(string: &str) -> Option<Ustr> {
-        let hash = {
-            let mut hasher = ahash::AHasher::default();
-            hasher.write(string.as_bytes());
-            hasher.finish()
-        };
-        let sc = STRING_CACHE.0[whichbin(hash)].lock();
+        // A string that's too long can't be in the cache, unless we truncate
+        // it first.
+        let canonical = canonical_str(string).ok()?;
+        let string: &str = &canonical;
+
+        let hash = hash_str(string);
+        if let Some(frozen) = frozen() {
+            // Nothing is added after freezing unless we fall back.
+            let found = frozen.get_canonical(string, hash);
+            if found.is_some() || frozen.policy() == FreezePolicy::Reject {
+                return found;
+            }
+        }
+        #[cfg(feature = "negative-filter")]
+        if !negative::may_contain(hash) {
+            return None;
+        }
+        let sc = read_bin(&STRING_CACHE.0[whichbin(hash)]);
         sc.get_existing(string, hash).map(|ptr| Ustr {
             char_ptr: unsafe { NonNull::new_unchecked(ptr as *mut _) },
         })
     }
 
+    /// Returns `true` if `string` is already in the cache.
+    ///
+    /// This is equivalent to `Ustr::from_existing(string).is_some()` and only
+    /// needs a shared lock on the cache (or none once it's [frozen](freeze)),
+    /// so it's cheap enough to gate more expensive work on whether a string
+    /// has been seen before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr, Ustr};
+    /// # unsafe { ustr::_clear_cache() };
+    ///
+    /// assert!(!Ustr::is_interned("never seen"));
+    /// ustr("never seen");
+    /// assert!(Ustr::is_interned("never seen"));
+    /// ```
+    pub fn is_interned(string: &str) -> bool {
+        let canonical = match canonical_str(string) {
+            Ok(canonical) => canonical,
+            Err(_) => return false,
+        };
+        let string: &str = &canonical;
+
+        let hash = hash_str(string);
+        if let Some(frozen) = frozen() {
+            let found = frozen.get_canonical(string, hash).is_some();
+            if found || frozen.policy() == FreezePolicy::Reject {
+                return found;
+            }
+        }
+        #[cfg(feature = "negative-filter")]
+        if !negative::may_contain(hash) {
+            return false;
+        }
+        read_bin(&STRING_CACHE.0[whichbin(hash)])
+            .get_existing(string, hash)
+            .is_some()
+    }
+
     /// Get the cached `Ustr` as a `str`.
     ///
     /// # Examples
@@ -272,7 +648,7 @@ impl Ustr {
     /// let words: Vec<&str> = u_fox.as_str().split_whitespace().collect();
     /// assert_eq!(words, ["the", "quick", "brown", "fox"]);
     /// ```
-    pub fn as_str(&self) -> &'static str {
+    pub const fn as_str(&self) -> &'static str {
         // This is safe if:
         // 1) self.char_ptr points to a valid address
         // 2) len is a usize stored usize aligned usize bytes before char_ptr
@@ -287,6 +663,119 @@ impl Ustr {
         }
     }
 
+    /// Call `f` with the cached string, returning what it returns.
+    ///
+    /// The string is only borrowed for the duration of the call, rather than
+    /// for `'static` as with [`as_str()`](Ustr::as_str). Code that only needs
+    /// the string for a moment can use this so that it doesn't depend on the
+    /// string living forever, and keeps working unchanged with symbols from
+    /// caches whose strings don't, such as [`LocalUstr`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let is_png = u("image.png").with_str(|s| s.ends_with(".png"));
+    /// assert!(is_png);
+    /// ```
+    #[inline]
+    pub fn with_str<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        f(self.as_str())
+    }
+
+    /// Get the bytes of the cached string, without the null terminator.
+    ///
+    /// This is the same as `as_str().as_bytes()`, but spelled out for code
+    /// that works with bytes, and the slice is `'static`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("bytes").as_bytes(), b"bytes");
+    /// ```
+    #[inline]
+    pub const fn as_bytes(&self) -> &'static [u8] {
+        self.as_str().as_bytes()
+    }
+
+    /// Get the bytes of the cached string, including the null terminator.
+    ///
+    /// This is the slice that [`as_char_ptr()`](Ustr::as_char_ptr) points
+    /// to, so it can be handed to FFI code that takes a pointer and a length
+    /// including the terminator, or hashed the way C code would see the
+    /// string, without rebuilding it with `slice::from_raw_parts()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let s = u("nul");
+    /// assert_eq!(s.as_bytes_with_nul(), b"nul\0");
+    /// assert_eq!(s.as_bytes_with_nul().as_ptr(), s.as_char_ptr().cast());
+    /// ```
+    #[inline]
+    pub const fn as_bytes_with_nul(&self) -> &'static [u8] {
+        // The null terminator is always written after the chars by
+        // StringCache::insert(), and by StaticUstr and the mapped
+        // dictionaries, which lay out their entries the same way.
+        unsafe { slice::from_raw_parts(self.char_ptr.as_ptr(), self.len() + 1) }
+    }
+
+    /// Get a substring of the cached string.
+    ///
+    /// Since the cached string is never freed, the substring is `'static` and
+    /// can be held on to without being tied to the lifetime of this `Ustr`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or does not lie on `char`
+    /// boundaries, just like indexing a `str`. Use [`Ustr::get`] for a
+    /// non-panicking alternative.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    /// # unsafe { ustr::_clear_cache() };
+    ///
+    /// let path = u("/usr/local/bin");
+    /// let dir: &'static str = path.slice(..10);
+    /// assert_eq!(dir, "/usr/local");
+    /// ```
+    pub fn slice<R>(&self, range: R) -> &'static str
+    where
+        R: SliceIndex<str, Output = str>,
+    {
+        &self.as_str()[range]
+    }
+
+    /// Get a substring of the cached string, or `None` if the range is out of
+    /// bounds or does not lie on `char` boundaries.
+    ///
+    /// This is the non-panicking version of [`Ustr::slice`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    /// # unsafe { ustr::_clear_cache() };
+    ///
+    /// let greek = u("αβγ");
+    /// assert_eq!(greek.get(..2), Some("α"));
+    /// assert_eq!(greek.get(..1), None);
+    /// assert_eq!(greek.get(..10), None);
+    /// ```
+    pub fn get<R>(&self, range: R) -> Option<&'static str>
+    where
+        R: SliceIndex<str, Output = str>,
+    {
+        self.as_str().get(range)
+    }
+
     /// Get the cached string as a C `char*`.
     ///
     /// This includes the null terminator so is safe to pass straight to FFI.
@@ -312,10 +801,90 @@ impl Ustr {
     ///
     /// The string is **immutable**. That means that if you modify it across the
     /// FFI boundary then all sorts of terrible things will happen.
-    pub fn as_char_ptr(&self) -> *const c_char {
+    pub const fn as_char_ptr(&self) -> *const c_char {
         self.char_ptr.as_ptr() as *const c_char
     }
 
+    /// Get the cached string as a non-null C `char*`.
+    ///
+    /// This is the same as [`Ustr::as_char_ptr`] but makes the non-nullness
+    /// explicit in the type. The pointer is valid for the lifetime of the
+    /// program.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    /// # unsafe { ustr::_clear_cache() };
+    ///
+    /// let ext = u("VK_KHR_swapchain");
+    /// let ptr = ext.as_ptr_nonnull();
+    /// assert_eq!(ptr.as_ptr() as *const _, ext.as_char_ptr());
+    /// ```
+    #[inline]
+    pub const fn as_ptr_nonnull(&self) -> NonNull<c_char> {
+        self.char_ptr.cast()
+    }
+
+    /// Get the `Ustr` for a pointer returned by [`Ustr::as_char_ptr`], without
+    /// checking it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from [`Ustr::as_char_ptr`] or
+    /// [`Ustr::as_ptr_nonnull`], and the cache must not have been cleared
+    /// since. Use [`Ustr::try_from_char_ptr`] for pointers that can't be
+    /// trusted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// let ptr = u("round trip").as_char_ptr();
+    /// assert_eq!(unsafe { Ustr::from_char_ptr_unchecked(ptr) }, "round trip");
+    /// ```
+    #[inline]
+    pub const unsafe fn from_char_ptr_unchecked(ptr: *const c_char) -> Ustr {
+        Ustr {
+            char_ptr: NonNull::new_unchecked(ptr as *mut u8),
+        }
+    }
+
+    /// Get the `Ustr` for a pointer returned by [`Ustr::as_char_ptr`], or
+    /// `None` if it isn't one.
+    ///
+    /// This is for pointers handed back from code that can't be trusted,
+    /// such as C plugins. Any pointer can be passed, including null, dangling
+    /// or misaligned pointers and pointers to the middle of a string, and it's
+    /// never dereferenced unless it points into the cache's storage. It's
+    /// found by checking which of the cache's storage it points into, then
+    /// looking up the string there in the cache to make sure it's the start
+    /// of one, so it's about as slow as [`Ustr::from_existing`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// let plugin = u("plugin");
+    /// let ptr = plugin.as_char_ptr();
+    /// assert_eq!(Ustr::try_from_char_ptr(ptr), Some(plugin));
+    /// assert_eq!(Ustr::try_from_char_ptr(ptr.wrapping_add(1)), None);
+    /// assert_eq!(Ustr::try_from_char_ptr(c"plugin".as_ptr()), None);
+    /// assert_eq!(Ustr::try_from_char_ptr(std::ptr::null()), None);
+    /// ```
+    pub fn try_from_char_ptr(ptr: *const c_char) -> Option<Ustr> {
+        let char_ptr = NonNull::new(ptr as *mut u8)?;
+        // The bin can't be worked out without reading the string, so check
+        // each in turn.
+        STRING_CACHE
+            .0
+            .iter()
+            .any(|bin| read_bin(bin).contains_entry(char_ptr.as_ptr()))
+            .then_some(Ustr { char_ptr })
+    }
+
     /// Get this `Ustr` as a [`CStr`]
     ///
     /// This is useful for passing to APIs (like ash) that use `CStr`.
@@ -325,18 +894,13 @@ impl Ustr {
     /// This function by itself is safe as the pointer and length are guaranteed
     /// to be valid. All the same caveats for the use of the `CStr` as given in
     /// the `CStr` docs apply.
-    pub fn as_cstr(&self) -> &CStr {
-        unsafe {
-            CStr::from_bytes_with_nul_unchecked(slice::from_raw_parts(
-                self.as_ptr(),
-                self.len() + 1,
-            ))
-        }
+    pub const fn as_cstr(&self) -> &CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(self.as_bytes_with_nul()) }
     }
 
     /// Get a raw pointer to the `StringCacheEntry`.
     #[inline]
-    fn as_string_cache_entry(&self) -> &StringCacheEntry {
+    const fn as_string_cache_entry(&self) -> &StringCacheEntry {
         // The allocator guarantees that the alignment is correct and that
         // this pointer is non-null
         unsafe { &*(self.char_ptr.as_ptr().cast::<StringCacheEntry>().sub(1)) }
@@ -344,21 +908,67 @@ impl Ustr {
 
     /// Get the length (in bytes) of this string.
     #[inline]
-    pub fn len(&self) -> usize {
+    pub const fn len(&self) -> usize {
         self.as_string_cache_entry().len
     }
 
     /// Returns true if the length is zero.
-    pub fn is_empty(&self) -> bool {
+    pub const fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
     /// Get the precomputed hash for this string.
+    ///
+    /// It's only the same in other processes if they all seed the hash
+    /// function with [`set_hash_seed()`].
     #[inline]
-    pub fn precomputed_hash(&self) -> u64 {
+    pub const fn precomputed_hash(&self) -> u64 {
         self.as_string_cache_entry().hash
     }
 
+    /// Get the secondary 32-bit hash for this string.
+    ///
+    /// This is computed once when the string is first added to the cache,
+    /// using the function set with [`set_hash32_fn()`] (32-bit FNV-1a by
+    /// default), and is useful where a 64-bit hash is too big, e.g. in packet
+    /// headers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("hello").hash32(), ustr::fnv1a_32("hello"));
+    /// ```
+    #[inline]
+    pub const fn hash32(&self) -> u32 {
+        self.as_string_cache_entry().hash32
+    }
+
+    /// Get the id of this string.
+    ///
+    /// Every unique string is given the next id when it's first added to the
+    /// cache, so ids are dense, start at 0 and are in insertion order. That
+    /// makes them handy for indexing a `Vec` by string instead of using a
+    /// map. Use [`from_id()`] to get the `Ustr` for an id.
+    ///
+    /// Ids are only meaningful within a single process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let a = u("first of two new strings");
+    /// let b = u("second of two new strings");
+    /// assert_eq!(b.id(), a.id() + 1);
+    /// assert_eq!(u("first of two new strings").id(), a.id());
+    /// ```
+    #[inline]
+    pub const fn id(&self) -> u32 {
+        self.as_string_cache_entry().generation
+    }
+
     /// Get an owned String copy of this string.
     pub fn to_owned(&self) -> String {
         self.as_str().to_owned()
@@ -451,25 +1061,25 @@ impl PartialEq<Ustr> for &Box<str> {
 
 impl PartialEq<Cow<'_, str>> for Ustr {
     fn eq(&self, other: &Cow<'_, str>) -> bool {
-        self.as_str() == &*other
+        self.as_str() == other
     }
 }
 
 impl PartialEq<Ustr> for Cow<'_, str> {
     fn eq(&self, u: &Ustr) -> bool {
-        &*self == u.as_str()
+        self == u.as_str()
     }
 }
 
 impl PartialEq<&Cow<'_, str>> for Ustr {
     fn eq(&self, other: &&Cow<'_, str>) -> bool {
-        self.as_str() == &**other
+        self.as_str() == *other
     }
 }
 
 impl PartialEq<Ustr> for &Cow<'_, str> {
     fn eq(&self, u: &Ustr) -> bool {
-        &**self == u.as_str()
+        *self == u.as_str()
     }
 }
 
@@ -512,12 +1122,14 @@ impl FromStr for Ustr {
     type Err = std::string::ParseError;
 
     #[inline]
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(Ustr::from(s))
     }
 }
 
 impl From<&str> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: &str) -> Ustr {
         Ustr::from(s)
     }
@@ -560,38 +1172,82 @@ impl From<Ustr> for Cow<'static, str> {
 }
 
 impl From<String> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: String) -> Ustr {
         Ustr::from(&s)
     }
 }
 
 impl From<&String> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: &String) -> Ustr {
-        Ustr::from(&**s)
+        Ustr::from(s)
     }
 }
 
 impl From<Box<str>> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: Box<str>) -> Ustr {
-        Ustr::from(&*s)
+        Ustr::from(&s)
     }
 }
 
 impl From<Rc<str>> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: Rc<str>) -> Ustr {
-        Ustr::from(&*s)
+        Ustr::from(&s)
     }
 }
 
 impl From<Arc<str>> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: Arc<str>) -> Ustr {
-        Ustr::from(&*s)
+        Ustr::from(&s)
     }
 }
 
 impl From<Cow<'_, str>> for Ustr {
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
     fn from(s: Cow<'_, str>) -> Ustr {
-        Ustr::from(&*s)
+        Ustr::from(&s)
+    }
+}
+
+/// Interns the bytes if they're valid UTF-8.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{Ustr, ustr as u};
+///
+/// assert_eq!(Ustr::try_from(&b"bytes"[..]), Ok(u("bytes")));
+/// assert!(Ustr::try_from(&b"\xc3"[..]).is_err());
+/// ```
+impl TryFrom<&[u8]> for Ustr {
+    type Error = str::Utf8Error;
+
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+    fn try_from(bytes: &[u8]) -> Result<Ustr, str::Utf8Error> {
+        str::from_utf8(bytes).map(Ustr::from)
+    }
+}
+
+/// Interns the string if it's valid UTF-8.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::OsStr;
+/// use ustr::{Ustr, ustr as u};
+///
+/// assert_eq!(Ustr::try_from(OsStr::new("--verbose")), Ok(u("--verbose")));
+/// ```
+impl TryFrom<&OsStr> for Ustr {
+    type Error = str::Utf8Error;
+
+    #[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+    fn try_from(s: &OsStr) -> Result<Ustr, str::Utf8Error> {
+        Ustr::try_from(s.as_encoded_bytes())
     }
 }
 
@@ -632,7 +1288,8 @@ impl Hash for Ustr {
 ///
 /// Clears the cache -- used for benchmarking and testing purposes to clear the
 /// cache. Calling this will invalidate any previously created `UStr`s and
-/// probably cause your house to burn down. DO NOT CALL THIS.
+/// probably cause your house to burn down. DO NOT CALL THIS. Tests that need
+/// an empty cache should use [`testing::CacheGuard`] instead.
 ///
 /// # Safety
 ///
@@ -640,8 +1297,24 @@ impl Hash for Ustr {
 #[doc(hidden)]
 pub unsafe fn _clear_cache() {
     for m in STRING_CACHE.0.iter() {
-        m.lock().clear();
-    }
+        m.write().clear();
+    }
+    NEXT_GENERATION.store(0, AtomicOrdering::Relaxed);
+    ids::clear();
+    case::clear();
+    frozen::clear();
+    memoize::clear();
+    pair::clear();
+    #[cfg(feature = "negative-filter")]
+    negative::free();
+    prewarm::clear();
+    static_ustr::clear();
+    transform::clear();
+    utf16::clear();
+    #[cfg(feature = "debug-alloc-tracking")]
+    tracking::clear();
+    #[cfg(feature = "debug-hash-collisions")]
+    collisions::clear();
 }
 
 /// Returns the total amount of memory allocated and in use by the cache in
@@ -651,7 +1324,7 @@ pub fn total_allocated() -> usize {
         .0
         .iter()
         .map(|sc| {
-            let t = sc.lock().total_allocated();
+            let t = sc.read().total_allocated();
 
             t
         })
@@ -664,51 +1337,154 @@ pub fn total_capacity() -> usize {
         .0
         .iter()
         .map(|sc| {
-            let t = sc.lock().total_capacity();
+            let t = sc.read().total_capacity();
             t
         })
         .sum()
 }
 
-/// Create a new `Ustr` from the given `str`.
+/// Shrink the cache's tables to fit the strings they hold, returning the
+/// number of bytes freed.
+///
+/// The tables double in size as strings are added and never shrink by
+/// themselves, so they can be much bigger than they need to be if the cache
+/// was created with a big [`InitConfig::initial_capacity`], or if the
+/// [`GrowthPolicy`]'s load factor is raised once loading is done to trade a
+/// little lookup speed for memory. Each table is rebuilt at the smallest size
+/// that's within the load factor, and the next string arena each bin
+/// allocates starts over at the initial size rather than growing from the
+/// last one.
+///
+/// Strings themselves can't be moved, since every `Ustr` points to one, so
+/// this doesn't free any string storage. It takes each bin's lock in turn,
+/// so other threads can keep using the cache while it runs.
 ///
 /// # Examples
 ///
 /// ```
-/// use ustr::ustr;
-/// # unsafe { ustr::_clear_cache() };
+/// use ustr::ustr as u;
 ///
-/// let u1 = ustr("the quick brown fox");
-/// let u2 = ustr("the quick brown fox");
-/// assert_eq!(u1, u2);
-/// assert_eq!(ustr::num_entries(), 1);
+/// let _ = u("Hello");
+/// ustr::compact();
+/// assert_eq!(u("Hello"), "Hello");
 /// ```
-#[inline]
-pub fn ustr(s: &str) -> Ustr {
-    Ustr::from(s)
+pub fn compact() -> usize {
+    let slots = STRING_CACHE
+        .0
+        .iter()
+        .map(|sc| write_bin(sc).compact())
+        .sum::<usize>();
+    slots * std::mem::size_of::<*const StringCacheEntry>()
 }
 
-/// Create a new `Ustr` from the given `str` but only if it already exists in
-/// the string cache.
+/// Returns `true` if `ptr` points into memory the cache holds strings in.
+///
+/// This is the case for any pointer into one of the cache's string arenas,
+/// including the parts that haven't been used yet, and for any pointer into
+/// a string that's stored outside the arenas, such as a
+/// [`StaticUstr`](crate::StaticUstr) or a loaded dictionary. Any pointer can be
+/// passed, and it's never dereferenced. Use [`Ustr::try_from_char_ptr`] to
+/// check that a pointer is actually the start of a string.
 ///
 /// # Examples
 ///
 /// ```
-/// use ustr::{ustr, existing_ustr};
-/// # unsafe { ustr::_clear_cache() };
+/// use ustr::ustr as u;
 ///
-/// let u1 = existing_ustr("the quick brown fox");
-/// let u2 = ustr("the quick brown fox");
-/// let u3 = existing_ustr("the quick brown fox");
-/// assert_eq!(u1, None);
-/// assert_eq!(u3, Some(u2));
+/// let s = u("owned");
+/// assert!(ustr::owns_ptr(s.as_char_ptr() as *const u8));
+/// assert!(ustr::owns_ptr(s.as_char_ptr().wrapping_add(2) as *const u8));
+/// assert!(!ustr::owns_ptr("owned".as_ptr()));
 /// ```
-#[inline]
-pub fn existing_ustr(s: &str) -> Option<Ustr> {
-    Ustr::from_existing(s)
+pub fn owns_ptr(ptr: *const u8) -> bool {
+    owning_bin(ptr).is_some()
 }
 
-/// Utility function to get a reference to the main cache object for use with
+/// Returns the index of the bin whose memory `ptr` points into, as described
+/// in [`owns_ptr()`], or `None` if it doesn't point into the cache's memory.
+///
+/// The index is the position of the bin in the list returned by
+/// [`bin_stats()`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let s = u("which bin?");
+/// let bin = ustr::owning_bin(s.as_char_ptr() as *const u8).unwrap();
+/// assert!(bin < ustr::bin_stats().len());
+/// assert_eq!(ustr::owning_bin(std::ptr::null()), None);
+/// ```
+pub fn owning_bin(ptr: *const u8) -> Option<usize> {
+    STRING_CACHE
+        .0
+        .iter()
+        .position(|sc| read_bin(sc).owns_ptr(ptr))
+}
+
+/// Create a new `Ustr` from the given `str`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr;
+/// # unsafe { ustr::_clear_cache() };
+///
+/// let u1 = ustr("the quick brown fox");
+/// let u2 = ustr("the quick brown fox");
+/// assert_eq!(u1, u2);
+/// assert_eq!(ustr::num_entries(), 1);
+/// ```
+#[inline]
+#[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+pub fn ustr(s: &str) -> Ustr {
+    Ustr::from(s)
+}
+
+/// Collect the C `char*`s of a slice of `Ustr`s into a `Vec`.
+///
+/// This is handy for APIs that take arrays of strings, such as the extension
+/// and layer name lists in Vulkan or OpenXR. Since the strings are never freed,
+/// the pointers remain valid even after the `Ustr`s themselves are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+/// # unsafe { ustr::_clear_cache() };
+///
+/// let extensions = [u("VK_KHR_surface"), u("VK_KHR_swapchain")];
+/// let ptrs = ustr::char_ptrs(&extensions);
+/// assert_eq!(ptrs.len(), 2);
+/// let len = unsafe { libc::strlen(ptrs[1]) };
+/// assert_eq!(len, 16);
+/// ```
+pub fn char_ptrs(ustrs: &[Ustr]) -> Vec<*const c_char> {
+    ustrs.iter().map(|u| u.as_char_ptr()).collect()
+}
+
+/// Create a new `Ustr` from the given `str` but only if it already exists in
+/// the string cache.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, existing_ustr};
+/// # unsafe { ustr::_clear_cache() };
+///
+/// let u1 = existing_ustr("the quick brown fox");
+/// let u2 = ustr("the quick brown fox");
+/// let u3 = existing_ustr("the quick brown fox");
+/// assert_eq!(u1, None);
+/// assert_eq!(u3, Some(u2));
+/// ```
+#[inline]
+pub fn existing_ustr(s: &str) -> Option<Ustr> {
+    Ustr::from_existing(s)
+}
+
+/// Utility function to get a reference to the main cache object for use with
 /// serialization.
 ///
 /// # Examples
@@ -744,7 +1520,7 @@ pub fn num_entries() -> usize {
         .0
         .iter()
         .map(|sc| {
-            let t = sc.lock().num_entries();
+            let t = sc.read().num_entries();
             t
         })
         .sum()
@@ -756,12 +1532,219 @@ pub fn num_entries_per_bin() -> Vec<usize> {
         .0
         .iter()
         .map(|sc| {
-            let t = sc.lock().num_entries();
+            let t = sc.read().num_entries();
             t
         })
         .collect::<Vec<_>>()
 }
 
+static ALLOCATOR: OnceLock<&'static dyn CacheAllocator> = OnceLock::new();
+
+/// Set the allocator used to provide the memory for the cache's string
+/// storage.
+///
+/// This must be called before the cache is first used, as the initial storage
+/// is allocated when the first string is created. If the allocator has already
+/// been set (or the default has already been installed by using the cache)
+/// then `allocator` is handed back in the `Err`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, SystemAllocator};
+///
+/// // We're too late, the cache is already in use.
+/// let _ = ustr("hello");
+/// assert!(ustr::set_allocator(&SystemAllocator).is_err());
+/// ```
+pub fn set_allocator(
+    allocator: &'static dyn CacheAllocator,
+) -> Result<(), &'static dyn CacheAllocator> {
+    ALLOCATOR.set(allocator)
+}
+
+// Get the allocator, installing the default if none was set.
+pub(crate) fn allocator() -> &'static dyn CacheAllocator {
+    *ALLOCATOR.get_or_init(|| &SystemAllocator)
+}
+
+static HASH32_FN: OnceLock<fn(&str) -> u32> = OnceLock::new();
+
+/// Set the function used to compute the secondary hash returned by
+/// [`Ustr::hash32()`].
+///
+/// This must be called before any strings are added to the cache, since the
+/// secondary hash is computed once on insertion and stored with the string. If
+/// the function has already been set (or the default has already been used)
+/// then `f` is handed back in the `Err`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr;
+///
+/// // We're too late, there are already strings in the cache.
+/// let _ = ustr("hello");
+/// assert!(ustr::set_hash32_fn(|s| s.len() as u32).is_err());
+/// ```
+pub fn set_hash32_fn(f: fn(&str) -> u32) -> Result<(), fn(&str) -> u32> {
+    HASH32_FN.set(f)
+}
+
+// Get the secondary hash function, installing the default if none was set.
+pub(crate) fn hash32_fn() -> fn(&str) -> u32 {
+    *HASH32_FN.get_or_init(|| fnv1a_32)
+}
+
+static HASH_SEED: OnceLock<Option<ahash::RandomState>> = OnceLock::new();
+
+/// Seed the hash function that [`Ustr::precomputed_hash()`] returns, so that
+/// every process that sets the same seed computes the same hashes.
+///
+/// By default the cache uses `ahash`'s default keys, which are generated
+/// randomly in each process if any crate in the build enables `ahash`'s
+/// `runtime-rng` feature, and otherwise differ between builds if it enables
+/// `compile-time-rng`. Processes that send hashes to each other or share
+/// dictionaries then disagree about every string, and benchmarks don't fill
+/// the cache's bins the same way from one run to the next. Processes built
+/// for different targets may still differ, since `ahash` uses AES
+/// instructions where they're available.
+///
+/// This must be called before anything is hashed, since every string's hash
+/// is stored with it. If the hash function is already in use then `seed` is
+/// handed back in the `Err`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr;
+///
+/// // We're too late, there are already strings in the cache.
+/// let _ = ustr("hello");
+/// assert!(ustr::set_hash_seed([1, 2, 3, 4]).is_err());
+/// ```
+pub fn set_hash_seed(seed: [u64; 4]) -> Result<(), [u64; 4]> {
+    let [k0, k1, k2, k3] = seed;
+    HASH_SEED
+        .set(Some(ahash::RandomState::with_seeds(k0, k1, k2, k3)))
+        .map_err(|_| seed)
+}
+
+// The seeded hasher set with `set_hash_seed()`, or `None` if `ahash`'s
+// default keys are being used, fixing them if nothing has been set yet.
+#[inline]
+pub(crate) fn hash_seed() -> Option<&'static ahash::RandomState> {
+    HASH_SEED.get_or_init(|| None).as_ref()
+}
+
+/// Statistics for a single bin (shard) of the string cache, as returned by
+/// [`bin_stats()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinStats {
+    /// Number of unique strings stored in this bin.
+    pub num_entries: usize,
+    /// Number of slots in this bin's entry table.
+    pub table_capacity: usize,
+    /// Number of lookups that had to wait for another thread to release the
+    /// bin's lock.
+    pub contended_reads: u64,
+    /// Number of inserts that had to wait for another thread to release the
+    /// bin's lock.
+    pub contended_writes: u64,
+}
+
+/// Returns per-bin statistics for the cache.
+///
+/// The cache is split into a fixed number of bins by the top bits of each
+/// string's hash, each with its own lock. If the contention counters show that
+/// a few bins are absorbing most of the traffic, the number of bins can be
+/// increased by setting the `USTR_BIN_SHIFT` environment variable when building
+/// (the default is 6, i.e. 64 bins).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let _ = u("Hello");
+/// let stats = ustr::bin_stats();
+/// assert_eq!(
+///     stats.iter().map(|b| b.num_entries).sum::<usize>(),
+///     ustr::num_entries()
+/// );
+/// ```
+pub fn bin_stats() -> Vec<BinStats> {
+    STRING_CACHE
+        .0
+        .iter()
+        .map(|sc| {
+            let sc = sc.read();
+            BinStats {
+                num_entries: sc.num_entries(),
+                table_capacity: sc.table_capacity(),
+                contended_reads: sc
+                    .contended_reads
+                    .load(AtomicOrdering::Relaxed),
+                contended_writes: sc
+                    .contended_writes
+                    .load(AtomicOrdering::Relaxed),
+            }
+        })
+        .collect()
+}
+
+/// Returns the current generation of the cache.
+///
+/// Every new string added to the cache is stamped with the generation at the
+/// time it was inserted, after which the generation is incremented. Pass the
+/// value returned from this function to [`entries_since()`] later on to find
+/// out which strings have been added in the meantime.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let before = ustr::current_generation();
+/// let _ = u("a string we've never seen before");
+/// assert_eq!(ustr::current_generation(), before + 1);
+/// ```
+pub fn current_generation() -> u32 {
+    NEXT_GENERATION.load(AtomicOrdering::Relaxed)
+}
+
+/// Returns all the strings that were added to the cache in or after
+/// `generation`, in the order they were added.
+///
+/// This lets incremental systems (e.g. syncing string tables over the network)
+/// find the new strings without having to diff the whole cache. Strings that
+/// are being added concurrently with this call may or may not be included, but
+/// a string stamped with a generation lower than [`current_generation()`] as
+/// read *before* this call will always be included.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let _ = u("old");
+/// let generation = ustr::current_generation();
+/// let new = u("new");
+/// let _ = u("old");
+/// assert_eq!(ustr::entries_since(generation).collect::<Vec<_>>(), vec![new]);
+/// ```
+pub fn entries_since(generation: u32) -> impl Iterator<Item = Ustr> {
+    let mut entries = Vec::new();
+    for m in STRING_CACHE.0.iter() {
+        m.read().entries_since(generation, &mut entries);
+    }
+    entries.sort_unstable_by_key(|(generation, _)| *generation);
+    entries.into_iter().map(|(_, char_ptr)| Ustr {
+        // SAFETY: entries are never null
+        char_ptr: unsafe { NonNull::new_unchecked(char_ptr as *mut _) },
+    })
+}
+
 /// Return an iterator over the entire string cache.
 ///
 /// If another thread is adding strings concurrently to this call then they
@@ -777,28 +1760,100 @@ pub fn num_entries_per_bin() -> Vec<usize> {
 pub fn string_cache_iter() -> StringCacheIterator {
     let mut allocs = Vec::new();
     for m in STRING_CACHE.0.iter() {
-        let sc = m.lock();
-        // the start of the allocator's data is actually the ptr, start() just
-        // points to the beginning of the allocated region. The first bytes will
-        // be uninitialized since we're bumping down
-        for a in &sc.old_allocs {
-            allocs.push((a.ptr(), a.end()));
-        }
-        let ptr = sc.alloc.ptr();
-        let end = sc.alloc.end();
-        if ptr != end {
-            allocs.push((sc.alloc.ptr(), sc.alloc.end()));
-        }
+        m.read().alloc_ranges(&mut allocs);
     }
+    StringCacheIterator::new(allocs)
+}
 
-    let current_ptr =
-        allocs.first().map(|s| s.0).unwrap_or_else(std::ptr::null);
+/// Return an iterator over the `Ustr`s of every string in the cache.
+///
+/// Like [`string_cache_iter()`], this takes each bin's lock once, when it's
+/// called, and then walks the strings' storage without locking, so building a
+/// map or set from the cache's contents doesn't need to look every string up
+/// again. Strings added concurrently might not be included.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrSet};
+/// # unsafe { ustr::_clear_cache() };
+///
+/// u("red");
+/// u("green");
+/// let set = ustr::ustr_iter().collect::<UstrSet>();
+/// assert!(set.contains(&u("green")));
+/// # assert_eq!(set.len(), 2);
+/// ```
+pub fn ustr_iter() -> UstrIter {
+    UstrIter(string_cache_iter())
+}
 
-    StringCacheIterator {
-        allocs,
-        current_alloc: 0,
-        current_ptr,
-    }
+/// Return an iterator over the `Ustr` and precomputed hash of every string in
+/// the cache, as [`ustr_iter()`] does.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+/// # unsafe { ustr::_clear_cache() };
+///
+/// let blue = u("blue");
+/// let hashes = ustr::ustr_hash_iter().collect::<Vec<_>>();
+/// assert_eq!(hashes, vec![(blue, blue.precomputed_hash())]);
+/// ```
+pub fn ustr_hash_iter() -> impl Iterator<Item = (Ustr, u64)> {
+    ustr_iter().map(|u| (u, u.precomputed_hash()))
+}
+
+/// Returns the number of bins the cache is split into, for [`iter_bin()`].
+///
+/// This is fixed when the crate is built; see [`bin_stats()`].
+pub const fn num_bins() -> usize {
+    NUM_BINS
+}
+
+/// Return an iterator over the `Ustr`s in one bin of the cache.
+///
+/// Every string is in exactly one of the [`num_bins()`] bins, and strings are
+/// spread evenly across them, so the bins can be shared out between threads
+/// to process the whole cache in parallel, e.g. to export it. Only `bin`'s
+/// lock is taken, once, when this is called, and strings added to the bin
+/// concurrently might not be included.
+///
+/// # Panics
+///
+/// Panics if `bin` isn't less than `num_bins()`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// for i in 0..100 {
+///     u(&format!("export {}", i));
+/// }
+///
+/// // Each thread takes every 4th bin.
+/// let num_threads = 4;
+/// let counts = std::thread::scope(|s| {
+///     let threads = (0..num_threads)
+///         .map(|t| {
+///             s.spawn(move || {
+///                 (t..ustr::num_bins())
+///                     .step_by(num_threads)
+///                     .flat_map(ustr::iter_bin)
+///                     .count()
+///             })
+///         })
+///         .collect::<Vec<_>>();
+///     threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>()
+/// });
+/// assert_eq!(counts.iter().sum::<usize>(), ustr::num_entries());
+/// ```
+pub fn iter_bin(bin: usize) -> UstrIter {
+    let mut allocs = Vec::new();
+    STRING_CACHE.0[bin].read().alloc_ranges(&mut allocs);
+    UstrIter(StringCacheIterator::new(allocs))
 }
 
 /// The type used for the global string cache.
@@ -806,20 +1861,18 @@ pub fn string_cache_iter() -> StringCacheIterator {
 /// This is exposed to allow e.g. serialization of the data returned by the
 /// [`cache()`] function.
 #[repr(transparent)]
-pub struct Bins(pub(crate) [Mutex<StringCache>; NUM_BINS]);
+pub struct Bins(pub(crate) [RwLock<StringCache>; NUM_BINS]);
 
 #[cfg(test)]
 lazy_static::lazy_static! {
-    static ref TEST_LOCK: Mutex<()> = Mutex::new(());
+    static ref TEST_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
 }
 
 #[cfg(test)]
 mod tests {
     use super::TEST_LOCK;
-    use lazy_static::lazy_static;
     use std::ffi::OsStr;
     use std::path::Path;
-    use std::sync::Mutex;
 
     #[test]
     fn it_works() {
@@ -1100,6 +2153,334 @@ mod tests {
         let s1 = ustr("hello world!");
         let s2 = existing_ustr("hello world!");
         assert_eq!(Some(s1), s2);
+        assert!(super::Ustr::is_interned("hello world!"));
+        assert!(!super::Ustr::is_interned("goodbye world!"));
+    }
+
+    #[test]
+    fn concurrent_interning_is_unique() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+
+        unsafe { super::_clear_cache() };
+
+        // Every thread races to intern the same strings, half of which are
+        // already in the cache, so both the shared-lock hit path and the
+        // exclusive-lock insert path get exercised.
+        let words: Vec<String> =
+            (0..1000).map(|i| format!("word{}", i)).collect();
+        for w in words.iter().step_by(2) {
+            u(w);
+        }
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let words = words.clone();
+                std::thread::spawn(move || {
+                    words.iter().map(|w| u(w)).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let results: Vec<_> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        for r in &results[1..] {
+            assert_eq!(r, &results[0]);
+        }
+        assert_eq!(super::num_entries(), words.len());
+    }
+
+    #[test]
+    fn bin_stats() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+
+        unsafe { super::_clear_cache() };
+        for i in 0..100 {
+            u(&format!("bin stats {}", i));
+        }
+
+        let stats = super::bin_stats();
+        assert_eq!(stats.len(), super::NUM_BINS);
+        assert_eq!(stats.iter().map(|b| b.num_entries).sum::<usize>(), 100);
+        assert!(stats
+            .iter()
+            .all(|b| b.table_capacity
+                == super::INITIAL_CAPACITY / super::NUM_BINS));
+    }
+
+    #[test]
+    // We have to disable miri here as it's far too slow unfortunately
+    #[cfg_attr(miri, ignore)]
+    fn compact() {
+        let _t = TEST_LOCK.lock();
+        use super::{growth::max_entries, ustr as u};
+
+        unsafe { super::_clear_cache() };
+        let slots = || {
+            super::bin_stats()
+                .iter()
+                .map(|b| b.table_capacity)
+                .sum::<usize>()
+        };
+        let keep = (0..1000)
+            .map(|i| u(&format!("keep {}", i)))
+            .collect::<Vec<_>>();
+        // Leave the tables much bigger than they need to be, as if the cache
+        // had been created with a big initial capacity.
+        for sc in super::STRING_CACHE.0.iter() {
+            unsafe {
+                sc.write().grow();
+                sc.write().grow();
+            }
+        }
+        let grown = slots();
+
+        let freed = super::compact();
+        let compacted = slots();
+        assert!(compacted < grown);
+        assert_eq!(
+            freed,
+            (grown - compacted) * std::mem::size_of::<*const u8>()
+        );
+        assert_eq!(super::compact(), 0);
+        for b in super::bin_stats() {
+            let slots = b.table_capacity;
+            assert!(b.num_entries < max_entries(slots));
+            assert!(slots == 1 || b.num_entries >= max_entries(slots / 2));
+        }
+
+        for (i, s) in keep.iter().enumerate() {
+            assert_eq!(*s, u(&format!("keep {}", i)));
+            assert_eq!(super::existing_ustr(s), Some(*s));
+        }
+        assert_eq!(super::num_entries(), keep.len());
+        unsafe { super::_clear_cache() };
+    }
+
+    #[test]
+    // We have to disable miri here as it's far too slow unfortunately
+    #[cfg_attr(miri, ignore)]
+    fn entries_since() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+
+        unsafe { super::_clear_cache() };
+        assert_eq!(super::current_generation(), 0);
+
+        let old = (0..1000)
+            .map(|i| u(&format!("old {}", i)))
+            .collect::<Vec<_>>();
+        let generation = super::current_generation();
+        assert_eq!(generation, 1000);
+        assert_eq!(super::entries_since(0).collect::<Vec<_>>(), old);
+
+        // Make sure we cross over into new allocators.
+        let new = (0..40_000)
+            .map(|i| u(&format!("new {:0>100}", i)))
+            .collect::<Vec<_>>();
+        for i in 0..1000 {
+            u(&format!("old {}", i));
+        }
+
+        assert!(super::total_capacity() > super::INITIAL_ALLOC);
+        assert_eq!(super::entries_since(generation).collect::<Vec<_>>(), new);
+        assert_eq!(
+            super::entries_since(super::current_generation()).count(),
+            0
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn ustr_iter() {
+        let _t = TEST_LOCK.lock();
+        use super::{ustr as u, UstrMap, UstrSet};
+
+        unsafe { super::_clear_cache() };
+        assert_eq!(super::ustr_iter().count(), 0);
+
+        // Enough to span several arenas in some bins.
+        let strings = (0..20_000)
+            .map(|i| u(&format!("iter {:0>200}", i)))
+            .collect::<UstrSet>();
+        let iterated = super::ustr_iter().collect::<Vec<_>>();
+        assert_eq!(iterated.len(), strings.len());
+        assert_eq!(iterated.iter().copied().collect::<UstrSet>(), strings);
+        assert!(super::string_cache_iter()
+            .zip(&iterated)
+            .all(|(s, u)| s.as_ptr() == u.as_char_ptr() as *const u8));
+
+        let hashes = super::ustr_hash_iter().collect::<UstrMap<_>>();
+        assert_eq!(hashes.len(), strings.len());
+        assert!(hashes.iter().all(|(u, h)| *h == super::hash_str(u)));
+
+        // Iterating every bin covers the cache, with each string in its bin.
+        let mut num_iterated = 0;
+        for bin in 0..super::num_bins() {
+            for u in super::iter_bin(bin) {
+                assert_eq!(super::whichbin(u.precomputed_hash()), bin);
+                assert!(strings.contains(&u));
+                num_iterated += 1;
+            }
+        }
+        assert_eq!(num_iterated, strings.len());
+        assert!(std::panic::catch_unwind(|| {
+            super::iter_bin(super::num_bins())
+        })
+        .is_err());
+
+        unsafe { super::_clear_cache() };
+    }
+
+    #[test]
+    fn as_bytes() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+
+        for s in ["", "a", "naïve", "interior\0nul"] {
+            let bytes = u(s).as_bytes_with_nul();
+            assert_eq!(u(s).as_bytes(), s.as_bytes());
+            assert_eq!(&bytes[..s.len()], s.as_bytes());
+            assert_eq!(bytes[s.len()], 0);
+            assert_eq!(bytes.as_ptr(), u(s).as_char_ptr() as *const u8);
+        }
+    }
+
+    #[test]
+    fn from_bytes() {
+        let _t = TEST_LOCK.lock();
+        use super::{ustr as u, Ustr};
+
+        unsafe { super::_clear_cache() };
+
+        let valid = "naïve 日本語".as_bytes();
+        assert_eq!(Ustr::try_from(valid), Ok(u("naïve 日本語")));
+        assert_eq!(Ustr::from_utf8_lossy(valid), u("naïve 日本語"));
+        assert_eq!(Ustr::try_from(&b""[..]), Ok(u("")));
+
+        // A truncated sequence and a lone continuation byte.
+        let invalid = b"na\xc3 \x80ve";
+        let err = Ustr::try_from(&invalid[..]).unwrap_err();
+        assert_eq!(err.valid_up_to(), 2);
+        assert_eq!(Ustr::from_utf8_lossy(invalid), u("na\u{fffd} \u{fffd}ve"));
+        assert_eq!(super::num_entries(), 3);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn try_from_char_ptr() {
+        let _t = TEST_LOCK.lock();
+        use super::{ustr as u, StringCacheEntry, Ustr};
+        use std::ffi::{c_char, CString};
+
+        unsafe { super::_clear_cache() };
+
+        // Enough strings to fill more than one arena in some bins.
+        let all = (0..5_000)
+            .map(|i| u(&format!("pointer {:0>i$}", i, i = i % 64)))
+            .collect::<Vec<_>>();
+        for s in &all {
+            assert_eq!(Ustr::try_from_char_ptr(s.as_char_ptr()), Some(*s));
+            // Pointers into the middle of a string, or to its header, are
+            // rejected however they're aligned.
+            for offset in 1..=s.len() + 1 {
+                let ptr = s.as_char_ptr().wrapping_add(offset);
+                assert_eq!(Ustr::try_from_char_ptr(ptr), None);
+            }
+            for offset in 1..=std::mem::size_of::<StringCacheEntry>() {
+                let ptr = s.as_char_ptr().wrapping_sub(offset);
+                assert_eq!(Ustr::try_from_char_ptr(ptr), None);
+            }
+        }
+
+        // A copy of a cached string isn't in the cache.
+        let copy = CString::new("pointer 0").unwrap();
+        assert_eq!(u("pointer 0"), copy.to_str().unwrap());
+        assert_eq!(Ustr::try_from_char_ptr(copy.as_ptr()), None);
+        assert_eq!(Ustr::try_from_char_ptr(std::ptr::null()), None);
+        assert_eq!(Ustr::try_from_char_ptr(8 as *const c_char), None);
+
+        // Strings stored outside the arenas are found too.
+        static STATIC: super::StaticUstr<6> = super::StaticUstr::new("static");
+        let stat = Ustr::intern_static(&STATIC);
+        assert_eq!(Ustr::try_from_char_ptr(stat.as_char_ptr()), Some(stat));
+    }
+
+    #[test]
+    fn owns_ptr() {
+        let _t = TEST_LOCK.lock();
+        use super::{
+            owning_bin, owns_ptr, ustr as u, whichbin, StaticUstr, Ustr,
+        };
+
+        unsafe { super::_clear_cache() };
+
+        let strings = (0..1000)
+            .map(|i| u(&format!("owned {}", i)))
+            .collect::<Vec<_>>();
+        for s in &strings {
+            let start = s.as_char_ptr() as *const u8;
+            let bin = whichbin(s.precomputed_hash());
+            assert_eq!(owning_bin(start), Some(bin));
+            assert_eq!(owning_bin(start.wrapping_add(s.len())), Some(bin));
+            assert_eq!(owning_bin(start.wrapping_sub(8)), Some(bin));
+        }
+
+        assert!(!owns_ptr(std::ptr::null()));
+        assert!(!owns_ptr(String::from("owned 1").as_ptr()));
+        assert!(!owns_ptr(&strings as *const _ as *const u8));
+
+        static STATIC: StaticUstr<5> = StaticUstr::new("owned");
+        let stat = Ustr::intern_static(&STATIC);
+        let start = stat.as_char_ptr() as *const u8;
+        assert_eq!(owning_bin(start), Some(whichbin(stat.precomputed_hash())));
+        assert!(!owns_ptr(start.wrapping_add(64)));
+    }
+
+    #[test]
+    fn slicing() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+
+        let s: &'static str = {
+            let fox = u("the quick brown fox");
+            fox.slice(4..9)
+        };
+        assert_eq!(s, "quick");
+
+        let fox = u("the quick brown fox");
+        assert_eq!(fox.slice(..), "the quick brown fox");
+        assert_eq!(fox.get(16..), Some("fox"));
+        assert_eq!(fox.get(16..100), None);
+        assert!(std::panic::catch_unwind(|| fox.slice(16..100)).is_err());
+
+        let odys = u("Τη γλώσσα");
+        assert_eq!(odys.get(0..1), None);
+        assert!(std::panic::catch_unwind(|| odys.slice(0..1)).is_err());
+    }
+
+    #[test]
+    fn char_ptrs() {
+        let _t = TEST_LOCK.lock();
+        use super::ustr as u;
+        use std::ffi::CStr;
+
+        let ptrs = {
+            let names = [u("VK_LAYER_KHRONOS_validation"), u("")];
+            super::char_ptrs(&names)
+        };
+        let names = ptrs
+            .iter()
+            .map(|p| unsafe { CStr::from_ptr(*p) }.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["VK_LAYER_KHRONOS_validation", ""]);
+
+        let u_empty = u("");
+        assert_eq!(
+            u_empty.as_ptr_nonnull().as_ptr() as *const _,
+            u_empty.as_char_ptr()
+        );
     }
 
     #[test]
@@ -1139,8 +2520,36 @@ mod tests {
     }
 }
 
-lazy_static::lazy_static! {
-    static ref STRING_CACHE: Bins = {
+primitives::lazy_static! {
+    static ref LOCAL_CACHE: Bins = {
+        let bins = Bins::new();
+        init::mark_initialized();
+        #[cfg(feature = "prewarm")]
+        prewarm::prewarm_new_cache(&bins);
+        bins
+    };
+}
+
+// The cache this copy of the crate uses. That's `LOCAL_CACHE`, unless it has
+// been attached to another copy's cache with `set_external_cache()`.
+struct StringCacheRef;
+
+static STRING_CACHE: StringCacheRef = StringCacheRef;
+
+impl Deref for StringCacheRef {
+    type Target = Bins;
+
+    #[inline]
+    fn deref(&self) -> &Bins {
+        match shared::external() {
+            Some(cache) => cache.bins(),
+            None => &LOCAL_CACHE,
+        }
+    }
+}
+
+impl Bins {
+    pub(crate) fn new() -> Bins {
         use std::mem::{self, MaybeUninit};
         // This deeply unsafe feeling dance allows us to initialize an array of
         // arbitrary size and will have to tide us over until const generics
@@ -1150,9 +2559,8 @@ lazy_static::lazy_static! {
         // Create an uninitialized array of `MaybeUninit`. The `assume_init` is
         // safe because the type we are claiming to have initialized here is a
         // bunch of `MaybeUninit`s, which do not require initialization.
-        let mut bins: [MaybeUninit<Mutex<StringCache>>; NUM_BINS] = unsafe {
-            MaybeUninit::uninit().assume_init()
-        };
+        let mut bins: [MaybeUninit<RwLock<StringCache>>; NUM_BINS] =
+            unsafe { MaybeUninit::uninit().assume_init() };
 
         // Dropping a `MaybeUninit` does nothing. Thus using raw pointer
         // assignment instead of `ptr::write` does not cause the old
@@ -1160,13 +2568,85 @@ lazy_static::lazy_static! {
         // this loop, we have a memory leak, but there is no memory safety
         // issue.
         for bin in &mut bins[..] {
-            *bin = MaybeUninit::new(Mutex::new(StringCache::default()));
+            *bin = MaybeUninit::new(RwLock::new(StringCache::default()));
         }
 
         // Everything is initialized. Transmute the array to the
         // initialized type.
         unsafe { mem::transmute::<_, Bins>(bins) }
+    }
+}
+
+// The form of `string` that's actually stored in the cache: normalized if
+// normalization is enabled and limited to the maximum length.
+pub(crate) fn canonical_str(string: &str) -> Result<Cow<'_, str>, InternError> {
+    #[cfg(feature = "unicode-normalization")]
+    if let Cow::Owned(normalized) = normalization::normalize(string) {
+        return limit_len(&normalized).map(|s| Cow::Owned(s.into_owned()));
+    }
+    limit_len(string)
+}
+
+// Compute the hash used to identify a string in the cache.
+#[inline]
+pub(crate) fn hash_str(string: &str) -> u64 {
+    let mut hasher = match hash_seed() {
+        Some(seeded) => seeded.build_hasher(),
+        None => ahash::AHasher::default(),
     };
+    hasher.write(string.as_bytes());
+    hasher.finish()
+}
+
+// Take the shared lock on a bin, counting it if we had to wait.
+#[inline]
+fn read_bin(bin: &RwLock<StringCache>) -> RwLockReadGuard<'_, StringCache> {
+    match bin.try_read() {
+        Some(sc) => sc,
+        None => {
+            let sc = bin.read();
+            sc.contended_reads.fetch_add(1, AtomicOrdering::Relaxed);
+            sc
+        }
+    }
+}
+
+// Take the exclusive lock on a bin, counting it if we had to wait.
+#[inline]
+fn write_bin(bin: &RwLock<StringCache>) -> RwLockWriteGuard<'_, StringCache> {
+    match bin.try_write() {
+        Some(sc) => sc,
+        None => {
+            let sc = bin.write();
+            sc.contended_writes.fetch_add(1, AtomicOrdering::Relaxed);
+            sc
+        }
+    }
+}
+
+// Take the exclusive lock on bin `index` of the cache in use, to insert into
+// it. `set_external_cache()` can switch to another cache while we wait for
+// the lock on one of our own bins, in which case we try again in the new one.
+fn write_current_bin(index: usize) -> RwLockWriteGuard<'static, StringCache> {
+    loop {
+        let bins: &'static Bins = &STRING_CACHE;
+        let sc = write_bin(&bins.0[index]);
+        if ptr::eq(bins, &*STRING_CACHE) {
+            return sc;
+        }
+    }
+}
+
+// Take the exclusive lock on every bin of the cache in use, in order, as
+// `write_current_bin()` does for one.
+fn write_current_bins() -> Vec<RwLockWriteGuard<'static, StringCache>> {
+    loop {
+        let bins: &'static Bins = &STRING_CACHE;
+        let locked = bins.0.iter().map(|b| b.write()).collect::<Vec<_>>();
+        if ptr::eq(bins, &*STRING_CACHE) {
+            return locked;
+        }
+    }
 }
 
 // Use the top bits of the hash to choose a bin
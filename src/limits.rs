@@ -0,0 +1,137 @@
+use super::InternError;
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// What to do with strings longer than the limit set with [`set_max_len()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxLenPolicy {
+    /// Refuse to add the string to the cache.
+    /// [`Ustr::try_intern`](crate::Ustr::try_intern) returns an error and
+    /// [`Ustr::from`](crate::Ustr::from) panics.
+    Reject,
+    /// Truncate the string and append `…#` followed by the string's hash in
+    /// hex, so that different long strings with the same prefix still end up
+    /// as different `Ustr`s.
+    Truncate,
+}
+
+/// The number of bytes appended to a truncated string: `…#` (4 bytes in UTF-8)
+/// followed by a 16-digit hex hash.
+pub const TRUNCATION_SUFFIX_LEN: usize = 20;
+
+static MAX_LEN: AtomicUsize = AtomicUsize::new(usize::MAX);
+static TRUNCATE: AtomicBool = AtomicBool::new(false);
+
+/// Limit the length of strings that can be added to the cache.
+///
+/// Since strings are never freed, a malicious or buggy input source could
+/// otherwise permanently pin huge amounts of memory. Strings longer than
+/// `max_len` bytes are handled according to `policy`. The limit only affects
+/// strings added after this call, and can be removed again by setting it to
+/// `usize::MAX`.
+///
+/// # Panics
+///
+/// Panics if `policy` is [`MaxLenPolicy::Truncate`] and `max_len` is shorter
+/// than [`TRUNCATION_SUFFIX_LEN`].
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{MaxLenPolicy, Ustr};
+///
+/// ustr::set_max_len(32, MaxLenPolicy::Reject);
+/// assert!(Ustr::try_intern(&"a".repeat(64)).is_err());
+/// # ustr::set_max_len(usize::MAX, MaxLenPolicy::Reject);
+/// ```
+pub fn set_max_len(max_len: usize, policy: MaxLenPolicy) {
+    let truncate = policy == MaxLenPolicy::Truncate;
+    assert!(
+        !truncate || max_len >= TRUNCATION_SUFFIX_LEN,
+        "max_len must be at least {} bytes to truncate",
+        TRUNCATION_SUFFIX_LEN
+    );
+    TRUNCATE.store(truncate, Ordering::Relaxed);
+    MAX_LEN.store(max_len, Ordering::Relaxed);
+}
+
+/// Returns the current maximum length and policy set with [`set_max_len()`].
+pub fn max_len() -> (usize, MaxLenPolicy) {
+    let policy = if TRUNCATE.load(Ordering::Relaxed) {
+        MaxLenPolicy::Truncate
+    } else {
+        MaxLenPolicy::Reject
+    };
+    (MAX_LEN.load(Ordering::Relaxed), policy)
+}
+
+// Check `s` against the length limit, truncating it if that's the policy.
+#[inline]
+pub(crate) fn limit_len(s: &str) -> Result<Cow<'_, str>, InternError> {
+    let max_len = MAX_LEN.load(Ordering::Relaxed);
+    if s.len() <= max_len {
+        return Ok(Cow::Borrowed(s));
+    }
+    if !TRUNCATE.load(Ordering::Relaxed) {
+        return Err(InternError::TooLong {
+            len: s.len(),
+            max_len,
+        });
+    }
+    Ok(Cow::Owned(truncate(s, max_len)))
+}
+
+fn truncate(s: &str, max_len: usize) -> String {
+    let mut end = max_len.saturating_sub(TRUNCATION_SUFFIX_LEN);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut truncated = String::with_capacity(end + TRUNCATION_SUFFIX_LEN);
+    truncated.push_str(&s[..end]);
+    write!(truncated, "…#{:016x}", super::hash_str(s)).unwrap();
+    debug_assert!(truncated.len() <= max_len);
+    truncated
+}
+
+#[test]
+fn test_max_len() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{existing_ustr, ustr as u, Ustr};
+
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            set_max_len(usize::MAX, MaxLenPolicy::Reject);
+        }
+    }
+    let _reset = Reset;
+
+    let long = format!("{}ü{}", "a".repeat(30), "b".repeat(30));
+
+    set_max_len(32, MaxLenPolicy::Reject);
+    assert_eq!(
+        Ustr::try_intern(&long),
+        Err(InternError::TooLong {
+            len: long.len(),
+            max_len: 32
+        })
+    );
+    assert!(existing_ustr(&long).is_none());
+    assert_eq!(Ustr::try_intern("short").unwrap(), "short");
+    assert!(std::panic::catch_unwind(|| u(&long)).is_err());
+
+    // The truncation would land in the middle of the 'ü' so the prefix should
+    // be backed up to the char boundary.
+    set_max_len(51, MaxLenPolicy::Truncate);
+    let truncated = u(&long);
+    assert_eq!(truncated.len(), 50);
+    assert!(truncated.starts_with(&format!("{}…#", "a".repeat(30))));
+    assert_eq!(existing_ustr(&long), Some(truncated));
+
+    // Different strings with the same prefix stay different.
+    let other = format!("{}ü{}", "a".repeat(30), "c".repeat(30));
+    assert_ne!(u(&other), truncated);
+}
@@ -0,0 +1,228 @@
+#[cfg(feature = "debug-alloc-tracking")]
+use super::tracking;
+use super::{
+    canonical_str, frozen, hash_str, whichbin, write_current_bin, InternError,
+    Ustr, NUM_BINS,
+};
+use std::{
+    io::{self, BufRead},
+    ptr::NonNull,
+};
+
+// Lines are interned in chunks of at least this many bytes, so that each
+// bin's lock is taken once per chunk rather than once per line.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Intern every line read from `reader`, returning their `Ustr`s in order.
+///
+/// This gives the same result as interning each of `reader.lines()`, without
+/// the overhead of doing it a line at a time: the input is read into a single
+/// buffer that's reused (or used straight from `reader`'s buffer if that
+/// holds whole chunks), its UTF-8 is checked once for each chunk of lines
+/// rather than for each line, and the lines of each chunk are added to the
+/// cache a bin at a time, taking each bin's lock once per chunk rather than
+/// once or twice per line.
+///
+/// As with [`BufRead::lines()`], lines end with `\n` or `\r\n`, which aren't
+/// included, and the last line doesn't need a line ending.
+///
+/// Returns an error of kind [`InvalidData`](io::ErrorKind::InvalidData) if
+/// the input isn't UTF-8, or of kind
+/// [`InvalidInput`](io::ErrorKind::InvalidInput) if a line can't be interned,
+/// e.g. because it's longer than [`max_len()`](crate::max_len). Lines from
+/// earlier chunks will have been interned by then.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let words = ustr::intern_lines(&b"alpha\nbeta\r\n\ngamma"[..])?;
+/// assert_eq!(words, [u("alpha"), u("beta"), u(""), u("gamma")]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+pub fn intern_lines<R: BufRead>(mut reader: R) -> io::Result<Vec<Ustr>> {
+    let mut ustrs = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            if !buf.is_empty() {
+                intern_chunk(&buf, &mut ustrs)?;
+            }
+            return Ok(ustrs);
+        }
+        // Lines can be interned straight from the reader's own buffer if
+        // it's big enough, e.g. when reading from a slice.
+        if buf.is_empty() && available.len() >= CHUNK_SIZE {
+            if let Some(end) = available.iter().rposition(|b| *b == b'\n') {
+                intern_chunk(&available[..=end], &mut ustrs)?;
+                reader.consume(end + 1);
+                continue;
+            }
+        }
+        buf.extend_from_slice(available);
+        let len = available.len();
+        reader.consume(len);
+
+        if buf.len() >= CHUNK_SIZE {
+            if let Some(end) = buf.iter().rposition(|b| *b == b'\n') {
+                intern_chunk(&buf[..=end], &mut ustrs)?;
+                buf.drain(..=end);
+            }
+        }
+    }
+}
+
+// Intern the lines of `chunk`, which ends at the end of a line, adding their
+// `Ustr`s to `ustrs`.
+#[cfg_attr(feature = "debug-alloc-tracking", track_caller)]
+fn intern_chunk(chunk: &[u8], ustrs: &mut Vec<Ustr>) -> io::Result<()> {
+    let chunk = std::str::from_utf8(chunk).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })?;
+    let chunk = chunk.strip_suffix('\n').unwrap_or(chunk);
+    let lines = chunk
+        .split('\n')
+        .map(|line| canonical_str(line.strip_suffix('\r').unwrap_or(line)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_input)?;
+
+    // Once frozen, most lines are likely to be in the snapshot, and the rest
+    // have to be checked against the freeze policy one at a time anyway.
+    if frozen().is_some() {
+        for line in &lines {
+            ustrs.push(
+                Ustr::intern_canonical(line, None).map_err(invalid_input)?,
+            );
+        }
+        return Ok(());
+    }
+
+    // Group the lines by bin with a counting sort, remembering where each one
+    // goes.
+    let hashes = lines.iter().map(|line| hash_str(line)).collect::<Vec<_>>();
+    let mut starts = [0; NUM_BINS + 1];
+    for hash in &hashes {
+        starts[whichbin(*hash) + 1] += 1;
+    }
+    for bin in 0..NUM_BINS {
+        starts[bin + 1] += starts[bin];
+    }
+    let mut order = vec![(0, 0); lines.len()];
+    let mut next = starts;
+    for (i, hash) in hashes.into_iter().enumerate() {
+        let bin = whichbin(hash);
+        order[next[bin]] = (hash, i);
+        next[bin] += 1;
+    }
+
+    let mut interned = vec![None; lines.len()];
+    for (bin, range) in starts.windows(2).enumerate() {
+        let group = &order[range[0]..range[1]];
+        if group.is_empty() {
+            continue;
+        }
+        let mut sc = write_current_bin(bin);
+        // The cache might have been frozen while we were waiting for the
+        // lock, in which case the policy has to be checked for each line.
+        if frozen().is_some() {
+            drop(sc);
+            for (_, i) in group {
+                interned[*i] = Some(
+                    Ustr::intern_canonical(&lines[*i], None)
+                        .map_err(invalid_input)?,
+                );
+            }
+            continue;
+        }
+        #[cfg(feature = "debug-alloc-tracking")]
+        let allocated = sc.total_allocated();
+        for (hash, i) in group {
+            // Most lines of a typical word list are repeats, which the
+            // lookup finds faster than inserting does.
+            let char_ptr = match sc.get_existing(&lines[*i], *hash) {
+                Some(char_ptr) => char_ptr,
+                None => sc.try_insert(&lines[*i], *hash).map_err(|oom| {
+                    invalid_input(InternError::OutOfMemory(oom))
+                })?,
+            };
+            // SAFETY: `try_insert()` never returns a null pointer.
+            interned[*i] = Some(Ustr {
+                char_ptr: unsafe { NonNull::new_unchecked(char_ptr as *mut _) },
+            });
+        }
+        #[cfg(feature = "debug-alloc-tracking")]
+        {
+            let bytes = sc.total_allocated() - allocated;
+            drop(sc);
+            if bytes != 0 {
+                tracking::record(std::panic::Location::caller(), bytes);
+            }
+        }
+    }
+    // Every line was in one of the groups.
+    ustrs.extend(interned.into_iter().map(Option::unwrap));
+    Ok(())
+}
+
+fn invalid_input(e: InternError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+#[test]
+fn test_intern_lines() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{
+        existing_ustr, set_max_len, ustr as u, MaxLenPolicy, Ustr, UstrSet,
+    };
+
+    unsafe { super::_clear_cache() };
+
+    // More than a chunk, with repeated lines and assorted line endings.
+    let mut input = String::new();
+    let mut expected = Vec::new();
+    for i in 0..100_000 {
+        let line = format!("line {:0>20}", i % 60_000);
+        input.push_str(&line);
+        input.push_str(if i % 3 == 0 { "\r\n" } else { "\n" });
+        expected.push(line);
+    }
+    input.push_str("\n\nlast");
+    expected.extend(["".to_owned(), "".to_owned(), "last".to_owned()]);
+    assert!(input.len() > 2 * CHUNK_SIZE);
+
+    // A small buffer means chunks end partway through the reader's buffer.
+    let reader = io::BufReader::with_capacity(1000, input.as_bytes());
+    let ustrs = intern_lines(reader).unwrap();
+    assert_eq!(ustrs.len(), expected.len());
+    for (u, line) in ustrs.iter().zip(&expected) {
+        assert_eq!(u.as_str(), line);
+        assert_eq!(existing_ustr(line), Some(*u));
+    }
+    assert_eq!(super::num_entries(), 60_002);
+    assert_eq!(
+        ustrs.iter().copied().collect::<UstrSet>().len(),
+        super::ustr_iter().count()
+    );
+    assert_eq!(intern_lines(&b""[..]).unwrap(), Vec::<Ustr>::new());
+    assert_eq!(intern_lines(&b"a\n"[..]).unwrap(), [u("a")]);
+
+    // Bad input is rejected.
+    let error = intern_lines(&b"ok\nbad \xff\n"[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    set_max_len(4, MaxLenPolicy::Reject);
+    let error = intern_lines(&b"ok\ntoo long\n"[..]).unwrap_err();
+    set_max_len(usize::MAX, MaxLenPolicy::Reject);
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    unsafe { super::_clear_cache() };
+}
@@ -0,0 +1,1103 @@
+//! Pre-built dictionaries of strings that are used in place.
+//!
+//! Interning a large, fixed vocabulary at startup means hashing and copying
+//! every string. Instead, [`build_dictionary()`] can lay the strings out ahead
+//! of time in exactly the form the cache stores them in, headers and all, and
+//! [`load_dictionary()`] then maps that file into memory and adds its entries
+//! to the cache without hashing or copying anything. Strings that aren't in the
+//! dictionary are added to the cache's own storage as usual.
+//!
+//! The file format depends on the target (pointer width and alignment), so a
+//! dictionary must be written for the same target that loads it. It also
+//! records which hasher computed the hashes stored in it, and how that was
+//! seeded. If the build that loads it hashes differently, e.g. because `ahash`
+//! picked different keys, every entry is rehashed as it's loaded rather than
+//! trusting the stored hashes.
+//!
+//! # Format
+//!
+//! All integers are native-endian. A 64-byte header holds the 8 bytes
+//! `USTRDICT`, a `u32` version, the `u32` size and alignment of an entry
+//! header, a `u32` check value of the secondary hash function, then the `u64`
+//! number of entries, the `u64` number of bytes of entries, a `u64` check
+//! value of the hasher, a `u64` checksum of the entries, and the `u32`
+//! identity and seed mode of the hasher. It's followed by the entries
+//! themselves, laid out as described in the cache's source. Version 1 files,
+//! which have no checksum, can still be loaded, but not with [`trusted`].
+//! Files before version 3 don't identify the hasher, so they're only rehashed
+//! if its check value differs, and their checksum depends on the hasher.
+use super::{
+    frozen, hash32_fn, hash_str, whichbin, write_current_bins, Bins,
+    FreezePolicy, StringCacheEntry,
+};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+const MAGIC: &[u8; 8] = b"USTRDICT";
+const VERSION: u32 = 3;
+const HEADER_SIZE: usize = 64;
+// Hashed when writing and loading to check that both use the same hashers.
+const CHECK_STRING: &str = "ustr dictionary hash check";
+// Identifies the hasher that computed the stored hashes: ahash 0.8's
+// `AHasher`.
+const HASHER_AHASH: u32 = 1;
+// The hasher was seeded with ahash's default keys, which can vary between
+// builds depending on ahash's features and the target.
+const SEED_DEFAULT: u32 = 1;
+// The hasher was seeded with `set_hash_seed()`.
+const SEED_EXPLICIT: u32 = 2;
+
+/// What happened when loading a dictionary, as returned by
+/// [`load_dictionary()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryLoad {
+    /// Number of strings in the dictionary.
+    pub num_entries: usize,
+    /// Number of strings that were added to the cache in place. The rest were
+    /// already in the cache.
+    pub num_inserted: usize,
+}
+
+// The header fields, in order.
+struct Header {
+    version: u32,
+    entry_size: u32,
+    entry_align: u32,
+    hash32_check: u32,
+    num_entries: u64,
+    data_len: u64,
+    hash_check: u64,
+    // Zero in version 1 files.
+    checksum: u64,
+    // Zero before version 3.
+    hasher: u32,
+    seed_mode: u32,
+}
+
+impl Header {
+    fn current(num_entries: u64, data_len: u64) -> Header {
+        Header {
+            version: VERSION,
+            entry_size: std::mem::size_of::<StringCacheEntry>() as u32,
+            entry_align: std::mem::align_of::<StringCacheEntry>() as u32,
+            hash32_check: hash32_fn()(CHECK_STRING),
+            num_entries,
+            data_len,
+            hash_check: hash_str(CHECK_STRING),
+            checksum: 0,
+            hasher: HASHER_AHASH,
+            seed_mode: match super::hash_seed() {
+                Some(_) => SEED_EXPLICIT,
+                None => SEED_DEFAULT,
+            },
+        }
+    }
+
+    // Whether the hashes stored in the dictionary were computed differently
+    // from how this build computes them, so must be recomputed.
+    fn needs_rehash(&self) -> bool {
+        let current = Header::current(0, 0);
+        self.hash_check != current.hash_check
+            || (self.version >= 3
+                && (self.hasher, self.seed_mode)
+                    != (current.hasher, current.seed_mode))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_ne_bytes());
+        bytes[12..16].copy_from_slice(&self.entry_size.to_ne_bytes());
+        bytes[16..20].copy_from_slice(&self.entry_align.to_ne_bytes());
+        bytes[20..24].copy_from_slice(&self.hash32_check.to_ne_bytes());
+        bytes[24..32].copy_from_slice(&self.num_entries.to_ne_bytes());
+        bytes[32..40].copy_from_slice(&self.data_len.to_ne_bytes());
+        bytes[40..48].copy_from_slice(&self.hash_check.to_ne_bytes());
+        bytes[48..56].copy_from_slice(&self.checksum.to_ne_bytes());
+        bytes[56..60].copy_from_slice(&self.hasher.to_ne_bytes());
+        bytes[60..64].copy_from_slice(&self.seed_mode.to_ne_bytes());
+        writer.write_all(&bytes)
+    }
+
+    fn read(bytes: &[u8]) -> io::Result<Header> {
+        if bytes.len() < HEADER_SIZE || &bytes[..8] != MAGIC {
+            return Err(invalid_data("not a ustr dictionary"));
+        }
+        let u32_at =
+            |i: usize| u32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at =
+            |i: usize| u64::from_ne_bytes(bytes[i..i + 8].try_into().unwrap());
+        Ok(Header {
+            version: u32_at(8),
+            entry_size: u32_at(12),
+            entry_align: u32_at(16),
+            hash32_check: u32_at(20),
+            num_entries: u64_at(24),
+            data_len: u64_at(32),
+            hash_check: u64_at(40),
+            checksum: u64_at(48),
+            hasher: u32_at(56),
+            seed_mode: u32_at(60),
+        })
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// The checksum of the entries of a dictionary with the given version.
+//
+// From version 3 this is FNV-1a over 64-bit words, which doesn't depend on
+// the hasher, so that a dictionary that's going to be rehashed can still be
+// checked.
+fn checksum(version: u32, data: &[u8]) -> u64 {
+    if version < 3 {
+        use std::hash::Hasher;
+        let mut hasher = ahash::AHasher::default();
+        hasher.write(data);
+        return hasher.finish();
+    }
+    let step = |hash: u64, word: u64| (hash ^ word).wrapping_mul(0x100000001b3);
+    let mut words = data.chunks_exact(8);
+    let hash = words.by_ref().fold(0xcbf29ce484222325, |hash, word| {
+        step(hash, u64::from_ne_bytes(word.try_into().unwrap()))
+    });
+    words
+        .remainder()
+        .iter()
+        .fold(hash, |hash, b| step(hash, *b as u64))
+}
+
+// Check the checksum of a dictionary whose header has been read.
+fn check_checksum(header: &Header, bytes: &[u8]) -> io::Result<()> {
+    if header.version < 2 {
+        return Err(invalid_data("ustr dictionary has no checksum"));
+    }
+    if header.checksum != checksum(header.version, &bytes[HEADER_SIZE..]) {
+        return Err(invalid_data("ustr dictionary has the wrong checksum"));
+    }
+    Ok(())
+}
+
+/// Write a dictionary of `strings` for [`load_dictionary()`] to `writer`.
+///
+/// Duplicates are written only once. Strings are written as given, so they
+/// should already be normalized if [`set_normalization()`] is in use, and
+/// shorter than any limit set with [`set_max_len()`]; any that aren't are
+/// copied into the cache's storage when loading rather than used in place.
+/// [`build_dictionary()`] takes care of that, and checks the result.
+///
+/// Returns the number of unique strings written.
+///
+/// [`set_normalization()`]: crate::set_normalization
+/// [`set_max_len()`]: crate::set_max_len
+///
+/// # Examples
+///
+/// ```
+/// let mut file = Vec::new();
+/// let n = ustr::write_dictionary(["alpha", "beta", "alpha"], &mut file)?;
+/// assert_eq!(n, 2);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write_dictionary<I, S, W>(strings: I, mut writer: W) -> io::Result<usize>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+    W: Write,
+{
+    let mut seen = std::collections::HashSet::new();
+    let mut unique = Vec::new();
+    for s in strings {
+        if seen.insert(s.as_ref().to_owned()) {
+            unique.push(s);
+        }
+    }
+    writer.write_all(&encode(unique.iter().map(|s| s.as_ref()))?)?;
+    Ok(unique.len())
+}
+
+/// Statistics about a dictionary, as returned by [`build_dictionary()`] and
+/// [`verify_dictionary()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryStats {
+    /// Number of strings the dictionary was built from, including duplicates.
+    /// For a verified dictionary this is the same as `num_entries`.
+    pub num_strings: usize,
+    /// Number of unique strings in the dictionary.
+    pub num_entries: usize,
+    /// Number of strings that were changed by normalization or the length
+    /// limit when building. For a verified dictionary, the number of entries
+    /// that would be changed, and so copied rather than used in place, if the
+    /// dictionary were loaded now.
+    pub num_changed: usize,
+    /// Total length of the strings, in bytes.
+    pub string_bytes: usize,
+    /// Size of the dictionary file, in bytes.
+    pub file_size: usize,
+    /// The most entries that will be added to any one bin of the cache.
+    pub max_bin_entries: usize,
+}
+
+/// Build a dictionary of `strings` for [`load_dictionary()`], write it to
+/// `writer` and return statistics about it.
+///
+/// This is meant to be run ahead of time, e.g. as part of an asset build, by
+/// the same build of `ustr` that will load the dictionary, with the same
+/// [`set_normalization()`] and [`set_max_len()`] settings. Each string is
+/// stored as it will be interned, duplicates (after normalization) are dropped
+/// and the entries are grouped by the bin of the cache they'll be added to, in
+/// the order they'll be added, so that loading fills each bin from one
+/// contiguous part of the file. The result is checked with
+/// [`verify_dictionary()`] before it's written.
+///
+/// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if
+/// any string is rejected by the length limit.
+///
+/// [`set_normalization()`]: crate::set_normalization
+/// [`set_max_len()`]: crate::set_max_len
+///
+/// # Examples
+///
+/// ```
+/// let words = ["alpha", "beta", "gamma", "beta"];
+/// let mut file = Vec::new();
+/// let stats = ustr::build_dictionary(words, &mut file)?;
+/// assert_eq!(stats.num_strings, 4);
+/// assert_eq!(stats.num_entries, 3);
+/// assert_eq!(stats.string_bytes, 14);
+/// assert_eq!(stats.file_size, file.len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn build_dictionary<I, S, W>(
+    strings: I,
+    mut writer: W,
+) -> io::Result<DictionaryStats>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+    W: Write,
+{
+    let mut seen = std::collections::HashSet::new();
+    let mut unique = Vec::new();
+    let mut num_strings = 0;
+    let mut num_changed = 0;
+    for s in strings {
+        num_strings += 1;
+        let canonical = super::canonical_str(s.as_ref())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if matches!(canonical, Cow::Owned(_)) {
+            num_changed += 1;
+        }
+        let canonical = canonical.into_owned();
+        if seen.insert(canonical.clone()) {
+            unique.push(canonical);
+        }
+    }
+    // A stable sort keeps the strings in each bin in the order given.
+    unique.sort_by_key(|s| whichbin(hash_str(s)));
+
+    let bytes = encode(unique.iter().map(String::as_str))?;
+    let stats = verify_dictionary(&bytes)?;
+    debug_assert_eq!(stats.num_changed, 0);
+    writer.write_all(&bytes)?;
+    Ok(DictionaryStats {
+        num_strings,
+        num_changed,
+        ..stats
+    })
+}
+
+/// Check that `bytes` holds a valid dictionary for [`load_dictionary()`] and
+/// return statistics about it.
+///
+/// As well as the checks made when loading, this checks the checksum, that
+/// every hash stored in the dictionary is correct (unless it was written with
+/// a different hasher, in which case they'd be recomputed when loading) and
+/// that every string is unique, so it's slower than loading. `bytes` doesn't
+/// need to be aligned.
+///
+/// # Examples
+///
+/// ```
+/// let mut file = Vec::new();
+/// ustr::write_dictionary(["alpha", "beta"], &mut file)?;
+/// assert_eq!(ustr::verify_dictionary(&file)?.num_entries, 2);
+///
+/// file[70] ^= 1;
+/// assert!(ustr::verify_dictionary(&file).is_err());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn verify_dictionary(bytes: &[u8]) -> io::Result<DictionaryStats> {
+    let (header, offsets) = check(bytes, true)?;
+    if header.version >= 2 {
+        check_checksum(&header, bytes)?;
+    }
+    let check_hash = !header.needs_rehash();
+    let check_hash32 = header.hash32_check == hash32_fn()(CHECK_STRING);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut bin_entries = [0; super::NUM_BINS];
+    let mut num_changed = 0;
+    let mut string_bytes = 0;
+    for (index, offset) in offsets.into_iter().enumerate() {
+        // SAFETY: `check()` made sure there's an entry at `offset`.
+        let (entry, string) = unsafe { read_entry(bytes, offset) };
+        let bad = |what: &str| {
+            invalid_data(format!(
+                "ustr dictionary entry {} has the wrong {}",
+                index, what
+            ))
+        };
+        let hash = hash_str(string);
+        if check_hash && entry.hash != hash {
+            return Err(bad("hash"));
+        }
+        if check_hash32 && entry.hash32 != hash32_fn()(string) {
+            return Err(bad("secondary hash"));
+        }
+        if entry.generation as usize != index {
+            return Err(bad("generation"));
+        }
+        if !seen.insert(string) {
+            return Err(invalid_data(format!(
+                "ustr dictionary entry {} is a duplicate",
+                index
+            )));
+        }
+        if !matches!(super::canonical_str(string), Ok(Cow::Borrowed(_))) {
+            num_changed += 1;
+        }
+        bin_entries[whichbin(hash)] += 1;
+        string_bytes += string.len();
+    }
+
+    Ok(DictionaryStats {
+        num_strings: seen.len(),
+        num_entries: seen.len(),
+        num_changed,
+        string_bytes,
+        file_size: bytes.len(),
+        max_bin_entries: bin_entries.into_iter().max().unwrap_or(0),
+    })
+}
+
+// Lay out `strings`, which must be unique, as a dictionary file.
+fn encode<'a, I>(strings: I) -> io::Result<Vec<u8>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut data = vec![0; HEADER_SIZE];
+    let mut num_entries = 0u32;
+    for s in strings {
+        // Number entries in order, which is what they'll get if the
+        // dictionary is loaded into an empty cache.
+        let entry = StringCacheEntry {
+            hash: hash_str(s),
+            generation: num_entries,
+            hash32: hash32_fn()(s),
+            len: s.len(),
+        };
+        num_entries = num_entries
+            .checked_add(1)
+            .ok_or_else(|| invalid_data("too many strings"))?;
+        let start = data.len();
+        data.resize(start + StringCacheEntry::size_for_len(s.len()), 0);
+        // SAFETY: we've just made room for the entry, and the header is
+        // written unaligned since `data` might not be.
+        unsafe {
+            std::ptr::write_unaligned(
+                data.as_mut_ptr().add(start) as *mut StringCacheEntry,
+                entry,
+            );
+        }
+        let chars = start + std::mem::size_of::<StringCacheEntry>();
+        data[chars..chars + s.len()].copy_from_slice(s.as_bytes());
+    }
+
+    let data_len = (data.len() - HEADER_SIZE) as u64;
+    let header = Header {
+        checksum: checksum(VERSION, &data[HEADER_SIZE..]),
+        ..Header::current(num_entries as u64, data_len)
+    };
+    header.write(&mut &mut data[..HEADER_SIZE])?;
+    Ok(data)
+}
+
+// Read the entry at `offset` in `bytes`, which may not be aligned.
+//
+// This is safe as long as `offset` was returned by `check()` for `bytes`.
+unsafe fn read_entry(bytes: &[u8], offset: usize) -> (StringCacheEntry, &str) {
+    let entry = std::ptr::read_unaligned(
+        bytes.as_ptr().add(offset) as *const StringCacheEntry
+    );
+    let start = offset + std::mem::size_of::<StringCacheEntry>();
+    let string =
+        std::str::from_utf8_unchecked(&bytes[start..start + entry.len]);
+    (entry, string)
+}
+
+// Check the header and structure of a dictionary and return the header and
+// the offset of every entry. The strings are checked to be UTF-8 if
+// `check_utf8` is set, but the checksum and hashes aren't checked.
+fn check(bytes: &[u8], check_utf8: bool) -> io::Result<(Header, Vec<usize>)> {
+    let header = Header::read(bytes)?;
+    if header.version == 0 || header.version > VERSION {
+        return Err(invalid_data(format!(
+            "unsupported ustr dictionary version {}",
+            header.version
+        )));
+    }
+    let current = Header::current(0, 0);
+    if header.entry_size != current.entry_size
+        || header.entry_align != current.entry_align
+    {
+        return Err(invalid_data("ustr dictionary is for a different target"));
+    }
+    if header.data_len != (bytes.len() - HEADER_SIZE) as u64 {
+        return Err(invalid_data("ustr dictionary has the wrong length"));
+    }
+
+    let mut offsets = Vec::new();
+    let mut offset = HEADER_SIZE;
+    let header_size = std::mem::size_of::<StringCacheEntry>();
+    while offset < bytes.len() {
+        let remaining = bytes.len() - offset;
+        if remaining < header_size {
+            return Err(invalid_data("ustr dictionary entry is truncated"));
+        }
+        // SAFETY: there's room for a header at `offset`.
+        let len = unsafe {
+            std::ptr::read_unaligned(
+                bytes.as_ptr().add(offset) as *const StringCacheEntry
+            )
+        }
+        .len;
+        let size = header_size
+            .checked_add(len)
+            .filter(|n| *n < remaining)
+            .map(|_| StringCacheEntry::size_for_len(len))
+            .filter(|size| *size <= remaining)
+            .ok_or_else(|| {
+                invalid_data("ustr dictionary entry is truncated")
+            })?;
+        let chars = &bytes[offset + header_size..][..=len];
+        if chars[len] != 0
+            || (check_utf8 && std::str::from_utf8(&chars[..len]).is_err())
+        {
+            return Err(invalid_data("ustr dictionary entry is invalid"));
+        }
+        offsets.push(offset);
+        offset += size;
+    }
+    if offsets.len() as u64 != header.num_entries {
+        return Err(invalid_data("ustr dictionary has the wrong entry count"));
+    }
+    Ok((header, offsets))
+}
+
+/// Add the strings in a dictionary written by [`write_dictionary()`] or
+/// [`build_dictionary()`] to the cache, using `bytes` as their storage.
+///
+/// This is what [`load_dictionary()`] does with the mapped file, for when the
+/// dictionary comes from somewhere else, e.g. a buffer read at startup.
+/// `bytes` must be aligned to 8 bytes. The structure of the dictionary and the
+/// UTF-8 of every string are checked, but the hashes stored in it are trusted
+/// if it was written with the same hasher; use [`verify_dictionary()`] to
+/// check those too. If it wasn't, every entry is rehashed in place.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let mut file = Vec::new();
+/// ustr::write_dictionary(["alpha", "beta"], &mut file)?;
+///
+/// // Copy into memory that's suitably aligned and lives forever.
+/// let words = file.len().div_ceil(8);
+/// let storage: &'static mut [u64] = vec![0u64; words].leak();
+/// let bytes = unsafe {
+///     let ptr = storage.as_mut_ptr() as *mut u8;
+///     std::slice::from_raw_parts_mut(ptr, file.len())
+/// };
+/// bytes.copy_from_slice(&file);
+///
+/// let range = bytes.as_ptr_range();
+/// let load = ustr::load_dictionary_bytes(bytes)?;
+/// assert_eq!(load.num_entries, 2);
+/// // The string wasn't copied.
+/// assert!(range.contains(&(u("beta").as_char_ptr() as *const u8)));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn load_dictionary_bytes(
+    bytes: &'static mut [u8],
+) -> io::Result<DictionaryLoad> {
+    load(bytes, true)
+}
+
+// Add the strings in `bytes` to the cache.
+//
+// This is safe as long as `check_utf8` is set or the strings are valid UTF-8.
+fn load(
+    bytes: &'static mut [u8],
+    check_utf8: bool,
+) -> io::Result<DictionaryLoad> {
+    load_into(None, bytes, check_utf8)
+}
+
+// Add the strings in `bytes` to `bins`, which is a cache that's being
+// created, or to the cache in use if it's `None`.
+fn load_into(
+    bins: Option<&Bins>,
+    bytes: &'static mut [u8],
+    check_utf8: bool,
+) -> io::Result<DictionaryLoad> {
+    // Check every entry before adding any of them.
+    let (header, offsets) = check(bytes, check_utf8)?;
+    if !check_utf8 {
+        check_checksum(&header, bytes)?;
+    }
+    let align = std::mem::align_of::<StringCacheEntry>();
+    if !(bytes.as_ptr() as usize).is_multiple_of(align) {
+        return Err(invalid_data("ustr dictionary is not aligned"));
+    }
+    // Every entry is aligned since `bytes` is and every entry is a multiple of
+    // the alignment.
+    let base = bytes.as_mut_ptr();
+    let entries = offsets
+        .into_iter()
+        .map(|offset| base.wrapping_add(offset) as *mut StringCacheEntry);
+
+    let current = Header::current(0, 0);
+    let rehash = header.needs_rehash();
+    let rehash32 = header.hash32_check != current.hash32_check;
+
+    // Hold every lock while inserting so that, in an otherwise empty cache,
+    // entries get the generations they were written with and their pages
+    // don't need to be touched.
+    let mut bins = match bins {
+        Some(bins) => bins.0.iter().map(|b| b.write()).collect::<Vec<_>>(),
+        None => write_current_bins(),
+    };
+    if frozen().map(|f| f.policy()) == Some(FreezePolicy::Reject) {
+        return Err(io::Error::other("the string cache is frozen"));
+    }
+    let mut num_inserted = 0;
+    for entry in entries {
+        // SAFETY: the entry was checked above, or the caller promised it's
+        // UTF-8, and `bytes` lives forever.
+        unsafe {
+            let string =
+                std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+                    entry.add(1) as *const u8,
+                    (*entry).len,
+                ));
+            if !matches!(super::canonical_str(string), Ok(Cow::Borrowed(_))) {
+                // The string would be changed when interned, so it can't be
+                // used in place.
+                if let Ok(canonical) = super::canonical_str(string) {
+                    let hash = hash_str(&canonical);
+                    bins[whichbin(hash)].insert(&canonical, hash);
+                }
+                continue;
+            }
+            let mut hash = (*entry).hash;
+            if rehash {
+                hash = hash_str(string);
+                if (*entry).hash != hash {
+                    (*entry).hash = hash;
+                }
+            }
+            if bins[whichbin(hash)].insert_prebuilt(entry, rehash32) {
+                num_inserted += 1;
+            }
+        }
+    }
+
+    Ok(DictionaryLoad {
+        num_entries: header.num_entries as usize,
+        num_inserted,
+    })
+}
+
+/// Map the dictionary file at `path`, written by [`build_dictionary()`] or
+/// [`write_dictionary()`], into memory and add its strings to the cache in
+/// place.
+///
+/// The file is mapped copy-on-write and never unmapped. Loading doesn't copy
+/// any strings, and if the cache is empty and neither hash function has
+/// changed since the file was written, it doesn't hash them or write to the
+/// mapping either, so the pages are only read in as the strings are used and
+/// can be shared between processes.
+///
+/// This is available with the `mmap` feature on Unix platforms.
+///
+/// # Examples
+///
+/// ```no_run
+/// let load = ustr::load_dictionary("vocabulary.ustrdict")?;
+/// println!("{} strings ready", load.num_entries);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(all(feature = "mmap", unix))]
+pub fn load_dictionary<P: AsRef<std::path::Path>>(
+    path: P,
+) -> io::Result<DictionaryLoad> {
+    load_mapped(None, path.as_ref(), true)
+}
+
+// Map the file at `path` and load it into `bins`, or the cache in use if
+// that's `None`, with `load_into()`.
+#[cfg(all(feature = "mmap", unix))]
+fn load_mapped(
+    bins: Option<&Bins>,
+    path: &std::path::Path,
+    check_utf8: bool,
+) -> io::Result<DictionaryLoad> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    let len = usize::try_from(file.metadata()?.len())
+        .map_err(|_| invalid_data("ustr dictionary is too big"))?;
+    if len < HEADER_SIZE {
+        return Err(invalid_data("not a ustr dictionary"));
+    }
+    // SAFETY: we map a private, copy-on-write view of the whole file. Mapped
+    // memory is page aligned, and it's never unmapped once loaded so it can
+    // be handed out as `'static`.
+    unsafe {
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let bytes = std::slice::from_raw_parts_mut(ptr as *mut u8, len);
+        let result = load_into(bins, bytes, check_utf8);
+        if result.is_err() {
+            libc::munmap(ptr, len);
+        }
+        result
+    }
+}
+
+// Load the dictionary file at `path` into `bins`, or the cache in use if
+// that's `None`, checking it. It's mapped if possible, or read into memory
+// that's leaked if not.
+pub(crate) fn load_file(
+    bins: Option<&Bins>,
+    path: &std::path::Path,
+) -> io::Result<DictionaryLoad> {
+    #[cfg(all(feature = "mmap", unix))]
+    {
+        load_mapped(bins, path, true)
+    }
+    #[cfg(not(all(feature = "mmap", unix)))]
+    {
+        let file = std::fs::read(path)?;
+        // Copy into `u64`s so that the entries are aligned.
+        let storage = vec![0u64; file.len().div_ceil(8)].into_boxed_slice();
+        let storage = Box::into_raw(storage);
+        // SAFETY: the storage is at least as long as the file, and is leaked
+        // unless loading fails, in which case it's freed again.
+        unsafe {
+            let ptr = storage as *mut u8;
+            std::ptr::copy_nonoverlapping(file.as_ptr(), ptr, file.len());
+            let bytes = std::slice::from_raw_parts_mut(ptr, file.len());
+            let result = load_into(bins, bytes, true);
+            if result.is_err() {
+                drop(Box::from_raw(storage));
+            }
+            result
+        }
+    }
+}
+
+/// Loading dictionaries without checking that their strings are UTF-8.
+///
+/// Checking every string is most of the time it takes to load a dictionary
+/// that's gigabytes in size. These functions check a checksum of the whole
+/// file instead, which catches a file that's been truncated or corrupted on
+/// disk, and otherwise trust that the file was written by
+/// [`write_dictionary()`] or [`build_dictionary()`]. Everything else that's
+/// checked when loading normally is still checked.
+///
+/// Only use these for dictionaries that ship with the program or that it
+/// built itself. A checksum is no protection against a file that's been
+/// crafted to hold invalid UTF-8, so anything from an untrusted source should
+/// be loaded with the checked [`load_dictionary()`] or
+/// [`load_dictionary_bytes()`].
+///
+/// [`write_dictionary()`]: crate::write_dictionary
+/// [`build_dictionary()`]: crate::build_dictionary
+/// [`load_dictionary()`]: crate::load_dictionary
+/// [`load_dictionary_bytes()`]: crate::load_dictionary_bytes
+pub mod trusted {
+    use super::{load, DictionaryLoad};
+    use std::io;
+
+    /// Add the strings in a dictionary to the cache, using `bytes` as their
+    /// storage, like [`load_dictionary_bytes()`], but checking the
+    /// dictionary's checksum instead of checking that every string is UTF-8.
+    ///
+    /// Dictionaries written before checksums were added can't be loaded this
+    /// way.
+    ///
+    /// [`load_dictionary_bytes()`]: crate::load_dictionary_bytes
+    /// [`write_dictionary()`]: crate::write_dictionary
+    /// [`build_dictionary()`]: crate::build_dictionary
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must have been written by [`write_dictionary()`] or
+    /// [`build_dictionary()`], or otherwise hold only valid UTF-8 strings.
+    /// If the checksum matches but a string isn't UTF-8, the behavior is
+    /// undefined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let mut file = Vec::new();
+    /// ustr::write_dictionary(["alpha", "beta"], &mut file)?;
+    ///
+    /// let words = file.len().div_ceil(8);
+    /// let storage: &'static mut [u64] = vec![0u64; words].leak();
+    /// let bytes = unsafe {
+    ///     let ptr = storage.as_mut_ptr() as *mut u8;
+    ///     std::slice::from_raw_parts_mut(ptr, file.len())
+    /// };
+    /// bytes.copy_from_slice(&file);
+    ///
+    /// // SAFETY: we just wrote the dictionary ourselves.
+    /// let load = unsafe { ustr::trusted::load_dictionary_bytes(bytes)? };
+    /// assert_eq!(load.num_entries, 2);
+    /// assert_eq!(ustr::existing_ustr("beta"), Some(u("beta")));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub unsafe fn load_dictionary_bytes(
+        bytes: &'static mut [u8],
+    ) -> io::Result<DictionaryLoad> {
+        load(bytes, false)
+    }
+
+    /// Map the dictionary file at `path` into memory and add its strings to
+    /// the cache in place, like [`load_dictionary()`], but checking the
+    /// file's checksum instead of checking that every string is UTF-8.
+    ///
+    /// This reads the whole file to compute the checksum, so unlike
+    /// [`load_dictionary()`] it doesn't leave the pages to be read in as the
+    /// strings are used.
+    ///
+    /// This is available with the `mmap` feature on Unix platforms.
+    ///
+    /// [`load_dictionary()`]: crate::load_dictionary
+    /// [`write_dictionary()`]: crate::write_dictionary
+    /// [`build_dictionary()`]: crate::build_dictionary
+    ///
+    /// # Safety
+    ///
+    /// The file must have been written by [`write_dictionary()`] or
+    /// [`build_dictionary()`], or otherwise hold only valid UTF-8 strings.
+    /// If the checksum matches but a string isn't UTF-8, the behavior is
+    /// undefined.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// // SAFETY: the dictionary is built with the game's assets.
+    /// let load =
+    ///     unsafe { ustr::trusted::load_dictionary("vocabulary.ustrdict")? };
+    /// println!("{} strings ready", load.num_entries);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(all(feature = "mmap", unix))]
+    pub unsafe fn load_dictionary<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> io::Result<DictionaryLoad> {
+        super::load_mapped(None, path.as_ref(), false)
+    }
+}
+
+#[test]
+fn test_dictionary() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{ustr as u, Ustr};
+
+    unsafe { super::_clear_cache() };
+
+    let words = (0..1000).map(|i| format!("word {}", i)).collect::<Vec<_>>();
+    let mut file = Vec::new();
+    assert_eq!(
+        write_dictionary(words.iter().chain(&words), &mut file).unwrap(),
+        1000
+    );
+
+    let leak = |file: &[u8]| {
+        let storage = vec![0u64; file.len().div_ceil(8)].leak();
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                storage.as_mut_ptr() as *mut u8,
+                file.len(),
+            )
+        };
+        bytes.copy_from_slice(file);
+        bytes
+    };
+
+    // Corrupt dictionaries are rejected without adding anything.
+    assert!(load_dictionary_bytes(leak(&file[..file.len() - 1])).is_err());
+    let mut bad = file.clone();
+    bad[HEADER_SIZE + 16] = 0xff;
+    assert!(load_dictionary_bytes(leak(&bad)).is_err());
+    assert_eq!(super::num_entries(), 0);
+
+    // Trusted loading doesn't look at the strings, but catches corruption
+    // with the checksum, and won't load files without one.
+    let trusted = |bytes| unsafe { trusted::load_dictionary_bytes(bytes) };
+    assert!(trusted(leak(&bad)).is_err());
+    let mut bad = file.clone();
+    bad[file.len() - 8] ^= 1;
+    assert!(trusted(leak(&bad)).is_err());
+    let mut old = file.clone();
+    old[8..12].copy_from_slice(&1u32.to_ne_bytes());
+    old[48..64].fill(0);
+    assert!(verify_dictionary(&old).is_ok());
+    assert!(trusted(leak(&old)).is_err());
+    assert_eq!(super::num_entries(), 0);
+    assert_eq!(trusted(leak(&file)).unwrap().num_inserted, 1000);
+    assert_eq!(super::existing_ustr("word 999").unwrap(), "word 999");
+    unsafe { super::_clear_cache() };
+
+    // Version 1 files still load with checking.
+    assert_eq!(
+        load_dictionary_bytes(leak(&old)).unwrap().num_inserted,
+        1000
+    );
+    unsafe { super::_clear_cache() };
+
+    let existing = u("word 500");
+    let bytes = leak(&file);
+    let range = bytes.as_ptr_range();
+    let before = bytes.to_vec();
+    let load = load_dictionary_bytes(bytes).unwrap();
+    assert_eq!(load.num_entries, 1000);
+    assert_eq!(load.num_inserted, 999);
+    assert_eq!(super::num_entries(), 1000);
+
+    for w in &words {
+        let found = Ustr::from_existing(w).unwrap();
+        assert_eq!(found.as_str(), w);
+        assert_eq!(found.precomputed_hash(), super::hash_str(w));
+        assert_eq!(found.hash32(), super::fnv1a_32(w));
+        if *w == "word 500" {
+            assert_eq!(found, existing);
+        } else {
+            assert!(range.contains(&(found.as_char_ptr() as *const u8)));
+        }
+        assert_eq!(super::from_id(found.id()), Some(found));
+    }
+    // Entries after the existing one were renumbered, the rest weren't
+    // touched.
+    let first =
+        unsafe { &*(range.start.add(HEADER_SIZE) as *const StringCacheEntry) };
+    assert_eq!(first.generation, 1);
+    assert_ne!(before, unsafe {
+        std::slice::from_raw_parts(range.start, before.len())
+    });
+    assert_eq!(super::entries_since(0).count(), 1000);
+    assert_eq!(super::string_cache_iter().count(), 1000);
+
+    unsafe { super::_clear_cache() };
+
+    // In an empty cache nothing needs rewriting.
+    let bytes = leak(&file);
+    let range = bytes.as_ptr_range();
+    load_dictionary_bytes(bytes).unwrap();
+    assert_eq!(
+        unsafe { std::slice::from_raw_parts(range.start, file.len()) },
+        &file[..]
+    );
+
+    unsafe { super::_clear_cache() };
+}
+
+#[test]
+fn test_build_dictionary() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{set_max_len, MaxLenPolicy};
+
+    let words = (0..500).map(|i| format!("build {}", i)).collect::<Vec<_>>();
+    let mut file = Vec::new();
+    let stats =
+        build_dictionary(words.iter().chain(&words), &mut file).unwrap();
+    assert_eq!(
+        stats,
+        DictionaryStats {
+            num_strings: 1000,
+            num_entries: 500,
+            num_changed: 0,
+            string_bytes: words.iter().map(|w| w.len()).sum(),
+            file_size: file.len(),
+            max_bin_entries: stats.max_bin_entries,
+        }
+    );
+    assert!(stats.max_bin_entries >= 500 / super::NUM_BINS);
+
+    // Entries are grouped by bin, keeping their order within each bin.
+    let (_, offsets) = check(&file, true).unwrap();
+    let strings = offsets
+        .iter()
+        .map(|offset| unsafe { read_entry(&file, *offset).1 })
+        .collect::<Vec<_>>();
+    let bins = strings.iter().map(|s| whichbin(hash_str(s)));
+    assert!(bins.clone().zip(bins.skip(1)).all(|(a, b)| a <= b));
+    let position = |s: &str| words.iter().position(|w| w == s).unwrap();
+    for pair in strings.windows(2) {
+        if whichbin(hash_str(pair[0])) == whichbin(hash_str(pair[1])) {
+            assert!(position(pair[0]) < position(pair[1]));
+        }
+    }
+
+    // Verifying catches bad hashes and duplicates, which loading doesn't
+    // check for.
+    let verified = verify_dictionary(&file).unwrap();
+    assert_eq!(verified.num_strings, 500);
+    assert_eq!(verified.max_bin_entries, stats.max_bin_entries);
+    let mut bad = file.clone();
+    bad[HEADER_SIZE] ^= 1;
+    assert!(check(&bad, true).is_ok());
+    assert!(verify_dictionary(&bad).is_err());
+    let mut duplicated = Vec::new();
+    write_dictionary(["a", "b"], &mut duplicated).unwrap();
+    let entry = StringCacheEntry::size_for_len(1);
+    duplicated
+        .copy_within(HEADER_SIZE..HEADER_SIZE + entry, HEADER_SIZE + entry);
+    assert!(verify_dictionary(&duplicated).is_err());
+
+    // Strings are stored as they'll be interned.
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            set_max_len(usize::MAX, MaxLenPolicy::Reject);
+        }
+    }
+    let _reset = Reset;
+    let long = "x".repeat(100);
+    set_max_len(50, MaxLenPolicy::Reject);
+    let error = build_dictionary([&long], io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    set_max_len(50, MaxLenPolicy::Truncate);
+    let mut file = Vec::new();
+    let stats = build_dictionary(["short", &long], &mut file).unwrap();
+    assert_eq!(stats.num_changed, 1);
+    assert_eq!(stats.string_bytes, 55);
+}
+
+#[test]
+fn test_rehash_dictionary() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{existing_ustr, Ustr};
+
+    unsafe { super::_clear_cache() };
+
+    let words = (0..300)
+        .map(|i| format!("rehash {}", i))
+        .collect::<Vec<_>>();
+    let mut file = Vec::new();
+    write_dictionary(&words, &mut file).unwrap();
+
+    // Pretend the dictionary was written by a build whose hasher was seeded
+    // differently, so every stored hash is wrong for this one.
+    let (_, offsets) = check(&file, true).unwrap();
+    for offset in offsets {
+        file[offset] ^= 0x5a;
+    }
+    file[40] ^= 1;
+    let sum = checksum(VERSION, &file[HEADER_SIZE..]);
+    file[48..56].copy_from_slice(&sum.to_ne_bytes());
+    assert!(Header::read(&file).unwrap().needs_rehash());
+    assert_eq!(verify_dictionary(&file).unwrap().num_entries, 300);
+
+    let leak = |file: &[u8]| {
+        let storage = vec![0u64; file.len().div_ceil(8)].leak();
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                storage.as_mut_ptr() as *mut u8,
+                file.len(),
+            )
+        };
+        bytes.copy_from_slice(file);
+        bytes
+    };
+    let check_found = || {
+        for w in &words {
+            let found = Ustr::from_existing(w).unwrap();
+            assert_eq!(found.precomputed_hash(), hash_str(w));
+            assert_eq!(existing_ustr(w), Some(found));
+        }
+    };
+
+    let load = load_dictionary_bytes(leak(&file)).unwrap();
+    assert_eq!(load.num_inserted, 300);
+    check_found();
+    unsafe { super::_clear_cache() };
+
+    // The checksum doesn't depend on the hasher, so trusted loading works
+    // too.
+    let load = unsafe { trusted::load_dictionary_bytes(leak(&file)) };
+    assert_eq!(load.unwrap().num_inserted, 300);
+    check_found();
+    unsafe { super::_clear_cache() };
+
+    // A different seed mode is enough, even if the check value matches.
+    file[40] ^= 1;
+    file[60] ^= 1;
+    assert!(Header::read(&file).unwrap().needs_rehash());
+    load_dictionary_bytes(leak(&file)).unwrap();
+    check_found();
+
+    unsafe { super::_clear_cache() };
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn test_load_dictionary() {
+    let _t = super::TEST_LOCK.lock();
+
+    unsafe { super::_clear_cache() };
+
+    let path = std::env::temp_dir()
+        .join(format!("ustr-test-{}.ustrdict", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    write_dictionary(["mapped", "strings"], file).unwrap();
+    let load = load_dictionary(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(load.num_inserted, 2);
+    assert_eq!(super::existing_ustr("strings").unwrap(), "strings");
+
+    unsafe { super::_clear_cache() };
+
+    let file = std::fs::File::create(&path).unwrap();
+    write_dictionary(["trusted", "strings"], file).unwrap();
+    let load = unsafe { trusted::load_dictionary(&path) }.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(load.num_inserted, 2);
+    assert_eq!(super::existing_ustr("trusted").unwrap(), "trusted");
+
+    unsafe { super::_clear_cache() };
+}
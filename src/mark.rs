@@ -0,0 +1,107 @@
+use super::{current_generation, total_allocated, StringCacheEntry, Ustr};
+
+/// How much of the cache is still referenced by the application, as returned
+/// by [`mark_and_report()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnreferencedReport {
+    /// Number of unique strings in the cache.
+    pub num_entries: usize,
+    /// Number of unique strings found in the roots.
+    pub num_referenced: usize,
+    /// Number of strings in the cache that weren't found in the roots.
+    pub num_unreferenced: usize,
+    /// Bytes of string storage used by the referenced strings, including
+    /// their headers and padding.
+    pub referenced_bytes: usize,
+    /// Bytes of string storage used by everything else.
+    pub unreferenced_bytes: usize,
+}
+
+/// Report how much of the cache is not referenced by `roots`.
+///
+/// Pass every `Ustr` your application still holds (duplicates are fine) and
+/// this tells you how many of the strings in the cache, and how many bytes,
+/// are dead weight, e.g. after unloading a level. Nothing is freed, since the
+/// cache never frees strings.
+///
+/// Strings added by other threads while this runs may or may not be counted.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+/// # unsafe { ustr::_clear_cache() };
+///
+/// let level = (0..100).map(|i| u(&format!("level1/entity{}", i))).count();
+/// let player = u("player");
+///
+/// let report = ustr::mark_and_report([player].into_iter());
+/// assert_eq!(report.num_referenced, 1);
+/// assert_eq!(report.num_unreferenced, level);
+/// ```
+pub fn mark_and_report<I>(roots: I) -> UnreferencedReport
+where
+    I: Iterator<Item = Ustr>,
+{
+    // Take the totals first, so anything added concurrently is at worst
+    // counted as referenced but not as part of the cache.
+    let num_entries = current_generation() as usize;
+    let total_bytes = total_allocated();
+
+    // Ids are dense, so a bitset indexed by id marks each string once.
+    let mut marked = vec![0u64; num_entries.div_ceil(64)];
+    let mut num_referenced = 0;
+    let mut referenced_bytes = 0;
+    for u in roots {
+        let id = u.id() as usize;
+        if id >= num_entries {
+            continue;
+        }
+        let (word, bit) = (id / 64, 1 << (id % 64));
+        if marked[word] & bit == 0 {
+            marked[word] |= bit;
+            num_referenced += 1;
+            referenced_bytes += StringCacheEntry::size_for_len(u.len());
+        }
+    }
+
+    UnreferencedReport {
+        num_entries,
+        num_referenced,
+        num_unreferenced: num_entries - num_referenced,
+        referenced_bytes,
+        unreferenced_bytes: total_bytes.saturating_sub(referenced_bytes),
+    }
+}
+
+#[test]
+fn test_mark_and_report() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let report = mark_and_report(std::iter::empty());
+    assert_eq!(report.num_entries, 0);
+    assert_eq!(report.unreferenced_bytes, 0);
+
+    let strings = (0..1000)
+        .map(|i| u(&format!("string {}", i)))
+        .collect::<Vec<_>>();
+    let kept = &strings[..10];
+
+    // Duplicates only count once.
+    let report =
+        mark_and_report(kept.iter().chain(kept.iter()).chain(kept).copied());
+    assert_eq!(report.num_entries, 1000);
+    assert_eq!(report.num_referenced, 10);
+    assert_eq!(report.num_unreferenced, 990);
+    assert_eq!(
+        report.referenced_bytes + report.unreferenced_bytes,
+        super::total_allocated()
+    );
+
+    let report = mark_and_report(strings.iter().copied());
+    assert_eq!(report.num_unreferenced, 0);
+    assert_eq!(report.unreferenced_bytes, 0);
+}
@@ -0,0 +1,377 @@
+use super::{Ustr, UstrMap};
+use parking_lot::RwLock;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Display,
+    hash::Hash,
+    str::FromStr,
+};
+
+// For each key type, a `HashMap<K, Ustr>` of the strings already formatted.
+type Maps = HashMap<TypeId, Box<dyn Any + Send + Sync>>;
+
+static MAPS: RwLock<Option<Maps>> = RwLock::new(None);
+
+// For each type, a `UstrMap<T>` of the strings already parsed as that type.
+static PARSED: RwLock<Option<Maps>> = RwLock::new(None);
+
+// The name of each type that `type_name()` has been called with.
+static TYPE_NAMES: RwLock<Option<HashMap<TypeId, Ustr>>> = RwLock::new(None);
+
+/// Get the `Ustr` for the `Display` form of `value`, only formatting it the
+/// first time it's seen.
+///
+/// This keeps a map from each value to its `Ustr` for every type it's called
+/// with, so turning the same numbers or enum variants into strings over and
+/// over, as UI code often does, is a hash map lookup rather than a call to
+/// `format!` and a lookup in the cache. The values are cloned into the map, so
+/// this is best suited to small, cheap-to-copy types with a limited number of
+/// distinct values. Use [`memoize_display_by()`] to key the map on something
+/// other than the value itself.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{memoize_display, ustr as u};
+///
+/// #[derive(Clone, PartialEq, Eq, Hash)]
+/// enum Level {
+///     Low,
+///     High,
+/// }
+///
+/// impl std::fmt::Display for Level {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         f.write_str(match self {
+///             Level::Low => "low",
+///             Level::High => "high",
+///         })
+///     }
+/// }
+///
+/// assert_eq!(memoize_display(&Level::High), u("high"));
+/// assert_eq!(memoize_display(&42u32), u("42"));
+/// ```
+pub fn memoize_display<T>(value: &T) -> Ustr
+where
+    T: Display + Hash + Eq + Clone + Send + Sync + 'static,
+{
+    // Only clone the value if it isn't in the map yet.
+    lookup(value).unwrap_or_else(|| insert(value.clone(), value))
+}
+
+/// Get the `Ustr` for the `Display` form of `value`, only formatting it the
+/// first time it's called with `key`.
+///
+/// This is like [`memoize_display()`], but for when the value is expensive to
+/// clone or hash, or doesn't implement those traits, and there's a cheaper key
+/// that identifies it. Each call with an equal key of the same type returns
+/// the same `Ustr`, however `value` has changed, so the key must determine the
+/// displayed string.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{memoize_display_by, ustr as u};
+///
+/// struct Point {
+///     id: u32,
+///     name: String,
+/// }
+///
+/// impl std::fmt::Display for Point {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "#{} {}", self.id, self.name)
+///     }
+/// }
+///
+/// let p = Point { id: 7, name: "origin".to_owned() };
+/// assert_eq!(memoize_display_by(p.id, &p), u("#7 origin"));
+/// ```
+pub fn memoize_display_by<K, T>(key: K, value: &T) -> Ustr
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Display + ?Sized,
+{
+    lookup(&key).unwrap_or_else(|| insert(key, value))
+}
+
+fn lookup<K: Hash + Eq + 'static>(key: &K) -> Option<Ustr> {
+    MAPS.read()
+        .as_ref()?
+        .get(&TypeId::of::<K>())?
+        .downcast_ref::<HashMap<K, Ustr>>()?
+        .get(key)
+        .copied()
+}
+
+fn insert<K, T>(key: K, value: &T) -> Ustr
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Display + ?Sized,
+{
+    // Format outside the lock, since `value`'s `Display` impl might use this
+    // too.
+    let u = Ustr::from(&value.to_string());
+    let mut maps = MAPS.write();
+    let map = maps
+        .get_or_insert_with(HashMap::new)
+        .entry(TypeId::of::<K>())
+        .or_insert_with(|| Box::new(HashMap::<K, Ustr>::new()))
+        .downcast_mut::<HashMap<K, Ustr>>()
+        .expect("map has the wrong type");
+    // Another thread may have got here first.
+    *map.entry(key).or_insert(u)
+}
+
+/// Get the name of the type `T` as a `Ustr`, as returned by
+/// [`std::any::type_name()`].
+///
+/// The name is only interned the first time it's asked for, after which it's
+/// looked up by `T`'s [`TypeId`], so reflection and serialization code can
+/// use it on every access without hashing the name again. As with
+/// `std::any::type_name()`, the exact name isn't guaranteed to be the same
+/// between compiler versions, and shouldn't be relied on to identify a type
+/// across builds.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{type_name, ustr as u};
+///
+/// assert_eq!(type_name::<u32>(), u("u32"));
+/// assert_eq!(type_name::<str>(), u("str"));
+/// let name = std::any::type_name::<Option<i8>>();
+/// assert_eq!(type_name::<Option<i8>>(), u(name));
+/// ```
+pub fn type_name<T: ?Sized + 'static>() -> Ustr {
+    let id = TypeId::of::<T>();
+    if let Some(name) = TYPE_NAMES.read().as_ref().and_then(|m| m.get(&id)) {
+        return *name;
+    }
+    let name = Ustr::from(std::any::type_name::<T>());
+    TYPE_NAMES
+        .write()
+        .get_or_insert_with(HashMap::new)
+        .insert(id, name);
+    name
+}
+
+/// Get the name of the type of `value` as a `Ustr`, as returned by
+/// [`std::any::type_name_of_val()`].
+///
+/// This is [`type_name()`] for when the type can't be named, such as that of
+/// a closure.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{type_name, type_name_of_val};
+///
+/// let values = vec![1.0f32, 2.0];
+/// assert_eq!(type_name_of_val(&values), type_name::<Vec<f32>>());
+/// ```
+pub fn type_name_of_val<T: ?Sized + 'static>(_value: &T) -> Ustr {
+    type_name::<T>()
+}
+
+impl Ustr {
+    /// Parse this string as a `T`, only calling [`FromStr::from_str()`] the
+    /// first time it's parsed as that type.
+    ///
+    /// Each successfully parsed value is remembered for its string and type,
+    /// so parsing the same interned values over and over, such as attribute
+    /// values like `"true"`, `"1.5"` or `"linear"`, is a hash map lookup.
+    /// Errors aren't remembered, so a string that fails to parse is parsed
+    /// again each time. `T`'s `FromStr` impl must always give the same result
+    /// for the same string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("1.5").parse_cached::<f32>(), Ok(1.5));
+    /// assert_eq!(u("true").parse_cached::<bool>(), Ok(true));
+    /// assert!(u("linear").parse_cached::<u32>().is_err());
+    /// ```
+    pub fn parse_cached<T>(&self) -> Result<T, T::Err>
+    where
+        T: FromStr + Copy + Send + Sync + 'static,
+    {
+        let parsed = PARSED
+            .read()
+            .as_ref()
+            .and_then(|maps| maps.get(&TypeId::of::<T>()))
+            .and_then(|map| map.downcast_ref::<UstrMap<T>>())
+            .and_then(|map| map.get(self).copied());
+        if let Some(value) = parsed {
+            return Ok(value);
+        }
+
+        // Parse outside the lock, since `T`'s `FromStr` impl might use this
+        // too.
+        let value = self.as_str().parse::<T>()?;
+        let mut maps = PARSED.write();
+        let map = maps
+            .get_or_insert_with(HashMap::new)
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(UstrMap::<T>::default()))
+            .downcast_mut::<UstrMap<T>>()
+            .expect("map has the wrong type");
+        map.insert(*self, value);
+        Ok(value)
+    }
+}
+
+// Forget every memoized string, parsed value and type name. Only called by
+// `_clear_cache()`.
+pub(crate) fn clear() {
+    *MAPS.write() = None;
+    *PARSED.write() = None;
+    *TYPE_NAMES.write() = None;
+}
+
+#[test]
+fn test_memoize_display() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use std::{
+        fmt,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    unsafe { super::_clear_cache() };
+
+    static FORMATTED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Clone, PartialEq, Eq, Hash)]
+    struct Counted(u32);
+
+    impl fmt::Display for Counted {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            FORMATTED.fetch_add(1, Ordering::Relaxed);
+            write!(f, "counted {}", self.0)
+        }
+    }
+
+    for _ in 0..10 {
+        for i in 0..5 {
+            assert_eq!(
+                memoize_display(&Counted(i)),
+                u(&format!("counted {}", i))
+            );
+        }
+    }
+    // Each value is only formatted once.
+    assert_eq!(FORMATTED.load(Ordering::Relaxed), 5);
+
+    // Different types get different maps, even with equal keys.
+    assert_eq!(memoize_display(&1u32), u("1"));
+    assert_eq!(memoize_display(&1u64), u("1"));
+    assert_eq!(memoize_display_by(1u32, "one"), u("1"));
+    assert_eq!(memoize_display_by(1u8, "one"), u("one"));
+    assert_eq!(memoize_display_by(1u8, "uno"), u("one"));
+
+    // A value whose `Display` impl memoizes doesn't deadlock.
+    struct Nested;
+    impl fmt::Display for Nested {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "nested {}", memoize_display(&2i32))
+        }
+    }
+    assert_eq!(memoize_display_by("nested", &Nested), u("nested 2"));
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(memoize_display_by(1u8, "uno"), u("uno"));
+}
+
+#[test]
+fn test_parse_cached() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    unsafe { super::_clear_cache() };
+
+    static PARSES: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Interpolation {
+        Linear,
+        Cubic,
+    }
+
+    impl FromStr for Interpolation {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Interpolation, ()> {
+            PARSES.fetch_add(1, Ordering::Relaxed);
+            match s {
+                "linear" => Ok(Interpolation::Linear),
+                "cubic" => Ok(Interpolation::Cubic),
+                _ => Err(()),
+            }
+        }
+    }
+
+    for _ in 0..10 {
+        assert_eq!(u("linear").parse_cached(), Ok(Interpolation::Linear));
+        assert_eq!(u("cubic").parse_cached(), Ok(Interpolation::Cubic));
+    }
+    assert_eq!(PARSES.load(Ordering::Relaxed), 2);
+
+    // Failures are parsed every time.
+    assert_eq!(u("nearest").parse_cached::<Interpolation>(), Err(()));
+    assert_eq!(u("nearest").parse_cached::<Interpolation>(), Err(()));
+    assert_eq!(PARSES.load(Ordering::Relaxed), 4);
+
+    // Each type has its own values for the same string.
+    assert_eq!(u("7").parse_cached::<u8>(), Ok(7));
+    assert_eq!(u("7").parse_cached::<f64>(), Ok(7.0));
+    assert_eq!(u("-7").parse_cached::<i32>(), Ok(-7));
+    assert!(u("-7").parse_cached::<u32>().is_err());
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(u("linear").parse_cached(), Ok(Interpolation::Linear));
+    assert_eq!(PARSES.load(Ordering::Relaxed), 5);
+}
+
+#[test]
+fn test_type_name() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use std::any;
+
+    unsafe { super::_clear_cache() };
+
+    struct Local;
+    assert_eq!(type_name::<Local>(), u(any::type_name::<Local>()));
+    assert_eq!(type_name::<u8>(), u("u8"));
+    assert_eq!(type_name::<[u8]>(), u("[u8]"));
+    assert_eq!(
+        type_name::<dyn Display>(),
+        u(any::type_name::<dyn Display>())
+    );
+    assert_eq!(TYPE_NAMES.read().as_ref().unwrap().len(), 4);
+
+    // Later calls find the name without interning it again.
+    let before = super::num_entries();
+    for _ in 0..10 {
+        assert_eq!(type_name::<Local>(), u(any::type_name::<Local>()));
+        assert_eq!(type_name_of_val(&1u8), u("u8"));
+    }
+    assert_eq!(super::num_entries(), before);
+    assert_eq!(TYPE_NAMES.read().as_ref().unwrap().len(), 4);
+
+    let closure = || ();
+    assert_eq!(
+        type_name_of_val(&closure),
+        u(any::type_name_of_val(&closure))
+    );
+
+    unsafe { super::_clear_cache() };
+    assert!(TYPE_NAMES.read().is_none());
+    assert_eq!(type_name::<u8>(), u("u8"));
+}
@@ -0,0 +1,215 @@
+// Bloom filters of the hashes in each global bin, so that lookups of strings
+// that aren't in the cache can usually return without taking the bin's lock
+// or probing its table.
+//
+// Each bin's filter is only added to while holding the bin's write lock, and
+// is sized for as many strings as the bin's table has slots, so it only has
+// to be replaced, by one built from the bin's entries, after the table has
+// grown. Readers load the filter without any lock, so filters that have been
+// replaced are retired rather than freed, until `_clear_cache()`. Since the
+// table at least doubles each time it grows, the retired filters take at most
+// as much memory as the current ones.
+use super::{primitives::AtomicPtr, shared, whichbin, StringCache, NUM_BINS};
+use parking_lot::Mutex;
+use std::{
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// With 16 bits per string and 2 set for each, a full filter is at most an
+// eighth set, so about 1 in 70 strings that aren't in the cache get past it.
+const BITS_PER_STRING: usize = 16;
+// Number of strings each bin's first filter is sized for.
+const MIN_CAPACITY: usize = 64;
+
+// Filters that have been replaced, which lookups may still be reading. They
+// stay boxed so that they don't move.
+#[allow(clippy::vec_box)]
+static RETIRED: Mutex<Vec<Box<Filter>>> = Mutex::new(Vec::new());
+
+// A bloom filter of the hashes of the strings in one bin.
+pub(crate) struct Filter {
+    bits: Box<[AtomicU64]>,
+    // `bits` holds a power of two bits, so this is that minus one.
+    mask: usize,
+    // Number of strings the filter is sized for.
+    capacity: usize,
+}
+
+impl Filter {
+    fn new(capacity: usize) -> Filter {
+        let num_bits = capacity
+            .saturating_mul(BITS_PER_STRING)
+            .next_power_of_two()
+            .max(64);
+        Filter {
+            bits: (0..num_bits / 64).map(|_| AtomicU64::new(0)).collect(),
+            mask: num_bits - 1,
+            capacity,
+        }
+    }
+
+    // The two bits that represent a string with hash `hash`.
+    #[inline]
+    fn bits_of(&self, hash: u64) -> (usize, usize) {
+        // The top bits of the hash are the same for every string in a bin,
+        // so use the low and middle ones.
+        (hash as usize & self.mask, (hash >> 32) as usize & self.mask)
+    }
+
+    #[inline]
+    fn get(&self, bit: usize) -> bool {
+        self.bits[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+    }
+
+    #[inline]
+    fn set(&self, bit: usize) {
+        self.bits[bit / 64].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn may_contain(&self, hash: u64) -> bool {
+        let (a, b) = self.bits_of(hash);
+        self.get(a) && self.get(b)
+    }
+
+    fn add(&self, hash: u64) {
+        let (a, b) = self.bits_of(hash);
+        self.set(a);
+        self.set(b);
+    }
+}
+
+// The filter of each bin, or null for a bin that hasn't had a string added
+// since the cache was created or replaced.
+pub(crate) struct Filters([AtomicPtr<Filter>; NUM_BINS]);
+
+impl Filters {
+    pub(crate) fn new() -> Filters {
+        Filters(std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())))
+    }
+}
+
+super::primitives::lazy_static! {
+    pub(crate) static ref LOCAL_FILTERS: Filters = Filters::new();
+}
+
+// `LOCAL_FILTERS`, or the filters of the cache this copy of the crate has
+// been attached to, like `STRING_CACHE`.
+#[inline]
+fn filters() -> &'static Filters {
+    match shared::external() {
+        Some(cache) => cache.filters(),
+        None => &LOCAL_FILTERS,
+    }
+}
+
+// Returns `false` if the string with hash `hash` is definitely not in the
+// global cache, and `true` if it might be.
+#[inline]
+pub(crate) fn may_contain(hash: u64) -> bool {
+    let filter = filters().0[whichbin(hash)].load(Ordering::Acquire);
+    // Filters are never freed once they've been published.
+    match unsafe { filter.as_ref() } {
+        Some(filter) => filter.may_contain(hash),
+        None => true,
+    }
+}
+
+// Record that a string with hash `hash` has been added to `bin`, which must
+// be the global bin it belongs in, with its write lock held.
+pub(crate) fn add(bin: &StringCache, hash: u64) {
+    let slot = &filters().0[whichbin(hash)];
+    match unsafe { slot.load(Ordering::Acquire).as_ref() } {
+        // The table never holds more strings than it has slots.
+        Some(filter) if bin.table_capacity() <= filter.capacity => {
+            filter.add(hash)
+        }
+        _ => {
+            let filter = Filter::new(bin.table_capacity().max(MIN_CAPACITY));
+            for entry in bin.entry_ptrs() {
+                // Every entry in the table has a valid header.
+                filter.add(unsafe { (*entry).hash });
+            }
+            let old =
+                slot.swap(Box::into_raw(Box::new(filter)), Ordering::AcqRel);
+            retire(old);
+        }
+    }
+}
+
+// Keep a filter that's been replaced until `free()`, since lookups may still
+// be reading it.
+fn retire(filter: *mut Filter) {
+    if !filter.is_null() {
+        // Every filter is created by `add()` with `Box::into_raw()`.
+        RETIRED.lock().push(unsafe { Box::from_raw(filter) });
+    }
+}
+
+// Forget every bin's filter, when the bins' contents have been replaced.
+// Each bin gets a new filter the next time a string is added to it, and
+// lookups in it take the lock until then.
+pub(crate) fn clear() {
+    for slot in filters().0.iter() {
+        retire(slot.swap(ptr::null_mut(), Ordering::AcqRel));
+    }
+}
+
+// Forget every bin's filter and free them, along with all the retired ones.
+// Only called by `_clear_cache()`. **DO NOT CALL THIS**.
+pub(crate) unsafe fn free() {
+    clear();
+    RETIRED.lock().clear();
+}
+
+#[test]
+fn test_negative_filter() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{existing_ustr, hash_str, testing::CacheGuard, ustr as u};
+
+    unsafe { super::_clear_cache() };
+
+    // Until a string is added, a bin's lookups aren't filtered.
+    assert!(may_contain(hash_str("anything")));
+
+    let present = (0..10_000)
+        .map(|i| u(&format!("present {}", i)))
+        .collect::<Vec<_>>();
+    for p in &present {
+        assert!(may_contain(p.precomputed_hash()));
+        assert_eq!(existing_ustr(p), Some(*p));
+    }
+    let false_positives = (0..10_000)
+        .filter(|i| may_contain(hash_str(&format!("absent {}", i))))
+        .count();
+    assert!(false_positives < 300, "{} false positives", false_positives);
+
+    // Filters are only replaced when their bin's table grows.
+    let initial = super::init::config().bin_capacity().max(MIN_CAPACITY);
+    let max_replaced = super::bin_stats()
+        .iter()
+        .map(|b| (b.table_capacity / initial).max(1).ilog2() as usize)
+        .sum::<usize>();
+    let replaced = RETIRED.lock().len();
+    assert!(replaced <= max_replaced, "{} > {}", replaced, max_replaced);
+    assert_eq!(existing_ustr("absent 1"), None);
+    assert!(!super::Ustr::is_interned("absent 2"));
+
+    // Replacing the cache doesn't leave stale filters behind.
+    {
+        let _guard = CacheGuard::new();
+        assert_eq!(existing_ustr("present 1"), None);
+        u("guarded");
+        assert!(existing_ustr("guarded").is_some());
+    }
+    assert_eq!(existing_ustr("present 1"), Some(present[1]));
+    u("after");
+    assert!(present.iter().all(|p| existing_ustr(p) == Some(*p)));
+    assert_eq!(existing_ustr("guarded"), None);
+
+    unsafe { super::_clear_cache() };
+    assert!(RETIRED.lock().is_empty());
+    assert!(may_contain(present[0].precomputed_hash()));
+    assert_eq!(existing_ustr("present 0"), None);
+}
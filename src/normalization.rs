@@ -0,0 +1,119 @@
+use std::{borrow::Cow, sync::OnceLock};
+use unicode_normalization::{
+    is_nfc_quick, is_nfd_quick, is_nfkc_quick, is_nfkd_quick, IsNormalized,
+    UnicodeNormalization,
+};
+
+/// A Unicode normalization form to apply to strings before they are added to
+/// (or looked up in) the cache.
+///
+/// Visually identical strings can be made up of different sequences of code
+/// points, e.g. macOS stores filenames decomposed (NFD) while most other
+/// systems produce composed (NFC) text. Normalizing on the way in makes sure
+/// they all end up as the same `Ustr`.
+///
+/// This is available with the `unicode-normalization` feature.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Normalization {
+    /// Store strings exactly as given.
+    None,
+    /// Normalization Form C: canonical decomposition followed by canonical
+    /// composition.
+    #[default]
+    Nfc,
+    /// Normalization Form D: canonical decomposition.
+    Nfd,
+    /// Normalization Form KC: compatibility decomposition followed by
+    /// canonical composition.
+    Nfkc,
+    /// Normalization Form KD: compatibility decomposition.
+    Nfkd,
+}
+
+static NORMALIZATION: OnceLock<Normalization> = OnceLock::new();
+
+/// Set the normalization form applied to every string passed to
+/// [`Ustr::from`](crate::Ustr::from) and friends.
+///
+/// No normalization is done unless this is called. It must be called before
+/// the cache is first used, otherwise strings interned before and after the
+/// change could be inconsistent. If the normalization form has already been
+/// set (or the default has already been used) then `normalization` is handed
+/// back in the `Err`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, Normalization};
+///
+/// // We're too late, the cache has already been used.
+/// let _ = ustr("hello");
+/// assert!(ustr::set_normalization(Normalization::Nfc).is_err());
+/// ```
+pub fn set_normalization(
+    normalization: Normalization,
+) -> Result<(), Normalization> {
+    NORMALIZATION.set(normalization)
+}
+
+/// Returns the normalization form applied to strings entering the cache.
+pub fn normalization() -> Normalization {
+    *NORMALIZATION.get_or_init(|| Normalization::None)
+}
+
+// Normalize `s` according to the current normalization form, only allocating
+// if it isn't already normalized.
+pub(crate) fn normalize(s: &str) -> Cow<'_, str> {
+    normalize_with(s, normalization())
+}
+
+fn normalize_with(s: &str, normalization: Normalization) -> Cow<'_, str> {
+    // ASCII is the same in every form, so skip the more expensive checks for
+    // the common case.
+    if normalization == Normalization::None || s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+
+    let quick = match normalization {
+        Normalization::None => unreachable!(),
+        Normalization::Nfc => is_nfc_quick(s.chars()),
+        Normalization::Nfd => is_nfd_quick(s.chars()),
+        Normalization::Nfkc => is_nfkc_quick(s.chars()),
+        Normalization::Nfkd => is_nfkd_quick(s.chars()),
+    };
+    if quick == IsNormalized::Yes {
+        return Cow::Borrowed(s);
+    }
+
+    Cow::Owned(match normalization {
+        Normalization::None => unreachable!(),
+        Normalization::Nfc => s.nfc().collect(),
+        Normalization::Nfd => s.nfd().collect(),
+        Normalization::Nfkc => s.nfkc().collect(),
+        Normalization::Nfkd => s.nfkd().collect(),
+    })
+}
+
+#[test]
+fn test_normalize() {
+    let composed = "caf\u{e9}";
+    let decomposed = "cafe\u{301}";
+
+    assert!(matches!(
+        normalize_with(decomposed, Normalization::None),
+        Cow::Borrowed(_)
+    ));
+    assert!(matches!(
+        normalize_with("plain ascii", Normalization::Nfd),
+        Cow::Borrowed(_)
+    ));
+    assert!(matches!(
+        normalize_with(composed, Normalization::Nfc),
+        Cow::Borrowed(_)
+    ));
+
+    assert_eq!(normalize_with(decomposed, Normalization::Nfc), composed);
+    assert_eq!(normalize_with(composed, Normalization::Nfd), decomposed);
+    assert_eq!(normalize_with("\u{fb01}", Normalization::Nfkc), "fi");
+    assert_eq!(normalize_with("\u{fb01}", Normalization::Nfc), "\u{fb01}");
+}
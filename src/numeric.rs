@@ -0,0 +1,169 @@
+use super::Ustr;
+use std::{cell::RefCell, fmt::Write};
+
+thread_local! {
+    // Reused for formatting so that interning a number that's already in the
+    // cache doesn't allocate.
+    static SCRATCH: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A primitive integer type that can be passed to [`Ustr::from_int()`].
+pub trait Integer: sealed::Sealed + Copy {
+    #[doc(hidden)]
+    fn push_to(self, string: &mut String);
+}
+
+/// A primitive floating-point type that can be passed to
+/// [`Ustr::from_float()`].
+pub trait Float: sealed::Sealed + Copy {
+    #[doc(hidden)]
+    fn push_to(self, string: &mut String);
+}
+
+macro_rules! impl_integer {
+    ($($t:ty)*) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl Integer for $t {
+            #[inline]
+            fn push_to(self, string: &mut String) {
+                string.push_str(itoa::Buffer::new().format(self));
+            }
+        }
+    )*};
+}
+
+impl_integer!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+macro_rules! impl_float {
+    ($($t:ty)*) => {$(
+        impl sealed::Sealed for $t {}
+
+        impl Float for $t {
+            #[inline]
+            fn push_to(self, string: &mut String) {
+                write!(string, "{}", self).expect("formatting can't fail");
+            }
+        }
+    )*};
+}
+
+impl_float!(f32 f64);
+
+// Clear the scratch buffer, let `f` fill it and intern the result. If `f`
+// calls this again, e.g. from an iterator it's draining, the inner call gets
+// a fresh buffer.
+pub(crate) fn with_scratch(f: impl FnOnce(&mut String)) -> Ustr {
+    SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+        Ok(mut scratch) => {
+            scratch.clear();
+            f(&mut scratch);
+            Ustr::from(&scratch)
+        }
+        Err(_) => {
+            let mut fresh = String::new();
+            f(&mut fresh);
+            Ustr::from(&fresh)
+        }
+    })
+}
+
+impl Ustr {
+    /// Create a `Ustr` from the decimal form of an integer.
+    ///
+    /// This gives the same string as `Ustr::from(&value.to_string())`, but is
+    /// faster and, if the string is already in the cache, doesn't allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// assert_eq!(Ustr::from_int(37), u("37"));
+    /// assert_eq!(Ustr::from_int(-1i8), u("-1"));
+    /// assert_eq!(Ustr::from_int(u64::MAX), u("18446744073709551615"));
+    /// ```
+    pub fn from_int<I: Integer>(value: I) -> Ustr {
+        with_scratch(|s| value.push_to(s))
+    }
+
+    /// Create a `Ustr` from `prefix` followed by the decimal form of an
+    /// integer, such as `"bone_37"`.
+    ///
+    /// This gives the same string as `Ustr::from(&format!("{}{}", prefix,
+    /// value))`, but is faster and, if the string is already in the cache,
+    /// doesn't allocate.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// assert_eq!(Ustr::from_prefixed_int("bone_", 37), u("bone_37"));
+    /// ```
+    pub fn from_prefixed_int<I: Integer>(prefix: &str, value: I) -> Ustr {
+        with_scratch(|s| {
+            s.push_str(prefix);
+            value.push_to(s);
+        })
+    }
+
+    /// Create a `Ustr` from a floating-point number, formatted as by its
+    /// `Display` impl.
+    ///
+    /// This gives the same string as `Ustr::from(&value.to_string())`, but if
+    /// the string is already in the cache, doesn't allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// assert_eq!(Ustr::from_float(24.0), u("24"));
+    /// assert_eq!(Ustr::from_float(0.1f32), u("0.1"));
+    /// assert_eq!(Ustr::from_float(f64::NAN), u("NaN"));
+    /// ```
+    pub fn from_float<F: Float>(value: F) -> Ustr {
+        with_scratch(|s| value.push_to(s))
+    }
+}
+
+#[test]
+fn test_from_numbers() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    assert_eq!(Ustr::from_int(0u8), u("0"));
+    assert_eq!(Ustr::from_int(i32::MIN), u(&i32::MIN.to_string()));
+    assert_eq!(Ustr::from_int(i128::MIN), u(&i128::MIN.to_string()));
+    assert_eq!(Ustr::from_int(u128::MAX), u(&u128::MAX.to_string()));
+    assert_eq!(Ustr::from_int(usize::MAX), u(&usize::MAX.to_string()));
+    for i in -1000..1000 {
+        assert_eq!(Ustr::from_int(i), u(&i.to_string()));
+        assert_eq!(
+            Ustr::from_prefixed_int("frame.", i as i64),
+            u(&format!("frame.{}", i))
+        );
+    }
+    assert_eq!(Ustr::from_prefixed_int("", 1u16), u("1"));
+
+    let floats = [0.0, -0.0, 1.5, -2.25, 1e30, 1e-7, f64::MAX, f64::INFINITY];
+    for f in floats {
+        assert_eq!(Ustr::from_float(f), u(&f.to_string()));
+        assert_eq!(Ustr::from_float(f as f32), u(&(f as f32).to_string()));
+    }
+    assert_eq!(Ustr::from_float(f32::NAN), u("NaN"));
+
+    // The scratch buffer doesn't leak into the next string.
+    assert_eq!(Ustr::from_int(7), u("7"));
+}
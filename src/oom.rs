@@ -0,0 +1,158 @@
+use parking_lot::RwLock;
+use std::{error::Error, fmt};
+
+/// An allocation the cache couldn't make, passed to the handler set with
+/// [`set_oom_handler()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OutOfMemory {
+    /// Size in bytes of the string arena the cache asked for.
+    pub size: usize,
+    /// Alignment of the arena the cache asked for.
+    pub align: usize,
+}
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of memory allocating a {} byte arena for the string cache",
+            self.size
+        )
+    }
+}
+
+impl Error for OutOfMemory {}
+
+/// What the cache should do when it runs out of memory, as returned by the
+/// handler set with [`set_oom_handler()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomAction {
+    /// Print the error to stderr and abort the process. This is what happens
+    /// if no handler has been set.
+    Abort,
+    /// Fail to add the string: [`Ustr::try_intern()`] returns
+    /// [`InternError::OutOfMemory`], and [`Ustr::from()`] panics.
+    ///
+    /// [`Ustr::try_intern()`]: crate::Ustr::try_intern
+    /// [`InternError::OutOfMemory`]: crate::InternError::OutOfMemory
+    /// [`Ustr::from()`]: crate::Ustr::from
+    Error,
+}
+
+type Handler = Box<dyn Fn(&OutOfMemory) -> OomAction + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Set the function that's called when the cache can't get the memory for a
+/// new string arena from its [`CacheAllocator`](crate::CacheAllocator).
+///
+/// The handler can log the failure or save state before deciding whether to
+/// abort the process, as the cache does by default, or to have the string
+/// that was being added fail with an error. Aborting always prints the error
+/// to stderr first, so that a job killed for lack of memory can be told
+/// apart from one that crashed.
+///
+/// The handler is called while the cache's lock on the string's bin is held,
+/// so it mustn't create `Ustr`s itself. Setting a handler replaces the last
+/// one.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{OomAction, OutOfMemory};
+///
+/// ustr::set_oom_handler(|oom: &OutOfMemory| {
+///     eprintln!("render job: {}, saving checkpoint", oom);
+///     OomAction::Error
+/// });
+/// # ustr::set_oom_handler(|_| OomAction::Abort);
+/// ```
+pub fn set_oom_handler<F>(handler: F)
+where
+    F: Fn(&OutOfMemory) -> OomAction + Send + Sync + 'static,
+{
+    *HANDLER.write() = Some(Box::new(handler));
+}
+
+// Run the handler for `oom`, returning it if the handler chose to fail the
+// allocation and never returning if it chose to abort.
+#[cold]
+pub(crate) fn handle(oom: OutOfMemory) -> OutOfMemory {
+    let action = match &*HANDLER.read() {
+        Some(handler) => handler(&oom),
+        None => OomAction::Abort,
+    };
+    match action {
+        OomAction::Error => oom,
+        OomAction::Abort => {
+            #[cfg(feature = "tracing")]
+            tracing::error!(target: "ustr", %oom, "aborting");
+            eprintln!("ustr: {}, aborting", oom);
+            std::process::abort();
+        }
+    }
+}
+
+#[test]
+fn test_oom_handler() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{
+        existing_ustr, num_entries, set_growth_policy, ustr as u, whichbin,
+        GrowthPolicy, InternError, Ustr,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    unsafe { super::_clear_cache() };
+
+    // Two strings in the same bin that each need an arena of their own.
+    let big = |i: usize| format!("{} {}", i, "x".repeat(1 << 20));
+    let bin = whichbin(super::hash_str(&big(0)));
+    let (first, second) = {
+        let mut same_bin = (0..)
+            .map(big)
+            .filter(|s| whichbin(super::hash_str(s)) == bin);
+        (same_bin.next().unwrap(), same_bin.next().unwrap())
+    };
+
+    static SIZE: AtomicUsize = AtomicUsize::new(0);
+    set_oom_handler(|oom| {
+        SIZE.store(oom.size, Ordering::Relaxed);
+        OomAction::Error
+    });
+    // The next arena is bigger than any allocator can provide.
+    set_growth_policy(GrowthPolicy {
+        chunk_size: Some(usize::MAX / 4),
+        ..GrowthPolicy::DEFAULT
+    });
+    let first = Ustr::try_intern(&first).unwrap();
+    let error = Ustr::try_intern(&second);
+    let panic = std::panic::catch_unwind(|| u(&second));
+    // Forks report it the same way.
+    let fork = super::ForkedCache::new();
+    let fork_error = (0..8)
+        .map(|i| fork.try_intern(&big(i)).map(|s| s.len()))
+        .find(Result::is_err);
+    drop(fork);
+    set_growth_policy(GrowthPolicy::DEFAULT);
+    set_oom_handler(|_| OomAction::Abort);
+
+    let oom = OutOfMemory {
+        size: usize::MAX / 4,
+        align: std::mem::align_of::<super::StringCacheEntry>(),
+    };
+    assert_eq!(SIZE.load(Ordering::Relaxed), usize::MAX / 4);
+    assert_eq!(error, Err(InternError::OutOfMemory(oom)));
+    assert_eq!(fork_error, Some(Err(InternError::OutOfMemory(oom))));
+    assert!(panic.is_err());
+    assert_eq!(existing_ustr(&second), None);
+    assert_eq!(num_entries(), 1);
+
+    // Nothing was left half-added.
+    let second = u(&second);
+    assert_eq!(first.id(), 0);
+    assert_eq!(second.id(), 1);
+    assert_eq!(num_entries(), 2);
+
+    unsafe { super::_clear_cache() };
+}
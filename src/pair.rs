@@ -0,0 +1,254 @@
+use super::{IdentityHasher, Ustr};
+use parking_lot::RwLock;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasherDefault, Hash, Hasher},
+};
+
+// An interned pair. These are leaked so they can be handed out as `'static`.
+struct PairEntry {
+    first: Ustr,
+    second: Ustr,
+    hash: u64,
+}
+
+// The key of a pair in `PAIRS`, which hashes as `pair_hash()` so the map can
+// use the identity hasher.
+#[derive(PartialEq, Eq)]
+struct PairKey(Ustr, Ustr);
+
+impl Hash for PairKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        pair_hash(self.0, self.1).hash(state);
+    }
+}
+
+type Pairs =
+    HashMap<PairKey, &'static PairEntry, BuildHasherDefault<IdentityHasher>>;
+
+static PAIRS: RwLock<Option<Pairs>> = RwLock::new(None);
+
+// Combine the hashes of the two halves of a pair, such that swapping them
+// gives a different hash.
+#[inline]
+fn pair_hash(first: Ustr, second: Ustr) -> u64 {
+    (first.precomputed_hash().rotate_left(5) ^ second.precomputed_hash())
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// An interned ordered pair of `Ustr`s, such as a namespace and a name.
+///
+/// Each distinct pair is stored once in a small cache of its own, so a
+/// `UstrPair` is a single pointer, and comparing two of them is a pointer
+/// comparison rather than comparing both halves. That matters for code that
+/// compares pairs over and over, such as looking up `(namespace, attribute)`
+/// bindings. Hashing uses a hash of the pair that's computed when it's
+/// interned, so `UstrPair`s work with the identity hasher too.
+///
+/// Like the strings in the global cache, interned pairs are never freed.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrPair};
+///
+/// let a = UstrPair::new(u("material"), u("diffuseColor"));
+/// let b = UstrPair::from(("material", "diffuseColor"));
+/// assert_eq!(a, b);
+/// assert_eq!(a.first(), u("material"));
+/// assert_eq!(a.second(), u("diffuseColor"));
+///
+/// // The order of the halves matters.
+/// let swapped = UstrPair::new(u("diffuseColor"), u("material"));
+/// assert_ne!(a, swapped);
+/// assert_eq!(UstrPair::get(u("material"), u("roughness")), None);
+/// ```
+#[derive(Copy, Clone)]
+#[repr(transparent)]
+pub struct UstrPair {
+    entry: &'static PairEntry,
+}
+
+impl UstrPair {
+    /// Intern the pair `(first, second)`, adding it to the pair cache if it
+    /// isn't already there.
+    pub fn new(first: Ustr, second: Ustr) -> UstrPair {
+        if let Some(pair) = UstrPair::get(first, second) {
+            return pair;
+        }
+        let mut pairs = PAIRS.write();
+        // Another thread may have added it while we waited for the lock.
+        let entry = pairs
+            .get_or_insert_with(Pairs::default)
+            .entry(PairKey(first, second))
+            .or_insert_with(|| {
+                Box::leak(Box::new(PairEntry {
+                    first,
+                    second,
+                    hash: pair_hash(first, second),
+                }))
+            });
+        UstrPair { entry }
+    }
+
+    /// Returns the pair `(first, second)` if it's been interned.
+    pub fn get(first: Ustr, second: Ustr) -> Option<UstrPair> {
+        let pairs = PAIRS.read();
+        let entry = pairs.as_ref()?.get(&PairKey(first, second))?;
+        Some(UstrPair { entry })
+    }
+
+    /// The first half of the pair.
+    #[inline]
+    pub fn first(&self) -> Ustr {
+        self.entry.first
+    }
+
+    /// The second half of the pair.
+    #[inline]
+    pub fn second(&self) -> Ustr {
+        self.entry.second
+    }
+
+    /// Both halves of the pair as a tuple.
+    #[inline]
+    pub fn as_tuple(&self) -> (Ustr, Ustr) {
+        (self.entry.first, self.entry.second)
+    }
+
+    /// Get the precomputed hash of the pair.
+    #[inline]
+    pub fn precomputed_hash(&self) -> u64 {
+        self.entry.hash
+    }
+
+    /// The number of distinct pairs that have been interned.
+    pub fn num_entries() -> usize {
+        PAIRS.read().as_ref().map_or(0, HashMap::len)
+    }
+}
+
+// Forget every interned pair. Only called by `_clear_cache()`.
+pub(crate) fn clear() {
+    *PAIRS.write() = None;
+}
+
+impl PartialEq for UstrPair {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.entry, other.entry)
+    }
+}
+
+impl Eq for UstrPair {}
+
+impl Hash for UstrPair {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.precomputed_hash().hash(state);
+    }
+}
+
+/// Orders by the first half and then the second, comparing the strings as
+/// for `Ustr`.
+impl Ord for UstrPair {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_tuple().cmp(&other.as_tuple())
+    }
+}
+
+impl PartialOrd for UstrPair {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<(Ustr, Ustr)> for UstrPair {
+    fn from((first, second): (Ustr, Ustr)) -> UstrPair {
+        UstrPair::new(first, second)
+    }
+}
+
+impl From<(&str, &str)> for UstrPair {
+    fn from((first, second): (&str, &str)) -> UstrPair {
+        UstrPair::new(Ustr::from(first), Ustr::from(second))
+    }
+}
+
+impl From<UstrPair> for (Ustr, Ustr) {
+    fn from(pair: UstrPair) -> (Ustr, Ustr) {
+        pair.as_tuple()
+    }
+}
+
+impl fmt::Debug for UstrPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("UstrPair")
+            .field(&self.first().as_str())
+            .field(&self.second().as_str())
+            .finish()
+    }
+}
+
+#[test]
+fn test_ustr_pair() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(UstrPair::num_entries(), 0);
+
+    let a = UstrPair::new(u("ns"), u("name"));
+    assert_eq!(UstrPair::new(u("ns"), u("name")), a);
+    assert_eq!(UstrPair::get(u("ns"), u("name")), Some(a));
+    assert_eq!(UstrPair::from(("ns", "name")), a);
+    assert_eq!(a.as_tuple(), (u("ns"), u("name")));
+    assert_eq!(<(Ustr, Ustr)>::from(a), (u("ns"), u("name")));
+    assert_eq!(format!("{:?}", a), r#"UstrPair("ns", "name")"#);
+
+    let b = UstrPair::new(u("name"), u("ns"));
+    assert_ne!(a, b);
+    assert_ne!(a.precomputed_hash(), b.precomputed_hash());
+    assert!(b < a);
+    assert_eq!(UstrPair::num_entries(), 2);
+
+    // Pairs sharing a half don't share a hash.
+    let mut hashes = (0..1000)
+        .flat_map(|i| {
+            let name = u(&format!("attr{}", i));
+            [u("ns a"), u("ns b")]
+                .map(|ns| UstrPair::new(ns, name).precomputed_hash())
+        })
+        .collect::<Vec<_>>();
+    hashes.sort_unstable();
+    hashes.dedup();
+    assert_eq!(hashes.len(), 2000);
+
+    // Pairs can be used as keys with the identity hasher.
+    let mut map = std::collections::HashMap::<
+        UstrPair,
+        usize,
+        BuildHasherDefault<IdentityHasher>,
+    >::default();
+    map.insert(a, 1);
+    map.insert(b, 2);
+    assert_eq!(map[&UstrPair::from(("ns", "name"))], 1);
+
+    // Pairs made on other threads are the same pairs.
+    let pairs = std::thread::scope(|s| {
+        let handles = (0..4)
+            .map(|_| s.spawn(|| UstrPair::from(("shared", "pair"))))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert!(pairs.iter().all(|p| *p == pairs[0]));
+    assert_eq!(UstrPair::num_entries(), 2003);
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(UstrPair::num_entries(), 0);
+    assert_eq!(UstrPair::get(u("ns"), u("name")), None);
+}
@@ -0,0 +1,112 @@
+//! Adapters that turn parsers of `&str` into parsers of [`Ustr`](crate::Ustr),
+//! with the `nom` and `winnow` features.
+//!
+//! Parsers are often the main source of new `Ustr`s, and the glue to intern
+//! what they return is always the same, so these wrap a parser that
+//! recognizes an identifier (or any other `&str`) and intern its output.
+//! The string is interned straight from the input slice, so nothing is
+//! copied unless it's new to the cache.
+
+/// Adapters for [`nom`](https://docs.rs/nom) parsers.
+#[cfg(feature = "nom")]
+pub mod nom {
+    use crate::Ustr;
+    use nom::Parser;
+
+    /// Wrap `parser` so that it returns a `Ustr` of what it recognized.
+    ///
+    /// # Panics
+    ///
+    /// The returned parser panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nom::{
+    ///     bytes::complete::take_while1, character::complete::char,
+    ///     sequence::separated_pair, Parser,
+    /// };
+    /// use ustr::{parse::nom::interned, ustr as u};
+    ///
+    /// let ident = || interned(take_while1(|c: char| c.is_alphanumeric()));
+    /// let mut assignment = separated_pair(ident(), char('='), ident());
+    /// let result: nom::IResult<_, _> = assignment.parse("color=red;");
+    /// assert_eq!(result, Ok((";", (u("color"), u("red")))));
+    /// ```
+    pub fn interned<'a, P>(
+        parser: P,
+    ) -> impl Parser<&'a str, Output = Ustr, Error = P::Error>
+    where
+        P: Parser<&'a str, Output = &'a str>,
+    {
+        parser.map(Ustr::from)
+    }
+}
+
+/// Adapters for [`winnow`](https://docs.rs/winnow) parsers.
+#[cfg(feature = "winnow")]
+pub mod winnow {
+    use crate::Ustr;
+    use winnow::Parser;
+
+    /// Wrap `parser` so that it returns a `Ustr` of what it recognized.
+    ///
+    /// # Panics
+    ///
+    /// The returned parser panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use winnow::{token::take_while, Parser};
+    /// use ustr::{parse::winnow::interned, ustr as u};
+    ///
+    /// let alphanumeric = |c: char| c.is_alphanumeric();
+    /// let mut ident = interned(take_while(1.., alphanumeric));
+    /// let mut input = "color=red";
+    /// let name: Result<_, ()> = ident.parse_next(&mut input);
+    /// assert_eq!(name, Ok(u("color")));
+    /// assert_eq!(input, "=red");
+    /// ```
+    pub fn interned<'a, P, E>(parser: P) -> impl Parser<&'a str, Ustr, E>
+    where
+        P: Parser<&'a str, &'a str, E>,
+    {
+        parser.map(Ustr::from)
+    }
+}
+
+#[cfg(feature = "nom")]
+#[test]
+fn test_nom() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use ::nom::{
+        bytes::complete::take_while1, character::complete::char,
+        multi::separated_list1, IResult, Parser,
+    };
+
+    let ident = nom::interned(take_while1(|c: char| c.is_alphanumeric()));
+    let mut list = separated_list1(char(','), ident);
+    let result: IResult<_, _> = list.parse("a,b,ab,a!");
+    assert_eq!(result, Ok(("!", vec![u("a"), u("b"), u("ab"), u("a")])));
+    assert!(list.parse(",").is_err());
+}
+
+#[cfg(feature = "winnow")]
+#[test]
+fn test_winnow() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use ::winnow::{combinator::separated, token::take_while, Parser};
+
+    let ident =
+        winnow::interned(take_while(1.., |c: char| c.is_alphanumeric()));
+    let mut list = separated(1.., ident, ',');
+    let mut input = "a,b,ab,a!";
+    let result: Result<Vec<_>, ()> = list.parse_next(&mut input);
+    assert_eq!(result, Ok(vec![u("a"), u("b"), u("ab"), u("a")]));
+    assert_eq!(input, "!");
+    let result: Result<Vec<_>, ()> = list.parse_next(&mut ",");
+    assert!(result.is_err());
+}
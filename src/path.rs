@@ -0,0 +1,311 @@
+use super::Ustr;
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    slice,
+    str::FromStr,
+};
+
+/// The separator between the components of a [`UstrPath`].
+pub const PATH_SEPARATOR: char = '/';
+
+/// An absolute, `/`-separated path made up of interned components, e.g. a
+/// prim path in a scene description like `/World/Geom/Sphere`.
+///
+/// Both the individual components and the full path are stored as `Ustr`s, so
+/// comparing and hashing paths is as cheap as for a `Ustr`, and walking up
+/// and down the hierarchy only ever touches the cache for the one component
+/// that changed.
+///
+/// Empty components are ignored, so `"a//b/"`, `"/a/b"` and `"a/b"` are all
+/// the same path.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr, UstrPath};
+///
+/// let mut path = UstrPath::new("/World/Geom");
+/// path.push("Sphere");
+/// assert_eq!(path.as_str(), "/World/Geom/Sphere");
+/// assert_eq!(path.name(), Some(ustr("Sphere")));
+///
+/// let parent = path.parent().unwrap();
+/// assert_eq!(parent, UstrPath::new("/World/Geom"));
+/// assert_eq!(parent.join("Cube/Mesh").as_str(), "/World/Geom/Cube/Mesh");
+///
+/// let names: Vec<&str> = path.iter().map(|c| c.as_str()).collect();
+/// assert_eq!(names, ["World", "Geom", "Sphere"]);
+/// ```
+#[derive(Clone)]
+pub struct UstrPath {
+    components: Vec<Ustr>,
+    joined: Ustr,
+}
+
+impl UstrPath {
+    /// The root path `/`, which has no components.
+    pub fn root() -> UstrPath {
+        UstrPath {
+            components: Vec::new(),
+            joined: Ustr::from("/"),
+        }
+    }
+
+    /// Create a new path by splitting `path` on `/`.
+    pub fn new(path: &str) -> UstrPath {
+        let mut result = UstrPath::root();
+        result.push(path);
+        result
+    }
+
+    /// Create a new path from its components.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the components is empty or contains a `/`.
+    pub fn from_components<I>(components: I) -> UstrPath
+    where
+        I: IntoIterator<Item = Ustr>,
+    {
+        let components = components.into_iter().collect::<Vec<_>>();
+        for c in &components {
+            assert!(
+                !c.is_empty() && !c.contains(PATH_SEPARATOR),
+                "invalid UstrPath component {:?}",
+                c
+            );
+        }
+        let joined = join_components(&components);
+        UstrPath { components, joined }
+    }
+
+    /// Append `path` to this path. If `path` contains `/` then each of its
+    /// components is appended in turn.
+    pub fn push(&mut self, path: &str) {
+        let len = self.components.len();
+        self.components.extend(
+            path.split(PATH_SEPARATOR)
+                .filter(|c| !c.is_empty())
+                .map(Ustr::from),
+        );
+        if self.components.len() != len {
+            self.joined = join_components(&self.components);
+        }
+    }
+
+    /// Remove the last component of this path and return it, or `None` if
+    /// this is the root.
+    pub fn pop(&mut self) -> Option<Ustr> {
+        let last = self.components.pop()?;
+        self.joined = join_components(&self.components);
+        Some(last)
+    }
+
+    /// Returns a new path with `path` appended to this one.
+    pub fn join(&self, path: &str) -> UstrPath {
+        let mut result = self.clone();
+        result.push(path);
+        result
+    }
+
+    /// Returns the path without its last component, or `None` if this is the
+    /// root.
+    pub fn parent(&self) -> Option<UstrPath> {
+        let (_, parent) = self.components.split_last()?;
+        Some(UstrPath {
+            components: parent.to_vec(),
+            joined: join_components(parent),
+        })
+    }
+
+    /// Returns the last component of this path, or `None` if this is the
+    /// root.
+    pub fn name(&self) -> Option<Ustr> {
+        self.components.last().copied()
+    }
+
+    /// Returns `true` if `base` is this path or one of its ancestors.
+    pub fn starts_with(&self, base: &UstrPath) -> bool {
+        self.components.starts_with(&base.components)
+    }
+
+    /// Returns `true` if this is the root path.
+    pub fn is_root(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// The number of components in this path.
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Returns `true` if this path has no components, i.e. is the root.
+    pub fn is_empty(&self) -> bool {
+        self.is_root()
+    }
+
+    /// The components of this path.
+    pub fn components(&self) -> &[Ustr] {
+        &self.components
+    }
+
+    /// Iterate over the components of this path.
+    pub fn iter(&self) -> slice::Iter<'_, Ustr> {
+        self.components.iter()
+    }
+
+    /// The full path as a `Ustr`.
+    pub fn as_ustr(&self) -> Ustr {
+        self.joined
+    }
+
+    /// The full path as a `&'static str`.
+    pub fn as_str(&self) -> &'static str {
+        self.joined.as_str()
+    }
+}
+
+fn join_components(components: &[Ustr]) -> Ustr {
+    if components.is_empty() {
+        return Ustr::from("/");
+    }
+    let len = components.iter().map(|c| c.len() + 1).sum();
+    let mut joined = String::with_capacity(len);
+    for c in components {
+        joined.push(PATH_SEPARATOR);
+        joined.push_str(c);
+    }
+    Ustr::from(joined.as_str())
+}
+
+impl Default for UstrPath {
+    fn default() -> Self {
+        UstrPath::root()
+    }
+}
+
+impl PartialEq for UstrPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.joined == other.joined
+    }
+}
+
+impl Eq for UstrPath {}
+
+impl Hash for UstrPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.joined.hash(state);
+    }
+}
+
+/// Paths are ordered component by component, so children sort directly after
+/// their parent.
+impl Ord for UstrPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.components.cmp(&other.components)
+    }
+}
+
+impl PartialOrd for UstrPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> IntoIterator for &'a UstrPath {
+    type Item = &'a Ustr;
+    type IntoIter = slice::Iter<'a, Ustr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl From<&str> for UstrPath {
+    fn from(path: &str) -> Self {
+        UstrPath::new(path)
+    }
+}
+
+impl FromStr for UstrPath {
+    type Err = std::string::ParseError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        Ok(UstrPath::new(path))
+    }
+}
+
+impl From<UstrPath> for Ustr {
+    fn from(path: UstrPath) -> Self {
+        path.joined
+    }
+}
+
+impl fmt::Display for UstrPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.joined)
+    }
+}
+
+impl fmt::Debug for UstrPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UstrPath({:?})", self.joined)
+    }
+}
+
+#[test]
+fn test_path() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    let root = UstrPath::root();
+    assert!(root.is_root());
+    assert_eq!(root.as_str(), "/");
+    assert_eq!(root.parent(), None);
+    assert_eq!(root.name(), None);
+    assert_eq!(UstrPath::new(""), root);
+    assert_eq!(UstrPath::new("//"), root);
+
+    let mut path = UstrPath::new("a//b/");
+    assert_eq!(path, UstrPath::new("/a/b"));
+    assert_eq!(path.as_ustr(), u("/a/b"));
+    assert_eq!(path.components(), [u("a"), u("b")]);
+
+    path.push("c/d");
+    assert_eq!(path.len(), 4);
+    assert_eq!(path.as_str(), "/a/b/c/d");
+    assert!(path.starts_with(&UstrPath::new("/a/b")));
+    assert!(!path.starts_with(&UstrPath::new("/a/c")));
+    assert!(path.starts_with(&root));
+
+    assert_eq!(path.pop(), Some(u("d")));
+    assert_eq!(path.as_str(), "/a/b/c");
+    assert_eq!(path.parent().unwrap().as_str(), "/a/b");
+    assert_eq!(path.join("x").as_str(), "/a/b/c/x");
+
+    assert_eq!(
+        UstrPath::from_components([u("a"), u("b")]),
+        UstrPath::new("a/b")
+    );
+
+    // Children sort directly after their parent.
+    let mut paths = [
+        UstrPath::new("/a-b"),
+        UstrPath::new("/a/b"),
+        UstrPath::new("/a"),
+    ];
+    paths.sort();
+    assert_eq!(
+        paths.iter().map(|p| p.as_str()).collect::<Vec<_>>(),
+        ["/a", "/a/b", "/a-b"]
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_path_invalid_component() {
+    let _t = super::TEST_LOCK.lock();
+    UstrPath::from_components([Ustr::from("a/b")]);
+}
@@ -0,0 +1,216 @@
+use super::{canonical_str, hash_str, simd, verify, StringCacheEntry, Ustr};
+use std::ptr::NonNull;
+
+// Average number of keys per bucket. Larger values make the table of seeds
+// smaller but take longer to build.
+const KEYS_PER_BUCKET: usize = 4;
+// Give up on a bucket after this many seeds. With distinct 64-bit hashes this
+// is never reached in practice.
+const MAX_SEED: u32 = 1 << 24;
+
+/// A minimal perfect hash table over the strings in a
+/// [`FrozenCache`](crate::FrozenCache), as returned by
+/// [`FrozenCache::compile_phf()`](crate::FrozenCache::compile_phf).
+///
+/// Every string maps to its own slot in a table exactly as big as the number
+/// of strings, so a lookup is the string's hash, a read of its bucket's seed
+/// and a read of the one slot it can be in, with no probing.
+///
+/// This uses the "hash, displace and compress" scheme: the strings are split
+/// into small buckets, and each bucket is given a seed under which all of its
+/// strings land in empty slots.
+#[derive(Debug)]
+pub struct PerfectHash {
+    seeds: Box<[u32]>,
+    entries: Box<[*const StringCacheEntry]>,
+}
+
+// The tables are never modified after they're built, and the entries they
+// point to are immutable and live forever.
+unsafe impl Send for PerfectHash {}
+unsafe impl Sync for PerfectHash {}
+
+// The bucket a hash falls in.
+#[inline]
+fn bucket(hash: u64, num_buckets: usize) -> usize {
+    ((hash >> 32) as usize) % num_buckets
+}
+
+// The slot a hash lands in under `seed`.
+#[inline]
+fn slot(hash: u64, seed: u32, len: usize) -> usize {
+    let mut x = hash ^ (seed as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    ((x ^ (x >> 31)) % len as u64) as usize
+}
+
+impl PerfectHash {
+    // Build a perfect hash over `entries`, or return `None` if two of them
+    // have the same 64-bit hash.
+    pub(crate) fn new(entries: &[*const StringCacheEntry]) -> Option<Self> {
+        let len = entries.len();
+        let num_buckets = len.div_ceil(KEYS_PER_BUCKET).max(1);
+
+        let mut buckets = vec![Vec::new(); num_buckets];
+        for e in entries {
+            // Entries always point to valid entries in the cache.
+            let hash = unsafe { (**e).hash };
+            buckets[bucket(hash, num_buckets)].push((hash, *e));
+        }
+
+        // Place the biggest buckets first, while there's plenty of room.
+        let mut order = (0..num_buckets).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|b| std::cmp::Reverse(buckets[*b].len()));
+
+        let mut seeds = vec![0u32; num_buckets].into_boxed_slice();
+        let mut table: Box<[*const StringCacheEntry]> =
+            vec![std::ptr::null(); len].into_boxed_slice();
+        let mut slots = Vec::new();
+        for b in order {
+            let bucket = &buckets[b];
+            if bucket.is_empty() {
+                break;
+            }
+            let mut seed = 0;
+            loop {
+                slots.clear();
+                let fits = bucket.iter().all(|(hash, _)| {
+                    let s = slot(*hash, seed, len);
+                    if table[s].is_null() && !slots.contains(&s) {
+                        slots.push(s);
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if fits {
+                    break;
+                }
+                seed += 1;
+                if seed == MAX_SEED {
+                    return None;
+                }
+            }
+            seeds[b] = seed;
+            for (s, (_, e)) in slots.iter().zip(bucket) {
+                table[*s] = *e;
+            }
+        }
+
+        Some(PerfectHash {
+            seeds,
+            entries: table,
+        })
+    }
+
+    // Look up a string that has already been through `canonical_str()`.
+    #[inline]
+    pub(crate) fn get_canonical(
+        &self,
+        string: &str,
+        hash: u64,
+    ) -> Option<Ustr> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let seed = self.seeds[bucket(hash, self.seeds.len())];
+        let entry = self.entries[slot(hash, seed, self.entries.len())];
+        // Every slot is filled, with a valid entry in the cache.
+        let sce = unsafe { &*entry };
+        if sce.hash != hash || sce.len != string.len() {
+            return None;
+        }
+        // Like the bins, trust the hash alone if verification is turned off
+        // with `set_verification()`.
+        let chars =
+            unsafe { std::slice::from_raw_parts(sce.char_ptr(), sce.len) };
+        if verify::hash_only() || simd::bytes_eq(chars, string.as_bytes()) {
+            Some(Ustr {
+                char_ptr: unsafe {
+                    NonNull::new_unchecked(sce.char_ptr() as *mut _)
+                },
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the `Ustr` for `string` if it's in the table.
+    pub fn get(&self, string: &str) -> Option<Ustr> {
+        let canonical = canonical_str(string).ok()?;
+        self.get_canonical(&canonical, hash_str(&canonical))
+    }
+
+    /// The number of strings in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the table has no strings.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the slot that `string` is stored in, which is unique to it
+    /// and less than [`len()`](PerfectHash::len), or `None` if it isn't in the
+    /// table.
+    pub fn index_of(&self, string: &str) -> Option<usize> {
+        let canonical = canonical_str(string).ok()?;
+        self.index_of_canonical(&canonical, hash_str(&canonical))
+    }
+
+    // The slot of a string that has already been through `canonical_str()`.
+    pub(crate) fn index_of_canonical(
+        &self,
+        string: &str,
+        hash: u64,
+    ) -> Option<usize> {
+        self.get_canonical(string, hash)?;
+        let seed = self.seeds[bucket(hash, self.seeds.len())];
+        Some(slot(hash, seed, self.entries.len()))
+    }
+}
+
+#[test]
+fn test_perfect_hash() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let empty = PerfectHash::new(&[]).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.get("anything"), None);
+
+    let words = (0..10_000)
+        .map(|i| u(&format!("phf {}", i)))
+        .collect::<Vec<_>>();
+    let entries = words
+        .iter()
+        .map(|w| unsafe { (w.as_char_ptr() as *const StringCacheEntry).sub(1) })
+        .collect::<Vec<_>>();
+    let phf = PerfectHash::new(&entries).unwrap();
+    assert_eq!(phf.len(), words.len());
+
+    let mut seen = vec![false; phf.len()];
+    for w in &words {
+        assert_eq!(phf.get(w), Some(*w));
+        let i = phf.index_of(w).unwrap();
+        assert!(!seen[i]);
+        seen[i] = true;
+    }
+    assert_eq!(phf.get("phf 10000"), None);
+    assert_eq!(phf.index_of("phf 10000"), None);
+
+    // Lookups respect `set_verification()`, so a different string of the
+    // same length with a forged hash is only found when trusting hashes.
+    let hash = words[0].precomputed_hash();
+    assert_eq!(phf.get_canonical("phf x", hash), None);
+    crate::set_verification(crate::Verification::HashOnly);
+    assert_eq!(phf.get_canonical("phf x", hash), Some(words[0]));
+    crate::set_verification(crate::Verification::Full);
+
+    // Entries with the same hash can't be told apart.
+    assert!(PerfectHash::new(&[entries[0], entries[0]]).is_none());
+}
@@ -0,0 +1,120 @@
+use super::{mapped, Bins, DictionaryLoad};
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// The environment variable naming the dictionary to load.
+const PREWARM_VAR: &str = "USTR_PREWARM";
+
+// Whether the dictionary named by `USTR_PREWARM` has been loaded, or loading
+// it has been tried.
+static PREWARMED: AtomicBool = AtomicBool::new(false);
+
+/// Load the dictionary named by the `USTR_PREWARM` environment variable into
+/// the cache, if it's set.
+///
+/// This lets the strings a program is going to need be loaded from a
+/// dictionary written by [`build_dictionary()`] on deployment, without
+/// building it into the program. The file is loaded like
+/// [`load_dictionary()`] if the `mmap` feature is enabled, and is read into
+/// memory that's never freed if not.
+///
+/// Returns `Ok(None)` if `USTR_PREWARM` isn't set or is empty, or if the
+/// dictionary has already been loaded, or failed to load, by an earlier call
+/// or by the `prewarm` feature.
+///
+/// With the `prewarm` feature enabled, this is called when the cache is
+/// first used, so that setting `USTR_PREWARM` is enough to prewarm any
+/// program that uses `ustr`. Errors are reported on stderr, or through
+/// `tracing` with the `tracing` feature, and don't stop the program.
+///
+/// [`build_dictionary()`]: crate::build_dictionary
+/// [`load_dictionary()`]: crate::load_dictionary
+///
+/// # Examples
+///
+/// ```no_run
+/// // Run with USTR_PREWARM=/opt/app/strings.ustrdict
+/// match ustr::prewarm_from_env() {
+///     Ok(Some(load)) => println!("prewarmed {} strings", load.num_inserted),
+///     Ok(None) => {}
+///     Err(e) => eprintln!("couldn't prewarm the string cache: {}", e),
+/// }
+/// ```
+pub fn prewarm_from_env() -> io::Result<Option<DictionaryLoad>> {
+    prewarm(None)
+}
+
+fn prewarm(bins: Option<&Bins>) -> io::Result<Option<DictionaryLoad>> {
+    let Some(path) = std::env::var_os(PREWARM_VAR).filter(|p| !p.is_empty())
+    else {
+        return Ok(None);
+    };
+    if PREWARMED.swap(true, Ordering::AcqRel) {
+        return Ok(None);
+    }
+    mapped::load_file(bins, path.as_ref()).map(Some)
+}
+
+// Prewarm the global cache while it's being created. This can't use
+// `STRING_CACHE`, which is what's being created.
+#[cfg(feature = "prewarm")]
+pub(crate) fn prewarm_new_cache(bins: &Bins) {
+    if let Err(error) = prewarm(Some(bins)) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            target: "ustr",
+            %error,
+            "failed to load the dictionary named by {}",
+            PREWARM_VAR
+        );
+        #[cfg(not(feature = "tracing"))]
+        eprintln!(
+            "ustr: failed to load the dictionary named by {}: {}",
+            PREWARM_VAR, error
+        );
+    }
+}
+
+// Allow loading the dictionary again. Only called by `_clear_cache()`.
+pub(crate) fn clear() {
+    PREWARMED.store(false, Ordering::Release);
+}
+
+#[test]
+fn test_prewarm_from_env() {
+    let _t = super::TEST_LOCK.lock();
+    use super::existing_ustr;
+
+    unsafe { super::_clear_cache() };
+
+    let path = std::env::temp_dir()
+        .join(format!("ustr-prewarm-{}.ustrdict", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    super::build_dictionary(["prewarmed", "strings"], file).unwrap();
+
+    // Other tests don't read the variable, and they're kept out of the cache
+    // by the lock while it's set.
+    std::env::set_var(PREWARM_VAR, &path);
+    let load = prewarm_from_env();
+    let again = prewarm_from_env();
+    let found = existing_ustr("prewarmed");
+    unsafe { super::_clear_cache() };
+    std::env::set_var(PREWARM_VAR, path.with_extension("missing"));
+    let missing = prewarm_from_env();
+    std::env::set_var(PREWARM_VAR, "");
+    let empty = prewarm_from_env();
+    std::env::remove_var(PREWARM_VAR);
+    std::fs::remove_file(&path).unwrap();
+
+    let load = load.unwrap().unwrap();
+    assert_eq!(load.num_entries, 2);
+    assert_eq!(load.num_inserted, 2);
+    assert!(again.unwrap().is_none());
+    assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    assert!(empty.unwrap().is_none());
+    assert_eq!(found.unwrap(), "prewarmed");
+
+    unsafe { super::_clear_cache() };
+}
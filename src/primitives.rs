@@ -0,0 +1,53 @@
+// The synchronization primitives used on the path that adds a string to the
+// cache and publishes it to other threads: the bins' locks, the generation
+// counter and the id table.
+//
+// Normally these are parking_lot's locks and std's atomics. When built with
+// `RUSTFLAGS="--cfg loom"` they're loom's instead, so that `tests/loom.rs` can
+// model-check that path by running it under every interleaving of a few
+// threads. Statics holding them are declared with `lazy_static!` from here,
+// which loom resets between interleavings.
+
+#[cfg(not(loom))]
+pub(crate) use lazy_static::lazy_static;
+#[cfg(not(loom))]
+pub(crate) use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicPtr, AtomicU32};
+
+#[cfg(loom)]
+pub(crate) use loom::{
+    lazy_static,
+    sync::{
+        atomic::{AtomicPtr, AtomicU32},
+        RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+// loom's `RwLock` with parking_lot's API. Panics can't poison the lock since
+// the model stops at the first one.
+#[cfg(loom)]
+pub(crate) struct RwLock<T>(loom::sync::RwLock<T>);
+
+#[cfg(loom)]
+impl<T> RwLock<T> {
+    pub(crate) fn new(value: T) -> RwLock<T> {
+        RwLock(loom::sync::RwLock::new(value))
+    }
+
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap()
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap()
+    }
+
+    pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.0.try_read().ok()
+    }
+
+    pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.0.try_write().ok()
+    }
+}
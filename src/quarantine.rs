@@ -0,0 +1,190 @@
+use super::{hash_str, Ustr};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// Only interns strings the second time it sees them.
+///
+/// Some inputs are full of strings that are never seen again, such as UUIDs
+/// and request ids, mixed in with identifiers that come up over and over.
+/// Interning everything fills the cache with the one-offs, and since the
+/// cache never frees anything, a flood of them leaks memory for good. A
+/// `Quarantine` remembers the strings it's seen once in a small filter, and
+/// only interns a string when it sees it again, so recurring strings are
+/// interned while one-offs are only ever held by the caller.
+///
+/// The filter is probabilistic: it uses about 2 bytes per string, and a
+/// small fraction of strings (around 1 in 60) look like they've been seen
+/// before when they haven't, and are interned the first time. Once it's
+/// remembered `capacity` strings it forgets them all and starts again, so
+/// a string that recurs less often than that may never be interned. Strings
+/// that are already in the cache are always returned straight away.
+///
+/// All of this is lock-free, so one `Quarantine` can be shared between
+/// threads.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, Quarantine};
+///
+/// let quarantine = Quarantine::new(1 << 16);
+/// let path = "GET /index.html";
+/// assert_eq!(quarantine.intern(path), None);
+/// assert_eq!(quarantine.intern(path), Some(u(path)));
+///
+/// // Strings that are already interned don't wait.
+/// let method = u("GET");
+/// assert_eq!(quarantine.intern("GET"), Some(method));
+/// ```
+pub struct Quarantine {
+    bits: Box<[AtomicU64]>,
+    // `bits` holds a power of two bits, so this is that minus one.
+    mask: usize,
+    // Number of bits set since the filter was last cleared.
+    num_set: AtomicUsize,
+    // Clear the filter once this many bits are set.
+    max_set: usize,
+}
+
+impl Quarantine {
+    /// Create a quarantine that remembers up to `capacity` strings it's
+    /// seen once before forgetting them, using about 2 bytes for each.
+    pub fn new(capacity: usize) -> Quarantine {
+        // With 16 bits per string and 2 set for each, the filter is at most
+        // an eighth full, which keeps false positives to about 1 in 64.
+        let num_bits = capacity.max(4).saturating_mul(16).next_power_of_two();
+        Quarantine {
+            bits: (0..num_bits / 64).map(|_| AtomicU64::new(0)).collect(),
+            mask: num_bits - 1,
+            num_set: AtomicUsize::new(0),
+            max_set: capacity.max(1).saturating_mul(2),
+        }
+    }
+
+    /// Intern `string` if it's already in the cache or if this is the second
+    /// time it's been seen, or remember it and return `None` if it's the
+    /// first.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn intern(&self, string: &str) -> Option<Ustr> {
+        if let Some(u) = Ustr::from_existing(string) {
+            return Some(u);
+        }
+        if self.check_and_set(hash_str(string)) {
+            Some(Ustr::from(string))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if `string` has been seen once, so that the next call
+    /// to [`intern()`](Quarantine::intern) will intern it. This can return
+    /// `true` for strings that haven't been seen.
+    pub fn is_quarantined(&self, string: &str) -> bool {
+        let (a, b) = self.bits_of(hash_str(string));
+        self.get(a) && self.get(b)
+    }
+
+    /// Forget every string that's been seen once.
+    pub fn clear(&self) {
+        for word in self.bits.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+        self.num_set.store(0, Ordering::Relaxed);
+    }
+
+    // The two bits that represent a string with hash `hash`.
+    #[inline]
+    fn bits_of(&self, hash: u64) -> (usize, usize) {
+        // The low bits of the hash choose a string's slot in the cache's
+        // tables, and the high ones its bin, so use each for one bit.
+        (hash as usize & self.mask, (hash >> 40) as usize & self.mask)
+    }
+
+    #[inline]
+    fn get(&self, bit: usize) -> bool {
+        self.bits[bit / 64].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+    }
+
+    // Set `bit`, returning whether it was already set.
+    #[inline]
+    fn set(&self, bit: usize) -> bool {
+        let mask = 1 << (bit % 64);
+        self.bits[bit / 64].fetch_or(mask, Ordering::Relaxed) & mask != 0
+    }
+
+    // Mark the string with hash `hash` as seen, returning whether it had
+    // been already.
+    fn check_and_set(&self, hash: u64) -> bool {
+        let (a, b) = self.bits_of(hash);
+        let seen = [self.set(a), self.set(b)];
+        if seen == [true, true] {
+            return true;
+        }
+        let newly_set = seen.iter().filter(|s| !**s).count();
+        let num_set = self.num_set.fetch_add(newly_set, Ordering::Relaxed);
+        if num_set + newly_set > self.max_set {
+            // Another thread might set bits while we clear them, which just
+            // means it might have to see its string a third time.
+            self.clear();
+        }
+        false
+    }
+}
+
+impl Default for Quarantine {
+    /// Create a quarantine that remembers up to 65536 strings, using 128KB.
+    fn default() -> Quarantine {
+        Quarantine::new(1 << 16)
+    }
+}
+
+impl fmt::Debug for Quarantine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Quarantine")
+            .field("capacity", &(self.max_set / 2))
+            .field("num_bits_set", &self.num_set.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+#[test]
+fn test_quarantine() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let quarantine = Quarantine::new(1000);
+    assert_eq!(quarantine.bits.len() * 64, 16384);
+    let existing = u("existing");
+    assert_eq!(quarantine.intern("existing"), Some(existing));
+    assert!(!quarantine.is_quarantined("recurring"));
+    assert_eq!(quarantine.intern("recurring"), None);
+    assert!(quarantine.is_quarantined("recurring"));
+    assert_eq!(super::num_entries(), 1);
+    assert_eq!(quarantine.intern("recurring"), Some(u("recurring")));
+    assert_eq!(quarantine.intern("recurring"), Some(u("recurring")));
+    quarantine.clear();
+    assert!(!quarantine.is_quarantined("recurring"));
+    assert_eq!(quarantine.intern("recurring"), Some(u("recurring")));
+
+    // A flood of unique strings barely touches the cache, and doesn't stop
+    // recurring ones being interned.
+    let before = super::num_entries();
+    let mut interned = 0;
+    for i in 0..100_000 {
+        let id = format!("request {:08x}", i);
+        interned += quarantine.intern(&id).is_some() as usize;
+        if i % 100 == 0 {
+            quarantine.intern("hot path");
+        }
+    }
+    assert!(interned < 100_000 / 30, "{} interned", interned);
+    assert!(super::existing_ustr("hot path").is_some());
+    assert!(super::num_entries() - before < 100_000 / 30);
+}
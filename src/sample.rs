@@ -0,0 +1,92 @@
+use super::{read_bin, Ustr, STRING_CACHE};
+use std::ptr::NonNull;
+
+/// Pick `n` different strings from the cache at random, with every string
+/// equally likely to be picked.
+///
+/// This is handy for turning a warmed-up cache into realistic inputs for
+/// fuzzing or property tests, or for showing a few of the strings a
+/// long-running process has interned on a dashboard. If the cache holds
+/// fewer than `n` strings then they're all returned, in a random order.
+///
+/// It takes the shared lock on every bin while it picks, so the sample is
+/// consistent, but it has to walk the bins' tables to find the strings, so
+/// it takes time proportional to the size of the cache.
+///
+/// This is available with the `rand` feature.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let names = ["vertex", "normal", "uv", "tangent"].map(u);
+/// let picked = ustr::sample(&mut rand::rng(), 2);
+/// assert_eq!(picked.len(), 2);
+/// assert_ne!(picked[0], picked[1]);
+/// assert!(picked.iter().all(|p| names.contains(p)));
+/// ```
+pub fn sample<R: rand::Rng + ?Sized>(rng: &mut R, n: usize) -> Vec<Ustr> {
+    let bins = STRING_CACHE.0.iter().map(read_bin).collect::<Vec<_>>();
+    let total = bins.iter().map(|sc| sc.num_entries()).sum::<usize>();
+
+    // Pick the positions of the strings among all the entries, then find
+    // them with a single walk over the bins, in position order.
+    let mut picks = rand::seq::index::sample(rng, total, n.min(total))
+        .into_iter()
+        .enumerate()
+        .map(|(i, position)| (position, i))
+        .collect::<Vec<_>>();
+    picks.sort_unstable();
+
+    let mut result = vec![None; picks.len()];
+    let mut picks = picks.into_iter().peekable();
+    let entries = bins.iter().flat_map(|sc| sc.entry_ptrs());
+    for (position, entry) in entries.enumerate() {
+        let Some(&(next, i)) = picks.peek() else {
+            break;
+        };
+        if position == next {
+            // SAFETY: entries are never null and live as long as the cache
+            let char_ptr = unsafe { (*entry).char_ptr() };
+            result[i] = Some(Ustr {
+                char_ptr: unsafe { NonNull::new_unchecked(char_ptr as *mut _) },
+            });
+            picks.next();
+        }
+    }
+    result.into_iter().flatten().collect()
+}
+
+#[test]
+fn test_sample() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{ustr as u, UstrSet};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    unsafe { super::_clear_cache() };
+
+    let mut rng = StdRng::seed_from_u64(7);
+    assert!(sample(&mut rng, 10).is_empty());
+
+    let all = (0..1000)
+        .map(|i| u(&format!("sample {}", i)))
+        .collect::<UstrSet>();
+    let picked = sample(&mut rng, 100);
+    assert_eq!(picked.len(), 100);
+    assert!(picked.iter().all(|p| all.contains(p)));
+    assert_eq!(picked.iter().copied().collect::<UstrSet>().len(), 100);
+
+    // Asking for more than there are gets them all.
+    assert_eq!(sample(&mut rng, 5000).into_iter().collect::<UstrSet>(), all);
+
+    // Every string gets picked about as often as the others.
+    let mut counts = super::UstrMap::default();
+    for _ in 0..200 {
+        for p in sample(&mut rng, 50) {
+            *counts.entry(p).or_insert(0) += 1;
+        }
+    }
+    assert!(counts.len() > 990);
+    assert!(counts.values().all(|&c| c < 40));
+}
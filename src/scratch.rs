@@ -0,0 +1,468 @@
+use super::{
+    canonical_str, hash_str, IdentityHasher, InternError, Interner,
+    InternerStats, StringCache, StringCacheEntry, StringCacheIterator, Ustr,
+};
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    marker::PhantomData,
+    ops::Deref,
+    ptr::NonNull,
+};
+
+// The number of consecutive frames each string has been seen in, by hash.
+type Streaks = HashMap<u64, u32, BuildHasherDefault<IdentityHasher>>;
+
+/// An interner for strings that only live for one frame, which promotes the
+/// ones that keep coming back to the global cache.
+///
+/// Code that runs once per frame, such as immediate-mode UI, tends to format
+/// new labels every frame ("FPS: 59.8", "Score: 1200"). Interning them fills
+/// the cache with strings that are never seen again, and since the cache
+/// never frees anything, that leaks memory for as long as the program runs.
+/// A `ScratchInterner` keeps the strings it hasn't seen before in its own
+/// storage, which is thrown away each frame by
+/// [`next_frame()`](ScratchInterner::next_frame), and moves a string to the
+/// global cache once it's been interned in `promote_after` frames in a row,
+/// so labels that never change end up as ordinary `Ustr`s.
+///
+/// Strings are handed out as [`ScratchUstr`]s, which borrow the interner, so
+/// the borrow checker makes sure none of them are still around when
+/// `next_frame()` frees them. Strings that are already in the global cache
+/// are returned from there and never use the scratch storage.
+///
+/// The scratch storage only holds the strings of the current frame. To
+/// decide what to promote the interner also remembers the hashes of the
+/// strings of the last frame, and stops tracking new strings once it's
+/// remembering `capacity` of them in a frame: they're still interned in the
+/// scratch storage, but they won't be promoted.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{existing_ustr, ustr, ScratchInterner};
+///
+/// let mut scratch = ScratchInterner::new(3, 1 << 16);
+/// for frame in 0..3 {
+///     let fps = scratch.intern(&format!("FPS: {}", 60 - frame));
+///     assert!(!fps.is_global());
+///     let title = scratch.intern("Inventory");
+///     assert_eq!(title.is_global(), frame == 2);
+///     scratch.next_frame();
+/// }
+/// assert_eq!(existing_ustr("FPS: 60"), None);
+/// assert_eq!(existing_ustr("Inventory"), Some(ustr("Inventory")));
+/// ```
+pub struct ScratchInterner {
+    frame: RwLock<Frame>,
+    promote_after: u32,
+    capacity: usize,
+}
+
+// The state that's thrown away or rolled over each frame.
+struct Frame {
+    cache: StringCache,
+    // Streaks of the strings seen this frame, and the frame before.
+    current: Streaks,
+    previous: Streaks,
+}
+
+impl ScratchInterner {
+    /// Create a scratch interner that promotes strings once they've been
+    /// interned in `promote_after` consecutive frames, remembering up to
+    /// `capacity` strings per frame to do so.
+    ///
+    /// A `promote_after` of 0 or 1 promotes every string straight away,
+    /// which is the same as interning it with [`ustr()`](crate::ustr).
+    pub fn new(promote_after: u32, capacity: usize) -> ScratchInterner {
+        ScratchInterner {
+            frame: RwLock::new(Frame {
+                cache: StringCache::new_local(),
+                current: Streaks::default(),
+                previous: Streaks::default(),
+            }),
+            promote_after: promote_after.max(1),
+            capacity,
+        }
+    }
+
+    /// Intern `string` for this frame, or in the global cache if it's
+    /// already there or has been interned in enough frames in a row.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn intern(&self, string: &str) -> ScratchUstr<'_> {
+        match self.try_intern(string) {
+            Ok(u) => u,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Intern `string`, returning an error if it can't be added to the
+    /// scratch storage or promoted.
+    pub fn try_intern(
+        &self,
+        string: &str,
+    ) -> Result<ScratchUstr<'_>, InternError> {
+        let canonical = canonical_str(string)?;
+        let string: &str = &canonical;
+        let hash = hash_str(string);
+
+        if let Some(u) = self.get_canonical(string, hash) {
+            return Ok(u);
+        }
+
+        let mut frame = self.frame.write();
+        // Another thread may have added it while we waited for the lock.
+        if let Some(ptr) = frame.cache.get_existing(string, hash) {
+            return Ok(unsafe { ScratchUstr::from_char_ptr(ptr) });
+        }
+        let streak = frame.previous.get(&hash).map_or(1, |n| n + 1);
+        if streak >= self.promote_after {
+            drop(frame);
+            return Ustr::intern_canonical(string, None).map(ScratchUstr::from);
+        }
+        if frame.current.len() < self.capacity {
+            frame.current.insert(hash, streak);
+        }
+        let ptr = frame
+            .cache
+            .try_insert(string, hash)
+            .map_err(InternError::OutOfMemory)?;
+        Ok(unsafe { ScratchUstr::from_char_ptr(ptr) })
+    }
+
+    /// Returns the string if it's been interned this frame or is in the
+    /// global cache.
+    pub fn get(&self, string: &str) -> Option<ScratchUstr<'_>> {
+        let canonical = canonical_str(string).ok()?;
+        let string: &str = &canonical;
+        self.get_canonical(string, hash_str(string))
+    }
+
+    fn get_canonical(
+        &self,
+        string: &str,
+        hash: u64,
+    ) -> Option<ScratchUstr<'_>> {
+        // Strings that have been promoted are the ones looked up most, so
+        // check the global cache first.
+        if let Some(u) = Ustr::from_existing(string) {
+            return Some(ScratchUstr::from(u));
+        }
+        let ptr = self.frame.read().cache.get_existing(string, hash)?;
+        Some(unsafe { ScratchUstr::from_char_ptr(ptr) })
+    }
+
+    /// Free the strings interned this frame, and start counting the next.
+    ///
+    /// Strings that weren't interned this frame lose their streak, so a
+    /// string has to be interned in `promote_after` frames with no gaps to
+    /// be promoted.
+    pub fn next_frame(&mut self) {
+        let frame = self.frame.get_mut();
+        std::mem::swap(&mut frame.current, &mut frame.previous);
+        frame.current.clear();
+        // Every `ScratchUstr` borrows us, so nothing points into the storage.
+        unsafe { frame.cache.clear() };
+    }
+
+    /// The number of strings in the scratch storage this frame (not counting
+    /// those found in or promoted to the global cache).
+    pub fn num_entries(&self) -> usize {
+        self.frame.read().cache.num_entries()
+    }
+
+    /// The number of consecutive frames a string has to be interned in to be
+    /// promoted to the global cache.
+    pub fn promote_after(&self) -> u32 {
+        self.promote_after
+    }
+
+    /// Iterate over the strings in the scratch storage this frame, in no
+    /// particular order.
+    pub fn iter_scratch(&self) -> impl Iterator<Item = ScratchUstr<'_>> {
+        let mut allocs = Vec::new();
+        self.frame.read().cache.alloc_ranges(&mut allocs);
+        StringCacheIterator::new(allocs)
+            .map(|s| unsafe { ScratchUstr::from_char_ptr(s.as_ptr()) })
+    }
+}
+
+impl Drop for ScratchInterner {
+    fn drop(&mut self) {
+        // As in `next_frame()`, no `ScratchUstr` can outlive us.
+        unsafe { self.frame.get_mut().cache.free() };
+    }
+}
+
+impl fmt::Debug for ScratchInterner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScratchInterner")
+            .field("num_entries", &self.num_entries())
+            .field("promote_after", &self.promote_after)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+/// A string interned with a [`ScratchInterner`], which may live in its
+/// scratch storage for the current frame or in the global cache.
+///
+/// Like [`Ustr`], this is a single pointer with the precomputed hash stored
+/// alongside the string, but it can't outlive the frame it came from.
+#[derive(Copy, Clone)]
+#[repr(transparent)]
+pub struct ScratchUstr<'a> {
+    char_ptr: NonNull<u8>,
+    _marker: PhantomData<&'a ScratchInterner>,
+}
+
+impl<'a> ScratchUstr<'a> {
+    // `char_ptr` must point to the chars of an entry in the scratch storage
+    // of the interner borrowed for `'a`.
+    unsafe fn from_char_ptr(char_ptr: *const u8) -> ScratchUstr<'a> {
+        ScratchUstr {
+            char_ptr: NonNull::new_unchecked(char_ptr as *mut u8),
+            _marker: PhantomData,
+        }
+    }
+
+    fn entry(&self) -> &StringCacheEntry {
+        // The entry header sits right before the chars, in the scratch
+        // storage or the global cache.
+        unsafe { &*(self.char_ptr.as_ptr().cast::<StringCacheEntry>().sub(1)) }
+    }
+
+    /// Get the string as a `str`, borrowed for as long as the frame.
+    pub fn as_str(&self) -> &'a str {
+        unsafe {
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+                self.char_ptr.as_ptr(),
+                self.entry().len,
+            ))
+        }
+    }
+
+    /// Call `f` with the string, returning what it returns.
+    ///
+    /// This is the same as [`Ustr::with_str()`], so code that takes either
+    /// kind of string can be written the same way.
+    #[inline]
+    pub fn with_str<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        f(self.as_str())
+    }
+
+    /// Get the length (in bytes) of this string.
+    pub fn len(&self) -> usize {
+        self.entry().len
+    }
+
+    /// Returns true if the length is zero.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the precomputed hash for this string.
+    pub fn precomputed_hash(&self) -> u64 {
+        self.entry().hash
+    }
+
+    /// Returns `true` if this string is in the global cache, i.e. it will
+    /// still be around after the next frame.
+    pub fn is_global(&self) -> bool {
+        self.to_global().is_some()
+    }
+
+    /// Returns this string as a `Ustr` if it's in the global cache.
+    pub fn to_global(&self) -> Option<Ustr> {
+        Ustr::from_existing(self.as_str())
+            .filter(|u| u.as_char_ptr() == self.char_ptr.as_ptr() as *const _)
+    }
+
+    /// Add this string to the global cache (if it isn't already there) and
+    /// return it as a `Ustr`, without waiting for it to be promoted.
+    pub fn to_ustr(&self) -> Ustr {
+        Ustr::from(self.as_str())
+    }
+}
+
+impl From<Ustr> for ScratchUstr<'_> {
+    fn from(u: Ustr) -> Self {
+        // Strings in the global cache live forever.
+        ScratchUstr {
+            char_ptr: u.char_ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A string may be interned in the scratch storage and then promoted later
+/// in the frame, so fall back to comparing the strings if the pointers
+/// differ.
+impl PartialEq for ScratchUstr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.char_ptr == other.char_ptr
+            || (self.precomputed_hash() == other.precomputed_hash()
+                && self.as_str() == other.as_str())
+    }
+}
+
+impl Eq for ScratchUstr<'_> {}
+
+impl PartialEq<Ustr> for ScratchUstr<'_> {
+    fn eq(&self, other: &Ustr) -> bool {
+        *self == ScratchUstr::from(*other)
+    }
+}
+
+impl PartialEq<str> for ScratchUstr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ScratchUstr<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for ScratchUstr<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.precomputed_hash().hash(state);
+    }
+}
+
+impl Deref for ScratchUstr<'_> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for ScratchUstr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for ScratchUstr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "u!({:?})", self.as_str())
+    }
+}
+
+// The interner is `Sync`, and the strings it points to are never modified
+// until it's borrowed mutably.
+unsafe impl Send for ScratchUstr<'_> {}
+unsafe impl Sync for ScratchUstr<'_> {}
+
+impl Interner for ScratchInterner {
+    type Symbol<'a> = ScratchUstr<'a>;
+    type Iter<'a> = Box<dyn Iterator<Item = ScratchUstr<'a>> + 'a>;
+
+    fn intern(&self, string: &str) -> ScratchUstr<'_> {
+        ScratchInterner::intern(self, string)
+    }
+
+    fn get(&self, string: &str) -> Option<ScratchUstr<'_>> {
+        ScratchInterner::get(self, string)
+    }
+
+    /// Iterates over this frame's scratch strings followed by the global
+    /// cache's.
+    fn iter(&self) -> Self::Iter<'_> {
+        Box::new(
+            self.iter_scratch()
+                .chain(super::cache().iter().map(ScratchUstr::from)),
+        )
+    }
+
+    /// Statistics for this frame's scratch storage only.
+    fn stats(&self) -> InternerStats {
+        let frame = self.frame.read();
+        InternerStats {
+            num_entries: frame.cache.num_entries(),
+            total_allocated: frame.cache.total_allocated(),
+            total_capacity: frame.cache.total_capacity(),
+        }
+    }
+}
+
+#[test]
+fn test_scratch_interner() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{existing_ustr, ustr as u};
+
+    unsafe { super::_clear_cache() };
+
+    let global = u("global");
+    let mut scratch = ScratchInterner::new(3, 100);
+    assert_eq!(scratch.promote_after(), 3);
+
+    // Labels that change every frame never reach the global cache, and the
+    // ones that don't are promoted on their third frame in a row.
+    for frame in 0..10 {
+        assert_eq!(scratch.intern("global"), global);
+        assert!(scratch.intern("global").is_global());
+        let fps = scratch.intern(&format!("fps {}", frame));
+        assert!(!fps.is_global());
+        assert_eq!(scratch.get(&format!("fps {}", frame)), Some(fps));
+        assert_eq!(fps.precomputed_hash(), hash_str(&fps));
+        let title = scratch.intern("title");
+        assert_eq!(title, "title");
+        assert_eq!(title.is_global(), frame >= 2);
+        assert_eq!(scratch.intern("title"), title);
+        assert_eq!(scratch.num_entries(), if frame >= 2 { 1 } else { 2 });
+        scratch.next_frame();
+        assert_eq!(scratch.num_entries(), 0);
+        assert_eq!(scratch.get(&format!("fps {}", frame)), None);
+    }
+    assert_eq!(existing_ustr("fps 0"), None);
+    assert_eq!(existing_ustr("title"), Some(u("title")));
+    assert_eq!(super::num_entries(), 2);
+
+    // Missing a frame resets a string's streak.
+    for frame in 0..5 {
+        if frame != 2 {
+            assert!(!scratch.intern("flicker").is_global());
+        }
+        scratch.next_frame();
+    }
+    assert_eq!(existing_ustr("flicker"), None);
+    assert!(scratch.intern("flicker").is_global());
+    scratch.next_frame();
+
+    // Only `capacity` strings a frame are tracked for promotion.
+    for frame in 0..3 {
+        for i in 0..200 {
+            scratch.intern(&format!("row {}", i));
+        }
+        let expected = if frame < 2 { 200 } else { 100 };
+        assert_eq!(scratch.iter_scratch().count(), expected);
+        assert_eq!(scratch.stats().num_entries, expected);
+        scratch.next_frame();
+    }
+    let promoted = (0..200)
+        .filter(|i| existing_ustr(&format!("row {}", i)).is_some())
+        .count();
+    assert_eq!(promoted, 100);
+
+    // A scratch string compares equal to the same string once promoted.
+    let mut scratch = ScratchInterner::new(2, 100);
+    let a = scratch.intern("late");
+    assert_eq!(a, u("late"));
+    assert!(!a.is_global());
+    assert_eq!(a.to_ustr(), u("late"));
+    scratch.next_frame();
+    assert_eq!(Interner::iter(&scratch).count(), super::num_entries());
+
+    // Every string is global straight away with a `promote_after` of 1.
+    let scratch = ScratchInterner::new(1, 100);
+    assert!(scratch.intern("at once").is_global());
+    assert_eq!(scratch.num_entries(), 0);
+}
@@ -0,0 +1,322 @@
+//! Intern strings as they're deserialized, for fields that aren't `Ustr`s.
+//!
+//! `Ustr` itself implements `Deserialize` by interning the string straight
+//! from the deserializer's buffer, so for a `Ustr` field, or an
+//! `Option<Ustr>`, `Vec<Ustr>` or `UstrMap<V>`, no temporary `String` is ever
+//! created. This module does the same for fields that hold the interned
+//! strings as `&'static str`, which can't otherwise be deserialized from
+//! input that doesn't outlive the program, and for containers of them.
+//!
+//! Use it with serde's `with` attribute:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Node {
+//!     #[serde(with = "ustr::serde_intern")]
+//!     name: &'static str,
+//!     #[serde(with = "ustr::serde_intern")]
+//!     parent: Option<&'static str>,
+//!     #[serde(with = "ustr::serde_intern")]
+//!     attributes: HashMap<&'static str, f32>,
+//! }
+//! ```
+//!
+//! Any type that implements [`Intern`] can be used. Values are serialized as
+//! the plain strings, so this only changes how they're deserialized.
+//!
+//! This is available with the `serde` feature.
+use super::Ustr;
+use serde::{
+    de::{Deserialize, Deserializer, MapAccess, Visitor},
+    ser::{Serialize, SerializeMap, Serializer},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+/// A type that can be serialized and deserialized with the
+/// [`serde_intern`](self) module.
+///
+/// This is implemented for `Ustr` and `&'static str`, and for `Option`s,
+/// `Vec`s, boxed slices and map keys of those. Map values are serialized and
+/// deserialized as usual, so they can't borrow from the input.
+pub trait Intern: Sized {
+    /// Serialize `self`.
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+
+    /// Deserialize a value, interning its strings.
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error>;
+}
+
+/// Serialize `value` with its strings as plain strings.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Intern,
+    S: Serializer,
+{
+    value.serialize_interned(serializer)
+}
+
+/// Deserialize a value, interning its strings without creating a temporary
+/// `String` for each.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+///
+/// let mut json = serde_json::Deserializer::from_str(r#"["a", "b", "a"]"#);
+/// let names: Vec<&'static str> = ustr::serde_intern::deserialize(&mut json)?;
+/// assert_eq!(names, ["a", "b", "a"]);
+/// assert_eq!(names[0].as_ptr(), u("a").as_char_ptr() as *const u8);
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Intern,
+    D: Deserializer<'de>,
+{
+    T::deserialize_interned(deserializer)
+}
+
+// Adapts an `Intern` type to serde's traits, for use inside containers.
+struct Interned<T>(T);
+
+impl<'de, T: Intern> Deserialize<'de> for Interned<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Interned<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_interned(deserializer).map(Interned)
+    }
+}
+
+struct InternedRef<'a, T>(&'a T);
+
+impl<T: Intern> Serialize for InternedRef<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize_interned(serializer)
+    }
+}
+
+impl Intern for Ustr {
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Ustr, D::Error> {
+        Ustr::deserialize(deserializer)
+    }
+}
+
+impl Intern for &'static str {
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<&'static str, D::Error> {
+        Ustr::deserialize(deserializer).map(|u| u.as_str())
+    }
+}
+
+impl<T: Intern> Intern for Option<T> {
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(value) => serializer.serialize_some(&InternedRef(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        Option::<Interned<T>>::deserialize(deserializer)
+            .map(|value| value.map(|i| i.0))
+    }
+}
+
+impl<T: Intern> Intern for Vec<T> {
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(InternedRef))
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error> {
+        Vec::<Interned<T>>::deserialize(deserializer)
+            .map(|values| values.into_iter().map(|i| i.0).collect())
+    }
+}
+
+impl<T: Intern> Intern for Box<[T]> {
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(InternedRef))
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<[T]>, D::Error> {
+        Vec::<T>::deserialize_interned(deserializer).map(Vec::into_boxed_slice)
+    }
+}
+
+// Serialize any map whose keys are `Intern`.
+fn serialize_map<'a, K, V, I, S>(
+    len: usize,
+    entries: I,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: Intern + 'a,
+    V: Serialize + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+    S: Serializer,
+{
+    let mut map = serializer.serialize_map(Some(len))?;
+    for (k, v) in entries {
+        map.serialize_entry(&InternedRef(k), v)?;
+    }
+    map.end()
+}
+
+// Deserializes a map into anything that can be extended with its entries.
+struct MapVisitor<M, K, V>(PhantomData<fn() -> M>, PhantomData<fn() -> (K, V)>);
+
+impl<'de, M, K, V> Visitor<'de> for MapVisitor<M, K, V>
+where
+    M: Default + Extend<(K, V)>,
+    K: Intern,
+    V: Deserialize<'de>,
+{
+    type Value = M;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<M, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = M::default();
+        while let Some((Interned(k), v)) = access.next_entry()? {
+            map.extend([(k, v)]);
+        }
+        Ok(map)
+    }
+}
+
+impl<K, V, H> Intern for HashMap<K, V, H>
+where
+    K: Intern + Hash + Eq,
+    V: Serialize + for<'de> Deserialize<'de>,
+    H: BuildHasher + Default,
+{
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_map(self.len(), self.iter(), serializer)
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<K, V, H>, D::Error> {
+        deserializer.deserialize_map(MapVisitor(PhantomData, PhantomData))
+    }
+}
+
+impl<K, V> Intern for BTreeMap<K, V>
+where
+    K: Intern + Ord,
+    V: Serialize + for<'de> Deserialize<'de>,
+{
+    fn serialize_interned<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_map(self.len(), self.iter(), serializer)
+    }
+
+    fn deserialize_interned<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<K, V>, D::Error> {
+        deserializer.deserialize_map(MapVisitor(PhantomData, PhantomData))
+    }
+}
+
+#[test]
+fn test_serde_intern() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{ustr as u, UstrMap};
+
+    unsafe { super::_clear_cache() };
+
+    fn roundtrip<T: Intern>(json: &str) -> T {
+        let value: T =
+            deserialize(&mut serde_json::Deserializer::from_str(json)).unwrap();
+        let mut out = Vec::new();
+        serialize(&value, &mut serde_json::Serializer::new(&mut out)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), json);
+        value
+    }
+
+    let name: &'static str = roundtrip(r#""node""#);
+    assert_eq!(name.as_ptr(), u("node").as_char_ptr() as *const u8);
+    // Escaped strings are unescaped into the deserializer's scratch buffer.
+    let escaped: &'static str = roundtrip(r#""tab\tbed""#);
+    assert_eq!(escaped.as_ptr(), u("tab\tbed").as_char_ptr() as *const u8);
+
+    assert_eq!(roundtrip::<Option<&str>>("null"), None);
+    assert_eq!(roundtrip::<Option<&str>>(r#""x""#), Some("x"));
+    assert_eq!(roundtrip::<Vec<Ustr>>(r#"["a","b"]"#), [u("a"), u("b")]);
+    assert_eq!(
+        &*roundtrip::<Box<[Option<&str>]>>(r#"["a",null]"#),
+        [Some("a"), None]
+    );
+    let tree = roundtrip::<BTreeMap<&str, Vec<u8>>>(r#"{"k":[1,2]}"#);
+    assert_eq!(tree["k"], [1, 2]);
+    let keys: &'static str = tree.keys().next().unwrap();
+    assert_eq!(keys.as_ptr(), u("k").as_char_ptr() as *const u8);
+    let map = roundtrip::<UstrMap<i32>>(r#"{"one":1}"#);
+    assert_eq!(map[&u("one")], 1);
+    let map: HashMap<&str, i32> =
+        deserialize(&mut serde_json::Deserializer::from_str(r#"{"a":1}"#))
+            .unwrap();
+    assert_eq!(map["a"], 1);
+
+    assert!(
+        deserialize::<&str, _>(&mut serde_json::Deserializer::from_str("1"))
+            .is_err()
+    );
+}
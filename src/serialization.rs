@@ -43,9 +43,9 @@ impl<'de> Visitor<'de> for BinsVisitor {
     where
         A: SeqAccess<'de>,
     {
-        while let Some(s) = seq.next_element::<String>()? {
-            ustr(&s);
-        }
+        // Deserializing each as a `Ustr` interns it without an intermediate
+        // `String`.
+        while seq.next_element::<Ustr>()?.is_some() {}
 
         Ok(DeserializedCache {})
     }
@@ -83,6 +83,20 @@ impl<'de> Visitor<'de> for UstrVisitor {
     {
         Ok(Ustr::from(s))
     }
+
+    // Binary formats may hand over strings as bytes, which can be interned
+    // without copying them into a `String` first.
+    fn visit_bytes<E>(self, b: &[u8]) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        match std::str::from_utf8(b) {
+            Ok(s) => Ok(Ustr::from(s)),
+            Err(_) => {
+                Err(E::invalid_value(serde::de::Unexpected::Bytes(b), &self))
+            }
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for Ustr {
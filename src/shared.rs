@@ -0,0 +1,338 @@
+#[cfg(feature = "negative-filter")]
+use super::negative::{Filters, LOCAL_FILTERS};
+use super::{
+    hash_str, primitives::AtomicU32, stringcache::LOCAL_NEXT_GENERATION, Bins,
+    StringCache, StringCacheEntry, LOCAL_CACHE, NUM_BINS,
+};
+use std::{
+    error::Error,
+    fmt,
+    mem::size_of,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        OnceLock,
+    },
+};
+
+/// The string cache of one copy of this crate, which other copies in the same
+/// process can use instead of their own.
+///
+/// Each copy of `ustr` that's statically linked into a binary has its own
+/// cache, so when a host application and the plugins it loads as `cdylib`s
+/// all use `ustr`, the same string can be interned once in each of them and
+/// `Ustr`s from different copies compare unequal even if their strings are
+/// the same. To avoid that, the host passes its [`shared_cache()`] to each
+/// plugin through its plugin API, and the plugin calls
+/// [`set_external_cache()`] with it before it interns anything.
+///
+/// `ustr_extern.rs` also exports the host's handle as `ustr_shared_cache()`,
+/// for plugins that look it up by symbol.
+///
+/// Only the strings themselves are shared. Each copy still has its own
+/// settings, such as [`set_max_len()`](crate::set_max_len) and
+/// [`set_allocator()`](crate::set_allocator), its own
+/// [`freeze()`](crate::freeze) snapshot, and [`from_id()`](crate::from_id)
+/// only finds the strings that were interned by the copy it's called in.
+#[repr(C)]
+pub struct SharedCache {
+    fingerprint: u64,
+    bins: &'static Bins,
+    next_generation: &'static AtomicU32,
+    #[cfg(feature = "negative-filter")]
+    filters: &'static Filters,
+}
+
+impl SharedCache {
+    pub(crate) fn bins(&self) -> &'static Bins {
+        self.bins
+    }
+
+    pub(crate) fn next_generation(&self) -> &'static AtomicU32 {
+        self.next_generation
+    }
+
+    #[cfg(feature = "negative-filter")]
+    pub(crate) fn filters(&self) -> &'static Filters {
+        self.filters
+    }
+}
+
+impl fmt::Debug for SharedCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedCache")
+            .field("fingerprint", &self.fingerprint)
+            .field("bins", &(self.bins as *const Bins))
+            .finish()
+    }
+}
+
+/// The error returned by [`set_external_cache()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttachError {
+    /// The cache belongs to a copy of this crate with a different version,
+    /// configuration or hasher, so the two can't share strings.
+    Incompatible,
+    /// This copy has already been attached to a different cache.
+    AlreadyAttached,
+    /// This copy has already interned strings in its own cache, which
+    /// `Ustr`s from the shared cache would never be equal to.
+    LocalStrings {
+        /// The number of strings in this copy's own cache.
+        num_strings: usize,
+    },
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttachError::Incompatible => {
+                write!(f, "the shared cache is from an incompatible ustr")
+            }
+            AttachError::AlreadyAttached => {
+                write!(f, "already attached to a different shared cache")
+            }
+            AttachError::LocalStrings { num_strings } => write!(
+                f,
+                "{} strings were interned before attaching to the shared cache",
+                num_strings
+            ),
+        }
+    }
+}
+
+impl Error for AttachError {}
+
+// The cache this copy has been attached to, or null if it uses its own.
+static EXTERNAL: AtomicPtr<SharedCache> = AtomicPtr::new(ptr::null_mut());
+
+#[inline]
+pub(crate) fn external() -> Option<&'static SharedCache> {
+    // Only ever set to a `&'static SharedCache`.
+    unsafe { EXTERNAL.load(Ordering::Acquire).as_ref() }
+}
+
+// Identifies the layout of the cache and the hash function, which must match
+// for two copies to share a cache.
+fn fingerprint() -> u64 {
+    hash_str(&format!(
+        "{} {} {} {} {} {}",
+        env!("CARGO_PKG_VERSION"),
+        NUM_BINS,
+        size_of::<StringCache>(),
+        size_of::<StringCacheEntry>(),
+        hash_str("ustr"),
+        // Copies without the filters wouldn't keep them up to date.
+        cfg!(feature = "negative-filter"),
+    ))
+}
+
+/// Get a handle to the cache this copy of the crate uses, to pass to
+/// [`set_external_cache()`] in other copies.
+///
+/// If this copy has itself been attached to another copy's cache, that cache
+/// is returned.
+///
+/// # Examples
+///
+/// ```
+/// let cache = ustr::shared_cache();
+/// // Attaching to the cache we're already using does nothing.
+/// assert_eq!(unsafe { ustr::set_external_cache(cache) }, Ok(()));
+/// assert!(!ustr::is_external_cache());
+/// ```
+pub fn shared_cache() -> &'static SharedCache {
+    static LOCAL: OnceLock<SharedCache> = OnceLock::new();
+    external().unwrap_or_else(|| {
+        LOCAL.get_or_init(|| SharedCache {
+            fingerprint: fingerprint(),
+            bins: &LOCAL_CACHE,
+            next_generation: &LOCAL_NEXT_GENERATION,
+            #[cfg(feature = "negative-filter")]
+            filters: &LOCAL_FILTERS,
+        })
+    })
+}
+
+/// Use the cache from another copy of this crate, such as the one in the host
+/// application that loaded this library, instead of this copy's own.
+///
+/// This must be called before this copy interns any strings, usually when the
+/// library is loaded. After that, every `Ustr` created by this copy is in the
+/// shared cache, so it's equal to a `Ustr` for the same string created by any
+/// other copy attached to the same cache. See [`SharedCache`] for what isn't
+/// shared.
+///
+/// Returns an error, and keeps using this copy's own cache, if the other copy
+/// is an incompatible version of `ustr`, if this copy has already been
+/// attached to another cache, or if it has already interned some strings.
+/// That last error is usually the symptom of a library that interns strings
+/// in its initialization before attaching, which would otherwise silently
+/// break equality between its `Ustr`s and the host's. Use
+/// [`owns_ptr()`](crate::owns_ptr) to check which cache a `Ustr` is from.
+///
+/// # Safety
+///
+/// `cache` must be a pointer returned by [`shared_cache()`] in a copy of
+/// `ustr` that's loaded in this process for as long as this copy is.
+pub unsafe fn set_external_cache(
+    cache: *const SharedCache,
+) -> Result<(), AttachError> {
+    let cache = &*cache;
+    if ptr::eq(cache, shared_cache()) {
+        return Ok(());
+    }
+    if cache.fingerprint != fingerprint() {
+        return Err(AttachError::Incompatible);
+    }
+    if external().is_some() {
+        return Err(AttachError::AlreadyAttached);
+    }
+    // Hold every lock on our own cache from counting its strings until the
+    // other cache is installed, so that nothing can be interned in between.
+    // Inserts that were waiting on one of these locks see the new cache once
+    // they get it and go there instead.
+    let bins = LOCAL_CACHE.0.iter().map(|b| b.write()).collect::<Vec<_>>();
+    let num_strings = bins.iter().map(|sc| sc.num_entries()).sum();
+    if num_strings != 0 {
+        return Err(AttachError::LocalStrings { num_strings });
+    }
+    EXTERNAL
+        .compare_exchange(
+            ptr::null_mut(),
+            cache as *const SharedCache as *mut SharedCache,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .map(|_| ())
+        .map_err(|_| AttachError::AlreadyAttached)
+}
+
+/// Returns true if this copy of the crate has been attached to another
+/// copy's cache with [`set_external_cache()`].
+pub fn is_external_cache() -> bool {
+    external().is_some()
+}
+
+#[test]
+fn test_shared_cache() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{num_entries, owns_ptr, ustr as u};
+
+    unsafe { super::_clear_cache() };
+
+    // A second cache, standing in for another copy of the crate.
+    let other_bins: &'static Bins = Box::leak(Box::new(Bins::new()));
+    let other_generation: &'static AtomicU32 =
+        Box::leak(Box::new(AtomicU32::new(0)));
+    #[cfg(feature = "negative-filter")]
+    let other_filters: &'static Filters = Box::leak(Box::new(Filters::new()));
+    let other: &'static SharedCache = Box::leak(Box::new(SharedCache {
+        fingerprint: fingerprint(),
+        bins: other_bins,
+        next_generation: other_generation,
+        #[cfg(feature = "negative-filter")]
+        filters: other_filters,
+    }));
+    let incompatible = SharedCache {
+        fingerprint: !fingerprint(),
+        bins: other_bins,
+        next_generation: other_generation,
+        #[cfg(feature = "negative-filter")]
+        filters: other_filters,
+    };
+
+    unsafe {
+        assert_eq!(set_external_cache(shared_cache()), Ok(()));
+        assert_eq!(
+            set_external_cache(&incompatible),
+            Err(AttachError::Incompatible)
+        );
+        let local = u("interned too early");
+        assert_eq!(
+            set_external_cache(other),
+            Err(AttachError::LocalStrings { num_strings: 1 })
+        );
+        assert!(!is_external_cache());
+        assert!(owns_ptr(local.as_char_ptr() as *const u8));
+
+        super::_clear_cache();
+        assert_eq!(set_external_cache(other), Ok(()));
+    }
+    assert!(is_external_cache());
+    assert!(ptr::eq(shared_cache(), other));
+
+    // Strings now go into the other cache.
+    let a = u("shared a");
+    let b = u("shared b");
+    assert_eq!(num_entries(), 2);
+    assert_eq!(other_generation.load(Ordering::Relaxed), 2);
+    assert_eq!(b.id(), 1);
+    assert_eq!(
+        LOCAL_CACHE
+            .0
+            .iter()
+            .map(|b| b.read().num_entries())
+            .sum::<usize>(),
+        0
+    );
+    let in_other =
+        |p: *const u8| other_bins.0.iter().any(|b| b.read().owns_ptr(p));
+    assert!(in_other(a.as_char_ptr() as *const u8));
+    assert_eq!(
+        unsafe { set_external_cache(&incompatible) },
+        Err(AttachError::Incompatible)
+    );
+
+    // Detach again so the other tests use the local cache.
+    EXTERNAL.store(ptr::null_mut(), Ordering::Release);
+    assert!(!in_other(u("local again").as_char_ptr() as *const u8));
+
+    // Strings interned while attaching either stop it or end up in the other
+    // cache, never in our own after it's attached.
+    let num_local = || {
+        LOCAL_CACHE
+            .0
+            .iter()
+            .map(|b| b.read().num_entries())
+            .sum::<usize>()
+    };
+    for round in 0..50 {
+        EXTERNAL.store(ptr::null_mut(), Ordering::Release);
+        unsafe { super::_clear_cache() };
+        let start = std::sync::Barrier::new(5);
+        let (attached, strings) = std::thread::scope(|s| {
+            let handles = (0..4)
+                .map(|t| {
+                    let start = &start;
+                    s.spawn(move || {
+                        start.wait();
+                        // Start later each round, so that the early rounds
+                        // intern first and the late ones attach first.
+                        for _ in 0..round * 4000 {
+                            std::hint::spin_loop();
+                        }
+                        u(&format!("race {} {}", round, t))
+                    })
+                })
+                .collect::<Vec<_>>();
+            start.wait();
+            let attached = unsafe { set_external_cache(other) };
+            let strings = handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>();
+            (attached, strings)
+        });
+        if attached.is_ok() {
+            assert_eq!(num_local(), 0);
+            assert!(strings.iter().all(|u| in_other(u.as_char_ptr() as _)));
+        } else {
+            assert!(matches!(attached, Err(AttachError::LocalStrings { .. })));
+        }
+    }
+
+    EXTERNAL.store(ptr::null_mut(), Ordering::Release);
+    unsafe { super::_clear_cache() };
+}
@@ -0,0 +1,87 @@
+// Comparing the bytes of a string we're looking up with an entry in the cache
+// whose hash and length match. Since the hashes match, the strings are nearly
+// always equal and every byte has to be compared, so for long strings such as
+// file paths this is worth vectorizing. The hash itself is left alone: it has
+// to be the same in every process, whatever CPU it's running on.
+
+/// Check if `a` and `b`, which must be the same length, are equal.
+#[inline]
+pub(crate) fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    debug_assert_eq!(a.len(), b.len());
+    #[cfg(all(target_arch = "x86_64", not(miri)))]
+    {
+        if a.len() >= 32 && std::is_x86_feature_detected!("avx2") {
+            // SAFETY: We've just checked that AVX2 is available.
+            return unsafe { x86_64::eq_avx2(a, b) };
+        }
+        if a.len() >= 16 {
+            // SSE2 is always available on x86_64.
+            return unsafe { x86_64::eq_sse2(a, b) };
+        }
+    }
+    a == b
+}
+
+#[cfg(all(target_arch = "x86_64", not(miri)))]
+mod x86_64 {
+    use std::arch::x86_64::*;
+
+    // Both of these compare a vector at a time, then compare the last full
+    // vector of the strings, which overlaps with bytes already compared, so
+    // there's no scalar tail. `a` and `b` must be at least one vector long.
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn eq_avx2(a: &[u8], b: &[u8]) -> bool {
+        let len = a.len();
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let eq = |i: usize| {
+            let x = _mm256_loadu_si256(a.add(i) as *const __m256i);
+            let y = _mm256_loadu_si256(b.add(i) as *const __m256i);
+            _mm256_movemask_epi8(_mm256_cmpeq_epi8(x, y)) == -1
+        };
+        let mut i = 0;
+        while i + 32 < len {
+            if !eq(i) {
+                return false;
+            }
+            i += 32;
+        }
+        eq(len - 32)
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn eq_sse2(a: &[u8], b: &[u8]) -> bool {
+        let len = a.len();
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let eq = |i: usize| {
+            let x = _mm_loadu_si128(a.add(i) as *const __m128i);
+            let y = _mm_loadu_si128(b.add(i) as *const __m128i);
+            _mm_movemask_epi8(_mm_cmpeq_epi8(x, y)) == 0xffff
+        };
+        let mut i = 0;
+        while i + 16 < len {
+            if !eq(i) {
+                return false;
+            }
+            i += 16;
+        }
+        eq(len - 16)
+    }
+}
+
+#[test]
+fn test_bytes_eq() {
+    let _t = super::TEST_LOCK.lock();
+
+    for len in (0..70).chain([255, 256, 257, 1000]) {
+        let a = (0..len).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        assert!(bytes_eq(&a, &a.clone()));
+        // A difference anywhere is found, including in the bytes that are
+        // compared twice.
+        for i in 0..len {
+            let mut b = a.clone();
+            b[i] ^= 0x80;
+            assert!(!bytes_eq(&a, &b), "len {} index {}", len, i);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use super::{cache, Interner, Ustr, UstrSet};
+use std::collections::HashMap;
+
+/// Find the strings in the cache that are within `max_distance` edits of
+/// `target`, for "did you mean" suggestions.
+///
+/// The distance is the Levenshtein distance counted in `char`s: the number of
+/// single-character insertions, deletions or substitutions needed to turn one
+/// string into the other. The results are sorted by distance, then
+/// alphabetically, and include `target` itself if it's in the cache.
+///
+/// This checks every string in the cache, skipping the ones whose length rules
+/// them out. If you search the same set of strings repeatedly, e.g. the
+/// identifiers in scope, build a [`SimilarityIndex`] instead.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr as u;
+/// # unsafe { ustr::_clear_cache() };
+///
+/// for name in ["length", "width", "height"] {
+///     u(name);
+/// }
+/// assert_eq!(ustr::similar("lenth", 1), [u("length")]);
+/// assert_eq!(ustr::similar("lenght", 2), [u("height"), u("length")]);
+/// assert_eq!(ustr::similar("weight", 1), [u("height")]);
+/// ```
+pub fn similar(target: &str, max_distance: usize) -> Vec<Ustr> {
+    let target = target.chars().collect::<Vec<_>>();
+    let mut matches = cache()
+        .iter()
+        .filter_map(|u| Some((distance_within(&target, &u, max_distance)?, u)))
+        .collect::<Vec<_>>();
+    sort_matches(&mut matches);
+    matches.into_iter().map(|(_, u)| u).collect()
+}
+
+fn sort_matches(matches: &mut [(usize, Ustr)]) {
+    matches.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+}
+
+// The Levenshtein distance between `a` and `b` if it's no more than `max`.
+fn distance_within(a: &[char], b: &str, max: usize) -> Option<usize> {
+    let b_len = b.chars().count();
+    if a.len().abs_diff(b_len) > max {
+        return None;
+    }
+
+    // Classic two-row dynamic programming, bailing out as soon as a whole row
+    // is over the limit.
+    let mut prev = (0..=a.len()).collect::<Vec<_>>();
+    let mut row = vec![0; a.len() + 1];
+    for (j, cb) in b.chars().enumerate() {
+        row[0] = j + 1;
+        let mut row_min = row[0];
+        for (i, ca) in a.iter().enumerate() {
+            let substitute = prev[i] + usize::from(*ca != cb);
+            row[i + 1] = substitute.min(prev[i + 1] + 1).min(row[i] + 1);
+            row_min = row_min.min(row[i + 1]);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    let distance = prev[a.len()];
+    (distance <= max).then_some(distance)
+}
+
+// Trigrams are taken over the string padded with two of these at each end, so
+// that every char is covered by three trigrams.
+const PAD: char = '\0';
+
+type Trigram = [char; 3];
+
+fn trigrams(chars: &[char]) -> HashMap<Trigram, u32> {
+    let mut padded = Vec::with_capacity(chars.len() + 4);
+    padded.extend([PAD, PAD]);
+    padded.extend_from_slice(chars);
+    padded.extend([PAD, PAD]);
+    let mut grams = HashMap::new();
+    for w in padded.windows(3) {
+        *grams.entry([w[0], w[1], w[2]]).or_insert(0) += 1;
+    }
+    grams
+}
+
+/// A trigram index over a set of strings, for repeated "did you mean" searches.
+///
+/// Each string is broken into overlapping three-char windows. Strings within a
+/// small edit distance of each other must share most of their trigrams, so a
+/// search only has to compute the edit distance to the few strings that share
+/// enough trigrams with the target, rather than to every string.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, SimilarityIndex};
+///
+/// let mut scope = SimilarityIndex::new();
+/// scope.extend(["counter", "count", "content", "context"].map(u));
+///
+/// assert_eq!(scope.similar("conter", 1), [u("counter")]);
+/// assert_eq!(scope.similar("contxt", 2), [u("context"), u("content")]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityIndex {
+    strings: Vec<(Ustr, u32)>,
+    present: UstrSet,
+    // For each trigram, the index into `strings` of every string with that
+    // trigram and the number of times it occurs in that string.
+    postings: HashMap<Trigram, Vec<(u32, u32)>>,
+}
+
+impl SimilarityIndex {
+    /// Create an empty index.
+    pub fn new() -> SimilarityIndex {
+        SimilarityIndex::default()
+    }
+
+    /// Add `u` to the index. Adding a string that's already there does
+    /// nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index already holds `u32::MAX` strings.
+    pub fn insert(&mut self, u: Ustr) {
+        if !self.present.insert(u) {
+            return;
+        }
+        let index = u32::try_from(self.strings.len())
+            .expect("too many strings for a SimilarityIndex");
+        let chars = u.chars().collect::<Vec<_>>();
+        self.strings.push((u, chars.len() as u32));
+        for (gram, count) in trigrams(&chars) {
+            self.postings.entry(gram).or_default().push((index, count));
+        }
+    }
+
+    /// The number of strings in the index.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns `true` if the index has no strings.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns `true` if `u` is in the index.
+    pub fn contains(&self, u: Ustr) -> bool {
+        self.present.contains(&u)
+    }
+
+    /// Find the strings in the index within `max_distance` edits of `target`,
+    /// sorted by distance and then alphabetically. See [`similar()`] for how
+    /// the distance is measured.
+    pub fn similar(&self, target: &str, max_distance: usize) -> Vec<Ustr> {
+        let target = target.chars().collect::<Vec<_>>();
+        let n = target.len();
+
+        // A string of `m` chars within `d` edits of the target shares at
+        // least `max(n, m) + 2 - 3d` trigrams with it, since each edit can
+        // break at most three. When that bound can't rule anything out, check
+        // every string.
+        let mut matches = Vec::new();
+        if n + 2 <= 3 * max_distance {
+            for (u, _) in &self.strings {
+                if let Some(d) = distance_within(&target, u, max_distance) {
+                    matches.push((d, *u));
+                }
+            }
+        } else {
+            let mut shared = HashMap::<u32, usize>::new();
+            for (gram, count) in trigrams(&target) {
+                for (index, c) in self.postings.get(&gram).into_iter().flatten()
+                {
+                    *shared.entry(*index).or_insert(0) +=
+                        count.min(*c) as usize;
+                }
+            }
+            for (index, common) in shared {
+                let (u, m) = self.strings[index as usize];
+                if common + 3 * max_distance < n.max(m as usize) + 2 {
+                    continue;
+                }
+                if let Some(d) = distance_within(&target, &u, max_distance) {
+                    matches.push((d, u));
+                }
+            }
+        }
+        sort_matches(&mut matches);
+        matches.into_iter().map(|(_, u)| u).collect()
+    }
+}
+
+impl Extend<Ustr> for SimilarityIndex {
+    fn extend<I: IntoIterator<Item = Ustr>>(&mut self, iter: I) {
+        for u in iter {
+            self.insert(u);
+        }
+    }
+}
+
+impl FromIterator<Ustr> for SimilarityIndex {
+    fn from_iter<I: IntoIterator<Item = Ustr>>(iter: I) -> SimilarityIndex {
+        let mut index = SimilarityIndex::new();
+        index.extend(iter);
+        index
+    }
+}
+
+#[test]
+fn test_similar() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let chars = |s: &str| s.chars().collect::<Vec<_>>();
+    assert_eq!(distance_within(&chars("kitten"), "sitting", 3), Some(3));
+    assert_eq!(distance_within(&chars("kitten"), "sitting", 2), None);
+    assert_eq!(distance_within(&chars(""), "abc", 3), Some(3));
+    assert_eq!(distance_within(&chars("naïve"), "naive", 1), Some(1));
+
+    let words = [
+        "apple", "apply", "ample", "maple", "applet", "banana", "bandana",
+        "cabana", "a", "b", "",
+    ];
+    let index = words.iter().map(|w| u(w)).collect::<SimilarityIndex>();
+    assert_eq!(index.len(), words.len());
+    assert!(index.contains(u("maple")));
+
+    // The index must agree with a scan of the whole cache, for both the
+    // filtered and the fallback paths.
+    let targets = ["appel", "aple", "banan", "x", "", "cabbage", "applesauce"];
+    for target in targets {
+        for max_distance in 0..4 {
+            assert_eq!(
+                index.similar(target, max_distance),
+                similar(target, max_distance),
+                "{:?} within {}",
+                target,
+                max_distance
+            );
+        }
+    }
+    assert_eq!(similar("apple", 0), [u("apple")]);
+    assert_eq!(
+        similar("apple", 1),
+        [u("apple"), u("ample"), u("applet"), u("apply")]
+    );
+    assert_eq!(similar("banan", 1), [u("banana")]);
+    assert!(similar("zzzzzzzz", 2).is_empty());
+}
@@ -0,0 +1,159 @@
+use super::{cache, Interner, Ustr};
+use std::{collections::HashSet, fmt, hash::Hash, ops::Deref};
+
+/// The set of strings in an [`Interner`] at some point in time, as returned
+/// by [`snapshot()`] or [`CacheSnapshot::of()`].
+///
+/// Comparing two snapshots with [`diff()`](CacheSnapshot::diff) tells you
+/// which strings were added in between, e.g. to find out which identifiers an
+/// operation leaks into the cache.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{snapshot, ustr as u};
+///
+/// let before = snapshot();
+/// u("temp_0");
+/// u("temp_1");
+/// let diff = before.diff(&snapshot());
+/// assert_eq!(diff.added, [u("temp_0"), u("temp_1")]);
+/// assert!(diff.removed.is_empty());
+/// ```
+pub struct CacheSnapshot<S = Ustr> {
+    symbols: HashSet<S>,
+}
+
+/// The difference between two [`CacheSnapshot`]s, as returned by
+/// [`CacheSnapshot::diff()`]. Both lists are sorted by string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDiff<S = Ustr> {
+    /// Strings in the newer snapshot that aren't in the older one.
+    pub added: Vec<S>,
+    /// Strings in the older snapshot that aren't in the newer one.
+    ///
+    /// The global cache and [`ForkedCache`](crate::ForkedCache)s never lose
+    /// strings, so this is only ever non-empty when the cache has been
+    /// replaced in between, such as by a
+    /// [`testing::CacheGuard`](crate::testing::CacheGuard).
+    pub removed: Vec<S>,
+}
+
+impl<S> CacheDiff<S> {
+    /// Check if the two snapshots had the same strings.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<S> CacheSnapshot<S>
+where
+    S: Clone + Eq + Hash + Deref<Target = str>,
+{
+    /// Take a snapshot of the strings in `interner`.
+    ///
+    /// Strings added by other threads while this runs may or may not be
+    /// included.
+    pub fn of<'a, I>(interner: &'a I) -> CacheSnapshot<I::Symbol<'a>>
+    where
+        I: Interner<Symbol<'a> = S> + ?Sized,
+    {
+        CacheSnapshot {
+            symbols: interner.iter().collect(),
+        }
+    }
+
+    /// The number of strings in the snapshot.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Check if the snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Check if `symbol` was in the interner when the snapshot was taken.
+    pub fn contains(&self, symbol: S) -> bool {
+        self.symbols.contains(&symbol)
+    }
+
+    /// Get the strings that are in `newer` but not in `self`, and the other
+    /// way around.
+    pub fn diff(&self, newer: &CacheSnapshot<S>) -> CacheDiff<S> {
+        let sorted = |a: &HashSet<S>, b: &HashSet<S>| {
+            let mut v = a.difference(b).cloned().collect::<Vec<_>>();
+            v.sort_unstable_by(|a, b| str::cmp(a, b));
+            v
+        };
+        CacheDiff {
+            added: sorted(&newer.symbols, &self.symbols),
+            removed: sorted(&self.symbols, &newer.symbols),
+        }
+    }
+}
+
+impl<S> fmt::Debug for CacheSnapshot<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheSnapshot")
+            .field("len", &self.symbols.len())
+            .finish()
+    }
+}
+
+/// Take a snapshot of the strings in the global cache.
+///
+/// This is the same as `CacheSnapshot::of(ustr::cache())`. See
+/// [`CacheSnapshot`] for details.
+pub fn snapshot() -> CacheSnapshot {
+    CacheSnapshot::of(cache())
+}
+
+#[test]
+fn test_snapshot() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{testing::CacheGuard, ustr as u, ForkedCache};
+
+    unsafe { super::_clear_cache() };
+
+    let empty = snapshot();
+    assert!(empty.is_empty());
+    let a = u("a");
+    let first = snapshot();
+    assert_eq!(first.len(), 1);
+    assert!(first.contains(a));
+    u("c");
+    u("b");
+    u("a");
+    let second = snapshot();
+
+    let diff = first.diff(&second);
+    assert_eq!(diff.added, [u("b"), u("c")]);
+    assert!(diff.removed.is_empty());
+    assert!(second.diff(&snapshot()).is_empty());
+    assert_eq!(
+        second.diff(&first),
+        CacheDiff {
+            added: vec![],
+            removed: vec![u("b"), u("c")],
+        }
+    );
+
+    // Strings are only removed if the cache is replaced.
+    let guarded = {
+        let _guard = CacheGuard::new();
+        u("d");
+        snapshot()
+    };
+    let diff = second.diff(&guarded);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.added[0], "d");
+    assert_eq!(diff.removed, [a, u("b"), u("c")]);
+
+    // Forks can be compared too.
+    let fork = ForkedCache::new();
+    let before = CacheSnapshot::of(&fork);
+    fork.intern("e");
+    let diff = before.diff(&CacheSnapshot::of(&fork));
+    assert_eq!(diff.added, [fork.intern("e")]);
+}
@@ -0,0 +1,317 @@
+use super::Ustr;
+use std::{cmp::Ordering, fmt, iter::FromIterator, slice};
+
+/// A set of `Ustr`s stored as a sorted `Vec`.
+///
+/// The strings are sorted by their address in the cache rather than by their
+/// contents. Strings never move, so that order never changes, and comparing
+/// two strings is a single integer comparison, without touching the strings
+/// themselves. Looking a string up is a binary search, and intersections,
+/// unions and differences walk both sets in step, in linear time with no
+/// hashing.
+///
+/// That makes this a good fit for lots of small sets of interned tags, such
+/// as the tags on each entity in a game, where a [`UstrSet`](crate::UstrSet)
+/// spends most of its memory on empty buckets and a `BTreeSet` compares
+/// strings byte by byte. Inserting into or removing from a big set is linear
+/// in its size though, so build sets in bulk with
+/// [`from_iter()`](UstrSortedSet::from_iter) or
+/// [`from_vec()`](UstrSortedSet::from_vec) where you can.
+///
+/// The order depends on where the strings ended up in memory, so it's not
+/// alphabetical and will be different from one run to the next. Use
+/// [`to_sorted_vec()`](UstrSortedSet::to_sorted_vec) when the order matters.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrSortedSet};
+///
+/// let player: UstrSortedSet = ["alive", "visible", "player"]
+///     .into_iter()
+///     .map(u)
+///     .collect();
+/// let enemy: UstrSortedSet =
+///     ["alive", "visible", "hostile"].into_iter().map(u).collect();
+///
+/// assert!(player.contains(u("player")));
+/// let both = player.intersection(&enemy);
+/// assert_eq!(both.to_sorted_vec(), [u("alive"), u("visible")]);
+/// assert_eq!(player.union(&enemy).len(), 4);
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct UstrSortedSet {
+    ustrs: Vec<Ustr>,
+}
+
+// The key the set is sorted by.
+#[inline]
+fn key(u: &Ustr) -> usize {
+    u.as_char_ptr() as usize
+}
+
+impl UstrSortedSet {
+    /// Create an empty set. This doesn't allocate until a string is added.
+    pub const fn new() -> UstrSortedSet {
+        UstrSortedSet { ustrs: Vec::new() }
+    }
+
+    /// Create an empty set with room for `capacity` strings without
+    /// reallocating.
+    pub fn with_capacity(capacity: usize) -> UstrSortedSet {
+        UstrSortedSet {
+            ustrs: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Create a set from the strings in `ustrs`, which may be in any order
+    /// and contain duplicates.
+    pub fn from_vec(mut ustrs: Vec<Ustr>) -> UstrSortedSet {
+        ustrs.sort_unstable_by_key(key);
+        ustrs.dedup();
+        UstrSortedSet { ustrs }
+    }
+
+    /// Returns the number of strings in the set.
+    pub fn len(&self) -> usize {
+        self.ustrs.len()
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ustrs.is_empty()
+    }
+
+    fn find(&self, u: Ustr) -> Result<usize, usize> {
+        let k = key(&u);
+        self.ustrs.binary_search_by_key(&k, key)
+    }
+
+    /// Returns `true` if `u` is in the set.
+    #[inline]
+    pub fn contains(&self, u: Ustr) -> bool {
+        self.find(u).is_ok()
+    }
+
+    /// Add `u` to the set, returning `true` if it wasn't there already.
+    pub fn insert(&mut self, u: Ustr) -> bool {
+        match self.find(u) {
+            Ok(_) => false,
+            Err(index) => {
+                self.ustrs.insert(index, u);
+                true
+            }
+        }
+    }
+
+    /// Remove `u` from the set, returning `true` if it was there.
+    pub fn remove(&mut self, u: Ustr) -> bool {
+        match self.find(u) {
+            Ok(index) => {
+                self.ustrs.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Remove every string, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.ustrs.clear();
+    }
+
+    /// Iterate over the strings, in the set's order.
+    pub fn iter(&self) -> std::iter::Copied<slice::Iter<'_, Ustr>> {
+        self.ustrs.iter().copied()
+    }
+
+    /// Get the strings as a slice, in the set's order.
+    pub fn as_slice(&self) -> &[Ustr] {
+        &self.ustrs
+    }
+
+    /// Get the strings sorted alphabetically.
+    pub fn to_sorted_vec(&self) -> Vec<Ustr> {
+        let mut v = self.ustrs.clone();
+        v.sort_unstable();
+        v
+    }
+
+    /// Get the strings that are in both `self` and `other`.
+    pub fn intersection(&self, other: &UstrSortedSet) -> UstrSortedSet {
+        let mut ustrs = Vec::with_capacity(self.len().min(other.len()));
+        merge(&self.ustrs, &other.ustrs, |u, ord| {
+            if ord == Ordering::Equal {
+                ustrs.push(u);
+            }
+        });
+        UstrSortedSet { ustrs }
+    }
+
+    /// Get the strings that are in `self`, `other` or both.
+    pub fn union(&self, other: &UstrSortedSet) -> UstrSortedSet {
+        let mut ustrs = Vec::with_capacity(self.len() + other.len());
+        merge(&self.ustrs, &other.ustrs, |u, _| ustrs.push(u));
+        UstrSortedSet { ustrs }
+    }
+
+    /// Get the strings that are in `self` but not in `other`.
+    pub fn difference(&self, other: &UstrSortedSet) -> UstrSortedSet {
+        let mut ustrs = Vec::with_capacity(self.len());
+        merge(&self.ustrs, &other.ustrs, |u, ord| {
+            if ord == Ordering::Less {
+                ustrs.push(u);
+            }
+        });
+        UstrSortedSet { ustrs }
+    }
+
+    /// Returns `true` if every string in `self` is also in `other`.
+    pub fn is_subset(&self, other: &UstrSortedSet) -> bool {
+        self.len() <= other.len() && self.difference(other).is_empty()
+    }
+
+    /// Returns `true` if `self` and `other` have no strings in common.
+    pub fn is_disjoint(&self, other: &UstrSortedSet) -> bool {
+        let mut disjoint = true;
+        merge(&self.ustrs, &other.ustrs, |_, ord| {
+            disjoint &= ord != Ordering::Equal;
+        });
+        disjoint
+    }
+}
+
+// Walk the sorted slices `a` and `b` in step, calling `f` once for each
+// distinct string, in order, with `Less` if it's only in `a`, `Greater` if
+// it's only in `b`, and `Equal` if it's in both.
+fn merge(a: &[Ustr], b: &[Ustr], mut f: impl FnMut(Ustr, Ordering)) {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match key(&a[i]).cmp(&key(&b[j])) {
+            Ordering::Less => {
+                f(a[i], Ordering::Less);
+                i += 1;
+            }
+            Ordering::Greater => {
+                f(b[j], Ordering::Greater);
+                j += 1;
+            }
+            Ordering::Equal => {
+                f(a[i], Ordering::Equal);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    for u in &a[i..] {
+        f(*u, Ordering::Less);
+    }
+    for u in &b[j..] {
+        f(*u, Ordering::Greater);
+    }
+}
+
+impl fmt::Debug for UstrSortedSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl From<Vec<Ustr>> for UstrSortedSet {
+    fn from(ustrs: Vec<Ustr>) -> UstrSortedSet {
+        UstrSortedSet::from_vec(ustrs)
+    }
+}
+
+impl FromIterator<Ustr> for UstrSortedSet {
+    fn from_iter<I: IntoIterator<Item = Ustr>>(iter: I) -> Self {
+        UstrSortedSet::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Ustr> for UstrSortedSet {
+    /// Adds all the strings and sorts the set once at the end, rather than
+    /// inserting them one at a time.
+    fn extend<I: IntoIterator<Item = Ustr>>(&mut self, iter: I) {
+        let mut ustrs = std::mem::take(&mut self.ustrs);
+        ustrs.extend(iter);
+        *self = UstrSortedSet::from_vec(ustrs);
+    }
+}
+
+impl<'a> IntoIterator for &'a UstrSortedSet {
+    type Item = Ustr;
+    type IntoIter = std::iter::Copied<slice::Iter<'a, Ustr>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for UstrSortedSet {
+    type Item = Ustr;
+    type IntoIter = std::vec::IntoIter<Ustr>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ustrs.into_iter()
+    }
+}
+
+#[test]
+fn test_ustr_sorted_set() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{ustr as u, UstrSet};
+
+    unsafe { super::_clear_cache() };
+
+    let tags = (0..20)
+        .map(|i| u(&format!("tag {}", i)))
+        .collect::<Vec<_>>();
+    let evens = tags.iter().step_by(2).copied().collect::<UstrSortedSet>();
+    let low = UstrSortedSet::from_vec(
+        tags[..10].iter().chain(&tags[..5]).rev().copied().collect(),
+    );
+    assert_eq!(evens.len(), 10);
+    assert_eq!(low.len(), 10);
+    assert!(evens.as_slice().windows(2).all(|w| key(&w[0]) < key(&w[1])));
+
+    // Check the set operations against `UstrSet`.
+    let as_set = |s: &UstrSortedSet| s.iter().collect::<UstrSet>();
+    let (e, l) = (as_set(&evens), as_set(&low));
+    assert_eq!(
+        as_set(&evens.intersection(&low)),
+        e.intersection(&l).copied().collect()
+    );
+    assert_eq!(as_set(&evens.union(&low)), e.union(&l).copied().collect());
+    assert_eq!(
+        as_set(&evens.difference(&low)),
+        e.difference(&l).copied().collect()
+    );
+    assert_eq!(
+        as_set(&low.difference(&evens)),
+        l.difference(&e).copied().collect()
+    );
+    assert_eq!(evens.union(&low), low.union(&evens));
+    assert!(evens.intersection(&low).is_subset(&evens));
+    assert!(!evens.is_subset(&low));
+    assert!(!evens.is_disjoint(&low));
+    assert!(evens.difference(&low).is_disjoint(&low));
+
+    let mut set = UstrSortedSet::new();
+    assert!(set.insert(tags[3]));
+    assert!(set.insert(tags[1]));
+    assert!(!set.insert(tags[3]));
+    assert!(set.contains(tags[1]));
+    assert!(!set.contains(tags[2]));
+    set.extend([tags[2], tags[1], tags[0]]);
+    assert_eq!(set.to_sorted_vec(), &tags[..4]);
+    assert!(set.remove(tags[0]));
+    assert!(!set.remove(tags[0]));
+    assert_eq!(set, UstrSortedSet::from(vec![tags[3], tags[2], tags[1]]));
+    assert_eq!((&set).into_iter().count(), 3);
+
+    let single = UstrSortedSet::from_iter([u("k")]);
+    assert_eq!(format!("{:?}", single), r#"{u!("k")}"#);
+    set.clear();
+    assert!(set.is_empty());
+}
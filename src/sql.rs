@@ -0,0 +1,141 @@
+//! Database column support, with the `sqlx` and `rusqlite` features.
+//!
+//! `Ustr` is stored as text. When decoding, the string is interned straight
+//! from the row's buffer, so reading identifier columns doesn't allocate a
+//! `String` for every row.
+#[cfg(feature = "sqlx")]
+mod sqlx_impls {
+    use crate::Ustr;
+    use sqlx::{
+        encode::IsNull, error::BoxDynError, Database, Decode, Encode, Type,
+    };
+
+    impl<DB: Database> Type<DB> for Ustr
+    where
+        str: Type<DB>,
+    {
+        fn type_info() -> DB::TypeInfo {
+            <str as Type<DB>>::type_info()
+        }
+
+        fn compatible(ty: &DB::TypeInfo) -> bool {
+            <str as Type<DB>>::compatible(ty)
+        }
+    }
+
+    impl<'q, DB: Database> Encode<'q, DB> for Ustr
+    where
+        &'q str: Encode<'q, DB>,
+    {
+        fn encode_by_ref(
+            &self,
+            buf: &mut DB::ArgumentBuffer<'q>,
+        ) -> Result<IsNull, BoxDynError> {
+            self.as_str().encode(buf)
+        }
+    }
+
+    impl<'r, DB: Database> Decode<'r, DB> for Ustr
+    where
+        &'r str: Decode<'r, DB>,
+    {
+        fn decode(value: DB::ValueRef<'r>) -> Result<Ustr, BoxDynError> {
+            let s = <&str as Decode<DB>>::decode(value)?;
+            Ok(Ustr::try_intern(s)?)
+        }
+    }
+}
+
+#[cfg(feature = "rusqlite")]
+mod rusqlite_impls {
+    use crate::Ustr;
+    use rusqlite::types::{
+        FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef,
+    };
+
+    impl ToSql for Ustr {
+        fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+            Ok(ToSqlOutput::from(self.as_str()))
+        }
+    }
+
+    impl FromSql for Ustr {
+        fn column_result(value: ValueRef<'_>) -> FromSqlResult<Ustr> {
+            let s = value.as_str()?;
+            Ustr::try_intern(s).map_err(|e| FromSqlError::Other(Box::new(e)))
+        }
+    }
+}
+
+#[cfg(all(feature = "sqlx", not(loom)))]
+#[test]
+fn test_sqlx() {
+    let _t = crate::TEST_LOCK.lock();
+    use crate::{ustr as u, Ustr};
+    use sqlx::{Connection, Row, SqliteConnection};
+
+    futures_executor::block_on(async {
+        let mut conn =
+            SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE nodes (name TEXT)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        for name in [u("root"), u("child/0"), u("child/1")] {
+            sqlx::query("INSERT INTO nodes VALUES (?)")
+                .bind(name)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+        }
+
+        let rows = sqlx::query("SELECT name FROM nodes ORDER BY name")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        let names = rows
+            .iter()
+            .map(|row| row.get::<Ustr, _>("name"))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [u("child/0"), u("child/1"), u("root")]);
+
+        let (name,): (Ustr,) = sqlx::query_as("SELECT 'from sql'")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(name, u("from sql"));
+    });
+}
+
+#[cfg(feature = "rusqlite")]
+#[test]
+fn test_rusqlite() {
+    let _t = crate::TEST_LOCK.lock();
+    use crate::{set_max_len, ustr as u, MaxLenPolicy, Ustr};
+    use rusqlite::Connection;
+
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute("CREATE TABLE nodes (name TEXT)", ()).unwrap();
+    for name in [u("root"), u("child/0"), u("child/1")] {
+        conn.execute("INSERT INTO nodes VALUES (?1)", [name])
+            .unwrap();
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT name FROM nodes ORDER BY name")
+        .unwrap();
+    let names = stmt
+        .query_map((), |row| row.get::<_, Ustr>(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(names, [u("child/0"), u("child/1"), u("root")]);
+
+    // Non-text columns and strings that can't be interned are errors.
+    let get = |sql| conn.query_row(sql, (), |row| row.get::<_, Ustr>(0));
+    assert!(get("SELECT 17").is_err());
+    set_max_len(4, MaxLenPolicy::Reject);
+    let too_long = get("SELECT 'too long'");
+    set_max_len(usize::MAX, MaxLenPolicy::Reject);
+    assert!(too_long.is_err());
+}
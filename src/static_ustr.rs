@@ -0,0 +1,238 @@
+use super::{canonical_str, StringCacheEntry, Ustr};
+use std::{
+    borrow::Cow,
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Mutex,
+    },
+};
+
+/// Storage for a string in static memory that can be added to the cache
+/// without copying it.
+///
+/// A `Ustr` expects its string to be preceded by a header and followed by a
+/// null terminator, so an arbitrary `&'static str` can't be referenced in
+/// place. A `StaticUstr` reserves room for the header in front of a copy of
+/// the string that the compiler puts in the binary's data, so when it's first
+/// interned with [`Ustr::intern_static()`] the cache fills in the header and
+/// points at the static bytes rather than copying them into its own storage.
+///
+/// You'll usually create these with the [`static_ustr!`](crate::static_ustr)
+/// macro, which works with `include_str!` too. If the string is already in the
+/// cache when the `StaticUstr` is first interned, or would be changed by
+/// normalization or the length limit, the existing or copied entry is used
+/// instead and the static storage goes unused.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, StaticUstr, Ustr};
+///
+/// static GREETING: StaticUstr<5> = StaticUstr::new("hello");
+///
+/// let greeting = Ustr::intern_static(&GREETING);
+/// assert_eq!(greeting, u("hello"));
+/// // No copy was made: the `Ustr` points into `GREETING`.
+/// assert_eq!(greeting.as_str().as_ptr(), GREETING.as_str().as_ptr());
+/// ```
+#[repr(C)]
+pub struct StaticUstr<const N: usize> {
+    // The chars must directly follow the header, as they do in the cache's
+    // storage.
+    header: UnsafeCell<MaybeUninit<StringCacheEntry>>,
+    chars: [u8; N],
+    nul: u8,
+    // The `Ustr` this was interned as, or null if it hasn't been yet. This is
+    // also at or beyond the end of the entry as the cache's iterators see it,
+    // so stepping past the entry stays within the struct.
+    interned: AtomicPtr<u8>,
+}
+
+// The header is only written while holding the bin's write lock, before the
+// entry is visible to any other thread.
+unsafe impl<const N: usize> Sync for StaticUstr<N> {}
+
+impl<const N: usize> StaticUstr<N> {
+    /// Create the storage for `string`, which must be `N` bytes long.
+    ///
+    /// # Panics
+    ///
+    /// Panics (at compile time in a `static`) if `string` isn't `N` bytes
+    /// long.
+    pub const fn new(string: &str) -> StaticUstr<N> {
+        let bytes = string.as_bytes();
+        assert!(bytes.len() == N, "string length doesn't match N");
+        let mut chars = [0u8; N];
+        let mut i = 0;
+        while i < N {
+            chars[i] = bytes[i];
+            i += 1;
+        }
+        StaticUstr {
+            header: UnsafeCell::new(MaybeUninit::uninit()),
+            chars,
+            nul: 0,
+            interned: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    /// The string, without interning it.
+    pub fn as_str(&self) -> &str {
+        // The chars were copied from a `str` in `new()`.
+        unsafe { std::str::from_utf8_unchecked(&self.chars) }
+    }
+}
+
+// Every `StaticUstr` that has been interned, so that `_clear_cache()` can
+// forget them.
+static INTERNED: Mutex<Vec<&'static AtomicPtr<u8>>> = Mutex::new(Vec::new());
+
+impl Ustr {
+    /// Get the `Ustr` for a string in static storage, adding it to the cache
+    /// without copying it if it isn't there already.
+    ///
+    /// After the first call this is a single atomic load. See [`StaticUstr`]
+    /// for details, and the [`static_ustr!`](crate::static_ustr) macro for a
+    /// shorthand.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    pub fn intern_static<const N: usize>(
+        storage: &'static StaticUstr<N>,
+    ) -> Ustr {
+        if let Some(char_ptr) =
+            NonNull::new(storage.interned.load(Ordering::Acquire))
+        {
+            return Ustr { char_ptr };
+        }
+
+        let string = storage.as_str();
+        let u = match canonical_str(string) {
+            Ok(Cow::Borrowed(canonical)) => {
+                debug_assert_eq!(canonical.as_ptr(), string.as_ptr());
+                // Take the pointer from the whole struct rather than just the
+                // header, since `Ustr` reads the chars through it too.
+                let header = (storage as *const StaticUstr<N>)
+                    .cast_mut()
+                    .cast::<StringCacheEntry>();
+                // SAFETY: `header` is followed by the chars of `string` and a
+                // null terminator, as checked in `test_static_ustr()`.
+                Ustr::intern_canonical(
+                    string,
+                    Some(unsafe { NonNull::new_unchecked(header) }),
+                )
+            }
+            Ok(Cow::Owned(canonical)) => {
+                Ustr::intern_canonical(&canonical, None)
+            }
+            Err(e) => Err(e),
+        }
+        .unwrap_or_else(|e| panic!("{}", e));
+
+        // Another thread may have got here first, but it'll have got the same
+        // `Ustr`.
+        if storage
+            .interned
+            .compare_exchange(
+                std::ptr::null_mut(),
+                u.char_ptr.as_ptr(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            INTERNED
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(&storage.interned);
+        }
+        u
+    }
+}
+
+/// Create a `Ustr` from a string constant without copying it into the cache.
+///
+/// This declares a [`StaticUstr`] for the string and interns it with
+/// [`Ustr::intern_static()`], so each use site costs a single atomic load after
+/// the first time it's run. The argument can be any constant `&str`
+/// expression, such as a literal or `include_str!(...)`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{static_ustr, ustr as u};
+///
+/// fn keyword() -> ustr::Ustr {
+///     static_ustr!("struct")
+/// }
+/// assert_eq!(keyword(), u("struct"));
+/// assert_eq!(keyword().as_char_ptr(), keyword().as_char_ptr());
+/// ```
+#[macro_export]
+macro_rules! static_ustr {
+    ($string:expr) => {{
+        const STRING: &str = $string;
+        static STORAGE: $crate::StaticUstr<{ STRING.len() }> =
+            $crate::StaticUstr::new(STRING);
+        $crate::Ustr::intern_static(&STORAGE)
+    }};
+}
+
+// Forget every interned `StaticUstr`. Only called by `_clear_cache()`.
+pub(crate) fn clear() {
+    let mut interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+    for ptr in interned.drain(..) {
+        ptr.store(std::ptr::null_mut(), Ordering::Release);
+    }
+}
+
+#[test]
+fn test_static_ustr() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+    use std::mem::{offset_of, size_of};
+
+    // The layout must match an entry in the cache's storage.
+    assert_eq!(
+        offset_of!(StaticUstr<3>, chars),
+        size_of::<StringCacheEntry>()
+    );
+    assert_eq!(
+        offset_of!(StaticUstr<3>, interned),
+        StringCacheEntry::size_for_len(3)
+    );
+
+    unsafe { super::_clear_cache() };
+
+    static ABC: StaticUstr<3> = StaticUstr::new("abc");
+    let abc = Ustr::intern_static(&ABC);
+    assert_eq!(abc.as_char_ptr() as *const u8, ABC.chars.as_ptr());
+    assert_eq!(abc.len(), 3);
+    assert_eq!(abc.as_cstr().to_bytes(), b"abc");
+    assert_eq!(u("abc"), abc);
+    assert_eq!(Ustr::intern_static(&ABC), abc);
+    assert_eq!(super::num_entries(), 1);
+    assert_eq!(super::total_allocated(), 0);
+
+    // Static entries show up everywhere the cache is iterated.
+    let def = u("def");
+    assert_eq!(super::entries_since(0).collect::<Vec<_>>(), [abc, def]);
+    let mut all = super::string_cache_iter().collect::<Vec<_>>();
+    all.sort();
+    assert_eq!(all, ["abc", "def"]);
+    assert_eq!(super::from_id(abc.id()), Some(abc));
+
+    // Strings that are already in the cache are shared.
+    let ghi = u("ghi");
+    assert_eq!(static_ustr!("ghi"), ghi);
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(super::num_entries(), 0);
+    assert_eq!(static_ustr!("ghi"), u("ghi"));
+    assert_eq!(Ustr::intern_static(&ABC), u("abc"));
+    assert_eq!(super::num_entries(), 2);
+}
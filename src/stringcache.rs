@@ -1,4 +1,16 @@
-use super::bumpalloc::LeakyBumpAlloc;
+#[cfg(feature = "debug-hash-collisions")]
+use super::collisions;
+#[cfg(feature = "negative-filter")]
+use super::negative;
+use super::{
+    allocator, bumpalloc::LeakyBumpAlloc, growth::max_entries, growth_policy,
+    hash32_fn, ids, init, primitives::AtomicU32, shared, simd, verify,
+    OutOfMemory, Ustr,
+};
+use std::{
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 // `StringCache` stores a `Vec` of pointers to the `StringCacheEntry` structs.
 // The actual memory for the `StringCacheEntry` is stored in the LeakyBumpAlloc,
@@ -8,26 +20,32 @@ use super::bumpalloc::LeakyBumpAlloc;
 //
 // The actual memory representation is as follows. Each `StringCacheEntry` is
 // aligned to 8 bytes on a 64-bit system. The 64-bit memoized hash of the string
-// is stored first, then a usize length, then the u8 characters, followed by a
-// null terminator (not included in len), then x<8 bytes of uninitialized memory
-// as padding before the next aligned entry.
+// is stored first, then the u32 generation in which it was inserted, then the
+// secondary u32 hash, then a usize length, then the u8 characters, followed by
+// a null terminator (not included in len), then x<8 bytes of zeroed padding
+// before the next aligned entry.
 //
-//       hash             len       H e l l o , W o r l d !\0
-// |. . . . . . . .|. . . . . . . .|. . . . . . . .|. . . . . . . .|
-// 0               8               16                     len
-// ^ StringCacheEntry              ^ u8 chars               ^ null ^ Next entry
+//       hash         gen   hash32        len        H e l l o , W o r l d !\0
+// |. . . . . . . .|. . . .|. . . .|. . . . . . . .|. . . . . . . .|. . . .
+// 0               8       12      16              24                  len
+// ^ StringCacheEntry                              ^ u8 chars          ^ null
+//
+// On 32-bit systems the length is a u32, so the chars start at offset 20. The
+// entry alignment is that of a u64, which is 4 on i686 (giving a 20-byte
+// header) and 8 on armv7 and wasm32 (where the header is padded to 24 bytes).
+// The assertions below `StringCacheEntry` check this at compile time.
 //
 // Proper alignment is guaranteed when allocating each entry as the alignment
 // is baked into the allocator. `StringCache` is responsible for monitoring the
-// Allocator and creating a new one when it would overflow -- the `Alloc` itself
-// will just `abort()` if it runs out of memory. Note that we abort() rather
-// than panic because the behaviour of the spinlock in case of a panic while
-// holding the lock is undefined.
+// Allocator and creating a new one when it would overflow -- if it can't get
+// the memory for a new one, the handler set with `set_oom_handler()` decides
+// whether to abort or to fail the insert, which leaves the bin as it was.
 //
 // Thread safety is ensured because we can only access the `StringCache` through
-// the spinlock in the `lazy_static` ref. The initial capacity of the cache is
-// divided evenly among a number of 'bins' or shards each with their own lock,
-// in order to reduce contention.
+// the `RwLock` in the `lazy_static` ref. Lookups of existing strings only need
+// the shared lock, while inserting a new string takes the exclusive lock. The
+// initial capacity of the cache is divided evenly among a number of 'bins' or
+// shards each with their own lock, in order to reduce contention.
 #[repr(align(128))]
 pub(crate) struct StringCache {
     pub(crate) alloc: LeakyBumpAlloc,
@@ -36,35 +54,100 @@ pub(crate) struct StringCache {
     num_entries: usize,
     mask: usize,
     total_allocated: usize,
+    // Number of times a thread had to wait for this bin's lock.
+    pub(crate) contended_reads: AtomicU64,
+    pub(crate) contended_writes: AtomicU64,
+    // Number of times the entry table has been grown.
+    pub(crate) num_grows: u64,
+    // Entries whose storage isn't in the allocators, from `insert_static()`
+    // and `insert_prebuilt()`.
+    static_entries: Vec<*mut StringCacheEntry>,
+    // Whether this is one of the global bins, whose entries get global ids.
+    global: bool,
+    // Whether lookups skip comparing the bytes of entries whose hash and
+    // length match. Only used by local caches: the global bins follow
+    // `set_verification()`.
+    pub(crate) hash_only: bool,
+    // Whether the next arena should be the initial size rather than bigger
+    // than the last one. Set by `compact()`.
+    restart_arenas: bool,
     // Padding and aligning to 128 bytes gives up to 20% performance
     // improvement this actually aligns to 256 bytes because of the Mutex
     // around it.
     _pad: [u32; 3],
 }
 
-// TODO: make these configurable?
-// Initial size of the StringCache table
-pub(crate) const INITIAL_CAPACITY: usize = 1 << 20;
-// Initial size of the allocator storage (in bytes)
+// Default initial size of the StringCache table, across all bins. It's tiny
+// so that programs that only intern a handful of strings don't pay for big
+// tables, and grows as needed. See `InitConfig`.
+pub(crate) const INITIAL_CAPACITY: usize = 16 * NUM_BINS;
+// Default size of each bin's first string arena (in bytes), across all bins.
+// Each arena is only allocated when the first string is added to its bin.
 pub(crate) const INITIAL_ALLOC: usize = 4 << 20;
-// Number of bins (shards) for map
-pub(crate) const BIN_SHIFT: usize = 6;
+// Number of bins (shards) for map. This can be overridden at build time by
+// setting the `USTR_BIN_SHIFT` environment variable, e.g. `USTR_BIN_SHIFT=8`
+// for 256 bins.
+pub(crate) const BIN_SHIFT: usize = match option_env!("USTR_BIN_SHIFT") {
+    Some(s) => parse_bin_shift(s),
+    None => 6,
+};
 pub(crate) const NUM_BINS: usize = 1 << BIN_SHIFT;
-// Shift for top bits to determine bin a hash falls into
-pub(crate) const TOP_SHIFT: usize =
-    8 * std::mem::size_of::<usize>() - BIN_SHIFT;
+// The generation that will be given to the next string inserted into the
+// cache. This is global across all bins so that generations are ordered by
+// insertion time, and is only incremented while holding a bin's write lock.
+#[cfg(not(loom))]
+pub(crate) static LOCAL_NEXT_GENERATION: AtomicU32 = AtomicU32::new(0);
+#[cfg(loom)]
+super::primitives::lazy_static! {
+    pub(crate) static ref LOCAL_NEXT_GENERATION: AtomicU32 = AtomicU32::new(0);
+}
+
+// `LOCAL_NEXT_GENERATION`, or the counter of the cache this copy of the crate
+// has been attached to, like `STRING_CACHE`.
+pub(crate) struct NextGenerationRef;
+
+pub(crate) static NEXT_GENERATION: NextGenerationRef = NextGenerationRef;
+
+impl std::ops::Deref for NextGenerationRef {
+    type Target = AtomicU32;
+
+    #[inline]
+    fn deref(&self) -> &AtomicU32 {
+        match shared::external() {
+            Some(cache) => cache.next_generation(),
+            None => &LOCAL_NEXT_GENERATION,
+        }
+    }
+}
+// Shift for top bits to determine bin a hash falls into. The hash is always 64
+// bits, even on 32-bit targets.
+pub(crate) const TOP_SHIFT: usize = 64 - BIN_SHIFT;
+// The default for the largest allocator we'll grow to (see `GrowthPolicy`). On
+// 32-bit targets asking for ever larger contiguous blocks will fail long before
+// the 4GB address space is used up, so once we get here we keep allocating
+// blocks of this size instead.
+#[cfg(target_pointer_width = "64")]
+pub(crate) const MAX_ALLOC: usize = 1 << 40;
+#[cfg(not(target_pointer_width = "64"))]
+pub(crate) const MAX_ALLOC: usize = 64 << 20;
 
 impl StringCache {
-    /// Create a new StringCache with the given starting capacity
+    /// Create a new StringCache for one of the global bins.
     pub fn new() -> StringCache {
-        let capacity = INITIAL_CAPACITY / NUM_BINS;
-        let alloc = LeakyBumpAlloc::new(
-            INITIAL_ALLOC / NUM_BINS,
-            std::mem::align_of::<StringCacheEntry>(),
-        );
+        StringCache::with_global(true)
+    }
+
+    /// Create a new StringCache that's separate from the global cache, so
+    /// its entries don't use up global ids.
+    pub(crate) fn new_local() -> StringCache {
+        StringCache::with_global(false)
+    }
+
+    fn with_global(global: bool) -> StringCache {
+        let capacity = init::config().bin_capacity();
         StringCache {
             // Current allocator.
-            alloc,
+            alloc: first_alloc(),
             // Old allocators we'll keep around for iteration purposes.
             // 16 would mean we've allocated 128GB of string storage since we
             // double each time.
@@ -74,15 +157,33 @@ impl StringCache {
             num_entries: 0,
             mask: capacity - 1,
             total_allocated: capacity,
+            contended_reads: AtomicU64::new(0),
+            contended_writes: AtomicU64::new(0),
+            num_grows: 0,
+            static_entries: Vec::new(),
+            global,
+            hash_only: false,
+            restart_arenas: false,
             _pad: [0u32; 3],
         }
     }
 
+    // Whether lookups in this cache only compare hashes and lengths.
+    #[inline]
+    fn is_hash_only(&self) -> bool {
+        if self.global {
+            verify::hash_only()
+        } else {
+            self.hash_only
+        }
+    }
+
     pub(crate) fn get_existing(
         &self,
         string: &str,
         hash: u64,
     ) -> Option<*const u8> {
+        let hash_only = self.is_hash_only();
         let mut pos = self.mask & hash as usize;
         let mut dist = 0;
         loop {
@@ -105,9 +206,11 @@ impl StringCache {
                 let sce = &**entry;
                 if sce.hash == hash
                     && sce.len == string.len()
-                    && std::str::from_utf8_unchecked(
-                        std::slice::from_raw_parts(entry_chars, sce.len),
-                    ) == string
+                    && (hash_only
+                        || simd::bytes_eq(
+                            std::slice::from_raw_parts(entry_chars, sce.len),
+                            string.as_bytes(),
+                        ))
                 {
                     // found matching string in the cache already, return it
                     return Some(entry_chars);
@@ -121,15 +224,45 @@ impl StringCache {
         }
     }
 
-    // Insert the given string with its given hash into the cache.
-    pub(crate) fn insert(&mut self, string: &str, hash: u64) -> *const u8 {
+    // Find the chars of the entry with the given hash and length, without
+    // knowing the string. If several strings share both, the first one in
+    // the probe sequence is returned.
+    pub(crate) fn get_by_hash(
+        &self,
+        hash: u64,
+        len: usize,
+    ) -> Option<*const u8> {
+        let mut pos = self.mask & hash as usize;
+        let mut dist = 0;
+        loop {
+            let entry = unsafe { *self.entries.get_unchecked(pos) };
+            if entry.is_null() {
+                return None;
+            }
+            // If entry is non-null then it must point to a valid
+            // `StringCacheEntry`.
+            let sce = unsafe { &*entry };
+            if sce.hash == hash && sce.len == len {
+                return Some(unsafe { entry.add(1) } as *const u8);
+            }
+            dist += 1;
+            debug_assert!(dist <= self.mask);
+            pos = (pos + dist) & self.mask;
+        }
+    }
+
+    // Find the slot for the given string: `Ok` with the chars of the existing
+    // entry if it's already in the cache, or `Err` with the empty slot to put
+    // it in if not.
+    fn find_slot(&self, string: &str, hash: u64) -> Result<*const u8, usize> {
+        let hash_only = self.is_hash_only();
         let mut pos = self.mask & hash as usize;
         let mut dist = 0;
         loop {
             let entry = unsafe { self.entries.get_unchecked(pos) };
             if entry.is_null() {
                 // found empty slot to insert
-                break;
+                return Err(pos);
             }
 
             // This is safe as long as entry points to a valid address and the
@@ -147,12 +280,14 @@ impl StringCache {
                 let sce = &**entry;
                 if sce.hash == hash
                     && sce.len == string.len()
-                    && std::str::from_utf8_unchecked(
-                        std::slice::from_raw_parts(entry_chars, sce.len),
-                    ) == string
+                    && (hash_only
+                        || simd::bytes_eq(
+                            std::slice::from_raw_parts(entry_chars, sce.len),
+                            string.as_bytes(),
+                        ))
                 {
                     // found matching string in the cache already, return it
-                    return entry_chars;
+                    return Ok(entry_chars);
                 }
             }
 
@@ -161,13 +296,30 @@ impl StringCache {
             debug_assert!(dist <= self.mask);
             pos = (pos + dist) & self.mask;
         }
+    }
+
+    // Insert the given string with its given hash into the cache.
+    pub(crate) fn insert(&mut self, string: &str, hash: u64) -> *const u8 {
+        self.try_insert(string, hash)
+            .unwrap_or_else(|oom| panic!("{}", oom))
+    }
+
+    // Like `insert()`, but returns an error if there's no memory for a new
+    // arena and the OOM handler didn't abort.
+    pub(crate) fn try_insert(
+        &mut self,
+        string: &str,
+        hash: u64,
+    ) -> Result<*const u8, OutOfMemory> {
+        let pos = match self.find_slot(string, hash) {
+            Ok(entry_chars) => return Ok(entry_chars),
+            Err(pos) => pos,
+        };
 
         //
         // Insert the new string.
         //
 
-        // We know pos is in bounds as it's &ed with the mask above.
-        let entry_ptr = unsafe { self.entries.get_unchecked_mut(pos) };
         // Ddd one to length for null byte.
         // There's no way we could overflow here in practice since that would
         // require having allocated a `u64::MAX`-length string, by which time
@@ -185,21 +337,51 @@ impl StringCache {
             .expect("overflowed alloc_size + allocated")
             > capacity
         {
-            let new_capacity = capacity
-                .checked_mul(2)
-                .expect("capacity * 2 overflowed")
-                .max(alloc_size);
-            let old_alloc = std::mem::replace(
-                &mut self.alloc,
-                LeakyBumpAlloc::new(
-                    new_capacity,
-                    std::mem::align_of::<StringCacheEntry>(),
+            let policy = growth_policy();
+            let new_capacity = match policy.chunk_size {
+                // This is the bin's first arena, which isn't allocated up
+                // front unless asked for, or the first since `compact()`.
+                _ if capacity == 0 || self.restart_arenas => {
+                    init::config().bin_arena_size().max(alloc_size)
+                }
+                Some(chunk_size) => chunk_size.max(alloc_size),
+                None => next_alloc_capacity(
+                    capacity,
+                    alloc_size,
+                    policy.arena_growth,
+                    policy.max_arena_size,
                 ),
-            );
-            self.old_allocs.push(old_alloc);
+            };
+            let new_alloc = LeakyBumpAlloc::new(
+                new_capacity,
+                std::mem::align_of::<StringCacheEntry>(),
+                allocator(),
+            )?;
+            let old_alloc = std::mem::replace(&mut self.alloc, new_alloc);
+            if old_alloc.capacity() != 0 {
+                self.old_allocs.push(old_alloc);
+            }
+            self.restart_arenas = false;
             self.total_allocated += new_capacity;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                target: "ustr",
+                global = self.global,
+                old_capacity = capacity,
+                new_capacity,
+                total_allocated = self.total_allocated,
+                monotonic_counter.ustr_arena_allocations = 1u64,
+                "started a new string arena"
+            );
         }
 
+        // Do this before writing anything so that we don't leave anything in
+        // an inconsistent state if we run out of generations, but after the
+        // arena is allocated so that running out of memory doesn't use one
+        // up.
+        let generation = self.next_generation();
+
         // This is safe as long as:
         // 1. `alloc_size` is calculated correctly.
         // 2. there is enough space in the allocator (checked in the block
@@ -207,21 +389,11 @@ impl StringCache {
         // 3. The `StringCacheEntry` layout descibed above holds and the memory
         //    returned by allocate() is prooperly aligned.
         unsafe {
-            *entry_ptr =
-                self.alloc.allocate(alloc_size) as *mut StringCacheEntry;
-
-            // Write the header.
-            // `entry_ptr` is guaranteed to point to a valid `StringCacheEntry`,
-            // or `alloc.allocate()` would have aborted.
-            std::ptr::write(
-                *entry_ptr,
-                StringCacheEntry {
-                    hash,
-                    len: string.len(),
-                },
-            );
+            let top = self.alloc.ptr();
+            let entry =
+                self.alloc.allocate(alloc_size)? as *mut StringCacheEntry;
             // Write the characters after the `StringCacheEntry`.
-            let char_ptr = entry_ptr.add(1) as *mut u8;
+            let char_ptr = entry.add(1) as *mut u8;
             std::ptr::copy_nonoverlapping(
                 string.as_bytes().as_ptr(),
                 char_ptr,
@@ -230,15 +402,170 @@ impl StringCache {
             // Write the trailing null.
             let write_ptr = char_ptr.add(string.len());
             std::ptr::write(write_ptr, 0u8);
+            // Zero the padding up to the previous entry, so that every byte
+            // of the allocator's used range is initialized and can be read by
+            // `contains_entry()`.
+            let padding = write_ptr.add(1);
+            std::ptr::write_bytes(padding, 0, top as usize - padding as usize);
+
+            Ok(self.add_entry(pos, entry, string, hash, generation))
+        }
+    }
+
+    // Insert the given string using `entry` as its storage rather than
+    // copying it into the allocator. Returns the chars of the existing entry
+    // instead if the string is already in the cache.
+    //
+    // This is safe as long as `entry` is valid for writes of a
+    // `StringCacheEntry`, is followed by the chars of `string` and a null
+    // terminator, and lives forever.
+    pub(crate) unsafe fn insert_static(
+        &mut self,
+        string: &str,
+        hash: u64,
+        entry: *mut StringCacheEntry,
+    ) -> *const u8 {
+        let pos = match self.find_slot(string, hash) {
+            Ok(entry_chars) => return entry_chars,
+            Err(pos) => pos,
+        };
+        let generation = self.next_generation();
+        self.static_entries.push(entry);
+        self.add_entry(pos, entry, string, hash, generation)
+    }
 
-            self.num_entries += 1;
-            // We want to keep an 0.5 load factor for the map, so grow if we've
-            // exceeded that.
-            if self.num_entries * 2 > self.mask {
-                self.grow();
+    fn next_generation(&self) -> u32 {
+        if self.global {
+            NEXT_GENERATION
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |g| {
+                    g.checked_add(1)
+                })
+                .expect("Exceeded u32::MAX unique strings")
+        } else {
+            u32::try_from(self.num_entries)
+                .expect("Exceeded u32::MAX unique strings")
+        }
+    }
+
+    // Write the header of `entry`, whose chars have already been written, and
+    // put it in the empty slot `pos`.
+    unsafe fn add_entry(
+        &mut self,
+        pos: usize,
+        entry: *mut StringCacheEntry,
+        string: &str,
+        hash: u64,
+        generation: u32,
+    ) -> *const u8 {
+        // `entry` is guaranteed to point to a valid `StringCacheEntry`, or
+        // `alloc.allocate()` would have failed.
+        std::ptr::write(
+            entry,
+            StringCacheEntry {
+                hash,
+                generation,
+                hash32: hash32_fn()(string),
+                len: string.len(),
+            },
+        );
+        self.place_entry(pos, entry, generation)
+    }
+
+    // Insert an entry whose header has already been written, e.g. by
+    // `write_dictionary()`, in place. Only the generation, and the secondary
+    // hash if `rehash32` is set, are rewritten, and only if they differ, so
+    // that pages of a mapped file that don't need changing stay clean.
+    // Returns `false` without touching `entry` if the string is already in the
+    // cache.
+    //
+    // This is safe as long as `entry` points to a valid `StringCacheEntry`
+    // that is followed by its chars and a null terminator, is valid for
+    // writes and lives forever.
+    pub(crate) unsafe fn insert_prebuilt(
+        &mut self,
+        entry: *mut StringCacheEntry,
+        rehash32: bool,
+    ) -> bool {
+        let StringCacheEntry {
+            hash,
+            generation: old_generation,
+            hash32: old_hash32,
+            len,
+        } = *entry;
+        let string = std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+            entry.add(1) as *const u8,
+            len,
+        ));
+        let pos = match self.find_slot(string, hash) {
+            Ok(_) => return false,
+            Err(pos) => pos,
+        };
+        let generation = self.next_generation();
+        if old_generation != generation {
+            (*entry).generation = generation;
+        }
+        if rehash32 {
+            let hash32 = hash32_fn()(string);
+            if old_hash32 != hash32 {
+                (*entry).hash32 = hash32;
             }
+        }
+        self.static_entries.push(entry);
+        self.place_entry(pos, entry, generation);
+        true
+    }
 
-            char_ptr
+    // Put `entry`, whose header has been written, in the empty slot `pos`.
+    unsafe fn place_entry(
+        &mut self,
+        pos: usize,
+        entry: *mut StringCacheEntry,
+        generation: u32,
+    ) -> *const u8 {
+        let char_ptr = entry.add(1) as *const u8;
+        #[cfg(feature = "debug-hash-collisions")]
+        if self.global {
+            self.record_collisions((*entry).hash, char_ptr);
+        }
+
+        // We know pos is in bounds as it was &ed with the mask.
+        *self.entries.get_unchecked_mut(pos) = entry;
+
+        if self.global {
+            ids::publish(generation, char_ptr);
+        }
+
+        self.num_entries += 1;
+        #[cfg(feature = "negative-filter")]
+        if self.global {
+            negative::add(self, (*entry).hash);
+        }
+        // Keep the load factor of the map at or below the one set in the
+        // growth policy (0.5 by default), so grow if we've reached it.
+        if self.num_entries >= max_entries(self.mask + 1) {
+            self.grow();
+        }
+
+        char_ptr
+    }
+
+    // Record a collision with every entry that has the same hash as a new
+    // string, before it's added. Entries with the same hash share a probe
+    // sequence, so they're all before the first empty slot.
+    #[cfg(feature = "debug-hash-collisions")]
+    unsafe fn record_collisions(&self, hash: u64, char_ptr: *const u8) {
+        let mut pos = self.mask & hash as usize;
+        let mut dist = 0;
+        loop {
+            let entry = *self.entries.get_unchecked(pos);
+            if entry.is_null() {
+                return;
+            }
+            if (*entry).hash == hash {
+                collisions::record(hash, entry.add(1) as *const u8, char_ptr);
+            }
+            dist += 1;
+            pos = (pos + dist) & self.mask;
         }
     }
 
@@ -250,7 +577,59 @@ impl StringCache {
     // If there's not enough memory for the new entry table, it will just abort
     pub(crate) unsafe fn grow(&mut self) {
         let new_mask = self.mask * 2 + 1;
+        self.num_grows += 1;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            target: "ustr",
+            "grow",
+            global = self.global,
+            num_entries = self.num_entries,
+            old_slots = self.mask + 1,
+            new_slots = new_mask + 1,
+        )
+        .entered();
+
+        self.rehash(new_mask);
+    }
 
+    // Shrink the map storage to the smallest size that's within the growth
+    // policy's load factor, and have the next arena start over at the
+    // initial size rather than growing from the last one. Returns the number
+    // of slots removed from the map.
+    pub(crate) fn compact(&mut self) -> usize {
+        self.restart_arenas = true;
+        let mut slots = 1;
+        while self.num_entries >= max_entries(slots) {
+            slots *= 2;
+        }
+        let old_slots = self.mask + 1;
+        if slots >= old_slots {
+            return 0;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            target: "ustr",
+            "compact",
+            global = self.global,
+            num_entries = self.num_entries,
+            old_slots,
+            new_slots = slots,
+        )
+        .entered();
+
+        // SAFETY: every entry in the map has a valid header.
+        unsafe { self.rehash(slots - 1) };
+        old_slots - slots
+    }
+
+    // Move every entry into a new map with `new_mask + 1` slots, which must
+    // be enough to hold them all.
+    //
+    // This is safe as long as:
+    // - The in-memory layout of the `StringCacheEntry` is correct.
+    unsafe fn rehash(&mut self, new_mask: usize) {
         let mut new_entries: std::vec::Vec<*mut StringCacheEntry> =
             vec![std::ptr::null_mut(); new_mask + 1];
 
@@ -272,8 +651,8 @@ impl StringCache {
                 }
 
                 dist += 1;
-                // This should be impossble as we've allocated twice as many
-                // slots as we have entries.
+                // This should be impossble as we've allocated more slots than
+                // we have entries.
                 debug_assert!(dist <= new_mask, "Probing wrapped around");
                 pos = pos.wrapping_add(dist) & new_mask;
             }
@@ -290,21 +669,123 @@ impl StringCache {
     }
 
     // This is only called by `clear()` during tests to clear the cache between
-    // runs. **DO NOT CALL THIS**.
+    // runs, and by `ScratchInterner` on its own storage. **DO NOT CALL THIS**
+    // on the global cache.
     pub(crate) unsafe fn clear(&mut self) {
-        // just zero all the pointers that have already been set
-        std::ptr::write_bytes(self.entries.as_mut_ptr(), 0, self.mask + 1);
+        // Go back to the initial table size, so that a cache that's been
+        // cleared behaves like a new one.
+        let capacity = init::config().bin_capacity();
+        self.entries = vec![std::ptr::null_mut(); capacity];
+        self.mask = capacity - 1;
         self.num_entries = 0;
         self.total_allocated = 0;
+        self.contended_reads.store(0, Ordering::Relaxed);
+        self.contended_writes.store(0, Ordering::Relaxed);
+        self.num_grows = 0;
+        self.static_entries = Vec::new();
         for a in self.old_allocs.iter_mut() {
             a.clear();
         }
         self.old_allocs = Vec::new();
         self.alloc.clear();
-        self.alloc = LeakyBumpAlloc::new(
-            INITIAL_ALLOC / NUM_BINS,
-            std::mem::align_of::<StringCacheEntry>(),
-        );
+        self.alloc = first_alloc();
+    }
+
+    // Free all the string storage. Nothing in this cache may be used again
+    // afterwards. **DO NOT CALL THIS** on the global cache.
+    pub(crate) unsafe fn free(&mut self) {
+        for a in self.old_allocs.iter_mut() {
+            a.clear();
+        }
+        self.alloc.clear();
+    }
+
+    // Push the `(ptr, end)` range of entries in each allocator onto `out`,
+    // for building a `StringCacheIterator`.
+    pub(crate) fn alloc_ranges(&self, out: &mut Vec<(*const u8, *const u8)>) {
+        // the start of the allocator's data is actually the ptr, start() just
+        // points to the beginning of the allocated region. The first bytes will
+        // be uninitialized since we're bumping down
+        for a in &self.old_allocs {
+            out.push((a.ptr(), a.end()));
+        }
+        let ptr = self.alloc.ptr();
+        let end = self.alloc.end();
+        if ptr != end {
+            out.push((ptr, end));
+        }
+        for e in &self.static_entries {
+            // Each static entry is a range of one entry.
+            let len = unsafe { (**e).len };
+            let ptr = *e as *const u8;
+            out.push((
+                ptr,
+                ptr.wrapping_add(StringCacheEntry::size_for_len(len)),
+            ));
+        }
+    }
+
+    // Returns true if `ptr` points anywhere in this bin's arenas or static
+    // entries.
+    pub(crate) fn owns_ptr(&self, ptr: *const u8) -> bool {
+        let in_arena = self
+            .old_allocs
+            .iter()
+            .chain([&self.alloc])
+            .any(|a| (a.start()..a.end()).contains(&ptr));
+        in_arena
+            || self.static_entries.iter().any(|e| {
+                let start = *e as *const u8;
+                let len = unsafe { (**e).len };
+                let end =
+                    start.wrapping_add(StringCacheEntry::size_for_len(len));
+                (start..end).contains(&ptr)
+            })
+    }
+
+    // Returns true if `ptr` points to the chars of one of this bin's entries.
+    // Any pointer at all can be passed.
+    pub(crate) fn contains_entry(&self, ptr: *const u8) -> bool {
+        if self
+            .static_entries
+            .iter()
+            .any(|e| e.wrapping_add(1) as *const u8 == ptr)
+        {
+            return true;
+        }
+
+        let header_size = std::mem::size_of::<StringCacheEntry>();
+        let align = std::mem::align_of::<StringCacheEntry>();
+        let addr = ptr as usize;
+        if !addr.is_multiple_of(align) {
+            return false;
+        }
+        let ranges = self
+            .old_allocs
+            .iter()
+            .chain([&self.alloc])
+            .map(|a| (a.ptr() as usize, a.end() as usize));
+        for (start, end) in ranges {
+            if addr < start + header_size || addr >= end {
+                continue;
+            }
+            // This is safe since every byte in the used range of an
+            // allocator is initialized and the header is aligned and in
+            // bounds, but `ptr` might be the middle of a string so nothing in
+            // the header can be trusted until it's checked against the table.
+            unsafe {
+                let entry = &*(ptr as *const StringCacheEntry).sub(1);
+                if entry.len >= end - addr {
+                    return false;
+                }
+                let bytes = std::slice::from_raw_parts(ptr, entry.len);
+                return match std::str::from_utf8(bytes) {
+                    Ok(string) => self.find_slot(string, entry.hash) == Ok(ptr),
+                    Err(_) => false,
+                };
+            }
+        }
+        false
     }
 
     pub(crate) fn total_allocated(&self) -> usize {
@@ -320,6 +801,120 @@ impl StringCache {
     pub(crate) fn num_entries(&self) -> usize {
         self.num_entries
     }
+
+    // Iterate over the pointers to every entry in this bin.
+    pub(crate) fn entry_ptrs(
+        &self,
+    ) -> impl Iterator<Item = *const StringCacheEntry> + '_ {
+        self.entries
+            .iter()
+            .filter(|e| !e.is_null())
+            .map(|e| *e as *const StringCacheEntry)
+    }
+
+    // Push the chars of every entry in this bin with a generation of at least
+    // `generation` onto `out`, along with their generation.
+    pub(crate) fn entries_since(
+        &self,
+        generation: u32,
+        out: &mut Vec<(u32, *const u8)>,
+    ) {
+        // Static entries aren't in the allocators, but are kept in the order
+        // they were added.
+        for e in self.static_entries.iter().rev() {
+            let sce = unsafe { &**e };
+            if sce.generation < generation {
+                break;
+            }
+            out.push((sce.generation, sce.char_ptr()));
+        }
+
+        // Entries are bumped downwards through each allocator, and the
+        // allocators are only ever replaced by newer ones (compaction may
+        // restart at a smaller size), so walking from the current allocator's
+        // ptr to the end of the oldest allocator visits the entries newest
+        // first. Generations are handed out in order under the lock, so we can
+        // stop as soon as we see an older one.
+        for alloc in
+            std::iter::once(&self.alloc).chain(self.old_allocs.iter().rev())
+        {
+            let mut ptr = alloc.ptr();
+            while ptr < alloc.end() {
+                // This is safe as long as the layout described above holds
+                // and ptr points to an initialized entry, which it does since
+                // everything between ptr and end has been allocated.
+                unsafe {
+                    let sce = &*(ptr as *const StringCacheEntry);
+                    if sce.generation < generation {
+                        return;
+                    }
+                    out.push((sce.generation, sce.char_ptr()));
+                    ptr = sce.next_entry();
+                }
+            }
+        }
+    }
+
+    // Number of slots in the entry table.
+    pub(crate) fn table_capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    // Number of entries whose storage isn't in the allocators.
+    pub(crate) fn num_static_entries(&self) -> usize {
+        self.static_entries.len()
+    }
+
+    // Count the entries by how many slots past their home slot they are in
+    // the probe sequence: `histogram[n]` entries take `n + 1` probes to find.
+    pub(crate) fn probe_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for (pos, entry) in self.entries.iter().enumerate() {
+            if entry.is_null() {
+                continue;
+            }
+            // If entry is non-null then it must point to a valid
+            // `StringCacheEntry`.
+            let hash = unsafe { (**entry).hash };
+            let mut probe = self.mask & hash as usize;
+            let mut dist = 0;
+            while probe != pos {
+                dist += 1;
+                probe = (probe + dist) & self.mask;
+            }
+            if histogram.len() <= dist {
+                histogram.resize(dist + 1, 0);
+            }
+            histogram[dist] += 1;
+        }
+        histogram
+    }
+}
+
+// Parse `USTR_BIN_SHIFT` at compile time. Values outside 1..=16 would leave us
+// with either a single lock or bins too small to be useful.
+const fn parse_bin_shift(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    if bytes.is_empty() {
+        panic!("USTR_BIN_SHIFT must not be empty");
+    }
+    let mut n = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if !b.is_ascii_digit() {
+            panic!("USTR_BIN_SHIFT must be a decimal integer");
+        }
+        n = n * 10 + (b - b'0') as usize;
+        if n > 16 {
+            panic!("USTR_BIN_SHIFT must be in the range 1..=16");
+        }
+        i += 1;
+    }
+    if n == 0 {
+        panic!("USTR_BIN_SHIFT must be in the range 1..=16");
+    }
+    n
 }
 
 impl Default for StringCache {
@@ -328,8 +923,12 @@ impl Default for StringCache {
     }
 }
 
-// We are safe to be `Send` but not `Sync` (we get Sync by wrapping in a mutex).
+// We are safe to be `Send`. We are also safe to be `Sync` since the only
+// methods taking `&self` just read from the entries table and the immutable
+// entries it points to. Mutation requires `&mut self`, which the `RwLock` makes
+// exclusive.
 unsafe impl Send for StringCache {}
+unsafe impl Sync for StringCache {}
 
 #[doc(hidden)]
 pub struct StringCacheIterator {
@@ -338,14 +937,55 @@ pub struct StringCacheIterator {
     pub(crate) current_ptr: *const u8,
 }
 
+// The arena a bin starts with: empty until the first string is added, unless
+// `InitConfig::preallocate_arenas` is set.
+fn first_alloc() -> LeakyBumpAlloc {
+    let config = init::config();
+    let align = std::mem::align_of::<StringCacheEntry>();
+    if config.preallocate_arenas {
+        LeakyBumpAlloc::new(config.bin_arena_size(), align, allocator())
+            .unwrap_or_else(|oom| panic!("{}", oom))
+    } else {
+        LeakyBumpAlloc::empty(align, allocator())
+    }
+}
+
+// The capacity of the allocator to create when one of `capacity` bytes can't
+// fit another `alloc_size` bytes.
+fn next_alloc_capacity(
+    capacity: usize,
+    alloc_size: usize,
+    growth: f64,
+    max_alloc: usize,
+) -> usize {
+    // Float to int casts saturate, so this can't overflow.
+    ((capacity as f64 * growth) as usize)
+        .min(max_alloc)
+        .max(alloc_size)
+}
+
 fn round_up_to(n: usize, align: usize) -> usize {
     debug_assert!(align.is_power_of_two());
     (n.checked_add(align).expect("round_up_to overflowed") - 1) & !(align - 1)
 }
 
-impl Iterator for StringCacheIterator {
-    type Item = &'static str;
-    fn next(&mut self) -> Option<Self::Item> {
+impl StringCacheIterator {
+    // Iterate over the entries in each of the `(ptr, end)` ranges in
+    // `allocs`.
+    pub(crate) fn new(allocs: Vec<(*const u8, *const u8)>) -> Self {
+        let current_ptr =
+            allocs.first().map(|s| s.0).unwrap_or_else(std::ptr::null);
+        StringCacheIterator {
+            allocs,
+            current_alloc: 0,
+            current_ptr,
+        }
+    }
+}
+
+impl StringCacheIterator {
+    // Step to the next entry, returning its header.
+    fn next_entry(&mut self) -> Option<&'static StringCacheEntry> {
         // check that the cache is not empty before accessing
         if self.allocs.is_empty() {
             return None;
@@ -365,31 +1005,79 @@ impl Iterator for StringCacheIterator {
             }
         }
 
-        // Cast the current ptr to a `StringCacheEntry` and create the next
-        // string from it.
+        // Cast the current ptr to a `StringCacheEntry`. Entries are never
+        // freed, so it lives forever.
         unsafe {
             let sce = &*(self.current_ptr as *const StringCacheEntry);
             // The next entry will be the size of the number of bytes in the
             // string, +1 for the null byte, rounded up to the alignment (8).
             self.current_ptr = sce.next_entry();
+            Some(sce)
+        }
+    }
+}
 
-            // We know we're safe not to check here since we put valid UTF-8 in.
-            let s = std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+impl Iterator for StringCacheIterator {
+    type Item = &'static str;
+    fn next(&mut self) -> Option<Self::Item> {
+        let sce = self.next_entry()?;
+        // We know we're safe not to check here since we put valid UTF-8 in.
+        unsafe {
+            Some(std::str::from_utf8_unchecked(std::slice::from_raw_parts(
                 sce.char_ptr(),
                 sce.len,
-            ));
-            Some(s)
+            )))
         }
     }
 }
 
+/// An iterator over the `Ustr`s in the cache, as returned by
+/// [`ustr_iter()`](crate::ustr_iter).
+pub struct UstrIter(pub(crate) StringCacheIterator);
+
+impl Iterator for UstrIter {
+    type Item = Ustr;
+
+    #[inline]
+    fn next(&mut self) -> Option<Ustr> {
+        let sce = self.0.next_entry()?;
+        // SAFETY: the iterator only walks the global cache's arenas, whose
+        // entries are interned strings.
+        Some(Ustr {
+            char_ptr: unsafe {
+                NonNull::new_unchecked(sce.char_ptr() as *mut _)
+            },
+        })
+    }
+}
+
 #[repr(C)]
 #[derive(Clone)]
 pub(crate) struct StringCacheEntry {
     pub(crate) hash: u64,
+    pub(crate) generation: u32,
+    pub(crate) hash32: u32,
     pub(crate) len: usize,
 }
 
+// Check the layout described in the comment at the top of this file, since
+// `Ustr` and the allocator iterators depend on it.
+const _: () = {
+    use std::mem::{align_of, offset_of, size_of};
+    assert!(offset_of!(StringCacheEntry, hash) == 0);
+    assert!(offset_of!(StringCacheEntry, generation) == 8);
+    assert!(offset_of!(StringCacheEntry, hash32) == 12);
+    assert!(offset_of!(StringCacheEntry, len) == 16);
+    assert!(align_of::<StringCacheEntry>() == align_of::<u64>());
+    #[cfg(target_pointer_width = "64")]
+    assert!(size_of::<StringCacheEntry>() == 24);
+    #[cfg(target_pointer_width = "32")]
+    assert!(
+        size_of::<StringCacheEntry>()
+            == 20 + (align_of::<u64>() == 8) as usize * 4
+    );
+};
+
 impl StringCacheEntry {
     // Get the pointer to the characters.
     pub(crate) fn char_ptr(&self) -> *const u8 {
@@ -398,6 +1086,13 @@ impl StringCacheEntry {
         unsafe { (self as *const StringCacheEntry).add(1) as *const u8 }
     }
 
+    // The number of bytes of arena used by an entry for a string of `len`
+    // bytes, including the header, null terminator and padding.
+    pub(crate) fn size_for_len(len: usize) -> usize {
+        std::mem::size_of::<StringCacheEntry>()
+            + round_up_to(len + 1, std::mem::align_of::<StringCacheEntry>())
+    }
+
     // Calcualte the address of the next entry in the cache. This is a utility
     // function to hide the pointer arithmetic in iterators.
     pub(crate) unsafe fn next_entry(&self) -> *const u8 {
@@ -407,4 +1102,87 @@ impl StringCacheEntry {
             std::mem::align_of::<StringCacheEntry>(),
         ))
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_next_alloc_capacity() {
+    // Normal doubling.
+    assert_eq!(next_alloc_capacity(1 << 16, 32, 2.0, MAX_ALLOC), 1 << 17);
+    // Strings bigger than the doubled allocator get one of their own.
+    assert_eq!(
+        next_alloc_capacity(1 << 16, 1 << 20, 2.0, MAX_ALLOC),
+        1 << 20
+    );
+
+    // Slower growth, and fixed-size arenas.
+    assert_eq!(next_alloc_capacity(1 << 16, 32, 1.5, MAX_ALLOC), 3 << 15);
+    assert_eq!(next_alloc_capacity(1 << 16, 32, 1.0, MAX_ALLOC), 1 << 16);
+
+    // What a 32-bit target sees as we approach 4GB of address space: growth
+    // stops at the maximum rather than overflowing or asking for a block the
+    // size of the address space.
+    let max = 64 << 20;
+    assert_eq!(next_alloc_capacity(48 << 20, 32, 2.0, max), max);
+    assert_eq!(next_alloc_capacity(max, 32, 2.0, max), max);
+    assert_eq!(next_alloc_capacity(0xc000_0000, 32, 2.0, max), max);
+    assert_eq!(next_alloc_capacity(usize::MAX / 2 + 1, 32, 2.0, max), max);
+    assert_eq!(next_alloc_capacity(max, 100 << 20, 2.0, max), 100 << 20);
+
+    // Bins are chosen from the top bits of the 64-bit hash whatever the
+    // pointer width.
+    assert_eq!(super::whichbin(u64::MAX), NUM_BINS - 1);
+    assert_eq!(super::whichbin(1 << 63), NUM_BINS / 2);
+    assert_eq!(super::whichbin(u64::MAX >> BIN_SHIFT), 0);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing() {
+    let _t = super::TEST_LOCK.lock();
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tracing::{span, Event, Metadata, Subscriber};
+
+    // Counts the spans and events coming from ustr.
+    #[derive(Default)]
+    struct Counter {
+        spans: AtomicUsize,
+        events: AtomicUsize,
+    }
+
+    struct CountingSubscriber(Arc<Counter>);
+
+    impl Subscriber for CountingSubscriber {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "ustr"
+        }
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            let id = self.0.spans.fetch_add(1, Ordering::Relaxed) + 1;
+            span::Id::from_u64(id as u64)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event<'_>) {
+            self.0.events.fetch_add(1, Ordering::Relaxed);
+        }
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let counter = Arc::new(Counter::default());
+    let subscriber = CountingSubscriber(Arc::clone(&counter));
+    tracing::subscriber::with_default(subscriber, || {
+        // Use a local cache so the global one keeps its initial size. This is
+        // enough to grow the table and start the first arena.
+        let mut sc = StringCache::new_local();
+        for i in 0..INITIAL_CAPACITY / NUM_BINS {
+            let s = format!("tracing {}", i);
+            sc.insert(&s, super::hash_str(&s));
+        }
+        unsafe { sc.free() };
+    });
+    assert!(counter.spans.load(Ordering::Relaxed) > 0);
+    assert!(counter.events.load(Ordering::Relaxed) > 0);
+}
@@ -0,0 +1,137 @@
+//! Support for the `string-interner` crate.
+use super::{from_id, Ustr};
+use string_interner::{backend::Backend, Symbol};
+
+/// Lets a `Ustr` be the symbol of a `string-interner` backend.
+///
+/// A `Ustr`'s `usize` form is its [`id()`](Ustr::id).
+impl Symbol for Ustr {
+    #[inline]
+    fn try_from_usize(index: usize) -> Option<Ustr> {
+        u32::try_from(index).ok().and_then(from_id)
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize {
+        self.id() as usize
+    }
+}
+
+/// A backend for the `string-interner` crate that stores strings in the
+/// global cache, with `Ustr`s as symbols.
+///
+/// Libraries that are generic over a `string_interner::StringInterner`'s
+/// backend can use this to share the global cache, and hand out `Ustr`s that
+/// work everywhere `Ustr` does, without being changed. Strings are stored
+/// once however many interners intern them, and each backend only keeps a
+/// list of the strings it was given, to iterate over.
+///
+/// Any `Ustr` resolves to its string, whether or not it was interned through
+/// this backend.
+///
+/// This is available with the `string-interner` feature.
+///
+/// # Examples
+///
+/// ```
+/// use string_interner::StringInterner;
+/// use ustr::{ustr as u, UstrBackend};
+///
+/// let mut interner = StringInterner::<UstrBackend>::new();
+/// let symbol = interner.get_or_intern("shared");
+/// assert_eq!(symbol, u("shared"));
+/// assert_eq!(interner.resolve(symbol), Some("shared"));
+/// assert_eq!(interner.get("shared"), Some(symbol));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UstrBackend {
+    ustrs: Vec<Ustr>,
+}
+
+impl Backend for UstrBackend {
+    type Symbol = Ustr;
+    type Iter<'a> = UstrBackendIter<'a>;
+
+    fn with_capacity(capacity: usize) -> UstrBackend {
+        UstrBackend {
+            ustrs: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn intern(&mut self, string: &str) -> Ustr {
+        let u = Ustr::from(string);
+        self.ustrs.push(u);
+        u
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.ustrs.shrink_to_fit();
+    }
+
+    fn resolve(&self, symbol: Ustr) -> Option<&str> {
+        Some(symbol.as_str())
+    }
+
+    unsafe fn resolve_unchecked(&self, symbol: Ustr) -> &str {
+        symbol.as_str()
+    }
+
+    fn iter(&self) -> UstrBackendIter<'_> {
+        UstrBackendIter {
+            inner: self.ustrs.iter(),
+        }
+    }
+}
+
+/// An iterator over the strings interned through a [`UstrBackend`], in the
+/// order they were interned.
+#[derive(Debug, Clone)]
+pub struct UstrBackendIter<'a> {
+    inner: std::slice::Iter<'a, Ustr>,
+}
+
+impl<'a> Iterator for UstrBackendIter<'a> {
+    type Item = (Ustr, &'a str);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|u| (*u, u.as_str()))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for UstrBackendIter<'_> {}
+
+#[test]
+fn test_ustr_backend() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+    use string_interner::StringInterner;
+
+    unsafe { super::_clear_cache() };
+
+    let existing = u("existing");
+    let mut a = StringInterner::<UstrBackend>::with_capacity(4);
+    let mut b = StringInterner::<UstrBackend>::new();
+    let x = a.get_or_intern("x");
+    assert_eq!(a.get_or_intern("existing"), existing);
+    assert_eq!(a.get_or_intern("x"), x);
+    assert_eq!(b.get_or_intern("x"), x);
+    assert_eq!(b.get_or_intern_static("static"), u("static"));
+    assert_eq!(a.len(), 2);
+    assert_eq!(b.get("existing"), None);
+    assert_eq!(b.resolve(existing), Some("existing"));
+    assert_eq!(
+        a.iter().collect::<Vec<_>>(),
+        [(x, "x"), (existing, "existing")]
+    );
+    assert_eq!(super::num_entries(), 3);
+
+    assert_eq!(Ustr::try_from_usize(x.to_usize()), Some(x));
+    assert_eq!(Ustr::try_from_usize(1 << 40), None);
+    assert_eq!(Ustr::try_from_usize(1000), None);
+}
@@ -0,0 +1,631 @@
+//! Helpers for keeping the string caches of two processes in sync.
+//!
+//! Since the precomputed hash of a `Ustr` is the same in every process built
+//! with the same hasher, it can be used as a compact identifier for a string
+//! on the wire. The other side needs to know which string each hash refers to
+//! though, which is what this module is for.
+//!
+//! A [`SyncClient`] asks a [`SyncServer`] for every string that has been added
+//! to the server's cache since the last time it asked (using
+//! [`entries_since()`](crate::entries_since)), and the server replies with
+//! `(hash, bytes)` frames. The client interns each string and checks that it
+//! gets the same hash, so that both sides are guaranteed to agree on the
+//! mapping from hash to string.
+//!
+//! If you'd rather not mirror the whole cache, send [`UstrWire`]s instead:
+//! each is just a string's hash and length, and the receiver resolves the
+//! ones it doesn't know yet with a single round trip using
+//! [`SyncClient::resolve()`].
+//!
+//! The protocol works over anything that is `Read + Write`, e.g. a
+//! `TcpStream`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::net::{TcpListener, TcpStream};
+//! use ustr::sync::{SyncClient, SyncServer};
+//!
+//! // On the server:
+//! let listener = TcpListener::bind("127.0.0.1:7878")?;
+//! let server = SyncServer::new();
+//! for stream in listener.incoming() {
+//!     server.serve(&mut stream?)?;
+//! }
+//!
+//! // On the client:
+//! let mut client = SyncClient::new();
+//! let mut stream = TcpStream::connect("127.0.0.1:7878")?;
+//! let new_strings = client.sync(&mut stream)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Wire format
+//!
+//! All integers are little-endian. Every message starts with the 4 bytes
+//! `USTR`, a version byte and a message kind byte.
+//!
+//! * A request is followed by the `u32` generation the client wants strings
+//!   from.
+//! * A response is followed by the server's `u32` generation at the time of
+//!   the request and a `u32` count of entries. Each entry is a `u64` hash, a
+//!   `u32` length and then that many bytes of UTF-8.
+//! * A resolve request is followed by a `u32` count of [`UstrWire`]s, each a
+//!   `u64` hash and a `u32` length.
+//! * A resolve response is followed by a `u32` count of entries, in the same
+//!   format as a response, for the requested strings the server knows.
+use super::{
+    current_generation, entries_since, read_bin, whichbin, Ustr, STRING_CACHE,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    io::{self, Read, Write},
+    ptr::NonNull,
+};
+
+const MAGIC: &[u8; 4] = b"USTR";
+const VERSION: u8 = 1;
+const KIND_REQUEST: u8 = 0;
+const KIND_RESPONSE: u8 = 1;
+const KIND_RESOLVE_REQUEST: u8 = 2;
+const KIND_RESOLVE_RESPONSE: u8 = 3;
+
+fn write_header<W: Write>(writer: &mut W, kind: u8) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_u8(VERSION)?;
+    writer.write_u8(kind)
+}
+
+fn read_header<R: Read>(reader: &mut R, kind: u8) -> io::Result<()> {
+    if read_kind(reader)? != kind {
+        return Err(invalid_data("unexpected ustr sync message kind"));
+    }
+    Ok(())
+}
+
+// Read a message header, returning its kind.
+fn read_kind<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a ustr sync message"));
+    }
+    let version = reader.read_u8()?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported ustr sync version {}",
+            version
+        )));
+    }
+    reader.read_u8()
+}
+
+fn write_count<W: Write>(writer: &mut W, count: usize) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(
+        u32::try_from(count).map_err(|_| invalid_data("too many strings"))?,
+    )
+}
+
+fn write_entry<W: Write>(writer: &mut W, u: Ustr) -> io::Result<()> {
+    writer.write_u64::<LittleEndian>(u.precomputed_hash())?;
+    writer.write_u32::<LittleEndian>(
+        u32::try_from(u.len()).map_err(|_| invalid_data("string too long"))?,
+    )?;
+    writer.write_all(u.as_bytes())
+}
+
+// Read an entry and add its string to the cache, checking that we get the
+// same hash as the sender. `buf` is scratch space.
+fn read_entry<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<Ustr> {
+    let hash = reader.read_u64::<LittleEndian>()?;
+    let len = reader.read_u32::<LittleEndian>()? as usize;
+    buf.resize(len, 0);
+    reader.read_exact(buf)?;
+    let s = std::str::from_utf8(buf).map_err(invalid_data)?;
+    let u = Ustr::from(s);
+    if u.precomputed_hash() != hash {
+        return Err(invalid_data(format!(
+            "hash mismatch for {:?}: server has {:#x}, we have {:#x}",
+            s,
+            hash,
+            u.precomputed_hash()
+        )));
+    }
+    Ok(u)
+}
+
+/// A `Ustr` as it's sent between processes: just its hash and length.
+///
+/// Since the hash of a string is the same in every process using the same
+/// hasher, a `UstrWire` identifies a string without sending its bytes. The
+/// receiver turns it back into a `Ustr` with [`resolve()`](Self::resolve) if
+/// it already has the string, or asks the sender for the ones it doesn't
+/// with [`SyncClient::resolve()`].
+///
+/// It's 12 bytes on the wire, written with [`write_to()`](Self::write_to),
+/// and with the `serde` feature it serializes as a `(hash, len)` tuple.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{sync::UstrWire, ustr as u};
+///
+/// let wire = UstrWire::from(u("shader/albedo"));
+/// let mut bytes = Vec::new();
+/// wire.write_to(&mut bytes)?;
+/// assert_eq!(bytes.len(), 12);
+///
+/// let received = UstrWire::read_from(&mut bytes.as_slice())?;
+/// assert_eq!(received.resolve(), Some(u("shader/albedo")));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UstrWire {
+    /// The string's precomputed hash.
+    pub hash: u64,
+    /// The length of the string in bytes.
+    pub len: u32,
+}
+
+impl UstrWire {
+    /// Get the wire form of `u`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `u` is longer than `u32::MAX` bytes.
+    pub fn new(u: Ustr) -> UstrWire {
+        UstrWire {
+            hash: u.precomputed_hash(),
+            len: u32::try_from(u.len()).expect("string too long for UstrWire"),
+        }
+    }
+
+    /// Look up the string this refers to in this process's cache, without
+    /// adding anything to it.
+    ///
+    /// Returns `None` if there's no string in the cache with this hash and
+    /// length. If two strings share both (which for a 64-bit hash is very
+    /// unlikely), one of them is returned.
+    pub fn resolve(&self) -> Option<Ustr> {
+        let bin = read_bin(&STRING_CACHE.0[whichbin(self.hash)]);
+        let char_ptr = bin.get_by_hash(self.hash, self.len as usize)?;
+        Some(Ustr {
+            char_ptr: unsafe { NonNull::new_unchecked(char_ptr as *mut u8) },
+        })
+    }
+
+    /// Write this as a `u64` hash and a `u32` length, both little-endian.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u64::<LittleEndian>(self.hash)?;
+        writer.write_u32::<LittleEndian>(self.len)
+    }
+
+    /// Read a `UstrWire` written by [`write_to()`](Self::write_to).
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<UstrWire> {
+        Ok(UstrWire {
+            hash: reader.read_u64::<LittleEndian>()?,
+            len: reader.read_u32::<LittleEndian>()?,
+        })
+    }
+}
+
+impl From<Ustr> for UstrWire {
+    fn from(u: Ustr) -> UstrWire {
+        UstrWire::new(u)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for UstrWire {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&(self.hash, self.len), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UstrWire {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<UstrWire, D::Error> {
+        let (hash, len) = serde::Deserialize::deserialize(deserializer)?;
+        Ok(UstrWire { hash, len })
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Serves the contents of this process's cache to [`SyncClient`]s.
+#[derive(Debug, Default)]
+pub struct SyncServer {}
+
+impl SyncServer {
+    /// Create a new `SyncServer`.
+    pub fn new() -> SyncServer {
+        SyncServer {}
+    }
+
+    /// Read a single request from `stream` and write the response back to it.
+    ///
+    /// This answers both [`SyncClient::sync()`] and
+    /// [`SyncClient::resolve()`].
+    pub fn serve<S: Read + Write>(&self, stream: &mut S) -> io::Result<()> {
+        match read_kind(stream)? {
+            KIND_REQUEST => {
+                let since = stream.read_u32::<LittleEndian>()?;
+                self.write_response(stream, since)?;
+            }
+            KIND_RESOLVE_REQUEST => {
+                let wires = read_wires(stream)?;
+                self.write_resolve_response(stream, &wires)?;
+            }
+            _ => return Err(invalid_data("unexpected ustr sync message kind")),
+        }
+        stream.flush()
+    }
+
+    /// Read a request from `reader`, returning the generation the client
+    /// wants strings from.
+    pub fn read_request<R: Read>(&self, reader: &mut R) -> io::Result<u32> {
+        read_header(reader, KIND_REQUEST)?;
+        reader.read_u32::<LittleEndian>()
+    }
+
+    /// Write a response containing every string added since generation
+    /// `since` to `writer`.
+    ///
+    /// If `since` is newer than our current generation then the client must
+    /// be talking to a different server (or this one has restarted), so we
+    /// send everything.
+    pub fn write_response<W: Write>(
+        &self,
+        writer: &mut W,
+        since: u32,
+    ) -> io::Result<()> {
+        // Read this before collecting the entries so that nothing added in
+        // the meantime can be missed by the client's next request.
+        let generation = current_generation();
+        let since = if since > generation { 0 } else { since };
+        let entries = entries_since(since).collect::<Vec<_>>();
+
+        write_header(writer, KIND_RESPONSE)?;
+        writer.write_u32::<LittleEndian>(generation)?;
+        write_count(writer, entries.len())?;
+        for u in entries {
+            write_entry(writer, u)?;
+        }
+        Ok(())
+    }
+
+    /// Read a resolve request from `reader`, returning the strings the
+    /// client wants.
+    pub fn read_resolve_request<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<Vec<UstrWire>> {
+        read_header(reader, KIND_RESOLVE_REQUEST)?;
+        read_wires(reader)
+    }
+
+    /// Write a response containing each of the strings in `wires` that's in
+    /// our cache to `writer`. Strings we don't have are left out.
+    pub fn write_resolve_response<W: Write>(
+        &self,
+        writer: &mut W,
+        wires: &[UstrWire],
+    ) -> io::Result<()> {
+        let found =
+            wires.iter().filter_map(|w| w.resolve()).collect::<Vec<_>>();
+        write_header(writer, KIND_RESOLVE_RESPONSE)?;
+        write_count(writer, found.len())?;
+        for u in found {
+            write_entry(writer, u)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_wires<R: Read>(reader: &mut R) -> io::Result<Vec<UstrWire>> {
+    let count = reader.read_u32::<LittleEndian>()?;
+    (0..count).map(|_| UstrWire::read_from(reader)).collect()
+}
+
+/// Fetches new strings from a [`SyncServer`] and adds them to this process's
+/// cache.
+#[derive(Debug, Default)]
+pub struct SyncClient {
+    generation: u32,
+}
+
+impl SyncClient {
+    /// Create a new `SyncClient` that has not seen any strings yet.
+    pub fn new() -> SyncClient {
+        SyncClient { generation: 0 }
+    }
+
+    /// The server generation that we have received all strings up to.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Ask the server on the other end of `stream` for any strings we haven't
+    /// seen yet and add them to the cache, returning the new strings.
+    pub fn sync<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+    ) -> io::Result<Vec<Ustr>> {
+        self.write_request(stream)?;
+        stream.flush()?;
+        self.read_response(stream)
+    }
+
+    /// Write a request for the strings we haven't seen yet to `writer`.
+    pub fn write_request<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_header(writer, KIND_REQUEST)?;
+        writer.write_u32::<LittleEndian>(self.generation)
+    }
+
+    /// Read the server's response from `reader` and add the strings it
+    /// contains to the cache.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the server
+    /// sends invalid UTF-8 or a string whose hash is different from the hash
+    /// we compute for it, as that means the two sides are using different
+    /// hashers.
+    pub fn read_response<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> io::Result<Vec<Ustr>> {
+        read_header(reader, KIND_RESPONSE)?;
+        let generation = reader.read_u32::<LittleEndian>()?;
+        let count = reader.read_u32::<LittleEndian>()?;
+
+        let mut buf = Vec::new();
+        let strings = (0..count)
+            .map(|_| read_entry(reader, &mut buf))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Only update once we've successfully received everything, so that a
+        // failed sync can just be retried.
+        self.generation = generation;
+        Ok(strings)
+    }
+
+    /// Turn each of `wires` back into a `Ustr`, asking the server on the
+    /// other end of `stream` for any we don't have yet.
+    ///
+    /// Strings that are already in our cache are resolved locally, and if
+    /// they all are, nothing is sent. The result has an entry for each of
+    /// `wires`, which is `None` if neither side has the string.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::TcpStream;
+    /// use ustr::sync::{SyncClient, UstrWire};
+    ///
+    /// # let wires: Vec<UstrWire> = Vec::new();
+    /// let mut stream = TcpStream::connect("127.0.0.1:7878")?;
+    /// let strings = SyncClient::new().resolve(&mut stream, &wires)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn resolve<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        wires: &[UstrWire],
+    ) -> io::Result<Vec<Option<Ustr>>> {
+        let mut resolved =
+            wires.iter().map(|w| w.resolve()).collect::<Vec<_>>();
+        let unknown = wires
+            .iter()
+            .zip(&resolved)
+            .filter(|(_, u)| u.is_none())
+            .map(|(w, _)| *w)
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            return Ok(resolved);
+        }
+
+        self.write_resolve_request(stream, &unknown)?;
+        stream.flush()?;
+        for u in self.read_resolve_response(stream)? {
+            let wire = UstrWire::from(u);
+            for (w, r) in wires.iter().zip(&mut resolved) {
+                if *w == wire {
+                    *r = Some(u);
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Write a request for the strings in `wires` to `writer`.
+    pub fn write_resolve_request<W: Write>(
+        &self,
+        writer: &mut W,
+        wires: &[UstrWire],
+    ) -> io::Result<()> {
+        write_header(writer, KIND_RESOLVE_REQUEST)?;
+        write_count(writer, wires.len())?;
+        for wire in wires {
+            wire.write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Read the server's response to a resolve request from `reader` and add
+    /// the strings it contains to the cache.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] in the same
+    /// cases as [`read_response()`](Self::read_response).
+    pub fn read_resolve_response<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<Vec<Ustr>> {
+        read_header(reader, KIND_RESOLVE_RESPONSE)?;
+        let count = reader.read_u32::<LittleEndian>()?;
+        let mut buf = Vec::new();
+        (0..count).map(|_| read_entry(reader, &mut buf)).collect()
+    }
+}
+
+#[test]
+fn test_sync() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let server = SyncServer::new();
+    let mut client = SyncClient::new();
+
+    let round_trip = |client: &mut SyncClient| {
+        let mut request = Vec::new();
+        client.write_request(&mut request).unwrap();
+        let since = server.read_request(&mut request.as_slice()).unwrap();
+        let mut response = Vec::new();
+        server.write_response(&mut response, since).unwrap();
+        client.read_response(&mut response.as_slice()).unwrap()
+    };
+
+    let first = vec![u("hello"), u("world")];
+    assert_eq!(round_trip(&mut client), first);
+    assert_eq!(client.generation(), 2);
+
+    // Only the new strings are sent the second time around.
+    let second = vec![u("Τη γλώσσα μου έδωσαν ελληνική")];
+    u("hello");
+    assert_eq!(round_trip(&mut client), second);
+    assert!(round_trip(&mut client).is_empty());
+
+    // A client that's ahead of the server gets everything.
+    let mut ahead = SyncClient { generation: 100 };
+    assert_eq!(round_trip(&mut ahead).len(), 3);
+    assert_eq!(ahead.generation(), 3);
+}
+
+#[test]
+fn test_sync_hash_mismatch() {
+    let _t = super::TEST_LOCK.lock();
+
+    let mut response = Vec::new();
+    write_header(&mut response, KIND_RESPONSE).unwrap();
+    response.write_u32::<LittleEndian>(1).unwrap();
+    response.write_u32::<LittleEndian>(1).unwrap();
+    response
+        .write_u64::<LittleEndian>(crate::ustr("hash").precomputed_hash() ^ 1)
+        .unwrap();
+    response.write_u32::<LittleEndian>(4).unwrap();
+    response.write_all(b"hash").unwrap();
+
+    let mut client = SyncClient::new();
+    let err = client.read_response(&mut response.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(client.generation(), 0);
+}
+
+#[test]
+fn test_resolve() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let known = u("known");
+    let wire = UstrWire::from(known);
+    assert_eq!(wire.len, 5);
+    assert_eq!(wire.resolve(), Some(known));
+
+    let mut bytes = Vec::new();
+    wire.write_to(&mut bytes).unwrap();
+    assert_eq!(UstrWire::read_from(&mut bytes.as_slice()).unwrap(), wire);
+
+    // Act as the server by interning the strings it has, then forget them
+    // and act as the client.
+    let remote = ["remote", "ρεμότε"];
+    let remote_wires = remote.map(|s| UstrWire::from(u(s)));
+    let missing = UstrWire { hash: 1, len: 3 };
+    let wires = [wire, remote_wires[0], missing, remote_wires[1]];
+
+    let client = SyncClient::new();
+    let server = SyncServer::new();
+    let mut request = Vec::new();
+    client
+        .write_resolve_request(&mut request, &wires[1..])
+        .unwrap();
+    let asked = server
+        .read_resolve_request(&mut request.as_slice())
+        .unwrap();
+    assert_eq!(asked, &wires[1..]);
+    let mut response = Vec::new();
+    server
+        .write_resolve_response(&mut response, &asked)
+        .unwrap();
+
+    unsafe { super::_clear_cache() };
+    assert_eq!(remote_wires[0].resolve(), None);
+    let known = u("known");
+
+    // The client only asks for what it doesn't know, and the server's reply
+    // fills in the gaps.
+    let mut stream = Stream {
+        input: response.as_slice(),
+        output: Vec::new(),
+    };
+    let resolved = client.resolve(&mut stream, &wires).unwrap();
+    assert_eq!(stream.output, request);
+    assert_eq!(
+        resolved,
+        [Some(known), Some(u("remote")), None, Some(u("ρεμότε"))]
+    );
+
+    // Once everything is known, nothing is sent.
+    let mut stream = Stream {
+        input: &[],
+        output: Vec::new(),
+    };
+    let resolved = client.resolve(&mut stream, &[wire]).unwrap();
+    assert_eq!(resolved, [Some(known)]);
+    assert!(stream.output.is_empty());
+
+    // `serve()` answers resolve requests too.
+    let mut stream = Stream {
+        input: request.as_slice(),
+        output: Vec::new(),
+    };
+    server.serve(&mut stream).unwrap();
+    assert_eq!(
+        client
+            .read_resolve_response(&mut stream.output.as_slice())
+            .unwrap(),
+        [u("remote"), u("ρεμότε")]
+    );
+
+    struct Stream<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    impl Read for Stream<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Stream<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::Ustr;
+use std::{fmt, ops::Deref, sync::OnceLock};
+
+/// A fixed table of `Ustr`s that can be declared in a `static`.
+///
+/// A `Ustr` can't be created at compile time since the cache only exists at
+/// run time, so this holds the strings as `&'static str`s and interns them
+/// all the first time the table is used. After that, accessing the table is a
+/// single atomic load.
+///
+/// This is handy for keyword tables and other sets of identifiers that are
+/// known up front: the `Ustr`s in the table can be compared against
+/// user-provided `Ustr`s with a pointer comparison.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrTable};
+///
+/// static KEYWORDS: UstrTable<3> = UstrTable::new(["fn", "let", "match"]);
+///
+/// assert_eq!(KEYWORDS.len(), 3);
+/// assert_eq!(KEYWORDS[1], u("let"));
+/// assert_eq!(KEYWORDS.index_of(u("match")), Some(2));
+/// assert!(!KEYWORDS.contains(u("my_var")));
+/// ```
+pub struct UstrTable<const N: usize> {
+    strings: [&'static str; N],
+    ustrs: OnceLock<[Ustr; N]>,
+}
+
+impl<const N: usize> UstrTable<N> {
+    /// Create a table of `strings`. Nothing is interned until the table is
+    /// first used.
+    pub const fn new(strings: [&'static str; N]) -> UstrTable<N> {
+        UstrTable {
+            strings,
+            ustrs: OnceLock::new(),
+        }
+    }
+
+    /// The `Ustr`s in the table, interning them if this is the first use.
+    #[inline]
+    pub fn get(&self) -> &[Ustr; N] {
+        self.ustrs.get_or_init(|| self.strings.map(Ustr::from))
+    }
+
+    /// The strings the table was created with, without interning them.
+    pub const fn strings(&self) -> &[&'static str; N] {
+        &self.strings
+    }
+
+    /// The number of strings in the table.
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the table has no strings.
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// The position of `u` in the table, if it's there.
+    ///
+    /// This is a linear scan of pointer comparisons, which is fast for the
+    /// small tables this is meant for. Use a [`UstrMap`](crate::UstrMap) for
+    /// large tables.
+    pub fn index_of(&self, u: Ustr) -> Option<usize> {
+        self.get().iter().position(|x| *x == u)
+    }
+
+    /// Returns `true` if `u` is in the table.
+    pub fn contains(&self, u: Ustr) -> bool {
+        self.index_of(u).is_some()
+    }
+}
+
+impl<const N: usize> Deref for UstrTable<N> {
+    type Target = [Ustr; N];
+    fn deref(&self) -> &[Ustr; N] {
+        self.get()
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a UstrTable<N> {
+    type Item = &'a Ustr;
+    type IntoIter = std::slice::Iter<'a, Ustr>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.get().iter()
+    }
+}
+
+impl<const N: usize> fmt::Debug for UstrTable<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.strings.iter()).finish()
+    }
+}
+
+#[test]
+fn test_ustr_table() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    static TABLE: UstrTable<2> = UstrTable::new(["alpha", "beta"]);
+    const STRINGS: &[&str; 2] = TABLE.strings();
+    assert_eq!(STRINGS, &["alpha", "beta"]);
+
+    // Nothing is interned until the table is used.
+    assert_eq!(super::num_entries(), 0);
+    assert_eq!(TABLE.get(), &[u("alpha"), u("beta")]);
+    assert_eq!(super::num_entries(), 2);
+
+    assert_eq!(TABLE.index_of(u("beta")), Some(1));
+    assert_eq!(TABLE.index_of(u("gamma")), None);
+    assert_eq!(TABLE.iter().count(), 2);
+    assert_eq!((&TABLE).into_iter().count(), 2);
+    assert_eq!(format!("{:?}", TABLE), r#"["alpha", "beta"]"#);
+
+    let empty = UstrTable::new([]);
+    assert!(empty.is_empty());
+    assert!(!empty.contains(u("alpha")));
+}
@@ -0,0 +1,184 @@
+//! Support for tests that use the global cache.
+//!
+//! Tests run in parallel by default, and since they all share the cache, a
+//! test that checks what's in it, such as with [`num_entries()`] or
+//! [`Ustr::id()`](crate::Ustr::id), sees the strings that other tests are
+//! adding. [`CacheGuard`] gives a test an empty cache of its own for as long
+//! as it's held, and serializes the tests that use one.
+//!
+//! [`num_entries()`]: crate::num_entries
+use super::{
+    frozen, ids, stringcache::NEXT_GENERATION, FrozenCache, StringCache,
+    STRING_CACHE,
+};
+use parking_lot::{Mutex, MutexGuard};
+use std::sync::atomic::Ordering;
+
+// Held by each `CacheGuard`, so only one test at a time replaces the cache.
+static GUARD_LOCK: Mutex<()> = Mutex::new(());
+
+// Everything that was in use while a guard was held, which is kept rather
+// than freed since `Ustr`s from it may outlive the guard.
+struct Retired {
+    bins: Vec<StringCache>,
+    ids: ids::Table,
+    frozen: *mut FrozenCache,
+}
+
+// The frozen snapshot is never mutated.
+unsafe impl Send for Retired {}
+
+static RETIRED: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+/// Gives the current thread an empty cache until it's dropped, then puts back
+/// the cache as it was.
+///
+/// While the guard is held the cache starts empty, ids start from 0 again and
+/// the cache isn't frozen, so tests see the same cache contents whatever
+/// other tests have done. When it's dropped, the strings, ids and frozen
+/// snapshot from before are restored. Only one guard can exist at a time, so
+/// [`CacheGuard::new()`] waits for any other test holding one to finish.
+///
+/// Unlike `_clear_cache()`, this is safe: no memory is ever freed, so every
+/// `Ustr` stays valid. A `Ustr` created while the guard was held is no longer
+/// in the cache once it's dropped, though, so it won't compare equal to a
+/// `Ustr` of the same string created before or after. The memory for the
+/// strings added while a guard is held is kept until the program exits.
+///
+/// Strings being added by other threads while the cache is replaced go into
+/// whichever cache is current, so tests that don't use a guard should only
+/// look at the strings they added themselves. Creating a second guard on the
+/// same thread while one is held deadlocks.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{testing::CacheGuard, ustr as u};
+///
+/// let before = u("from before");
+/// {
+///     let _guard = CacheGuard::new();
+///     assert_eq!(ustr::num_entries(), 0);
+///     assert_eq!(u("first").id(), 0);
+/// }
+/// assert_eq!(u("from before"), before);
+/// ```
+pub struct CacheGuard {
+    saved: Option<Retired>,
+    next_generation: u32,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl CacheGuard {
+    /// Set the cache aside and replace it with an empty one, waiting for any
+    /// other guard to be dropped first.
+    pub fn new() -> CacheGuard {
+        let lock = GUARD_LOCK.lock();
+        let (saved, next_generation) = swap_cache(Retired {
+            bins: Vec::new(),
+            ids: ids::Table::EMPTY,
+            frozen: std::ptr::null_mut(),
+        });
+        CacheGuard {
+            saved: Some(saved),
+            next_generation,
+            _lock: lock,
+        }
+    }
+}
+
+impl Default for CacheGuard {
+    fn default() -> CacheGuard {
+        CacheGuard::new()
+    }
+}
+
+impl Drop for CacheGuard {
+    fn drop(&mut self) {
+        let saved = self.saved.take().expect("cache already restored");
+        let (retired, _) = swap_cache(saved);
+        NEXT_GENERATION.store(self.next_generation, Ordering::Release);
+        RETIRED.lock().push(retired);
+    }
+}
+
+// Swap the cache for `cache`, with a fresh bin wherever it has none, and
+// return the old one and its next generation. The next generation is reset
+// to 0.
+fn swap_cache(mut cache: Retired) -> (Retired, u32) {
+    // Hold every write lock so that nothing is added while we swap.
+    let mut bins = STRING_CACHE.0.iter().map(|b| b.write()).collect::<Vec<_>>();
+    let mut new_bins = cache.bins.drain(..);
+    let old_bins = bins
+        .iter_mut()
+        .map(|bin| {
+            std::mem::replace(&mut **bin, new_bins.next().unwrap_or_default())
+        })
+        .collect();
+    let next_generation = NEXT_GENERATION.swap(0, Ordering::AcqRel);
+    let old = Retired {
+        bins: old_bins,
+        ids: ids::replace(cache.ids),
+        frozen: frozen::replace(cache.frozen),
+    };
+    // The filters describe the old bins, so forget them before anything can
+    // look in the new ones.
+    #[cfg(feature = "negative-filter")]
+    super::negative::clear();
+    drop(bins);
+
+    // The side tables refer to strings in the old cache, and are only caches
+    // themselves.
+    super::case::clear();
+    super::memoize::clear();
+    super::static_ustr::clear();
+    super::transform::clear();
+    super::utf16::clear();
+    (old, next_generation)
+}
+
+#[test]
+fn test_cache_guard() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{
+        freeze, from_id, memoize_display, num_entries, ustr as u, FreezePolicy,
+    };
+
+    unsafe { super::_clear_cache() };
+
+    let before = (0..100)
+        .map(|i| u(&format!("before {}", i)))
+        .collect::<Vec<_>>();
+    let memoized = memoize_display(&1);
+    freeze(FreezePolicy::Fallback);
+
+    let during = {
+        let _guard = CacheGuard::new();
+        assert_eq!(num_entries(), 0);
+        assert!(frozen().is_none());
+        assert_eq!(from_id(0), None);
+
+        let a = u("during a");
+        assert_eq!(a.id(), 0);
+        assert_eq!(from_id(0), Some(a));
+        assert_eq!(u("during b").id(), 1);
+        assert_ne!(u("before 3"), before[3]);
+        assert_ne!(memoize_display(&1), memoized);
+        assert_eq!(num_entries(), 4);
+        a
+    };
+
+    // Everything is as it was.
+    assert_eq!(num_entries(), 101);
+    assert!(frozen().is_some());
+    for b in &before {
+        assert_eq!(u(b.as_str()), *b);
+        assert_eq!(from_id(b.id()), Some(*b));
+    }
+    assert_eq!(super::current_generation(), 101);
+    // Strings from the guard are still valid, but not in the cache.
+    assert_eq!(during.as_str(), "during a");
+    assert_ne!(u("during a"), during);
+
+    unsafe { super::_clear_cache() };
+}
@@ -0,0 +1,91 @@
+use parking_lot::Mutex;
+use std::{collections::HashMap, panic::Location};
+
+/// The number of strings and bytes added to the cache from a single call site,
+/// as returned by [`allocation_report()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationSite {
+    /// The location of the call that created the strings.
+    pub location: &'static Location<'static>,
+    /// The number of new strings added to the cache from this location.
+    pub count: usize,
+    /// The number of bytes of string storage used by those strings, including
+    /// the per-string header and null terminator.
+    pub bytes: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref SITES: Mutex<HashMap<&'static Location<'static>, (usize, usize)>> =
+        Mutex::new(HashMap::new());
+}
+
+// Record that a new string of `bytes` bytes was added to the cache from
+// `location`. This is only called when the string wasn't already in the cache.
+pub(crate) fn record(location: &'static Location<'static>, bytes: usize) {
+    let mut sites = SITES.lock();
+    let site = sites.entry(location).or_insert((0, 0));
+    site.0 += 1;
+    site.1 += bytes;
+}
+
+pub(crate) fn clear() {
+    SITES.lock().clear();
+}
+
+/// Returns the number of strings and bytes added to the cache from each call
+/// site, sorted by bytes in descending order.
+///
+/// Only strings that were newly added to the cache are counted, so this tells
+/// you which parts of your program are responsible for the cache growing. Call
+/// sites are tracked through [`Ustr::from`](crate::Ustr::from), the [`ustr()`]
+/// function and the `From` impls for `Ustr`, so if you wrap those in your own
+/// functions you'll want to mark them `#[track_caller]` as well.
+///
+/// This is available with the `debug-alloc-tracking` feature.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::ustr;
+///
+/// ustr("tracked");
+/// let report = ustr::allocation_report();
+/// assert!(report.iter().any(|site| site.location.file() == file!()));
+/// ```
+///
+/// [`ustr()`]: crate::ustr()
+pub fn allocation_report() -> Vec<AllocationSite> {
+    let mut report = SITES
+        .lock()
+        .iter()
+        .map(|(location, (count, bytes))| AllocationSite {
+            location,
+            count: *count,
+            bytes: *bytes,
+        })
+        .collect::<Vec<_>>();
+    report.sort_by_key(|site| std::cmp::Reverse(site.bytes));
+    report
+}
+
+#[test]
+fn test_allocation_report() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let line = line!() + 2;
+    for i in 0..10 {
+        u(&format!("tracked string {}", i));
+    }
+    // Hits don't add anything to the report.
+    u("tracked string 0");
+
+    let report = allocation_report();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].location.file(), file!());
+    assert_eq!(report[0].location.line(), line);
+    assert_eq!(report[0].count, 10);
+    assert_eq!(report[0].bytes, super::total_allocated());
+}
@@ -0,0 +1,186 @@
+use super::Ustr;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+// The results of `map_str_memoized()`, keyed by transformation id and input.
+static MAPPED: RwLock<Option<HashMap<(Ustr, Ustr), Ustr>>> = RwLock::new(None);
+
+impl Ustr {
+    /// Apply `f` to this string and intern the result.
+    ///
+    /// `f` can return a slice of its argument, such as from
+    /// [`str::trim_start()`], or a new `String`. If it returns the whole
+    /// string unchanged, this `Ustr` is returned without looking anything up
+    /// in the cache. Use [`map_str_memoized()`](Ustr::map_str_memoized) to
+    /// remember the result for the next time.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let path = u("assets/textures/wood.png");
+    /// let name = path.map_str(|s| s.rsplit('/').next().unwrap());
+    /// assert_eq!(name, u("wood.png"));
+    /// assert_eq!(u("ab").map_str(|s| s.repeat(2)), u("abab"));
+    /// ```
+    pub fn map_str<F, R>(&self, f: F) -> Ustr
+    where
+        F: FnOnce(&'static str) -> R,
+        R: AsRef<str>,
+    {
+        let s = self.as_str();
+        let mapped = f(s);
+        let mapped = mapped.as_ref();
+        if mapped.as_ptr() == s.as_ptr() && mapped.len() == s.len() {
+            *self
+        } else {
+            Ustr::from(mapped)
+        }
+    }
+
+    /// Apply the transformation identified by `id` to this string and intern
+    /// the result, calling `f` only the first time it's seen.
+    ///
+    /// The result is remembered for each pair of `id` and string, so applying
+    /// the same transformation to the same strings over and over is a hash
+    /// map lookup. `f` must always return the same result for the same string,
+    /// and each `id` must only ever be used with one transformation.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{static_ustr, ustr as u};
+    ///
+    /// fn normalize(name: ustr::Ustr) -> ustr::Ustr {
+    ///     name.map_str_memoized(static_ustr!("normalize"), |s| {
+    ///         s.trim().to_ascii_lowercase().replace(' ', "_")
+    ///     })
+    /// }
+    ///
+    /// assert_eq!(normalize(u(" Base Color ")), u("base_color"));
+    /// assert_eq!(normalize(u(" Base Color ")), u("base_color"));
+    /// ```
+    pub fn map_str_memoized<F, R>(&self, id: Ustr, f: F) -> Ustr
+    where
+        F: FnOnce(&'static str) -> R,
+        R: AsRef<str>,
+    {
+        let key = (id, *self);
+        if let Some(mapped) = MAPPED
+            .read()
+            .as_ref()
+            .and_then(|map| map.get(&key).copied())
+        {
+            return mapped;
+        }
+        // Transform outside the lock, since `f` might use this too.
+        let mapped = self.map_str(f);
+        *MAPPED
+            .write()
+            .get_or_insert_with(HashMap::new)
+            .entry(key)
+            .or_insert(mapped)
+    }
+
+    /// Get this string with leading and trailing whitespace removed, as a
+    /// `Ustr`.
+    ///
+    /// The result is remembered for each string, like
+    /// [`map_str_memoized()`](Ustr::map_str_memoized).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("  padded\n").trim_interned(), u("padded"));
+    /// ```
+    pub fn trim_interned(&self) -> Ustr {
+        self.map_str_memoized(crate::static_ustr!("ustr::trim"), str::trim)
+    }
+
+    /// Get this string with every match of `from` replaced with `to`, as a
+    /// `Ustr`.
+    ///
+    /// If `from` doesn't occur in the string, this `Ustr` is returned without
+    /// allocating. The result isn't memoized since it depends on `from` and
+    /// `to`; use [`map_str_memoized()`](Ustr::map_str_memoized) with an id of
+    /// your own for replacements done in a hot loop.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// assert_eq!(u("a.b.c").replace_interned(".", "::"), u("a::b::c"));
+    /// ```
+    pub fn replace_interned(&self, from: &str, to: &str) -> Ustr {
+        self.map_str(|s| {
+            if s.contains(from) {
+                s.replace(from, to).into()
+            } else {
+                std::borrow::Cow::Borrowed(s)
+            }
+        })
+    }
+}
+
+// Forget every memoized transformation. Only called by `_clear_cache()`.
+pub(crate) fn clear() {
+    *MAPPED.write() = None;
+}
+
+#[test]
+fn test_map_str() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{static_ustr, ustr as u};
+    use std::cell::Cell;
+
+    unsafe { super::_clear_cache() };
+
+    // Returning the string unchanged doesn't touch the cache, but a prefix
+    // of it is a different string.
+    let abc = u("abc");
+    assert_eq!(abc.map_str(|s| s), abc);
+    assert_eq!(abc.map_str(|s| &s[..2]), u("ab"));
+    assert_eq!(abc.map_str(str::to_uppercase), u("ABC"));
+    assert_eq!(abc.replace_interned("x", "y"), abc);
+    assert_eq!(abc.replace_interned("b", ""), u("ac"));
+    assert_eq!(u(" \tabc ").trim_interned(), abc);
+    assert_eq!(abc.trim_interned(), abc);
+
+    let calls = Cell::new(0);
+    let id = static_ustr!("double");
+    let double = |u: Ustr| {
+        u.map_str_memoized(id, |s| {
+            calls.set(calls.get() + 1);
+            s.repeat(2)
+        })
+    };
+    for _ in 0..3 {
+        assert_eq!(double(abc), u("abcabc"));
+        assert_eq!(double(u("x")), u("xx"));
+    }
+    assert_eq!(calls.get(), 2);
+
+    // Each id has its own results.
+    let other = abc.map_str_memoized(u("other"), |_| "other result");
+    assert_eq!(other, u("other result"));
+    assert_eq!(double(abc), u("abcabc"));
+
+    unsafe { super::_clear_cache() };
+    assert!(MAPPED.read().is_none());
+}
@@ -0,0 +1,209 @@
+use super::{Ustr, UstrMap};
+
+/// A map from sequences of `Ustr`s to values, supporting longest-prefix
+/// queries.
+///
+/// Each node stores its children in a [`UstrMap`], so walking down the trie
+/// only ever uses the precomputed hash and pointer comparisons. Keys are
+/// slices of components, which makes it a natural fit for
+/// [`UstrPath::components`](crate::UstrPath::components).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{UstrPath, UstrTrie};
+///
+/// let mut materials = UstrTrie::new();
+/// materials.insert(UstrPath::new("/World").components(), "default");
+/// materials.insert(UstrPath::new("/World/Car/Body").components(), "paint");
+///
+/// let wheel = UstrPath::new("/World/Car/Wheel");
+/// let (prefix, material) =
+///     materials.longest_prefix(wheel.components()).unwrap();
+/// assert_eq!(prefix.len(), 1);
+/// assert_eq!(*material, "default");
+///
+/// let door = UstrPath::new("/World/Car/Body/Door");
+/// let (_, material) = materials.longest_prefix(door.components()).unwrap();
+/// assert_eq!(*material, "paint");
+/// ```
+#[derive(Clone, Debug)]
+pub struct UstrTrie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node<V> {
+    value: Option<V>,
+    children: UstrMap<Node<V>>,
+}
+
+impl<V> Node<V> {
+    fn new() -> Node<V> {
+        Node {
+            value: None,
+            children: UstrMap::default(),
+        }
+    }
+}
+
+impl<V> Default for UstrTrie<V> {
+    fn default() -> Self {
+        UstrTrie::new()
+    }
+}
+
+impl<V> UstrTrie<V> {
+    /// Create an empty `UstrTrie`.
+    pub fn new() -> UstrTrie<V> {
+        UstrTrie {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    /// The number of values stored in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the trie contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert `value` at `key`, returning the previous value if there was
+    /// one. The empty key is valid and refers to the root.
+    pub fn insert(&mut self, key: &[Ustr], value: V) -> Option<V> {
+        let mut node = &mut self.root;
+        for c in key {
+            node = node.children.entry(*c).or_insert_with(Node::new);
+        }
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    fn node(&self, key: &[Ustr]) -> Option<&Node<V>> {
+        let mut node = &self.root;
+        for c in key {
+            node = node.children.get(c)?;
+        }
+        Some(node)
+    }
+
+    /// Returns a reference to the value stored at exactly `key`.
+    pub fn get(&self, key: &[Ustr]) -> Option<&V> {
+        self.node(key)?.value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored at exactly `key`.
+    pub fn get_mut(&mut self, key: &[Ustr]) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        for c in key {
+            node = node.children.get_mut(c)?;
+        }
+        node.value.as_mut()
+    }
+
+    /// Returns `true` if there is a value stored at exactly `key`.
+    pub fn contains_key(&self, key: &[Ustr]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value stored at the longest prefix of `key` that has one,
+    /// along with that prefix.
+    pub fn longest_prefix<'k>(
+        &self,
+        key: &'k [Ustr],
+    ) -> Option<(&'k [Ustr], &V)> {
+        let mut node = &self.root;
+        let mut best = node.value.as_ref().map(|v| (0, v));
+        for (i, c) in key.iter().enumerate() {
+            node = match node.children.get(c) {
+                Some(node) => node,
+                None => break,
+            };
+            if let Some(v) = &node.value {
+                best = Some((i + 1, v));
+            }
+        }
+        best.map(|(len, v)| (&key[..len], v))
+    }
+
+    /// Remove the value stored at `key` and return it. Any nodes left without
+    /// values or children are freed.
+    pub fn remove(&mut self, key: &[Ustr]) -> Option<V> {
+        fn remove<V>(node: &mut Node<V>, key: &[Ustr]) -> Option<V> {
+            let (first, rest) = match key.split_first() {
+                Some(split) => split,
+                None => return node.value.take(),
+            };
+            let child = node.children.get_mut(first)?;
+            let value = remove(child, rest);
+            if child.value.is_none() && child.children.is_empty() {
+                node.children.remove(first);
+            }
+            value
+        }
+
+        let value = remove(&mut self.root, key);
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    /// Remove all values from the trie.
+    pub fn clear(&mut self) {
+        self.root = Node::new();
+        self.len = 0;
+    }
+}
+
+#[test]
+fn test_trie() {
+    let _t = super::TEST_LOCK.lock();
+    use crate::ustr as u;
+
+    let a = u("a");
+    let b = u("b");
+    let c = u("c");
+
+    let mut trie = UstrTrie::new();
+    assert!(trie.is_empty());
+    assert_eq!(trie.longest_prefix(&[a, b]), None);
+
+    assert_eq!(trie.insert(&[a], 1), None);
+    assert_eq!(trie.insert(&[a, b, c], 3), None);
+    assert_eq!(trie.insert(&[a], 10), Some(1));
+    assert_eq!(trie.len(), 2);
+
+    assert_eq!(trie.get(&[a]), Some(&10));
+    assert_eq!(trie.get(&[a, b]), None);
+    assert!(trie.contains_key(&[a, b, c]));
+    *trie.get_mut(&[a, b, c]).unwrap() += 1;
+
+    assert_eq!(trie.longest_prefix(&[a, b]), Some((&[a][..], &10)));
+    assert_eq!(
+        trie.longest_prefix(&[a, b, c, a]),
+        Some((&[a, b, c][..], &4))
+    );
+    assert_eq!(trie.longest_prefix(&[b]), None);
+
+    // The root can hold a value too.
+    trie.insert(&[], 0);
+    assert_eq!(trie.longest_prefix(&[b]), Some((&[][..], &0)));
+
+    assert_eq!(trie.remove(&[a, b, c]), Some(4));
+    assert_eq!(trie.remove(&[a, b, c]), None);
+    assert_eq!(trie.len(), 2);
+    assert!(trie.root.children[&a].children.is_empty());
+
+    trie.clear();
+    assert!(trie.is_empty());
+    assert_eq!(trie.get(&[]), None);
+}
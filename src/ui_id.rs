@@ -0,0 +1,111 @@
+use super::Ustr;
+
+/// Widget ids for immediate-mode UIs.
+///
+/// Interned names are what editor UIs usually key their widgets on, and the
+/// string's precomputed hash is already a good id, so these make one without
+/// hashing the string again.
+impl Ustr {
+    /// Get an [`egui::Id`] for this string, made from its precomputed hash.
+    ///
+    /// This is the same id as `egui::Id::new(u)`, and like that it's a root
+    /// id, so use [`egui::Id::with()`] to make child ids from it. In debug
+    /// builds egui also remembers the string, so the id prints as the name
+    /// it came from.
+    ///
+    /// This is available with the `egui` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let id = u("inspector").egui_id();
+    /// assert_eq!(id, egui::Id::new(u("inspector")));
+    /// assert_ne!(id, u("outliner").egui_id());
+    /// ```
+    #[cfg(feature = "egui")]
+    #[inline]
+    pub fn egui_id(&self) -> egui::Id {
+        egui::Id::new(*self)
+    }
+
+    /// Get an [`imgui::Id`] for this string, made from its precomputed hash
+    /// and seeded by the id stack of `ui` like every other imgui id.
+    ///
+    /// This is available with the `imgui` feature.
+    #[cfg(feature = "imgui")]
+    #[inline]
+    pub fn imgui_id(&self, ui: &imgui::Ui) -> imgui::Id {
+        ui.new_id_int(self.imgui_int())
+    }
+
+    /// Push this string's precomputed hash onto the imgui id stack, so that
+    /// it scopes the ids of the widgets made until the returned token is
+    /// popped or dropped.
+    ///
+    /// This is the same as `ui.push_id(u)`, without hashing the string.
+    ///
+    /// This is available with the `imgui` feature.
+    #[cfg(feature = "imgui")]
+    #[inline]
+    pub fn push_imgui_id<'ui>(
+        &self,
+        ui: &'ui imgui::Ui,
+    ) -> imgui::IdStackToken<'ui> {
+        ui.push_id_int(self.imgui_int())
+    }
+
+    // imgui ids are 32 bits, so fold the hash down to fit.
+    #[cfg(feature = "imgui")]
+    #[inline]
+    fn imgui_int(&self) -> i32 {
+        let hash = self.precomputed_hash();
+        ((hash >> 32) ^ hash) as u32 as i32
+    }
+}
+
+#[cfg(feature = "egui")]
+#[test]
+fn test_egui_id() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let id = u("inspector").egui_id();
+    assert_eq!(id, u("inspector").egui_id());
+    assert_eq!(id, egui::Id::new(u("inspector")));
+    assert_ne!(id, u("outliner").egui_id());
+    assert_eq!(id.with(u("row")), id.with(u("row")));
+    assert_ne!(id.with(u("row")), u("row").egui_id());
+
+    // Clearing the cache doesn't change the ids of the names.
+    unsafe { super::_clear_cache() };
+    assert_eq!(u("inspector").egui_id(), id);
+}
+
+#[cfg(feature = "imgui")]
+#[test]
+fn test_imgui_id() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let mut ctx = imgui::Context::create();
+    ctx.set_ini_filename(None);
+    ctx.io_mut().display_size = [320.0, 240.0];
+    ctx.fonts().build_rgba32_texture();
+    let ui = ctx.new_frame();
+
+    let id = u("inspector").imgui_id(ui);
+    assert_eq!(id, u("inspector").imgui_id(ui));
+    assert_ne!(id, u("outliner").imgui_id(ui));
+    let child = {
+        let _token = u("panel").push_imgui_id(ui);
+        u("inspector").imgui_id(ui)
+    };
+    assert_ne!(child, id);
+    assert_eq!(u("inspector").imgui_id(ui), id);
+}
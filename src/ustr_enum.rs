@@ -0,0 +1,129 @@
+use super::{PerfectHash, Ustr};
+
+/// Conversions between a fieldless enum and the `Ustr`s of its variants'
+/// names.
+///
+/// Derive this with `#[derive(UstrEnum)]` from the `derive` feature rather
+/// than implementing it by hand. The derive also implements `From<E> for
+/// Ustr`, and `TryFrom<Ustr> for E`, which hands back the `Ustr` if it isn't
+/// the name of a variant.
+///
+/// The variants' names are interned the first time the enum is converted,
+/// along with a [`PerfectHash`] over them, so turning a `Ustr` into a variant
+/// is a single table lookup instead of a string comparison for each variant,
+/// and turning a variant into a `Ustr` is an array index.
+///
+/// Variants are named by their identifiers, unless they're renamed with
+/// `#[ustr(rename = "...")]`, or the enum has a `#[ustr(rename_all =
+/// "...")]` attribute with one of `"lowercase"`, `"UPPERCASE"`,
+/// `"snake_case"`, `"SCREAMING_SNAKE_CASE"`, `"kebab-case"` or
+/// `"camelCase"`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, Ustr, UstrEnum};
+///
+/// #[derive(Debug, Clone, Copy, PartialEq, UstrEnum)]
+/// #[ustr(rename_all = "snake_case")]
+/// enum Wrap {
+///     Repeat,
+///     MirroredRepeat,
+///     #[ustr(rename = "clamp")]
+///     ClampToEdge,
+/// }
+///
+/// let wrap = Wrap::from_ustr(u("mirrored_repeat"));
+/// assert_eq!(wrap, Some(Wrap::MirroredRepeat));
+/// assert_eq!(Wrap::from_name("clamp"), Some(Wrap::ClampToEdge));
+/// assert_eq!(Wrap::try_from(u("border")), Err(u("border")));
+/// let name: Ustr = Wrap::Repeat.into();
+/// assert_eq!(name, u("repeat"));
+/// ```
+pub trait UstrEnum: Sized + Copy + 'static {
+    /// The name of each variant, in declaration order.
+    const NAMES: &'static [&'static str];
+
+    /// Every variant, in declaration order.
+    const VARIANTS: &'static [Self];
+
+    /// The position of this variant in [`VARIANTS`](UstrEnum::VARIANTS).
+    fn index(self) -> usize;
+
+    /// The interned names and their perfect hash, built the first time
+    /// they're needed.
+    #[doc(hidden)]
+    fn table() -> &'static UstrEnumTable;
+
+    /// The `Ustr` of this variant's name.
+    #[inline]
+    fn to_ustr(self) -> Ustr {
+        Self::table().ustrs[self.index()]
+    }
+
+    /// The variant named by `name`, if there is one.
+    #[inline]
+    fn from_ustr(name: Ustr) -> Option<Self> {
+        Self::table()
+            .index_of(name)
+            .map(|index| Self::VARIANTS[index])
+    }
+
+    /// The variant named by `name`, if there is one.
+    ///
+    /// This looks up `name` in the perfect hash without adding it to the
+    /// cache.
+    fn from_name(name: &str) -> Option<Self> {
+        let table = Self::table();
+        let slot = table.phf.index_of(name)?;
+        Some(Self::VARIANTS[table.variants[slot] as usize])
+    }
+}
+
+/// The lookup tables behind a [`UstrEnum`], which its derive creates.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct UstrEnumTable {
+    // The `Ustr` of each variant's name, in declaration order.
+    ustrs: Box<[Ustr]>,
+    phf: PerfectHash,
+    // The variant whose name is in each slot of `phf`.
+    variants: Box<[u32]>,
+}
+
+impl UstrEnumTable {
+    /// Intern `names` and build a perfect hash over them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two of the names are the same.
+    pub fn new(names: &[&str]) -> UstrEnumTable {
+        let ustrs = names.iter().map(|n| Ustr::from(n)).collect::<Box<[_]>>();
+        let entries = ustrs
+            .iter()
+            .map(|u| u.as_string_cache_entry() as *const _)
+            .collect::<Vec<_>>();
+        let phf = PerfectHash::new(&entries).expect("duplicate variant names");
+        let mut variants = vec![0; ustrs.len()].into_boxed_slice();
+        for (i, u) in ustrs.iter().enumerate() {
+            let slot = phf
+                .index_of_canonical(u, u.precomputed_hash())
+                .expect("every name is in the table");
+            variants[slot] = i as u32;
+        }
+        UstrEnumTable {
+            ustrs,
+            phf,
+            variants,
+        }
+    }
+
+    // The position of the variant named `name`, if there is one.
+    #[inline]
+    fn index_of(&self, name: Ustr) -> Option<usize> {
+        let slot = self
+            .phf
+            .index_of_canonical(&name, name.precomputed_hash())?;
+        Some(self.variants[slot] as usize)
+    }
+}
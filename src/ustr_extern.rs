@@ -15,3 +15,71 @@ pub extern "C" fn ustr_len(u: Ustr) -> usize {
 pub extern "C" fn ustr_hash(u: Ustr) -> u64 {
     u.precomputed_hash()
 }
+
+#[no_mangle]
+pub extern "C" fn ustr_shared_cache() -> *const std::ffi::c_void {
+    ustr::shared_cache() as *const ustr::SharedCache as *const _
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_from_utf16(units: *const u16) -> Ustr {
+    // Find the null terminator.
+    let mut len = 0;
+    while unsafe { *units.add(len) } != 0 {
+        len += 1;
+    }
+    Ustr::from_utf16_lossy(unsafe { std::slice::from_raw_parts(units, len) })
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_to_utf16(u: Ustr) -> *const u16 {
+    u.as_wide_cached().as_ptr()
+}
+
+// `wchar_t` is UTF-16 on Windows.
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn ustr_from_wide(chars: *const u16) -> Ustr {
+    ustr_from_utf16(chars)
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn ustr_to_wide(u: Ustr) -> *const u16 {
+    ustr_to_utf16(u)
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_cache_new() -> *mut ustr::ForkedCache {
+    Box::into_raw(Box::new(ustr::ForkedCache::new()))
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_cache_free(cache: *mut ustr::ForkedCache) {
+    if !cache.is_null() {
+        drop(unsafe { Box::from_raw(cache) });
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_cache_intern(
+    cache: *const ustr::ForkedCache,
+    chars: *const std::os::raw::c_char,
+) -> *const std::os::raw::c_char {
+    let cs = unsafe { std::ffi::CStr::from_ptr(chars).to_string_lossy() };
+    let cache = unsafe { &*cache };
+    cache.intern(&cs).as_str().as_ptr().cast()
+}
+
+#[no_mangle]
+pub extern "C" fn ustr_cache_get(
+    cache: *const ustr::ForkedCache,
+    chars: *const std::os::raw::c_char,
+) -> *const std::os::raw::c_char {
+    let cs = unsafe { std::ffi::CStr::from_ptr(chars).to_string_lossy() };
+    let cache = unsafe { &*cache };
+    match cache.get(&cs) {
+        Some(u) => u.as_str().as_ptr().cast(),
+        None => std::ptr::null(),
+    }
+}
@@ -0,0 +1,155 @@
+use super::Ustr;
+use parking_lot::RwLock;
+use std::{cell::RefCell, char::DecodeUtf16Error, collections::HashMap};
+
+thread_local! {
+    // Reused for decoding so that interning a string that's already in the
+    // cache doesn't allocate.
+    static SCRATCH: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+// Decode `units` into the scratch buffer, replacing unpaired surrogates with
+// `replacement` or failing if there isn't one, then pass it to `f`.
+fn with_decoded<R>(
+    units: &[u16],
+    replacement: Option<char>,
+    f: fn(&str) -> R,
+) -> Result<R, DecodeUtf16Error> {
+    SCRATCH.with(|scratch| {
+        let mut decoded = scratch.borrow_mut();
+        decoded.clear();
+        for c in char::decode_utf16(units.iter().copied()) {
+            decoded.push(match (c, replacement) {
+                (Ok(c), _) => c,
+                (Err(_), Some(r)) => r,
+                (Err(e), None) => return Err(e),
+            });
+        }
+        Ok(f(&decoded))
+    })
+}
+
+// The UTF-16 form of each string that `as_wide_cached()` has been called on.
+// The buffers are leaked so they can be handed out as `'static`.
+static WIDE: RwLock<Option<HashMap<Ustr, &'static [u16]>>> = RwLock::new(None);
+
+impl Ustr {
+    /// Create a `Ustr` from UTF-16, such as a string from a Windows API.
+    ///
+    /// The UTF-16 is converted to UTF-8 in a buffer that's reused between
+    /// calls, so if the string is already in the cache this doesn't allocate.
+    /// Returns an error if `units` contains an unpaired surrogate, which isn't
+    /// valid Unicode but is allowed in Windows paths and registry keys. Use
+    /// [`Ustr::from_utf16_lossy()`] to accept those.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// let wide = "C:\\Windows".encode_utf16().collect::<Vec<_>>();
+    /// assert_eq!(Ustr::from_utf16(&wide), Ok(u("C:\\Windows")));
+    /// assert!(Ustr::from_utf16(&[0xd800]).is_err());
+    /// ```
+    pub fn from_utf16(units: &[u16]) -> Result<Ustr, DecodeUtf16Error> {
+        with_decoded(units, None, Ustr::from)
+    }
+
+    /// Create a `Ustr` from UTF-16, replacing any unpaired surrogates with
+    /// U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// This is like [`Ustr::from_utf16()`], but never fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Ustr::from`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::{ustr as u, Ustr};
+    ///
+    /// let wide = [0x61, 0xd800, 0x62];
+    /// assert_eq!(Ustr::from_utf16_lossy(&wide), u("a\u{fffd}b"));
+    /// ```
+    pub fn from_utf16_lossy(units: &[u16]) -> Ustr {
+        with_decoded(units, Some(char::REPLACEMENT_CHARACTER), Ustr::from)
+            .expect("lossy decoding can't fail")
+    }
+
+    /// Get this string as null-terminated UTF-16, encoding it the first time
+    /// this is called for each string.
+    ///
+    /// The returned slice ends with a 0, so its pointer can be passed straight
+    /// to Windows APIs that take a wide string. The encoding is kept for the
+    /// life of the program, so this is meant for the strings, such as paths,
+    /// that are handed back to those APIs repeatedly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ustr::ustr as u;
+    ///
+    /// let path = u("C:\\Temp");
+    /// let wide = path.as_wide_cached();
+    /// assert_eq!(wide.len(), path.len() + 1);
+    /// assert_eq!(wide.last(), Some(&0));
+    /// assert_eq!(wide.as_ptr(), path.as_wide_cached().as_ptr());
+    /// ```
+    pub fn as_wide_cached(&self) -> &'static [u16] {
+        if let Some(wide) =
+            WIDE.read().as_ref().and_then(|map| map.get(self).copied())
+        {
+            return wide;
+        }
+        let mut map = WIDE.write();
+        map.get_or_insert_with(HashMap::new)
+            .entry(*self)
+            .or_insert_with(|| {
+                self.encode_utf16().chain([0]).collect::<Vec<_>>().leak()
+            })
+    }
+}
+
+// Forget the cached UTF-16 of every string. Only called by `_clear_cache()`.
+pub(crate) fn clear() {
+    *WIDE.write() = None;
+}
+
+#[test]
+fn test_utf16() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let strings = ["", "ascii", "naïve", "日本語", "emoji 🦀 crab"];
+    for s in strings {
+        let wide = s.encode_utf16().collect::<Vec<_>>();
+        assert_eq!(Ustr::from_utf16(&wide).unwrap(), s);
+        assert_eq!(Ustr::from_utf16_lossy(&wide), s);
+
+        let cached = u(s).as_wide_cached();
+        assert_eq!(&cached[..wide.len()], &wide[..]);
+        assert_eq!(cached[wide.len()], 0);
+    }
+
+    // Unpaired surrogates, leading and trailing.
+    assert!(Ustr::from_utf16(&[0x61, 0xdc00]).is_err());
+    assert!(Ustr::from_utf16(&[0xd83e]).is_err());
+    assert_eq!(Ustr::from_utf16_lossy(&[0xdc00, 0x61]), "\u{fffd}a");
+    assert_eq!(Ustr::from_utf16_lossy(&[0x61, 0xd83e]), "a\u{fffd}");
+
+    // Nothing is cached twice.
+    let a = u("cached").as_wide_cached();
+    assert_eq!(a.as_ptr(), u("cached").as_wide_cached().as_ptr());
+
+    unsafe { super::_clear_cache() };
+    assert!(WIDE.read().is_none());
+    // Slices handed out before clearing are still valid.
+    assert_eq!(a.len(), 7);
+}
@@ -0,0 +1,277 @@
+use super::Ustr;
+use std::{
+    fmt,
+    iter::FromIterator,
+    ops::{Index, IndexMut},
+};
+
+/// A map keyed by `Ustr` that stores its values in a `Vec` indexed by each
+/// key's [`id()`](Ustr::id).
+///
+/// Since ids are dense, small integers handed out in insertion order, looking
+/// up a key is an array access, with no hashing or probing at all. That makes
+/// this a good fit for per-name component storage, where the same set of
+/// names is looked up over and over. The `Vec` grows to fit the largest id
+/// used as a key, so it's best suited to keys that were added to the cache
+/// around the same time, such as the names loaded from a single file: a map
+/// holding just the millionth string in the cache allocates room for a
+/// million values. Use a [`UstrMap`](crate::UstrMap) for sparse keys.
+///
+/// Indexing with a key that's not in the map panics, like `HashMap`.
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, UstrVecMap};
+///
+/// let mut health = UstrVecMap::new();
+/// health.insert(u("player"), 100);
+/// health.insert(u("enemy"), 30);
+///
+/// health[u("enemy")] -= 10;
+/// assert_eq!(health[u("enemy")], 20);
+/// assert_eq!(health.get(u("boss")), None);
+/// ```
+#[derive(Clone)]
+pub struct UstrVecMap<V> {
+    slots: Vec<Option<(Ustr, V)>>,
+    len: usize,
+}
+
+impl<V> UstrVecMap<V> {
+    /// Create an empty map. This doesn't allocate until a value is inserted.
+    pub const fn new() -> UstrVecMap<V> {
+        UstrVecMap {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Create an empty map with room for keys with ids up to `capacity`
+    /// without reallocating.
+    pub fn with_capacity(capacity: usize) -> UstrVecMap<V> {
+        UstrVecMap {
+            slots: Vec::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the map has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert `value` for `key`, returning the value that was there before.
+    pub fn insert(&mut self, key: Ustr, value: V) -> Option<V> {
+        let index = key.id() as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        let old = self.slots[index].replace((key, value)).map(|(_, v)| v);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Get the value for `key`, if there is one.
+    #[inline]
+    pub fn get(&self, key: Ustr) -> Option<&V> {
+        match self.slots.get(key.id() as usize) {
+            Some(Some((_, value))) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable reference to the value for `key`, if there is one.
+    #[inline]
+    pub fn get_mut(&mut self, key: Ustr) -> Option<&mut V> {
+        match self.slots.get_mut(key.id() as usize) {
+            Some(Some((_, value))) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value for `key`, inserting the result of `f` if there isn't
+    /// one.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(
+        &mut self,
+        key: Ustr,
+        f: F,
+    ) -> &mut V {
+        if self.get(key).is_none() {
+            self.insert(key, f());
+        }
+        self.get_mut(key).expect("value was just inserted")
+    }
+
+    /// Returns `true` if there's a value for `key`.
+    pub fn contains_key(&self, key: Ustr) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove the value for `key`, returning it if there was one.
+    pub fn remove(&mut self, key: Ustr) -> Option<V> {
+        let old = self
+            .slots
+            .get_mut(key.id() as usize)
+            .and_then(Option::take)
+            .map(|(_, v)| v);
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    /// Remove every value, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.len = 0;
+    }
+
+    /// Iterate over the keys and values, in the order the keys were added
+    /// to the cache.
+    pub fn iter(&self) -> impl Iterator<Item = (Ustr, &V)> + '_ {
+        self.slots.iter().flatten().map(|(k, v)| (*k, v))
+    }
+
+    /// Iterate over the keys and mutable references to the values, in the
+    /// order the keys were added to the cache.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Ustr, &mut V)> + '_ {
+        self.slots.iter_mut().flatten().map(|(k, v)| (*k, v))
+    }
+
+    /// Iterate over the keys, in the order they were added to the cache.
+    pub fn keys(&self) -> impl Iterator<Item = Ustr> + '_ {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Iterate over the values, in the order their keys were added to the
+    /// cache.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<V> Default for UstrVecMap<V> {
+    fn default() -> UstrVecMap<V> {
+        UstrVecMap::new()
+    }
+}
+
+impl<V> Index<Ustr> for UstrVecMap<V> {
+    type Output = V;
+
+    #[inline]
+    fn index(&self, key: Ustr) -> &V {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("no value for {:?} in UstrVecMap", key.as_str()),
+        }
+    }
+}
+
+impl<V> IndexMut<Ustr> for UstrVecMap<V> {
+    #[inline]
+    fn index_mut(&mut self, key: Ustr) -> &mut V {
+        match self.get_mut(key) {
+            Some(value) => value,
+            None => panic!("no value for {:?} in UstrVecMap", key.as_str()),
+        }
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for UstrVecMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V: PartialEq> PartialEq for UstrVecMap<V> {
+    fn eq(&self, other: &UstrVecMap<V>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<V: Eq> Eq for UstrVecMap<V> {}
+
+impl<V> Extend<(Ustr, V)> for UstrVecMap<V> {
+    fn extend<I: IntoIterator<Item = (Ustr, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<V> FromIterator<(Ustr, V)> for UstrVecMap<V> {
+    fn from_iter<I: IntoIterator<Item = (Ustr, V)>>(iter: I) -> Self {
+        let mut map = UstrVecMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+#[test]
+fn test_ustr_vec_map() {
+    let _t = super::TEST_LOCK.lock();
+    use super::ustr as u;
+
+    unsafe { super::_clear_cache() };
+
+    let names = (0..100)
+        .map(|i| u(&format!("vec {}", i)))
+        .collect::<Vec<_>>();
+    let mut map = UstrVecMap::new();
+    for (i, name) in names.iter().enumerate().rev().step_by(2) {
+        assert_eq!(map.insert(*name, i), None);
+    }
+    assert_eq!(map.len(), 50);
+    assert_eq!(map[names[99]], 99);
+    assert_eq!(map.get(names[98]), None);
+    assert_eq!(map.insert(names[99], 0), Some(99));
+    map[names[99]] += 1;
+    assert_eq!(map[names[99]], 1);
+    *map.get_or_insert_with(names[0], || 7) += 1;
+    assert_eq!(map[names[0]], 8);
+    assert_eq!(map.len(), 51);
+
+    // Iteration is in id order.
+    let keys = map.keys().collect::<Vec<_>>();
+    assert_eq!(keys[0], names[0]);
+    assert_eq!(keys[1], names[1]);
+    assert!(keys.windows(2).all(|w| w[0].id() < w[1].id()));
+
+    assert_eq!(map.remove(names[1]), Some(1));
+    assert_eq!(map.remove(names[1]), None);
+    assert!(!map.contains_key(names[1]));
+    assert_eq!(map.len(), 50);
+    assert_eq!(map.values().count(), 50);
+    for (_, v) in map.iter_mut() {
+        *v = 0;
+    }
+    assert!(map.values().all(|v| *v == 0));
+
+    let collected = map.iter().map(|(k, v)| (k, *v)).collect::<UstrVecMap<_>>();
+    assert_eq!(collected, map);
+    assert_eq!(
+        format!("{:?}", UstrVecMap::from_iter([(u("k"), 1)])),
+        r#"{u!("k"): 1}"#
+    );
+
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.get(names[99]), None);
+}
+
+#[test]
+#[should_panic(expected = "no value for \"missing\" in UstrVecMap")]
+fn test_ustr_vec_map_missing() {
+    let _t = super::TEST_LOCK.lock();
+    let map = UstrVecMap::<i32>::new();
+    let _ = map[super::ustr("missing")];
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How a lookup checks that an entry in the cache is the string it's looking
+/// for, as passed to [`set_verification()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verification {
+    /// Compare the hash, the length and then every byte of the string. This
+    /// is the default.
+    #[default]
+    Full,
+    /// Treat an entry with the same 64-bit hash and length as the string
+    /// without comparing the bytes.
+    ///
+    /// This makes looking up long strings such as file paths faster, but if
+    /// two different strings of the same length ever have the same hash, one
+    /// of them will be looked up as the other. Only use it if you trust the
+    /// hash to be unique for your strings, e.g. because they're known ahead
+    /// of time and have been checked for collisions.
+    HashOnly,
+}
+
+static HASH_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Set how lookups in the global cache check that they've found the right
+/// string.
+///
+/// This can be called at any time and affects every lookup after it, including
+/// lookups in the [`frozen`](crate::freeze) cache. Each
+/// [`ForkedCache`](crate::ForkedCache) has its own setting, given when it's
+/// created with
+/// [`ForkedCache::with_verification()`](crate::ForkedCache::with_verification).
+///
+/// # Examples
+///
+/// ```
+/// use ustr::{ustr as u, Verification};
+///
+/// let path = u("/projects/show/seq010/shot0040/lighting/v012/beauty.exr");
+/// ustr::set_verification(Verification::HashOnly);
+/// assert_eq!(
+///     u("/projects/show/seq010/shot0040/lighting/v012/beauty.exr"),
+///     path
+/// );
+/// # ustr::set_verification(Verification::Full);
+/// ```
+pub fn set_verification(verification: Verification) {
+    HASH_ONLY.store(verification == Verification::HashOnly, Ordering::Relaxed);
+}
+
+/// Returns the verification mode for the global cache set with
+/// [`set_verification()`].
+pub fn verification() -> Verification {
+    if hash_only() {
+        Verification::HashOnly
+    } else {
+        Verification::Full
+    }
+}
+
+#[inline]
+pub(crate) fn hash_only() -> bool {
+    HASH_ONLY.load(Ordering::Relaxed)
+}
+
+#[test]
+fn test_verification() {
+    let _t = super::TEST_LOCK.lock();
+    use super::{hash_str, ustr as u, ForkedCache, StringCache};
+
+    // Force a collision in a local cache by looking up a different string
+    // with the hash of one that's in it.
+    let mut sc = StringCache::new_local();
+    let hash = hash_str("abc");
+    let abc = sc.insert("abc", hash);
+    assert_eq!(sc.get_existing("xyz", hash), None);
+    assert_eq!(sc.get_existing("abcd", hash), None);
+    sc.hash_only = true;
+    assert_eq!(sc.get_existing("xyz", hash), Some(abc));
+    assert_eq!(sc.get_existing("abcd", hash), None);
+    unsafe { sc.free() };
+
+    // Lookups of different strings still work in either mode.
+    assert_eq!(verification(), Verification::Full);
+    let long = "long/path/".repeat(20);
+    let a = u(&long);
+    set_verification(Verification::HashOnly);
+    assert_eq!(verification(), Verification::HashOnly);
+    assert_eq!(u(&long), a);
+    assert_ne!(u(&long[1..]), a);
+    set_verification(Verification::Full);
+
+    let fork = ForkedCache::with_verification(Verification::HashOnly);
+    let f = fork.intern("only in the fork");
+    assert_eq!(fork.intern("only in the fork"), f);
+    assert_ne!(fork.intern("also in the fork"), f);
+}
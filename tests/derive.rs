@@ -0,0 +1,57 @@
+//! `#[derive(UstrEnum)]`, which has to be used from outside the crate.
+#![cfg(feature = "derive")]
+
+use ustr::{existing_ustr, ustr as u, Ustr, UstrEnum};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UstrEnum)]
+enum Filter {
+    Nearest,
+    Linear,
+    #[ustr(rename = "cubic-bezier")]
+    Cubic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UstrEnum)]
+#[ustr(rename_all = "SCREAMING_SNAKE_CASE")]
+enum Blend {
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    #[ustr(rename = "one")]
+    One,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, UstrEnum)]
+enum Empty {}
+
+#[test]
+fn test_derive_ustr_enum() {
+    assert_eq!(Filter::NAMES, ["Nearest", "Linear", "cubic-bezier"]);
+    assert_eq!(
+        Filter::VARIANTS,
+        [Filter::Nearest, Filter::Linear, Filter::Cubic]
+    );
+    for (i, v) in Filter::VARIANTS.iter().enumerate() {
+        assert_eq!(v.index(), i);
+        assert_eq!(Filter::from_ustr(v.to_ustr()), Some(*v));
+        assert_eq!(Filter::try_from(Into::<Ustr>::into(*v)), Ok(*v));
+    }
+    assert_eq!(Filter::Cubic.to_ustr(), u("cubic-bezier"));
+    assert_eq!(Filter::from_name("Linear"), Some(Filter::Linear));
+    assert_eq!(Filter::from_name("linear"), None);
+    assert_eq!(Filter::try_from(u("Cubic")), Err(u("Cubic")));
+
+    // Looking up a name doesn't intern it.
+    assert_eq!(Filter::from_name("Anisotropic"), None);
+    assert_eq!(existing_ustr("Anisotropic"), None);
+
+    assert_eq!(Blend::NAMES, ["SRC_ALPHA", "ONE_MINUS_SRC_ALPHA", "one"]);
+    assert_eq!(
+        Blend::from_name("ONE_MINUS_SRC_ALPHA"),
+        Some(Blend::OneMinusSrcAlpha)
+    );
+    assert_eq!(Blend::from_ustr(u("SRC_ALPHA")), Some(Blend::SrcAlpha));
+
+    assert!(Empty::NAMES.is_empty());
+    assert_eq!(Empty::from_name("anything"), None);
+    assert_eq!(Empty::from_ustr(u("anything")), None);
+}
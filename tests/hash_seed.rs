@@ -0,0 +1,28 @@
+//! Seeding the hash function, which has to happen before anything is hashed,
+//! so it gets a process of its own.
+#![cfg(not(loom))]
+
+use std::hash::{BuildHasher, Hasher};
+use ustr::ustr as u;
+
+#[test]
+fn set_hash_seed_before_first_use() {
+    const SEED: [u64; 4] = [0x1234, 0x5678, 0x9abc, 0xdef0];
+    assert_eq!(ustr::set_hash_seed(SEED), Ok(()));
+    assert_eq!(ustr::set_hash_seed([0; 4]), Err([0; 4]));
+
+    let seeded =
+        ahash::RandomState::with_seeds(SEED[0], SEED[1], SEED[2], SEED[3]);
+    let expected = |s: &str| {
+        let mut hasher = seeded.build_hasher();
+        hasher.write(s.as_bytes());
+        hasher.finish()
+    };
+    let strings = (0..1000)
+        .map(|i| u(&format!("seeded {}", i)))
+        .collect::<Vec<_>>();
+    for s in &strings {
+        assert_eq!(s.precomputed_hash(), expected(s));
+        assert_eq!(ustr::existing_ustr(s), Some(*s));
+    }
+}
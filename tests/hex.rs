@@ -0,0 +1,111 @@
+//! Checking that `Ustr::from_hex()` and `Ustr::from_uuid()` don't allocate
+//! anything on the way to the cache, which needs a counting global allocator,
+//! so it gets a process of its own.
+//!
+//! Allocation tracking allocates to record new strings, so this is skipped
+//! when it's enabled.
+#![cfg(not(any(loom, feature = "debug-alloc-tracking")))]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+use ustr::{ustr as u, InitConfig, Ustr};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// The number of allocations made by this thread while running `f`.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn formatting_does_not_allocate() {
+    // Make the cache big enough up front that adding the strings below
+    // doesn't grow it, so any allocation would be from formatting them.
+    ustr::init_with(InitConfig {
+        initial_capacity: 1 << 16,
+        initial_arena_size: 64 << 20,
+        preallocate_arenas: true,
+    });
+    // Fill the first segments of the id table, and put a string in every bin
+    // so that each has a filter with the `"negative-filter"` feature, which
+    // is otherwise only allocated when the bin's first string is added.
+    let mut i = 0;
+    while i < 1100 || ustr::bin_stats().iter().any(|b| b.num_entries == 0) {
+        u(&format!("warm up {}", i));
+        i += 1;
+    }
+    let long = (0..=255u8).collect::<Vec<_>>();
+    Ustr::from_hex(&long);
+
+    let blobs = (0..300u32)
+        .map(|i| [i.to_le_bytes(), [0xab; 4]].concat())
+        .collect::<Vec<_>>();
+    let long_blobs = (0..100u8)
+        .map(|i| {
+            let mut blob = long.clone();
+            blob[0] = i;
+            blob
+        })
+        .collect::<Vec<_>>();
+    let expected = blobs
+        .iter()
+        .chain(&long_blobs)
+        .map(|b| b.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        .collect::<Vec<_>>();
+
+    let mut interned = Vec::with_capacity(blobs.len() + long_blobs.len());
+    let n = allocations(|| {
+        for blob in blobs.iter().chain(&long_blobs) {
+            interned.push(Ustr::from_hex(blob));
+        }
+    });
+    assert_eq!(n, 0);
+    for (u, expected) in interned.iter().zip(&expected) {
+        assert_eq!(u.as_str(), expected);
+    }
+    let n = allocations(|| {
+        for blob in blobs.iter().chain(&long_blobs) {
+            Ustr::from_hex(blob);
+        }
+    });
+    assert_eq!(n, 0);
+
+    #[cfg(feature = "uuid")]
+    {
+        let ids = (0..300u128)
+            .map(|i| uuid::Uuid::from_u128(i << 64 | 0xfeed))
+            .collect::<Vec<_>>();
+        let mut interned = Vec::with_capacity(ids.len());
+        let n = allocations(|| {
+            for id in &ids {
+                interned.push(Ustr::from_uuid(id));
+            }
+        });
+        assert_eq!(n, 0);
+        for (u, id) in interned.iter().zip(&ids) {
+            assert_eq!(u.as_str(), id.to_string());
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Initializing the cache with a custom configuration, which has to happen
+//! before anything else uses it, so it gets a process of its own.
+#![cfg(not(loom))]
+
+use ustr::{ustr as u, InitConfig};
+
+#[test]
+fn init_with_tiny_config() {
+    assert!(!ustr::is_initialized());
+    ustr::init_with(InitConfig {
+        initial_capacity: 0,
+        initial_arena_size: 0,
+        preallocate_arenas: true,
+    });
+    assert!(ustr::is_initialized());
+    assert!(ustr::bin_stats().iter().all(|b| b.table_capacity == 1));
+
+    // Everything still works, it just has to grow straight away.
+    let strings = (0..10_000)
+        .map(|i| u(&format!("string {}", i)))
+        .collect::<Vec<_>>();
+    for (i, s) in strings.iter().enumerate() {
+        assert_eq!(*s, u(&format!("string {}", i)));
+    }
+    assert_eq!(ustr::num_entries(), strings.len());
+
+    ustr::init();
+    assert!(std::panic::catch_unwind(|| {
+        ustr::init_with(InitConfig::default())
+    })
+    .is_err());
+}
@@ -0,0 +1,78 @@
+//! Model checks of adding strings to the cache from several threads at once.
+//!
+//! These only build with loom's primitives swapped in, and explore every
+//! interleaving of the threads, so they're slow. Run them with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+#![cfg(loom)]
+
+use loom::thread::{self, JoinHandle};
+use ustr::{existing_ustr, from_id, ustr as u};
+
+// loom's threads have small stacks by default, which isn't enough for
+// creating the cache.
+fn spawn<T, F>(f: F) -> JoinHandle<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    thread::Builder::new().stack_size(1 << 20).spawn(f).unwrap()
+}
+
+// Run `f` in a thread with a big enough stack, under every interleaving.
+fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let f = std::sync::Arc::new(f);
+    loom::model(move || {
+        let f = f.clone();
+        spawn(move || f()).join().unwrap();
+    });
+}
+
+#[test]
+fn same_string() {
+    model(|| {
+        let t = spawn(|| u("same"));
+        let a = u("same");
+        let b = t.join().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.id(), 0);
+        assert_eq!(ustr::num_entries(), 1);
+        assert_eq!(from_id(0), Some(a));
+    });
+}
+
+#[test]
+fn different_strings() {
+    model(|| {
+        let t = spawn(|| u("first"));
+        let a = u("second");
+        let b = t.join().unwrap();
+        assert_ne!(a, b);
+        // Both threads race to allocate the first segment of the id table.
+        let mut ids = [a.id(), b.id()];
+        ids.sort();
+        assert_eq!(ids, [0, 1]);
+        assert_eq!(from_id(a.id()), Some(a));
+        assert_eq!(from_id(b.id()), Some(b));
+    });
+}
+
+#[test]
+fn lookup_while_adding() {
+    model(|| {
+        let t = spawn(|| u("published"));
+        // Whenever another thread can see the new string, it must be
+        // complete.
+        if let Some(found) = from_id(0) {
+            assert_eq!(found, "published");
+        }
+        if let Some(found) = existing_ustr("published") {
+            assert_eq!(found.id(), 0);
+            assert_eq!(from_id(0), Some(found));
+        }
+        let added = t.join().unwrap();
+        assert_eq!(existing_ustr("published"), Some(added));
+    });
+}
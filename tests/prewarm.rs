@@ -0,0 +1,23 @@
+//! Prewarming the cache from `USTR_PREWARM` when it's first used, which has
+//! to happen before anything else uses it, so it gets a process of its own.
+#![cfg(all(feature = "prewarm", not(loom)))]
+
+use ustr::ustr as u;
+
+#[test]
+fn prewarm_on_first_use() {
+    let path = std::env::temp_dir()
+        .join(format!("ustr-prewarm-test-{}.ustrdict", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    ustr::build_dictionary(["warm", "strings"], file).unwrap();
+    assert!(!ustr::is_initialized());
+
+    std::env::set_var("USTR_PREWARM", &path);
+    let existing = ustr::existing_ustr("warm");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(existing, Some(u("warm")));
+    assert_eq!(ustr::num_entries(), 2);
+    // It's only loaded once.
+    assert!(ustr::prewarm_from_env().unwrap().is_none());
+}